@@ -0,0 +1,323 @@
+//! Sources of measured zone data, independent of where the data is stored.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+
+use chrono::{DateTime, Utc};
+use uom::si::{
+    f64::{Power, Ratio, ThermodynamicTemperature, Velocity},
+    power::watt,
+    ratio::percent,
+    thermodynamic_temperature::degree_celsius,
+    velocity::meter_per_second,
+};
+
+use crate::model::Model;
+use crate::simulation::{solar_gains, Inputs};
+
+/// Values of the measurements of a zone, keyed by the measurement name.
+/// Each measurement has its values with their timestamps, sorted by time.
+pub type ZoneValues = HashMap<String, Vec<(DateTime<Utc>, String)>>;
+
+/// Future returned by the data source methods, boxed so that the trait can be used as `dyn`.
+pub type DataSourceFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// Anything that can provide the measurements of zones.
+pub trait ZoneDataSource {
+    /// Check if the source has measurements of a zone.
+    fn has_zone(&self, zone: &str) -> bool;
+
+    /// Read values of all measurements configured for a zone.
+    fn read_zone<'a>(&'a self, zone: &'a str) -> DataSourceFuture<'a, ZoneValues>;
+}
+
+/// In-memory data source returning fixed values, for testing without a live database.
+#[derive(Clone, Debug, Default)]
+pub struct MockDataSource {
+    zones: HashMap<String, ZoneValues>,
+}
+
+impl MockDataSource {
+    /// Create the data source from measurement values of each zone.
+    /// Values of each measurement are sorted by time, like a real data source returns them.
+    pub fn new(mut zones: HashMap<String, ZoneValues>) -> Self {
+        for values in zones.values_mut().flat_map(|zone| zone.values_mut()) {
+            values.sort_by_key(|(time, _)| *time);
+        }
+        MockDataSource { zones }
+    }
+}
+
+impl ZoneDataSource for MockDataSource {
+    fn has_zone(&self, zone: &str) -> bool {
+        self.zones.contains_key(zone)
+    }
+
+    fn read_zone<'a>(&'a self, zone: &'a str) -> DataSourceFuture<'a, ZoneValues> {
+        Box::pin(async move {
+            self.zones
+                .get(zone)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Zone {} not found", zone))
+        })
+    }
+}
+
+/// Read several zones concurrently.
+/// Each zone gets its own result, so a failing zone doesn't prevent reading the others.
+pub async fn read_zones<'a, S: ZoneDataSource + ?Sized>(
+    source: &'a S,
+    zones: &[&'a str],
+) -> HashMap<String, anyhow::Result<ZoneValues>> {
+    let mut pending: Vec<_> = zones
+        .iter()
+        .map(|zone| (*zone, Some(source.read_zone(zone))))
+        .collect();
+    let mut results = HashMap::new();
+    std::future::poll_fn(|cx| {
+        for (zone, future) in pending.iter_mut() {
+            if let Some(running) = future {
+                if let Poll::Ready(result) = running.as_mut().poll(cx) {
+                    results.insert(zone.to_string(), result);
+                    *future = None;
+                }
+            }
+        }
+        if pending.iter().all(|(_, future)| future.is_none()) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    results
+}
+
+/// Read the current weather from a data source and assemble it into inputs for solving
+/// the model at time `now`.
+///
+/// Uses the latest values of measurements of the reserved zones:
+/// * `outside`/`temperature` [°C] - required
+/// * `outside`/`cloud_cover` [%] - used for solar gains, clear sky is assumed if missing
+/// * `outside`/`wind_speed` [m/s] - optional
+/// * `ground`/`temperature` [°C] - optional, ground is left free if missing
+///
+/// Heat gains are the solar gains and the internal gains of the zones at `now`.
+pub async fn current_inputs<S: ZoneDataSource + ?Sized>(
+    source: &S,
+    model: &Model,
+    now: &DateTime<Utc>,
+) -> anyhow::Result<Inputs> {
+    let outside = source.read_zone("outside").await?;
+    let outside_temperature = latest_value(&outside, "outside", "temperature")?
+        .ok_or_else(|| anyhow::anyhow!("Zone outside has no temperature measurement"))?;
+    let mut boundary_temperatures = HashMap::from([(
+        "outside".to_string(),
+        ThermodynamicTemperature::new::<degree_celsius>(outside_temperature),
+    )]);
+
+    if source.has_zone("ground") {
+        let ground = source.read_zone("ground").await?;
+        if let Some(ground_temperature) = latest_value(&ground, "ground", "temperature")? {
+            boundary_temperatures.insert(
+                "ground".to_string(),
+                ThermodynamicTemperature::new::<degree_celsius>(ground_temperature),
+            );
+        }
+    }
+
+    let wind_speed =
+        latest_value(&outside, "outside", "wind_speed")?.map(Velocity::new::<meter_per_second>);
+
+    let cloud_cover =
+        Ratio::new::<percent>(latest_value(&outside, "outside", "cloud_cover")?.unwrap_or(0.0));
+    let mut heat_gains = solar_gains(model, now, cloud_cover)?;
+    for (zone, gain) in model.internal_gains(now) {
+        *heat_gains
+            .entry(zone)
+            .or_insert_with(|| Power::new::<watt>(0.0)) += gain;
+    }
+
+    Ok(Inputs {
+        boundary_temperatures,
+        heat_gains,
+        wind_speed,
+        ..Default::default()
+    })
+}
+
+/// Latest value of a measurement of a zone, parsed as a number.
+/// Returns `None` if the zone doesn't have the measurement, fails if it has no values.
+fn latest_value(values: &ZoneValues, zone: &str, measurement: &str) -> anyhow::Result<Option<f64>> {
+    let Some(series) = values.get(measurement) else {
+        return Ok(None);
+    };
+    let (_, value) = series.last().ok_or_else(|| {
+        anyhow::anyhow!("No data for measurement {} of zone {}", measurement, zone)
+    })?;
+    let number = value.parse().map_err(|e| {
+        anyhow::anyhow!(
+            "Can't parse value {:?} of measurement {}: {}",
+            value,
+            measurement,
+            e
+        )
+    })?;
+    Ok(Some(number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2023-01-01T{:02}:00:00Z", hour))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn values(values: &[(u32, &str)]) -> Vec<(DateTime<Utc>, String)> {
+        values
+            .iter()
+            .map(|(hour, value)| (time(*hour), value.to_string()))
+            .collect()
+    }
+
+    fn source() -> Box<dyn ZoneDataSource> {
+        Box::new(MockDataSource::new(HashMap::from([(
+            "kitchen".to_string(),
+            HashMap::from([(
+                "temperature".to_string(),
+                values(&[(1, "21.5"), (0, "21.0")]),
+            )]),
+        )])))
+    }
+
+    #[tokio::test]
+    async fn mock_read_zone() {
+        let kitchen = source().read_zone("kitchen").await.unwrap();
+        assert_eq!(kitchen["temperature"], values(&[(0, "21.0"), (1, "21.5")]));
+    }
+
+    #[tokio::test]
+    async fn mock_read_zones() {
+        let source = MockDataSource::new(HashMap::from([
+            (
+                "kitchen".to_string(),
+                HashMap::from([("temperature".to_string(), values(&[(0, "21.5")]))]),
+            ),
+            (
+                "bedroom".to_string(),
+                HashMap::from([("temperature".to_string(), values(&[(0, "19.0")]))]),
+            ),
+        ]));
+        let results = read_zones(&source, &["kitchen", "bedroom", "attic"]).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results["kitchen"].as_ref().unwrap()["temperature"],
+            values(&[(0, "21.5")])
+        );
+        assert_eq!(
+            results["bedroom"].as_ref().unwrap()["temperature"],
+            values(&[(0, "19.0")])
+        );
+        assert!(results["attic"].is_err());
+    }
+
+    fn weather_source(outside: &[(&str, &str)], ground: Option<&str>) -> MockDataSource {
+        let zone = |measurements: &[(&str, &str)]| {
+            measurements
+                .iter()
+                .map(|(measurement, value)| {
+                    (measurement.to_string(), values(&[(0, "0"), (1, value)]))
+                })
+                .collect()
+        };
+        let mut zones = HashMap::from([("outside".to_string(), zone(outside))]);
+        if let Some(ground) = ground {
+            zones.insert("ground".to_string(), zone(&[("temperature", ground)]));
+        }
+        MockDataSource::new(zones)
+    }
+
+    #[tokio::test]
+    async fn current_inputs_from_mock() {
+        let model = Model::from_json(
+            r#"{
+            location: { latitude: 49.4949522, longitude: 17.4302361 },
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { office: { volume: 50, internal_gains: 100 } },
+            boundaries: [{ boundary_type: "window", zones: ["office", "outside"], area: 2 }],
+        }"#,
+        )
+        .unwrap();
+        // Night, no solar gains
+        let night = time(22);
+        let source = weather_source(
+            &[
+                ("temperature", "-5"),
+                ("cloud_cover", "50"),
+                ("wind_speed", "3"),
+            ],
+            Some("8"),
+        );
+
+        let inputs = current_inputs(&source, &model, &night).await.unwrap();
+
+        assert_eq!(
+            inputs.boundary_temperatures,
+            HashMap::from([
+                (
+                    "outside".to_string(),
+                    ThermodynamicTemperature::new::<degree_celsius>(-5.0)
+                ),
+                (
+                    "ground".to_string(),
+                    ThermodynamicTemperature::new::<degree_celsius>(8.0)
+                ),
+            ])
+        );
+        assert_eq!(
+            inputs.wind_speed,
+            Some(Velocity::new::<meter_per_second>(3.0))
+        );
+        assert_eq!(
+            inputs.heat_gains,
+            HashMap::from([("office".to_string(), Power::new::<watt>(100.0))])
+        );
+
+        // Noon, clear sky is assumed without cloud cover, ground is optional
+        let noon = time(11);
+        let source = weather_source(&[("temperature", "-5")], None);
+
+        let inputs = current_inputs(&source, &model, &noon).await.unwrap();
+
+        assert_eq!(inputs.boundary_temperatures.len(), 1);
+        assert_eq!(inputs.wind_speed, None);
+        let solar = solar_gains(&model, &noon, Ratio::new::<percent>(0.0)).unwrap();
+        assert!(solar["office"].get::<watt>() > 0.0);
+        assert_eq!(
+            inputs.heat_gains["office"],
+            solar["office"] + Power::new::<watt>(100.0)
+        );
+
+        let message = current_inputs(&weather_source(&[], None), &model, &noon)
+            .await
+            .unwrap_err()
+            .to_string();
+        message
+            .find("no temperature measurement")
+            .expect("Error message should mention the missing temperature");
+    }
+
+    #[tokio::test]
+    async fn mock_unknown_zone() {
+        let message = format!("{}", source().read_zone("attic").await.unwrap_err());
+        message
+            .find("attic")
+            .expect("Error message should contain the zone name");
+    }
+}
@@ -1,12 +1,28 @@
 extern crate influxrs;
 
-use influxrs::{InfluxClient, Query};
+use chrono::{DateTime, SecondsFormat, Utc};
+use influxrs::{InfluxClient, InfluxError, Query};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
+use std::future::Future;
 use std::path::Path;
+use std::time::Duration;
+use uom::si::{
+    f64::{HeatFluxDensity, Ratio, ThermodynamicTemperature, Velocity},
+    heat_flux_density::watt_per_square_meter,
+    ratio::percent,
+    thermodynamic_temperature::degree_celsius,
+    velocity::meter_per_second,
+};
 
-#[derive(Clone)]
+use crate::data_source::{
+    current_inputs, read_zones, DataSourceFuture, ZoneDataSource, ZoneValues,
+};
+use crate::model::Model;
+use crate::simulation::Inputs;
+
+#[derive(Clone, Debug)]
 pub struct InfluxQuery {
     query: Vec<String>,
 }
@@ -46,36 +62,175 @@ impl InfluxQuery {
         self
     }
 
+    /// Downsample the data to windows of `every` (Flux duration, e.g. `"15m"`),
+    /// aggregated by the Flux function `fn_name` (e.g. `"mean"`). Empty windows are skipped.
+    pub fn aggregate_window(&mut self, every: &str, fn_name: &str) -> &mut InfluxQuery {
+        self.query.push(format!(
+            "|> aggregateWindow(every: {}, fn: {}, createEmpty: false)",
+            every, fn_name
+        ));
+        self
+    }
+
     pub fn get_query_string(&self) -> String {
         self.query.join(" ")
     }
 }
 
+/// Physical quantity stored in a measurement, determines its unit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasurementKind {
+    /// Stored in °C
+    Temperature,
+    /// Stored in %
+    CloudCover,
+    /// Stored in m/s
+    WindSpeed,
+    /// Stored in W/m²
+    Irradiance,
+}
+impl MeasurementKind {
+    /// Kind of a measurement with the given name, if the name is one of the kinds
+    /// (e.g. measurement `temperature` is a temperature).
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "temperature" => Some(MeasurementKind::Temperature),
+            "cloud_cover" => Some(MeasurementKind::CloudCover),
+            "wind_speed" => Some(MeasurementKind::WindSpeed),
+            "irradiance" => Some(MeasurementKind::Irradiance),
+            _ => None,
+        }
+    }
+
+    /// Convert a value read from the database to a quantity of this kind.
+    pub fn parse(self, value: &str) -> anyhow::Result<MeasurementValue> {
+        let number: f64 = value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Can't parse value {:?} as {:?}: {}", value, self, e))?;
+        Ok(match self {
+            MeasurementKind::Temperature => MeasurementValue::Temperature(
+                ThermodynamicTemperature::new::<degree_celsius>(number),
+            ),
+            MeasurementKind::CloudCover => {
+                MeasurementValue::CloudCover(Ratio::new::<percent>(number))
+            }
+            MeasurementKind::WindSpeed => {
+                MeasurementValue::WindSpeed(Velocity::new::<meter_per_second>(number))
+            }
+            MeasurementKind::Irradiance => {
+                MeasurementValue::Irradiance(HeatFluxDensity::new::<watt_per_square_meter>(number))
+            }
+        })
+    }
+}
+
+/// Value of a measurement converted to a quantity according to its kind.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeasurementValue {
+    Temperature(ThermodynamicTemperature),
+    CloudCover(Ratio),
+    WindSpeed(Velocity),
+    Irradiance(HeatFluxDensity),
+}
+
+/// Latest weather measured outside, converted to quantities for the simulation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutsideConditions {
+    /// Fixed temperature of the `outside` zone
+    pub temperature: ThermodynamicTemperature,
+    /// Cloud cover for `calculate_tilted_irradiance`, `None` if it is not measured
+    pub cloud_cover: Option<Ratio>,
+}
+
+impl OutsideConditions {
+    /// Check that the measured values have the expected kinds.
+    fn new(
+        temperature: MeasurementValue,
+        cloud_cover: Option<MeasurementValue>,
+    ) -> anyhow::Result<Self> {
+        let MeasurementValue::Temperature(temperature) = temperature else {
+            anyhow::bail!(
+                "Measurement temperature of zone outside is not a temperature, got {:?}",
+                temperature
+            );
+        };
+        let cloud_cover = match cloud_cover {
+            Some(MeasurementValue::CloudCover(cloud_cover)) => Some(cloud_cover),
+            Some(other) => anyhow::bail!(
+                "Measurement cloud_cover of zone outside is not a cloud cover, got {:?}",
+                other
+            ),
+            None => None,
+        };
+        Ok(OutsideConditions {
+            temperature,
+            cloud_cover,
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct ConfigDB {
     host: String,
     org: String,
+    /// How many times a query is repeated after a transient failure
+    #[serde(default = "default_max_retries")]
+    max_retries: u32,
+    /// Time limit of a single query attempt [s]
+    #[serde(default = "default_timeout")]
+    timeout: f64,
 }
-#[derive(Debug, Deserialize)]
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_timeout() -> f64 {
+    30.0
+}
+
+/// Handling of failed queries, see `with_retries`.
+#[derive(Clone, Debug, PartialEq)]
+struct RetryPolicy {
+    max_retries: u32,
+    timeout: Duration,
+    /// Wait before the first retry, doubled for each following one
+    initial_backoff: Duration,
+}
+#[derive(Clone, Debug, Deserialize)]
 struct JSONConfigMeasurement {
     bucket: String,
     measurement: String,
     tags: HashMap<String, String>,
     field: String,
+    /// Defaults to the kind matching the measurement type name
+    #[serde(default)]
+    kind: Option<MeasurementKind>,
 }
 #[derive(Debug, Deserialize)]
 struct JSONConfig {
     db: ConfigDB,
     zone_mappings: HashMap<String, HashMap<String, JSONConfigMeasurement>>,
 }
+/// Only the zone mappings part of the config file, used when reloading.
+#[derive(Debug, Deserialize)]
+struct JSONMappingsConfig {
+    zone_mappings: HashMap<String, HashMap<String, JSONConfigMeasurement>>,
+}
 
+#[derive(Debug)]
 pub struct InfluxMeasurement {
     measurement: String,
+    /// Query for the latest value
     query: InfluxQuery,
+    mapping: JSONConfigMeasurement,
+    kind: Option<MeasurementKind>,
 }
 pub struct InfluxDB {
     client: InfluxClient,
     zones: HashMap<String, Vec<InfluxMeasurement>>,
+    retry: RetryPolicy,
 }
 impl InfluxDB {
     pub fn from_config<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
@@ -87,63 +242,858 @@ impl InfluxDB {
                 anyhow::bail!("Error parsing config file: {}", e);
             }
         };
-        let mut zones = HashMap::new();
-
-        for (zone_name, mappings) in config.zone_mappings {
-            for (measurement_name, mapping) in mappings {
-                let query = InfluxQuery::new(&mapping.bucket, "-30d", None)
-                    .filter("_measurement", &mapping.measurement)
-                    .filter("_field", &mapping.field)
-                    .filter_tags(&mapping.tags)
-                    .last()
-                    .clone();
-
-                zones
-                    .entry(zone_name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(InfluxMeasurement {
-                        measurement: measurement_name,
-                        query,
-                    });
-            }
+        let zones = build_zone_queries(config.zone_mappings)?;
+
+        if !(config.db.timeout > 0.0 && config.db.timeout.is_finite()) {
+            anyhow::bail!("Database timeout must be a positive number of seconds");
         }
+        let retry = RetryPolicy {
+            max_retries: config.db.max_retries,
+            timeout: Duration::from_secs_f64(config.db.timeout),
+            initial_backoff: Duration::from_millis(500),
+        };
 
         let key = std::env::var("INFLUX_TOKEN")?;
         let client = InfluxClient::builder(config.db.host, key, config.db.org).build()?;
-        Ok(InfluxDB { client, zones })
+        Ok(InfluxDB {
+            client,
+            zones,
+            retry,
+        })
     }
 
+    /// Re-read the zone mappings from the config file, keeping the existing client.
+    /// The `db` section of the file is ignored.
+    /// If the new mappings are not valid, the current ones are kept and an error is returned.
+    pub fn reload_mappings<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let string = fs::read_to_string(path)?;
+        let config: JSONMappingsConfig = json5::from_str(&string)
+            .map_err(|e| anyhow::anyhow!("Error parsing config file: {}", e))?;
+        self.zones = build_zone_queries(config.zone_mappings)?;
+        Ok(())
+    }
+
+    /// Run a query, retrying on transient failures (see `with_retries`).
     pub async fn read(&self, query: &InfluxQuery) -> anyhow::Result<Vec<HashMap<String, String>>> {
-        let influxrs_query = Query::raw(query.get_query_string());
-        let result = self.client.query(influxrs_query).await?;
+        let query_string = query.get_query_string();
+        with_retries(&self.retry, || {
+            self.client.query(Query::raw(query_string.clone()))
+        })
+        .await
+    }
+
+    pub async fn read_zone(&self, zone: &str) -> anyhow::Result<ZoneValues> {
+        self.read_zone_with(zone, |measurement| measurement.query.clone())
+            .await
+    }
+
+    /// Read all configured zones concurrently, keyed by the zone name.
+    /// A zone that fails to read doesn't abort the others.
+    pub async fn read_all_zones(&self) -> HashMap<String, anyhow::Result<ZoneValues>> {
+        let zones: Vec<&str> = self.zones.keys().map(String::as_str).collect();
+        read_zones(self, &zones).await
+    }
+
+    /// Read the last value of each measurement of a zone within the window
+    /// from `start` to `stop`, like `read_zone` does for the last 30 days.
+    /// Used to replay past days.
+    pub async fn read_zone_at(
+        &self,
+        zone: &str,
+        start: DateTime<Utc>,
+        stop: DateTime<Utc>,
+    ) -> anyhow::Result<ZoneValues> {
+        let start = flux_time(&start);
+        let stop = flux_time(&stop);
+        self.read_zone_with(zone, |measurement| {
+            measurement_query(&measurement.mapping, &start, Some(&stop))
+                .last()
+                .clone()
+        })
+        .await
+    }
+
+    /// Run a query for each measurement of a zone and collect the values, sorted by time.
+    async fn read_zone_with(
+        &self,
+        zone: &str,
+        query: impl Fn(&InfluxMeasurement) -> InfluxQuery,
+    ) -> anyhow::Result<ZoneValues> {
+        let mut result = ZoneValues::new();
+        let measurements = self
+            .zones
+            .get(zone)
+            .ok_or_else(|| anyhow::anyhow!("Zone {} not found", zone))?;
+        for measurement in measurements {
+            let query = query(measurement);
+            println!("Query: {}", query.get_query_string());
+            let query_result = self.read(&query).await?;
+            result.insert(
+                measurement.measurement.clone(),
+                parse_rows(&measurement.measurement, &query_result)?,
+            );
+        }
+        Ok(result)
+    }
+
+    /// Read all values of the measurements of a zone between `start` and `stop`.
+    /// If `window` is given (Flux duration, e.g. `"15m"`), the values are averaged
+    /// over windows of that length, see `InfluxQuery::aggregate_window`.
+    /// Returns samples of each measurement sorted by time.
+    pub async fn read_zone_series(
+        &self,
+        zone: &str,
+        start: DateTime<Utc>,
+        stop: DateTime<Utc>,
+        window: Option<&str>,
+    ) -> anyhow::Result<HashMap<String, Vec<(DateTime<Utc>, f64)>>> {
+        let measurements = self
+            .zones
+            .get(zone)
+            .ok_or_else(|| anyhow::anyhow!("Zone {} not found", zone))?;
+        let start = flux_time(&start);
+        let stop = flux_time(&stop);
+        let mut result = HashMap::new();
+        for measurement in measurements {
+            let query = series_query(&measurement.mapping, &start, &stop, window)?;
+            let query_result = self.read(&query).await?;
+            result.insert(
+                measurement.measurement.clone(),
+                parse_series(&measurement.measurement, &query_result)?,
+            );
+        }
         Ok(result)
     }
 
-    pub async fn read_zone(&self, zone: &str) -> anyhow::Result<HashMap<String, Vec<String>>> {
-        let mut result: HashMap<String, Vec<String>> = HashMap::new();
+    /// Read the most recent value of each measurement of a zone, converted according
+    /// to the kind of the measurement.
+    pub async fn read_zone_typed(
+        &self,
+        zone: &str,
+    ) -> anyhow::Result<HashMap<String, MeasurementValue>> {
         let measurements = self
             .zones
             .get(zone)
             .ok_or_else(|| anyhow::anyhow!("Zone {} not found", zone))?;
+        let mut result = HashMap::new();
         for measurement in measurements {
-            result
-                .entry(measurement.measurement.clone())
-                .or_insert_with(Vec::new);
-            println!("Query: {}", measurement.query.get_query_string());
-            let query_result = self.read(&measurement.query).await?;
-            for row in query_result {
-                let value = row.get("_value").ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "No _value in query result for measurement {}",
-                        measurement.measurement
-                    )
-                })?;
-                result
-                    .get_mut(measurement.measurement.as_str())
-                    .unwrap()
-                    .push(value.clone());
+            let value = self.read_latest_typed(zone, measurement).await?;
+            result.insert(measurement.measurement.clone(), value);
+        }
+        Ok(result)
+    }
+
+    /// Read the latest outside temperature and cloud cover, using the measurements
+    /// `temperature` and `cloud_cover` of the `outside` zone.
+    /// Fails if the `outside` zone or its temperature is not configured in `zone_mappings`,
+    /// cloud cover is optional.
+    pub async fn read_outside_conditions(&self) -> anyhow::Result<OutsideConditions> {
+        let temperature =
+            find_measurement(&self.zones, "outside", "temperature")?.ok_or_else(|| {
+                anyhow::anyhow!("Zone outside has no temperature measurement in zone_mappings")
+            })?;
+        let temperature = self.read_latest_typed("outside", temperature).await?;
+        let cloud_cover = match find_measurement(&self.zones, "outside", "cloud_cover")? {
+            Some(cloud_cover) => Some(self.read_latest_typed("outside", cloud_cover).await?),
+            None => None,
+        };
+        OutsideConditions::new(temperature, cloud_cover)
+    }
+
+    /// Read the most recent value of a measurement, converted according to its kind.
+    async fn read_latest_typed(
+        &self,
+        zone: &str,
+        measurement: &InfluxMeasurement,
+    ) -> anyhow::Result<MeasurementValue> {
+        let kind = measurement.kind.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Measurement {} of zone {} has unknown kind",
+                measurement.measurement,
+                zone
+            )
+        })?;
+        let value = self.read_latest_string(zone, measurement).await?;
+        kind.parse(&value).map_err(|e| {
+            anyhow::anyhow!(
+                "Measurement {} of zone {}: {}",
+                measurement.measurement,
+                zone,
+                e
+            )
+        })
+    }
+
+    /// Read the most recent value of a measurement as returned by the database.
+    async fn read_latest_string(
+        &self,
+        zone: &str,
+        measurement: &InfluxMeasurement,
+    ) -> anyhow::Result<String> {
+        let query_result = self.read(&measurement.query).await?;
+        let row = query_result.last().ok_or_else(|| {
+            anyhow::anyhow!(
+                "No data for measurement {} of zone {}",
+                measurement.measurement,
+                zone
+            )
+        })?;
+        let value = row.get("_value").ok_or_else(|| {
+            anyhow::anyhow!(
+                "No _value in query result for measurement {}",
+                measurement.measurement
+            )
+        })?;
+        Ok(value.clone())
+    }
+
+    /// Read the current weather and assemble it into inputs for solving the model
+    /// at the current time, see `data_source::current_inputs`.
+    pub async fn current_inputs(&self, model: &Model) -> anyhow::Result<Inputs> {
+        current_inputs(self, model, &Utc::now()).await
+    }
+}
+
+impl ZoneDataSource for InfluxDB {
+    fn has_zone(&self, zone: &str) -> bool {
+        self.zones.contains_key(zone)
+    }
+
+    fn read_zone<'a>(&'a self, zone: &'a str) -> DataSourceFuture<'a, ZoneValues> {
+        Box::pin(InfluxDB::read_zone(self, zone))
+    }
+}
+
+/// Run a request, repeating it with exponential backoff when it times out or fails
+/// with a transient error (see `is_transient`), at most `policy.max_retries` times.
+/// Returns the last error if all attempts fail.
+async fn with_retries<T, F, Fut>(policy: &RetryPolicy, mut request: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, InfluxError>>,
+{
+    let mut backoff = policy.initial_backoff;
+    let mut retries = 0;
+    loop {
+        let error = match tokio::time::timeout(policy.timeout, request()).await {
+            Ok(Ok(result)) => return Ok(result),
+            Ok(Err(error)) if !is_transient(&error) => return Err(error.into()),
+            Ok(Err(error)) => anyhow::Error::from(error),
+            Err(_) => anyhow::anyhow!("Query timed out after {:?}", policy.timeout),
+        };
+        if retries >= policy.max_retries {
+            return Err(error.context(format!("Query failed after {} retries", retries)));
+        }
+        println!("Query failed, retrying in {:?}: {}", backoff, error);
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        retries += 1;
+    }
+}
+
+/// Check if a failed query may succeed when repeated: connection problems and
+/// server side errors are transient, malformed queries and responses are not.
+fn is_transient(error: &InfluxError) -> bool {
+    match error {
+        InfluxError::IsahcError(_) | InfluxError::IoError(_) => true,
+        InfluxError::NonSuccessResponse(status, _) => {
+            status.is_server_error() || status.as_u16() == 429
+        }
+        InfluxError::HttpError(_) | InfluxError::CsvError(_) => false,
+    }
+}
+
+/// Find a configured measurement of a zone.
+/// Returns `None` if the zone doesn't have the measurement, fails if the zone
+/// has no mappings at all.
+fn find_measurement<'a>(
+    zones: &'a HashMap<String, Vec<InfluxMeasurement>>,
+    zone: &str,
+    measurement: &str,
+) -> anyhow::Result<Option<&'a InfluxMeasurement>> {
+    let measurements = zones
+        .get(zone)
+        .ok_or_else(|| anyhow::anyhow!("Zone {} has no entry in zone_mappings", zone))?;
+    Ok(measurements.iter().find(|m| m.measurement == measurement))
+}
+
+/// Format a time for the range of a Flux query (RFC3339 in UTC).
+fn flux_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Query for all values of a configured measurement in the given time range.
+fn measurement_query(
+    mapping: &JSONConfigMeasurement,
+    start: &str,
+    stop: Option<&str>,
+) -> InfluxQuery {
+    InfluxQuery::new(&mapping.bucket, start, stop)
+        .filter("_measurement", &mapping.measurement)
+        .filter("_field", &mapping.field)
+        .filter_tags(&mapping.tags)
+        .clone()
+}
+
+/// Query for the values of a configured measurement in the given time range,
+/// averaged over windows of `window` if given.
+/// Fails if the window is not a plain Flux duration (letters and digits only).
+fn series_query(
+    mapping: &JSONConfigMeasurement,
+    start: &str,
+    stop: &str,
+    window: Option<&str>,
+) -> anyhow::Result<InfluxQuery> {
+    let mut query = measurement_query(mapping, start, Some(stop));
+    if let Some(window) = window {
+        if window.is_empty() || !window.chars().all(|c| c.is_ascii_alphanumeric()) {
+            anyhow::bail!("Invalid aggregation window {:?}", window);
+        }
+        query.aggregate_window(window, "mean");
+    }
+    Ok(query)
+}
+
+/// Extract time and raw value of each row of a query result, sorted by time.
+fn parse_rows(
+    measurement: &str,
+    rows: &[HashMap<String, String>],
+) -> anyhow::Result<Vec<(DateTime<Utc>, String)>> {
+    let column = |row: &HashMap<String, String>, name: &str| -> anyhow::Result<String> {
+        row.get(name).cloned().ok_or_else(|| {
+            anyhow::anyhow!(
+                "No {} in query result for measurement {}",
+                name,
+                measurement
+            )
+        })
+    };
+    let mut values = rows
+        .iter()
+        .map(|row| {
+            let time = column(row, "_time")?;
+            let time = DateTime::parse_from_rfc3339(&time).map_err(|e| {
+                anyhow::anyhow!(
+                    "Can't parse time {:?} of measurement {}: {}",
+                    time,
+                    measurement,
+                    e
+                )
+            })?;
+            Ok((time.with_timezone(&Utc), column(row, "_value")?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    values.sort_by_key(|(time, _)| *time);
+    Ok(values)
+}
+
+/// Extract time and numeric value of each row of a query result, sorted by time.
+fn parse_series(
+    measurement: &str,
+    rows: &[HashMap<String, String>],
+) -> anyhow::Result<Vec<(DateTime<Utc>, f64)>> {
+    parse_rows(measurement, rows)?
+        .into_iter()
+        .map(|(time, value)| {
+            let number = value.parse().map_err(|e| {
+                anyhow::anyhow!(
+                    "Can't parse value {:?} of measurement {}: {}",
+                    value,
+                    measurement,
+                    e
+                )
+            })?;
+            Ok((time, number))
+        })
+        .collect()
+}
+
+/// Check the zone mappings and build the queries for each configured measurement.
+fn build_zone_queries(
+    zone_mappings: HashMap<String, HashMap<String, JSONConfigMeasurement>>,
+) -> anyhow::Result<HashMap<String, Vec<InfluxMeasurement>>> {
+    let mut zones = HashMap::new();
+
+    for (zone_name, mappings) in zone_mappings {
+        for (measurement_name, mapping) in mappings {
+            for (what, value) in [
+                ("bucket", &mapping.bucket),
+                ("measurement", &mapping.measurement),
+                ("field", &mapping.field),
+            ] {
+                if value.is_empty() {
+                    anyhow::bail!(
+                        "Measurement {} of zone {} has empty {}",
+                        measurement_name,
+                        zone_name,
+                        what
+                    );
+                }
+            }
+            let all_strings = [&mapping.bucket, &mapping.measurement, &mapping.field]
+                .into_iter()
+                .chain(mapping.tags.iter().flat_map(|(tag, value)| [tag, value]));
+            if let Some(bad) = all_strings.into_iter().find(|value| value.contains('"')) {
+                anyhow::bail!(
+                    "Measurement {} of zone {} contains a quote in {:?}",
+                    measurement_name,
+                    zone_name,
+                    bad
+                );
+            }
+
+            let query = measurement_query(&mapping, "-30d", None).last().clone();
+            let kind = mapping
+                .kind
+                .or_else(|| MeasurementKind::from_name(&measurement_name));
+
+            zones
+                .entry(zone_name.clone())
+                .or_insert_with(Vec::new)
+                .push(InfluxMeasurement {
+                    measurement: measurement_name,
+                    query,
+                    mapping,
+                    kind,
+                });
+        }
+    }
+
+    Ok(zones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn mappings(json: &str) -> HashMap<String, HashMap<String, JSONConfigMeasurement>> {
+        json5::from_str::<JSONMappingsConfig>(json)
+            .unwrap()
+            .zone_mappings
+    }
+
+    #[test]
+    fn build_zone_queries_valid() {
+        let zones = build_zone_queries(mappings(
+            r#"{
+            db: { host: "ignored", org: "ignored" },
+            zone_mappings: {
+                kitchen: {
+                    temperature: {
+                        bucket: "loxone",
+                        measurement: "temperature",
+                        tags: { room: "kuchyne" },
+                        field: "temperature_kuchyne",
+                    }
+                }
+            }
+        }"#,
+        ))
+        .unwrap();
+
+        let kitchen = &zones["kitchen"];
+        assert_eq!(kitchen.len(), 1);
+        assert_eq!(kitchen[0].measurement, "temperature");
+        assert_eq!(
+            kitchen[0].query.get_query_string(),
+            "from(bucket: \"loxone\") |> range(start: -30d) \
+             |> filter(fn: (r) => r[\"_measurement\"] == \"temperature\") \
+             |> filter(fn: (r) => r[\"_field\"] == \"temperature_kuchyne\") \
+             |> filter(fn: (r) => r[\"room\"] == \"kuchyne\") |> last()"
+        );
+    }
+
+    #[test]
+    fn aggregate_window_query() {
+        let query = InfluxQuery::new(
+            "loxone",
+            "2023-01-01T00:00:00Z",
+            Some("2023-01-02T00:00:00Z"),
+        )
+        .filter("_field", "temperature")
+        .aggregate_window("15m", "mean")
+        .get_query_string();
+        assert_eq!(
+            query,
+            "from(bucket: \"loxone\") \
+             |> range(start: 2023-01-01T00:00:00Z, stop: 2023-01-02T00:00:00Z) \
+             |> filter(fn: (r) => r[\"_field\"] == \"temperature\") \
+             |> aggregateWindow(every: 15m, fn: mean, createEmpty: false)"
+        );
+    }
+
+    #[test]
+    fn latest_query_in_window() {
+        let zones = build_zone_queries(mappings(
+            r#"{
+            zone_mappings: {
+                kitchen: {
+                    temperature: {
+                        bucket: "loxone",
+                        measurement: "temperature",
+                        tags: {},
+                        field: "value",
+                    }
+                }
+            }
+        }"#,
+        ))
+        .unwrap();
+        let start = DateTime::parse_from_rfc3339("2023-01-15T01:00:00+01:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        let stop = DateTime::parse_from_rfc3339("2023-01-16T01:00:00+01:00")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let query = measurement_query(
+            &zones["kitchen"][0].mapping,
+            &flux_time(&start),
+            Some(&flux_time(&stop)),
+        )
+        .last()
+        .get_query_string();
+        assert!(query.contains("|> range(start: 2023-01-15T00:00:00Z, stop: 2023-01-16T00:00:00Z)"));
+        assert!(query.ends_with("|> last()"));
+    }
+
+    #[test]
+    fn series_query_window() {
+        let zones = build_zone_queries(mappings(
+            r#"{
+            zone_mappings: {
+                kitchen: {
+                    temperature: {
+                        bucket: "loxone",
+                        measurement: "temperature",
+                        tags: {},
+                        field: "value",
+                    }
+                }
+            }
+        }"#,
+        ))
+        .unwrap();
+        let mapping = &zones["kitchen"][0].mapping;
+        let start = "2023-01-01T00:00:00Z";
+        let stop = "2023-01-02T00:00:00Z";
+
+        let raw = series_query(mapping, start, stop, None)
+            .unwrap()
+            .get_query_string();
+        assert!(!raw.contains("aggregateWindow"));
+
+        let averaged = series_query(mapping, start, stop, Some("15m"))
+            .unwrap()
+            .get_query_string();
+        assert_eq!(
+            averaged,
+            format!(
+                "{} |> aggregateWindow(every: 15m, fn: mean, createEmpty: false)",
+                raw
+            )
+        );
+
+        let message = series_query(mapping, start, stop, Some("15m, fn: last"))
+            .unwrap_err()
+            .to_string();
+        message
+            .find("Invalid aggregation window")
+            .expect("Error message should mention the window");
+    }
+
+    fn row(time: &str, value: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("_time".to_string(), time.to_string()),
+            ("_value".to_string(), value.to_string()),
+        ])
+    }
+
+    #[test]
+    fn parse_series_sorted() {
+        let series = parse_series(
+            "temperature",
+            &[
+                row("2023-01-01T01:00:00Z", "21.5"),
+                row("2023-01-01T00:00:00Z", "20"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            series,
+            [
+                (
+                    DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z")
+                        .unwrap()
+                        .with_timezone(&Utc),
+                    20.0
+                ),
+                (
+                    DateTime::parse_from_rfc3339("2023-01-01T01:00:00Z")
+                        .unwrap()
+                        .with_timezone(&Utc),
+                    21.5
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_series_bad_value() {
+        let message = format!(
+            "{}",
+            parse_series(
+                "humidity",
+                &[
+                    row("2023-01-01T00:00:00Z", "50"),
+                    row("2023-01-01T01:00:00Z", "wet")
+                ],
+            )
+            .unwrap_err()
+        );
+        message
+            .find("humidity")
+            .expect("Error message should contain the measurement name");
+        message
+            .find("wet")
+            .expect("Error message should contain the bad value");
+    }
+
+    #[test]
+    fn parse_rows_missing_time() {
+        let message = format!(
+            "{}",
+            parse_rows(
+                "temperature",
+                &[HashMap::from([("_value".to_string(), "21.5".to_string())])],
+            )
+            .unwrap_err()
+        );
+        message
+            .find("_time")
+            .expect("Error message should contain the missing column");
+    }
+
+    #[test]
+    fn build_zone_queries_kind() {
+        let zones = build_zone_queries(mappings(
+            r#"{
+            zone_mappings: {
+                outside: {
+                    temperature: {
+                        bucket: "weather",
+                        measurement: "temperature",
+                        tags: {},
+                        field: "value",
+                    },
+                    sun: {
+                        bucket: "weather",
+                        measurement: "irradiance",
+                        tags: {},
+                        field: "value",
+                        kind: "irradiance",
+                    },
+                    humidity: {
+                        bucket: "weather",
+                        measurement: "humidity",
+                        tags: {},
+                        field: "value",
+                    },
+                }
+            }
+        }"#,
+        ))
+        .unwrap();
+
+        let kind = |name: &str| {
+            zones["outside"]
+                .iter()
+                .find(|m| m.measurement == name)
+                .unwrap()
+                .kind
+        };
+        assert_eq!(kind("temperature"), Some(MeasurementKind::Temperature));
+        assert_eq!(kind("sun"), Some(MeasurementKind::Irradiance));
+        assert_eq!(kind("humidity"), None);
+    }
+
+    #[test]
+    fn find_measurement_missing_zone() {
+        let zones = build_zone_queries(mappings(
+            r#"{
+            zone_mappings: {
+                kitchen: {
+                    temperature: {
+                        bucket: "loxone",
+                        measurement: "temperature",
+                        tags: {},
+                        field: "value",
+                    }
+                }
             }
+        }"#,
+        ))
+        .unwrap();
+
+        assert!(find_measurement(&zones, "kitchen", "temperature")
+            .unwrap()
+            .is_some());
+        assert!(find_measurement(&zones, "kitchen", "cloud_cover")
+            .unwrap()
+            .is_none());
+        let message = format!(
+            "{}",
+            find_measurement(&zones, "outside", "temperature").unwrap_err()
+        );
+        message
+            .find("outside")
+            .expect("Error message should contain the zone name");
+    }
+
+    #[test]
+    fn outside_conditions() {
+        let temperature =
+            MeasurementValue::Temperature(ThermodynamicTemperature::new::<degree_celsius>(-3.0));
+        let conditions = OutsideConditions::new(
+            temperature,
+            Some(MeasurementValue::CloudCover(Ratio::new::<percent>(75.0))),
+        )
+        .unwrap();
+        assert_eq!(
+            conditions.temperature,
+            ThermodynamicTemperature::new::<degree_celsius>(-3.0)
+        );
+        assert_eq!(conditions.cloud_cover, Some(Ratio::new::<percent>(75.0)));
+
+        assert_eq!(
+            OutsideConditions::new(temperature, None)
+                .unwrap()
+                .cloud_cover,
+            None
+        );
+
+        let message = format!(
+            "{}",
+            OutsideConditions::new(
+                MeasurementValue::WindSpeed(Velocity::new::<meter_per_second>(3.0)),
+                None
+            )
+            .unwrap_err()
+        );
+        message
+            .find("temperature")
+            .expect("Error message should contain the measurement name");
+    }
+
+    fn retry_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            timeout: Duration::from_secs(10),
+            initial_backoff: Duration::ZERO,
         }
-        Ok(result.clone())
+    }
+
+    fn connection_error() -> InfluxError {
+        InfluxError::IoError(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "connection reset",
+        ))
+    }
+
+    #[tokio::test]
+    async fn retry_until_success() {
+        let attempts = Cell::new(0);
+        let result = with_retries(&retry_policy(3), || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt <= 2 {
+                    Err(connection_error())
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up() {
+        let attempts = Cell::new(0);
+        let result: anyhow::Result<()> = with_retries(&retry_policy(2), || {
+            attempts.set(attempts.get() + 1);
+            async { Err(connection_error()) }
+        })
+        .await;
+        assert_eq!(attempts.get(), 3);
+        let message = format!("{:#}", result.unwrap_err());
+        message
+            .find("connection reset")
+            .expect("Error message should contain the last error");
+        message
+            .find("after 2 retries")
+            .expect("Error message should contain the retry count");
+    }
+
+    #[test]
+    fn measurement_kind_parse() {
+        assert_eq!(
+            MeasurementKind::Temperature.parse("21.5").unwrap(),
+            MeasurementValue::Temperature(ThermodynamicTemperature::new::<degree_celsius>(21.5))
+        );
+        assert_eq!(
+            MeasurementKind::CloudCover.parse("40").unwrap(),
+            MeasurementValue::CloudCover(Ratio::new::<percent>(40.0))
+        );
+        assert_eq!(
+            MeasurementKind::WindSpeed.parse("3").unwrap(),
+            MeasurementValue::WindSpeed(Velocity::new::<meter_per_second>(3.0))
+        );
+        assert_eq!(
+            MeasurementKind::Irradiance.parse("800").unwrap(),
+            MeasurementValue::Irradiance(HeatFluxDensity::new::<watt_per_square_meter>(800.0))
+        );
+    }
+
+    #[test]
+    fn measurement_kind_parse_error() {
+        let message = format!(
+            "{}",
+            MeasurementKind::Temperature.parse("21,5").unwrap_err()
+        );
+        message
+            .find("21,5")
+            .expect("Error message should contain the bad value");
+    }
+
+    #[test]
+    fn build_zone_queries_empty_field() {
+        let message = format!(
+            "{}",
+            build_zone_queries(mappings(
+                r#"{
+                zone_mappings: {
+                    kitchen: {
+                        temperature: {
+                            bucket: "loxone",
+                            measurement: "temperature",
+                            tags: {},
+                            field: "",
+                        }
+                    }
+                }
+            }"#,
+            ))
+            .unwrap_err()
+        );
+        message
+            .find("kitchen")
+            .expect("Error message should contain the zone name");
+        message
+            .find("field")
+            .expect("Error message should say what is wrong");
     }
 }
@@ -0,0 +1,13 @@
+extern crate nalgebra as na;
+
+pub mod data_source;
+pub mod influxdb;
+pub mod lumped;
+pub mod model;
+pub mod mpc;
+pub mod observer;
+pub mod rc_network;
+pub mod schedule;
+pub mod simulation;
+pub mod state_space;
+pub mod tools;
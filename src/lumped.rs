@@ -0,0 +1,359 @@
+//! Reduction of an RC network to a single node per zone, for exporting the parameters
+//! to other building simulation tools (EnergyPlus, Modelica, ...).
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+use uom::si::{
+    f64::{HeatCapacity, ThermalConductance, ThermodynamicTemperature},
+    heat_capacity::joule_per_kelvin,
+    thermal_conductance::watt_per_kelvin,
+    thermodynamic_temperature::kelvin,
+};
+
+use crate::rc_network::RcNetwork;
+use crate::simulation::{Inputs, Simulation};
+
+/// Zone node of a lumped network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LumpedZone {
+    pub name: String,
+    /// Heat capacity of the zone air together with its share of the boundaries.
+    /// Infinite for outside and ground.
+    pub heat_capacity: HeatCapacity,
+}
+
+/// Effective conductance between two zones of a lumped network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LumpedConductance {
+    pub zones: [String; 2],
+    pub conductance: ThermalConductance,
+}
+
+/// RC network reduced to one node per zone.
+///
+/// Conductances between zones are the exact steady state conductances of the full network
+/// (all boundary nodes eliminated). Heat capacity of each boundary node is distributed between
+/// the zones with finite heat capacity, weighted by how much the node temperature follows
+/// each zone in the steady state. Total heat capacity of the network is therefore preserved,
+/// except for boundaries between two infinite zones (outside and ground).
+#[derive(Clone, Debug, PartialEq)]
+pub struct LumpedNetwork {
+    /// Zones, sorted by name
+    pub zones: Vec<LumpedZone>,
+    /// Non-zero conductances between pairs of zones, sorted by zone names
+    pub conductances: Vec<LumpedConductance>,
+}
+
+impl LumpedNetwork {
+    /// Reduce the network to one node per zone.
+    /// Fails if some boundary node is not thermally connected to any zone.
+    pub fn from_network(network: &RcNetwork) -> anyhow::Result<Self> {
+        let graph = &network.graph;
+        let mut zone_names: Vec<_> = network.zone_indices.keys().cloned().collect();
+        zone_names.sort();
+
+        // Temperature profile of the whole network with a single zone at 1 K
+        // and all other zones at 0 K, for each zone.
+        let profiles = zone_names
+            .iter()
+            .map(|zone| {
+                let inputs = Inputs {
+                    boundary_temperatures: zone_names
+                        .iter()
+                        .map(|other| {
+                            let temperature = if other == zone { 1.0 } else { 0.0 };
+                            (
+                                other.clone(),
+                                ThermodynamicTemperature::new::<kelvin>(temperature),
+                            )
+                        })
+                        .collect(),
+                    ..Default::default()
+                };
+                let simulation = Simulation::steady_state(network, &inputs)?;
+                Ok(simulation
+                    .temperatures()
+                    .iter()
+                    .map(|t| t.get::<kelvin>())
+                    .collect::<Vec<_>>())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let zone_nodes: Vec<_> = zone_names
+            .iter()
+            .map(|name| network.zone_indices[name])
+            .collect();
+        let is_finite_zone: Vec<_> = zone_nodes
+            .iter()
+            .map(|&node| graph[node].heat_capacity.is_finite())
+            .collect();
+
+        let mut heat_capacities: Vec<_> = zone_nodes
+            .iter()
+            .map(|&node| graph[node].heat_capacity)
+            .collect();
+        for node in graph.node_indices() {
+            if graph[node].zone_name.is_some() {
+                continue;
+            }
+            let weight_sum: f64 = profiles
+                .iter()
+                .zip(is_finite_zone.iter())
+                .filter(|(_, &finite)| finite)
+                .map(|(profile, _)| profile[node.index()])
+                .sum();
+            if weight_sum <= 0.0 {
+                continue;
+            }
+            for (i, profile) in profiles.iter().enumerate() {
+                if is_finite_zone[i] {
+                    heat_capacities[i] +=
+                        graph[node].heat_capacity * profile[node.index()] / weight_sum;
+                }
+            }
+        }
+
+        let zones = zone_names
+            .iter()
+            .zip(heat_capacities)
+            .map(|(name, heat_capacity)| LumpedZone {
+                name: name.clone(),
+                heat_capacity,
+            })
+            .collect();
+
+        // Heat flowing into zone j while zone i is at 1 K and all others at 0 K
+        // is the conductance between i and j.
+        let mut conductances = Vec::new();
+        for (i, profile) in profiles.iter().enumerate() {
+            for (j, &zone_node) in zone_nodes.iter().enumerate().skip(i + 1) {
+                let heat_flow: f64 = graph
+                    .edges(zone_node)
+                    .map(|edge| {
+                        let other = if edge.source() == zone_node {
+                            edge.target()
+                        } else {
+                            edge.source()
+                        };
+                        edge.weight().conductance.get::<watt_per_kelvin>()
+                            * (profile[other.index()] - profile[zone_node.index()])
+                    })
+                    .sum();
+                if heat_flow > 0.0 {
+                    conductances.push(LumpedConductance {
+                        zones: [zone_names[i].clone(), zone_names[j].clone()],
+                        conductance: ThermalConductance::new::<watt_per_kelvin>(heat_flow),
+                    });
+                }
+            }
+        }
+
+        Ok(LumpedNetwork {
+            zones,
+            conductances,
+        })
+    }
+
+    /// Serialize the lumped parameters as JSON.
+    /// Heat capacities are in J/K (`null` for infinite zones), conductances in W/K.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        #[derive(Serialize)]
+        struct Exported<'a> {
+            heat_capacities: BTreeMap<&'a str, Option<f64>>,
+            conductances: Vec<(&'a str, &'a str, f64)>,
+        }
+
+        let exported = Exported {
+            heat_capacities: self
+                .zones
+                .iter()
+                .map(|zone| {
+                    let heat_capacity = zone.heat_capacity.get::<joule_per_kelvin>();
+                    (
+                        zone.name.as_str(),
+                        heat_capacity.is_finite().then_some(heat_capacity),
+                    )
+                })
+                .collect(),
+            conductances: self
+                .conductances
+                .iter()
+                .map(|c| {
+                    (
+                        c.zones[0].as_str(),
+                        c.zones[1].as_str(),
+                        c.conductance.get::<watt_per_kelvin>(),
+                    )
+                })
+                .collect(),
+        };
+        Ok(json5::to_string(&exported)?)
+    }
+
+    /// Write the lumped parameters as a Modelica record.
+    /// Zones with infinite heat capacity are boundary conditions and only appear
+    /// in the conductances.
+    pub fn to_modelica(&self, record_name: &str) -> String {
+        let mut output = String::new();
+        writeln!(output, "record {}", modelica_identifier(record_name)).unwrap();
+        for zone in self.zones.iter() {
+            if zone.heat_capacity.is_finite() {
+                writeln!(
+                    output,
+                    "  parameter Modelica.Units.SI.HeatCapacity C_{} = {:e};",
+                    modelica_identifier(&zone.name),
+                    zone.heat_capacity.get::<joule_per_kelvin>()
+                )
+                .unwrap();
+            }
+        }
+        for c in self.conductances.iter() {
+            writeln!(
+                output,
+                "  parameter Modelica.Units.SI.ThermalConductance G_{}_{} = {:e};",
+                modelica_identifier(&c.zones[0]),
+                modelica_identifier(&c.zones[1]),
+                c.conductance.get::<watt_per_kelvin>()
+            )
+            .unwrap();
+        }
+        writeln!(output, "end {};", modelica_identifier(record_name)).unwrap();
+        output
+    }
+}
+
+/// Replace characters that can't appear in a Modelica identifier.
+fn modelica_identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use approx::assert_ulps_eq;
+
+    fn sample_network() -> (Model, RcNetwork) {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.5,
+                    specific_heat_capacity: 1000,
+                    density: 1500,
+                },
+                insulation: {
+                    thermal_conductivity: 0.04,
+                    specific_heat_capacity: 1200,
+                    density: 30,
+                }
+            },
+            boundary_types: {
+                exterior_wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                        { material: "insulation", thickness: 0.15 },
+                    ]
+                },
+                interior_wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.15 },
+                    ]
+                },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                a: { volume: 50 },
+                "b-1": { volume: 70 },
+            },
+            boundaries: [
+                { boundary_type: "exterior_wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "window", zones: ["outside", "b-1"], area: 3 },
+                { boundary_type: "interior_wall", zones: ["a", "b-1"], area: 10 },
+                { boundary_type: "exterior_wall", zones: ["b-1", "ground"], area: 25 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let network = RcNetwork::try_from(&model).unwrap();
+        (model, network)
+    }
+
+    fn conductance(lumped: &LumpedNetwork, z1: &str, z2: &str) -> f64 {
+        lumped
+            .conductances
+            .iter()
+            .find(|c| c.zones == [z1, z2])
+            .unwrap()
+            .conductance
+            .get::<watt_per_kelvin>()
+    }
+
+    #[test]
+    fn sample_model_reduced() {
+        let (model, network) = sample_network();
+        let lumped = LumpedNetwork::from_network(&network).unwrap();
+
+        let names: Vec<_> = lumped.zones.iter().map(|z| z.name.as_str()).collect();
+        assert_eq!(names, ["a", "b-1", "ground", "outside"]);
+        assert!(!lumped.zones[2].heat_capacity.is_finite());
+        assert!(!lumped.zones[3].heat_capacity.is_finite());
+
+        // Outside and ground are not connected, so there is no conductance between them
+        assert_eq!(lumped.conductances.len(), 4);
+        for boundary in model.boundaries.iter() {
+            let mut zones = [
+                boundary.zones[0].name.as_str(),
+                boundary.zones[1].name.as_str(),
+            ];
+            zones.sort();
+            assert_ulps_eq!(
+                conductance(&lumped, zones[0], zones[1]),
+                model
+                    .boundary_conductance(boundary)
+                    .get::<watt_per_kelvin>(),
+                max_ulps = 1000
+            );
+        }
+
+        let network_heat_capacity: f64 = network
+            .graph
+            .node_weights()
+            .map(|node| node.heat_capacity.get::<joule_per_kelvin>())
+            .filter(|c| c.is_finite())
+            .sum();
+        let lumped_heat_capacity: f64 = lumped.zones[0..2]
+            .iter()
+            .map(|zone| zone.heat_capacity.get::<joule_per_kelvin>())
+            .sum();
+        assert_ulps_eq!(lumped_heat_capacity, network_heat_capacity, max_ulps = 1000);
+    }
+
+    #[test]
+    fn export() {
+        let (_, network) = sample_network();
+        let lumped = LumpedNetwork::from_network(&network).unwrap();
+
+        #[derive(serde::Deserialize)]
+        struct Exported {
+            heat_capacities: BTreeMap<String, Option<f64>>,
+            conductances: Vec<(String, String, f64)>,
+        }
+        let json: Exported = json5::from_str(&lumped.to_json().unwrap()).unwrap();
+        assert_eq!(json.heat_capacities["outside"], None);
+        assert!(json.heat_capacities["a"].unwrap() > 0.0);
+        assert_eq!(json.conductances.len(), 4);
+
+        let modelica = lumped.to_modelica("house");
+        assert!(modelica.starts_with("record house\n"));
+        assert!(modelica.ends_with("end house;\n"));
+        assert!(modelica.contains("parameter Modelica.Units.SI.HeatCapacity C_b_1 = "));
+        assert!(!modelica.contains("C_outside"));
+        assert!(modelica.contains("parameter Modelica.Units.SI.ThermalConductance G_a_b_1 = "));
+    }
+}
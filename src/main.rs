@@ -1,10 +1,3 @@
-extern crate nalgebra as na;
-
-mod influxdb;
-mod model;
-mod rc_network;
-mod tools;
-
 use chrono::prelude::*;
 use uom::si::heat_flux_density::watt_per_square_meter;
 use uom::si::{
@@ -13,20 +6,46 @@ use uom::si::{
     ratio::percent,
 };
 
-use influxdb::*;
-use model::*;
-use tools::sun::*;
+use mpc_home_control::influxdb::*;
+use mpc_home_control::model::*;
+use mpc_home_control::rc_network;
+use mpc_home_control::tools::sun::*;
+
+/// Load a model file and report all problems in it.
+fn check(path: &str) -> anyhow::Result<()> {
+    let report = Model::validate_file(path);
+    println!("{}", report);
+    if !report.is_ok() {
+        anyhow::bail!("Model {} is not valid", path);
+    }
+    Ok(())
+}
+
+/// Print the current measurements of all zones.
+async fn print_zones(db: &InfluxDB) {
+    let mut zones: Vec<_> = db.read_all_zones().await.into_iter().collect();
+    zones.sort_by(|a, b| a.0.cmp(&b.0));
+    for (zone, values) in zones {
+        println!("{}: {:?}", zone, values);
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("check") {
+        return check(args.get(2).map(String::as_str).unwrap_or("model.json5"));
+    }
+
     let model = Model::load("model.json5")?;
     println!("{:?}", model);
 
     let db = InfluxDB::from_config("config.json5");
     match db {
         Ok(db) => {
-            let livingroom = db.read_zone("livingroom").await;
-            println!("livingroom: {:?}", livingroom);
+            print_zones(&db).await;
+            let inputs = db.current_inputs(&model).await;
+            println!("current inputs: {:?}", inputs);
         }
         Err(e) => {
             println!("Error: {}", e);
@@ -50,13 +69,18 @@ async fn main() -> anyhow::Result<()> {
         cloud_cover,
         surface_angle,
         surface_azimuth,
+        &model
+            .location
+            .as_ref()
+            .map(|location| location.climate.clone())
+            .unwrap_or_default(),
     );
     println!(
         "Total irradiance on tilted surface: {:.2} W/m^2",
         tilted_irradiance.get::<watt_per_square_meter>()
     );
 
-    let rcnet: rc_network::RcNetwork = (&model).into();
+    let rcnet = rc_network::RcNetwork::try_from(&model)?;
     println!("{}", rcnet.to_dot());
 
     anyhow::Result::Ok(())
@@ -1,19 +1,39 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::rc::Rc;
 
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use itertools::Itertools;
 use uom::si::{
+    angle::degree,
+    area::square_meter,
     f64::{
-        Area, HeatCapacity, HeatTransfer, Length, MassDensity, Ratio, SpecificHeatCapacity,
-        ThermalConductance, ThermalConductivity, Volume,
+        Angle, Area, HeatCapacity, HeatTransfer, Length, MassDensity, Power, Ratio,
+        SpecificHeatCapacity, ThermalConductance, ThermalConductivity, Time, Velocity, Volume,
     },
     heat_capacity::joule_per_kelvin,
+    heat_transfer::watt_per_square_meter_kelvin,
+    length::{meter, millimeter},
     mass_density::kilogram_per_cubic_meter,
+    ratio::ratio,
     specific_heat_capacity::joule_per_kilogram_kelvin,
     thermal_conductivity::watt_per_meter_kelvin,
+    time::{hour, second},
+    velocity::meter_per_second,
 };
 
+use na::{Complex, ComplexField};
+
+use crate::rc_network::air_convection_conductance;
+use crate::schedule::Interpolate;
+use crate::tools::reciprocal_sum;
+use crate::tools::sun::{ClimateConfig, HorizonProfile, TiltedIrradiance};
+
 #[cfg(test)]
 use proptest::{
     arbitrary::Arbitrary,
@@ -21,49 +41,616 @@ use proptest::{
     strategy::{BoxedStrategy, Strategy},
 };
 #[cfg(test)]
-use uom::si::{
-    area::square_meter, heat_transfer::watt_per_square_meter_kelvin, length::meter, ratio::percent,
-    thermal_conductance::watt_per_kelvin, volume::cubic_meter,
-};
+use uom::si::{ratio::percent, thermal_conductance::watt_per_kelvin, volume::cubic_meter};
 
 #[derive(Clone, Debug)]
 pub struct Model {
     pub zones: HashMap<String, Rc<Zone>>,
     pub boundaries: Vec<Boundary>,
     pub air: Rc<Material>,
+    /// Geographic location of the building, needed for solar calculations.
+    pub location: Option<Location>,
+    /// Controllable heaters and coolers, each injecting power into a zone.
+    pub heat_sources: Vec<HeatSource>,
+    /// Soil separating boundaries to `ground` from the ground temperature.
+    pub ground: Ground,
+    /// Junctions between boundaries with extra heat transfer (see `ThermalBridge`).
+    pub thermal_bridges: Vec<ThermalBridge>,
 }
 
 impl Model {
+    /// Load a model from file.
+    /// Relative paths in the `include` list are resolved against the directory of the file.
+    /// Only errors prevent loading, suspicious values (e.g. layers thinner than
+    /// `DEFAULT_MIN_LAYER_THICKNESS`) are accepted, `validate_all` reports them as warnings.
+    /// See `load_strict` to reject them.
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let string = fs::read_to_string(path)?;
-        Self::from_json(&string)
+        let string = fs::read_to_string(&path)?;
+        let base_dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        Self::from_json_with_base_dir(&string, base_dir)
     }
 
+    /// Load a model from file like `load`, but also reject material layers thinner
+    /// than `DEFAULT_MIN_LAYER_THICKNESS` (see `check_layer_thickness`).
+    pub fn load_strict<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::strict(Self::load(path)?)
+    }
+
+    /// Parse a model from a string.
+    /// Relative paths in the `include` list are resolved against the current directory.
+    /// Suspicious values are accepted like in `load`, see `from_json_strict`.
     pub fn from_json(json: &str) -> anyhow::Result<Self> {
-        let loaded: as_loaded::Model = json5::from_str(json)?;
+        Self::from_json_with_base_dir(json, Path::new(""))
+    }
+
+    /// Parse a model from a string like `from_json`, but also reject material layers
+    /// thinner than `DEFAULT_MIN_LAYER_THICKNESS` (see `check_layer_thickness`).
+    pub fn from_json_strict(json: &str) -> anyhow::Result<Self> {
+        Self::strict(Self::from_json(json)?)
+    }
+
+    fn strict(model: Self) -> anyhow::Result<Self> {
+        model.check_layer_thickness(Length::new::<meter>(DEFAULT_MIN_LAYER_THICKNESS))?;
+        Ok(model)
+    }
+
+    fn from_json_with_base_dir(json: &str, base_dir: &Path) -> anyhow::Result<Self> {
+        let mut loaded: as_loaded::Model = json5::from_str(json)?;
+        loaded.resolve_includes(base_dir)?;
         let converted = loaded.try_into()?;
         Ok(converted)
     }
+
+    /// Serialize the model to JSON5, in the same format as read by `from_json`.
+    /// Only materials and boundary types used by the model are written, includes are
+    /// inlined and sub-boundaries are written as separate boundaries.
+    pub fn to_json5(&self) -> anyhow::Result<String> {
+        Ok(json5::to_string(&as_loaded::Model::from_model(self)?)?)
+    }
+
+    /// Save the model to a file, see `to_json5`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        fs::write(path, self.to_json5()?)?;
+        Ok(())
+    }
+
+    /// Add zones, boundaries, heat sources and thermal bridges of another model
+    /// (e.g. a garage modeled in a separate file) to this one.
+    /// Zones, materials, boundary types and heat sources with the same name must be
+    /// identical in both models, `outside` and `ground` are shared. Boundaries may
+    /// connect zones of both models.
+    /// Both models must be saveable (see `to_json5`). On error the model is unchanged.
+    pub fn merge(&mut self, other: Model) -> anyhow::Result<()> {
+        let mut merged = as_loaded::Model::from_model(self)?;
+        merged.merge(as_loaded::Model::from_model(&other)?)?;
+        *self = merged.try_into()?;
+        Ok(())
+    }
+
+    /// Hash of everything that affects the thermal network of the model, for caching
+    /// results derived from it (e.g. state space matrices).
+    /// Zones, boundaries, heat sources and thermal bridges are hashed independently
+    /// of their order. Quantities are hashed by the bits of their values in base units.
+    /// The location is not included.
+    /// The hash is stable between runs, but may change with the Rust version.
+    pub fn content_hash(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.air.content_hash(&mut state);
+        hash_unordered(self.zones.values(), &mut state);
+        hash_unordered(self.boundaries.iter(), &mut state);
+        hash_unordered(self.heat_sources.iter(), &mut state);
+        hash_unordered(self.thermal_bridges.iter(), &mut state);
+        self.ground.content_hash(&mut state);
+        state.finish()
+    }
+
+    /// Sum of U·A of all boundaries between interior zones and outside or ground, plus
+    /// the thermal bridges between them and air exchange of the zones.
+    /// This is the simple building code estimate of the heat loss coefficient of
+    /// the building envelope, for comparison with `RcNetwork::heat_loss_coefficient`
+    /// of a network built in still air (`RcNetwork::try_from`).
+    pub fn ua_value(&self) -> ThermalConductance {
+        let boundaries: ThermalConductance = self
+            .boundaries
+            .iter()
+            .filter(|boundary| boundary.is_exterior())
+            .map(|boundary| self.boundary_conductance(boundary))
+            .sum();
+        let thermal_bridges: ThermalConductance = self
+            .thermal_bridges
+            .iter()
+            .filter(|thermal_bridge| thermal_bridge.is_exterior())
+            .map(|thermal_bridge| thermal_bridge.conductance())
+            .sum();
+        let air_exchange: ThermalConductance = self
+            .zones
+            .values()
+            .filter_map(|zone| zone.ventilation_conductance(zone.air_material(&self.air)))
+            .sum();
+        boundaries + thermal_bridges + air_exchange
+    }
+
+    /// U·A of a boundary, for boundaries to ground in series with the soil layer
+    /// under it (see `Ground`).
+    /// U value of simple boundaries doesn't include surface resistances, still air
+    /// convection is added on the sides not facing ground, like in the network.
+    pub fn boundary_conductance(&self, boundary: &Boundary) -> ThermalConductance {
+        let mut conductance = boundary.u_value() * boundary.area;
+        if let BoundaryType::Simple { .. } = boundary.boundary_type.as_ref() {
+            let convection =
+                air_convection_conductance(Velocity::new::<meter_per_second>(0.0)) * boundary.area;
+            for side in [0, 1] {
+                if boundary.ground_side() != Some(side) {
+                    conductance = reciprocal_sum!(conductance, convection);
+                }
+            }
+        }
+        if boundary.ground_side().is_some() {
+            reciprocal_sum!(conductance, self.ground.conductance(boundary.area))
+        } else {
+            conductance
+        }
+    }
+
+    /// Internal gains of the zones that have them at the given time, by zone name,
+    /// e.g. to be added to `simulation::Inputs::heat_gains`.
+    pub fn internal_gains(&self, time: &DateTime<Utc>) -> HashMap<String, Power> {
+        self.zones
+            .iter()
+            .filter_map(|(name, zone)| {
+                let gains = zone.internal_gains.as_ref()?;
+                Some((name.clone(), gains.power_at(time)))
+            })
+            .collect()
+    }
+
+    /// Total heat capacity of the building: air of the interior zones (`air`, unless
+    /// a zone has its own), their internal mass, layers of the boundaries and the soil
+    /// under boundaries to ground.
+    /// This is the heat capacity of all finite nodes of the RC network built from
+    /// the model, see `RcNetwork::total_heat_capacity`.
+    pub fn total_heat_capacity(&self, air: &Material) -> HeatCapacity {
+        let zones: HeatCapacity = self
+            .zones
+            .values()
+            .filter(|zone| zone.volume.is_some())
+            .map(|zone| {
+                zone.heat_capacity(zone.air_material(air))
+                    + zone
+                        .internal_mass
+                        .as_ref()
+                        .map_or(HeatCapacity::default(), |mass| mass.heat_capacity)
+            })
+            .sum();
+        let boundaries: HeatCapacity = self
+            .boundaries
+            .iter()
+            .map(|boundary| {
+                let layers = match boundary.boundary_type.as_ref() {
+                    BoundaryType::Layered { layers, .. } => layers
+                        .iter()
+                        .map(|layer| layer.heat_capacity(boundary.area))
+                        .sum(),
+                    BoundaryType::Simple { .. } => HeatCapacity::default(),
+                };
+                let soil = match boundary.ground_side() {
+                    Some(_) => self.ground.heat_capacity(boundary.area),
+                    None => HeatCapacity::default(),
+                };
+                layers + soil
+            })
+            .sum();
+        zones + boundaries
+    }
+
+    /// Boundary types that appear on at least one boundary (after sub-boundary expansion),
+    /// each listed once, sorted by name.
+    pub fn used_boundary_types(&self) -> Vec<&Rc<BoundaryType>> {
+        let mut used: Vec<&Rc<BoundaryType>> = Vec::new();
+        for boundary in self.boundaries.iter() {
+            if !used
+                .iter()
+                .any(|bt| Rc::ptr_eq(bt, &boundary.boundary_type))
+            {
+                used.push(&boundary.boundary_type);
+            }
+        }
+        used.sort_by(|a, b| a.name().cmp(b.name()));
+        used
+    }
+
+    /// Reject material layers thinner than `min_thickness` (usually
+    /// `DEFAULT_MIN_LAYER_THICKNESS`), for callers that want to be strict about
+    /// suspicious model data. Loading only warns about them in `validate_all`.
+    pub fn check_layer_thickness(&self, min_thickness: Length) -> Result<(), ModelError> {
+        for boundary_type in self.used_boundary_types() {
+            if let BoundaryType::Layered { name, layers, .. } = boundary_type.as_ref() {
+                for layer in layers.iter() {
+                    layer.check_thickness(name, min_thickness)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Boundary type with the given name, if any boundary uses it.
+    pub fn boundary_type(&self, name: &str) -> Option<&BoundaryType> {
+        self.boundaries
+            .iter()
+            .map(|boundary| boundary.boundary_type.as_ref())
+            .find(|boundary_type| boundary_type.name() == name)
+    }
+
+    /// Modify the boundary type with the given name, e.g. to fit its parameters
+    /// without reloading the model.
+    /// The boundary type is shared by all boundaries that use it, the edit applies to all
+    /// of them. It is copied on write (`Rc::make_mut`), so other models sharing the
+    /// boundary type (e.g. clones of this one) are unaffected.
+    /// Returns `None` if no boundary uses the boundary type.
+    pub fn boundary_type_mut<R>(
+        &mut self,
+        name: &str,
+        edit: impl FnOnce(&mut BoundaryType) -> R,
+    ) -> Option<R> {
+        let original = self
+            .boundaries
+            .iter()
+            .map(|boundary| &boundary.boundary_type)
+            .find(|boundary_type| boundary_type.name() == name)?
+            .clone();
+        let mut edited = original.clone();
+        let result = edit(Rc::make_mut(&mut edited));
+        for boundary in self.boundaries.iter_mut() {
+            if Rc::ptr_eq(&boundary.boundary_type, &original) {
+                boundary.boundary_type = edited.clone();
+            }
+            if let Some(parent) = boundary.parent.as_mut() {
+                if Rc::ptr_eq(&parent.boundary_type, &original) {
+                    parent.boundary_type = edited.clone();
+                }
+            }
+        }
+        Some(result)
+    }
+
+    /// Set the thickness of all layers of the given material in a layered boundary type,
+    /// see `boundary_type_mut`.
+    pub fn set_layer_thickness(
+        &mut self,
+        boundary_type: &str,
+        material: &str,
+        thickness: Length,
+    ) -> anyhow::Result<()> {
+        if !as_loaded::is_positive(thickness.value) {
+            anyhow::bail!(
+                "Thickness of layer {:?} of boundary type {:?} must be positive, got {:?}",
+                material,
+                boundary_type,
+                thickness
+            );
+        }
+        self.boundary_type_mut(boundary_type, |edited| {
+            let BoundaryType::Layered { layers, .. } = edited else {
+                anyhow::bail!("Boundary type {:?} has no layers", boundary_type);
+            };
+            let mut found = false;
+            for layer in layers.iter_mut() {
+                if let LayerContent::Material {
+                    material: layer_material,
+                    thickness: layer_thickness,
+                } = &mut layer.content
+                {
+                    if layer_material.name == material {
+                        *layer_thickness = thickness;
+                        found = true;
+                    }
+                }
+            }
+            if !found {
+                return Err(ModelError::MissingMaterial(material.to_string()).into());
+            }
+            Ok(())
+        })
+        .ok_or_else(|| ModelError::MissingBoundaryType(boundary_type.to_string()))?
+    }
+
+    /// Boundaries (after sub-boundary expansion) that have `zone` on either side.
+    pub fn boundaries_of<'a>(&'a self, zone: &str) -> impl Iterator<Item = &'a Boundary> {
+        let zone = zone.to_string();
+        self.boundaries
+            .iter()
+            .filter(move |boundary| boundary.zones.iter().any(|z| z.name == zone))
+    }
+
+    /// Names of zones sharing at least one boundary with `zone`, each listed once, sorted.
+    pub fn neighbors(&self, zone: &str) -> Vec<&str> {
+        self.boundaries_of(zone)
+            .map(|boundary| {
+                if boundary.zones[0].name == zone {
+                    boundary.zones[1].name.as_str()
+                } else {
+                    boundary.zones[0].name.as_str()
+                }
+            })
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Run all validation checks on a model file without stopping on the first problem.
+    /// Relative paths in the `include` list are resolved against the directory of the file,
+    /// like in `load`.
+    pub fn validate_file<P: AsRef<Path>>(path: P) -> ValidationReport {
+        match fs::read_to_string(&path) {
+            Ok(string) => {
+                let base_dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+                Self::validate_with_base_dir(&string, base_dir)
+            }
+            Err(e) => ValidationReport {
+                errors: vec![format!("Can't read model: {}", e)],
+                warnings: Vec::new(),
+            },
+        }
+    }
+
+    /// Run all validation checks on a JSON5 model without stopping on the first problem.
+    /// Relative paths in the `include` list are resolved against the current directory.
+    pub fn validate_all(json: &str) -> ValidationReport {
+        Self::validate_with_base_dir(json, Path::new(""))
+    }
+
+    fn validate_with_base_dir(json: &str, base_dir: &Path) -> ValidationReport {
+        match json5::from_str::<as_loaded::Model>(json) {
+            Ok(mut loaded) => {
+                if let Err(e) = loaded.resolve_includes(base_dir) {
+                    return ValidationReport {
+                        errors: vec![e.to_string()],
+                        warnings: Vec::new(),
+                    };
+                }
+                loaded.validate()
+            }
+            Err(e) => ValidationReport {
+                errors: vec![format!("Can't parse model: {}", e)],
+                warnings: Vec::new(),
+            },
+        }
+    }
+}
+
+/// All problems found in a model by `Model::validate_all`.
+/// Errors prevent the model from loading, warnings are likely mistakes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in self.errors.iter() {
+            writeln!(f, "error: {}", error)?;
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            self.errors.len(),
+            self.warnings.len()
+        )
+    }
+}
+
+/// Reason why a loaded model can't be converted to a `Model`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModelError {
+    /// The model defines a zone with a reserved name (`outside` or `ground`)
+    ReservedZone(String),
+    MissingMaterial(String),
+    MissingBoundaryType(String),
+    MissingZone(String),
+    /// A material property that must be finite and positive is not
+    InvalidMaterialProperty {
+        material: String,
+        property: &'static str,
+    },
+    /// Sub-boundaries of the boundary between `zones` have more area than the boundary
+    SubBoundaryTooLarge {
+        zones: [String; 2],
+    },
+    /// Zones of a sub-boundary don't include any zone of its boundary
+    UnrelatedSubBoundaryZones {
+        zones: [String; 2],
+        boundary_zones: [String; 2],
+    },
+    /// A layered boundary type consists only of markers
+    NoNonMarkerLayer(String),
+    /// A layered boundary type has two markers without a layer between them
+    ConsecutiveMarkers(String),
+    /// Zones that don't appear in any boundary, sorted
+    OrphanedZones(Vec<String>),
+    DuplicateHeatSource(String),
+    /// A heat source is placed in a zone without volume (outside or ground)
+    ExteriorHeatSource(String),
+    /// Maximum power of a heat source is negative or NaN
+    InvalidHeatSourcePower(String),
+    /// A thermal bridge has the same zone on both sides
+    SelfThermalBridge(String),
+    /// Length or linear transmittance of a thermal bridge is negative or NaN
+    InvalidThermalBridge {
+        zones: [String; 2],
+    },
+    /// A zone has both volume and floor area or height
+    AmbiguousZoneVolume(String),
+    /// A zone has neither volume nor both floor area and height
+    MissingZoneVolume(String),
+    /// Tilt or azimuth is given for a boundary between two interior zones
+    InteriorOrientation {
+        zones: [String; 2],
+    },
+    /// A material refers to a preset that is not in the built-in catalog
+    MissingMaterialPreset {
+        material: String,
+        preset: String,
+    },
+    /// A material property is neither given nor taken from a preset
+    MissingMaterialProperty {
+        material: String,
+        property: &'static str,
+    },
+    /// Area of a shared surface of the zone can't be divided between its neighbors
+    SharedSurfaceArea(String),
+    /// A boundary or sub-boundary has the same zone on both sides
+    SelfBoundary(String),
+    /// Hourly internal gains of the zone don't have 24 values or have an unknown time zone
+    InvalidInternalGains(String),
+    /// A material layer is thinner than the minimum of `Model::check_layer_thickness`
+    ThinLayer {
+        boundary_type: String,
+        thickness: Length,
+        min_thickness: Length,
+    },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::ReservedZone(name) => write!(
+                f,
+                "'{}' is a reserved zone name and must not be defined in model",
+                name
+            ),
+            ModelError::MissingMaterial(name) => write!(f, "Could not find material {:?}", name),
+            ModelError::MissingBoundaryType(name) => {
+                write!(f, "Could not find boundary type {:?}", name)
+            }
+            ModelError::MissingZone(name) => write!(f, "Could not find zone {:?}", name),
+            ModelError::InvalidMaterialProperty { material, property } => write!(
+                f,
+                "Material {:?} has {} that is not a positive number",
+                material, property
+            ),
+            ModelError::SubBoundaryTooLarge { zones } => write!(
+                f,
+                "Boundary {:?} has less area than the sum of its sub-boundaries",
+                zones
+            ),
+            ModelError::UnrelatedSubBoundaryZones {
+                zones,
+                boundary_zones,
+            } => write!(
+                f,
+                "Sub-boundary zones {:?} don't share any zone with boundary {:?}",
+                zones, boundary_zones
+            ),
+            ModelError::NoNonMarkerLayer(name) => write!(
+                f,
+                "Boundary type {:?} does not have at least non-marker layer",
+                name
+            ),
+            ModelError::ConsecutiveMarkers(name) => {
+                write!(f, "Boundary type {:?} has two consecutive markers", name)
+            }
+            ModelError::OrphanedZones(zones) => {
+                write!(f, "Zones {:?} are not enclosed by any boundary", zones)
+            }
+            ModelError::DuplicateHeatSource(name) => {
+                write!(f, "Model has two heat sources named {:?}", name)
+            }
+            ModelError::ExteriorHeatSource(name) => {
+                write!(f, "Heat source {:?} is not in an interior zone", name)
+            }
+            ModelError::InvalidHeatSourcePower(name) => write!(
+                f,
+                "Heat source {:?} has max power that is not zero or a positive number",
+                name
+            ),
+            ModelError::SelfThermalBridge(zone) => {
+                write!(f, "Thermal bridge connects zone {:?} to itself", zone)
+            }
+            ModelError::InvalidThermalBridge { zones } => write!(
+                f,
+                "Thermal bridge between {:?} has length or psi that is not zero or a positive number",
+                zones
+            ),
+            ModelError::AmbiguousZoneVolume(name) => write!(
+                f,
+                "Zone {:?} has both volume and floor area or height, only one may be given",
+                name
+            ),
+            ModelError::MissingZoneVolume(name) => write!(
+                f,
+                "Zone {:?} needs either volume, or floor area and height",
+                name
+            ),
+            ModelError::InteriorOrientation { zones } => write!(
+                f,
+                "Boundary {:?} between interior zones can't have tilt or azimuth",
+                zones
+            ),
+            ModelError::MissingMaterialPreset { material, preset } => write!(
+                f,
+                "Could not find preset {:?} of material {:?}",
+                preset, material
+            ),
+            ModelError::MissingMaterialProperty { material, property } => write!(
+                f,
+                "Material {:?} needs {} unless it is taken from a preset",
+                material, property
+            ),
+            ModelError::SharedSurfaceArea(zone) => write!(
+                f,
+                "Areas of the neighbors of a shared surface of zone {:?} don't add up to its area",
+                zone
+            ),
+            ModelError::SelfBoundary(zone) => {
+                write!(f, "Boundary connects zone {:?} to itself", zone)
+            }
+            ModelError::InvalidInternalGains(zone) => write!(
+                f,
+                "Internal gains of zone {:?} need 24 hourly values and a known time zone",
+                zone
+            ),
+            ModelError::ThinLayer {
+                boundary_type,
+                thickness,
+                min_thickness,
+            } => write!(
+                f,
+                "Boundary type {:?} has a layer {} mm thick, thinner than {} mm",
+                boundary_type,
+                thickness.get::<millimeter>(),
+                min_thickness.get::<millimeter>()
+            ),
+        }
+    }
 }
 
+impl std::error::Error for ModelError {}
+
 impl TryFrom<as_loaded::Model> for Model {
-    type Error = anyhow::Error;
+    type Error = ModelError;
     fn try_from(value: as_loaded::Model) -> Result<Self, Self::Error> {
-        let reserved_outer_zones = vec!["outside", "ground"];
+        let reserved_outer_zones = ["outside", "ground"];
         for z in reserved_outer_zones.iter() {
             if value.zones.contains_key(*z) {
-                anyhow::bail!(
-                    "'{}' is a reserved zone name and must not be defined in model",
-                    z
-                );
+                return Err(ModelError::ReservedZone((*z).to_string()));
             }
         }
 
         let mut converted_materials: HashMap<_, _> = value
             .materials
             .into_iter()
-            .map(|(name, material)| (name.clone(), Rc::new(material.convert(name))))
-            .collect();
+            .map(|(name, material)| Ok((name.clone(), Rc::new(material.convert(name)?))))
+            .collect::<Result<_, ModelError>>()?;
 
         let default_air = Material::default_air();
         if !converted_materials.contains_key(&default_air.name) {
@@ -79,75 +666,209 @@ impl TryFrom<as_loaded::Model> for Model {
                     Rc::new(boundary_type.convert(name, &converted_materials)?),
                 ))
             })
-            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+            .collect::<Result<HashMap<_, _>, ModelError>>()?;
         let mut converted_zones = value
             .zones
             .into_iter()
             .map(|(name, zone)| {
-                (
+                let volume = zone.volume(&name)?;
+                let internal_mass = zone
+                    .internal_mass
+                    .map(|internal_mass| internal_mass.convert(&converted_materials))
+                    .transpose()?;
+                let air = zone
+                    .air
+                    .map(|air| get(&converted_materials, &air, ModelError::MissingMaterial))
+                    .transpose()?;
+                let internal_gains = zone
+                    .internal_gains
+                    .map(|internal_gains| internal_gains.convert(&name))
+                    .transpose()?;
+                Ok((
                     name.clone(),
                     Rc::new(Zone {
                         name,
-                        volume: Some(zone.volume),
+                        volume: Some(volume),
+                        internal_mass,
+                        air_changes_per_hour: zone.air_changes_per_hour,
+                        air,
+                        internal_gains,
                     }),
-                )
+                ))
             })
-            .collect::<HashMap<_, _>>();
+            .collect::<Result<HashMap<_, _>, ModelError>>()?;
         for z in reserved_outer_zones.iter() {
             converted_zones.insert(
                 (*z).into(),
                 Rc::new(Zone {
                     name: (*z).into(),
                     volume: None,
+                    internal_mass: None,
+                    air_changes_per_hour: None,
+                    air: None,
+                    internal_gains: None,
                 }),
             );
         }
 
         let mut converted_boundaries = Vec::new();
 
-        for boundary in value.boundaries.into_iter() {
+        let mut boundaries = value.boundaries;
+        for shared_surface in value.shared_surfaces.iter() {
+            boundaries.extend(shared_surface.expand()?);
+        }
+        for boundary in boundaries.into_iter() {
+            if boundary.zones[0] == boundary.zones[1] {
+                return Err(ModelError::SelfBoundary(boundary.zones[0].clone()));
+            }
             let mut remaining_area = boundary.area;
+            // Sub-boundaries share the surface resistances of their parent
+            let surface_resistances = SurfaceResistances {
+                interior: boundary.interior_surface_resistance,
+                exterior: boundary.exterior_surface_resistance,
+            };
             let zone_pair = [
-                get(&converted_zones, &boundary.zones[0], "zone")?,
-                get(&converted_zones, &boundary.zones[1], "zone")?,
+                get(
+                    &converted_zones,
+                    &boundary.zones[0],
+                    ModelError::MissingZone,
+                )?,
+                get(
+                    &converted_zones,
+                    &boundary.zones[1],
+                    ModelError::MissingZone,
+                )?,
             ];
+            let is_exterior =
+                |zones: &[Rc<Zone>; 2]| zones[0].volume.is_some() != zones[1].volume.is_some();
+            if !is_exterior(&zone_pair) && (boundary.tilt.is_some() || boundary.azimuth.is_some()) {
+                return Err(ModelError::InteriorOrientation {
+                    zones: boundary.zones,
+                });
+            }
+            // Sub-boundaries lie in the plane of their parent
+            let default_orientation = Orientation::default();
+            let orientation = Orientation {
+                tilt: boundary
+                    .tilt
+                    .map_or(default_orientation.tilt, Angle::new::<degree>),
+                azimuth: boundary
+                    .azimuth
+                    .map_or(default_orientation.azimuth, Angle::new::<degree>),
+            };
+            let boundary_type = get(
+                &converted_boundary_types,
+                &boundary.boundary_type,
+                ModelError::MissingBoundaryType,
+            )?;
             for sub_boundary in boundary.sub_boundaries {
-                if sub_boundary.area > remaining_area {
-                    anyhow::bail!(
-                        "Boundary {:?} has less area than the sum of its sub-boundaries",
-                        boundary.zones
-                    )
+                if sub_boundary.area > remaining_area
+                    && !is_negligible_area(remaining_area - sub_boundary.area, boundary.area)
+                {
+                    return Err(ModelError::SubBoundaryTooLarge {
+                        zones: boundary.zones,
+                    });
                 }
                 remaining_area -= sub_boundary.area;
 
+                let sub_zone_pair = match &sub_boundary.zones {
+                    Some(zones) => {
+                        if !zones.iter().any(|z| boundary.zones.contains(z)) {
+                            return Err(ModelError::UnrelatedSubBoundaryZones {
+                                zones: zones.clone(),
+                                boundary_zones: boundary.zones,
+                            });
+                        }
+                        if zones[0] == zones[1] {
+                            return Err(ModelError::SelfBoundary(zones[0].clone()));
+                        }
+                        [
+                            get(&converted_zones, &zones[0], ModelError::MissingZone)?,
+                            get(&converted_zones, &zones[1], ModelError::MissingZone)?,
+                        ]
+                    }
+                    None => zone_pair.clone(),
+                };
+
                 converted_boundaries.push(Boundary {
                     boundary_type: get(
                         &converted_boundary_types,
                         &sub_boundary.boundary_type,
-                        "boundary type",
+                        ModelError::MissingBoundaryType,
                     )?,
-                    zones: zone_pair.clone(),
+                    orientation: is_exterior(&sub_zone_pair).then_some(orientation),
+                    zones: sub_zone_pair,
                     area: sub_boundary.area,
+                    surface_resistances: surface_resistances.clone(),
+                    parent: Some(ParentBoundary {
+                        boundary_type: Rc::clone(&boundary_type),
+                        zones: zone_pair.clone(),
+                    }),
                 })
             }
 
-            converted_boundaries.push(Boundary {
-                boundary_type: get(
-                    &converted_boundary_types,
-                    &boundary.boundary_type,
-                    "boundary type",
-                )?,
-                zones: zone_pair,
-                area: remaining_area,
+            // A parent fully covered by its sub-boundaries would only add zero
+            // conductance edges to the network
+            if !is_negligible_area(remaining_area, boundary.area) {
+                converted_boundaries.push(Boundary {
+                    boundary_type,
+                    orientation: is_exterior(&zone_pair).then_some(orientation),
+                    zones: zone_pair,
+                    area: remaining_area,
+                    surface_resistances,
+                    parent: None,
+                })
+            }
+        }
+
+        let enclosed_zones: HashSet<&str> = converted_boundaries
+            .iter()
+            .flat_map(|boundary| boundary.zones.iter().map(|zone| zone.name.as_str()))
+            .collect();
+        let orphaned_zones: Vec<String> = converted_zones
+            .keys()
+            .filter(|name| {
+                !reserved_outer_zones.contains(&name.as_str())
+                    && !enclosed_zones.contains(name.as_str())
             })
+            .sorted()
+            .cloned()
+            .collect();
+        if !orphaned_zones.is_empty() {
+            return Err(ModelError::OrphanedZones(orphaned_zones));
         }
 
-        let air = get(&converted_materials, "air", "material")?;
+        let air = get(&converted_materials, "air", ModelError::MissingMaterial)?;
+
+        let mut heat_source_names = HashSet::new();
+        let heat_sources = value
+            .heat_sources
+            .into_iter()
+            .map(|heat_source| {
+                if !heat_source_names.insert(heat_source.name.clone()) {
+                    return Err(ModelError::DuplicateHeatSource(heat_source.name));
+                }
+                heat_source.convert(&converted_zones)
+            })
+            .collect::<Result<Vec<_>, ModelError>>()?;
+
+        let thermal_bridges = value
+            .thermal_bridges
+            .into_iter()
+            .map(|thermal_bridge| thermal_bridge.convert(&converted_zones))
+            .collect::<Result<Vec<_>, ModelError>>()?;
 
         Ok(Model {
             zones: converted_zones,
             boundaries: converted_boundaries,
             air,
+            location: value.location.map(|location| location.convert()),
+            heat_sources,
+            ground: value
+                .ground
+                .map(|ground| ground.convert())
+                .unwrap_or_default(),
+            thermal_bridges,
         })
     }
 }
@@ -168,9 +889,21 @@ impl Arbitrary for Model {
                         1..20,
                     ),
                     prop::collection::vec(Zone::arbitrary().prop_map(Rc::new), 2..10),
+                    prop::bool::ANY,
                 )
             })
-            .prop_flat_map(|(materials, boundary_types, zones)| {
+            .prop_flat_map(|(materials, boundary_types, mut zones, with_ground)| {
+                // Boundaries to ground get a soil node
+                if with_ground {
+                    zones.push(Rc::new(Zone {
+                        name: "ground".to_string(),
+                        volume: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    }));
+                }
                 let boundary_types = Rc::new(boundary_types);
                 let zones = Rc::new(zones);
                 (
@@ -186,18 +919,152 @@ impl Arbitrary for Model {
                     .collect::<HashMap<_, _>>(),
                 boundaries,
                 air: Rc::clone(materials.iter().next().unwrap()),
+                location: None,
+                heat_sources: Vec::new(),
+                ground: Ground::default(),
+                thermal_bridges: Vec::new(),
             })
             .boxed()
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub latitude: Angle,
+    pub longitude: Angle,
+    /// Calibration of the solar model for the local climate
+    pub climate: ClimateConfig,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Zone {
     pub name: String,
     pub volume: Option<Volume>,
+    /// Thermal mass of the zone contents (furniture, books, ...), if any.
+    pub internal_mass: Option<InternalMass>,
+    /// Exchange of the zone air with outside air (infiltration, ventilation),
+    /// in zone volumes per hour.
+    pub air_changes_per_hour: Option<f64>,
+    /// Air contained in the zone, if it differs from the air of the model
+    /// (e.g. humid air in a bathroom).
+    pub air: Option<Rc<Material>>,
+    /// Heat released by occupants, appliances and lighting, if any.
+    pub internal_gains: Option<InternalGains>,
+}
+
+/// Heat released in a zone by occupants, appliances and lighting, injected into
+/// the zone air regardless of its temperature.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InternalGains {
+    Constant(Power),
+    /// Values at the full hours of the day in local time of `timezone`, starting
+    /// at midnight, interpolated linearly between the hours (and from 23:00 to midnight).
+    Hourly {
+        values: [Power; 24],
+        timezone: Tz,
+    },
+}
+
+impl InternalGains {
+    /// Power of the gains at the given time.
+    pub fn power_at(&self, time: &DateTime<Utc>) -> Power {
+        match self {
+            InternalGains::Constant(power) => *power,
+            InternalGains::Hourly { values, timezone } => {
+                let local = time.with_timezone(timezone);
+                let index = local.hour() as usize;
+                let fraction = f64::from(local.minute() * 60 + local.second()) / 3600.0;
+                values[index].interpolate(values[(index + 1) % 24], fraction)
+            }
+        }
+    }
+}
+
+/// Controllable heater or cooler (radiator, AC unit, ...) injecting power into the air
+/// of an interior zone. The power itself is an input of the simulation
+/// (`Inputs::heat_sources`), negative for cooling.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeatSource {
+    pub name: String,
+    pub zone: Rc<Zone>,
+    /// Largest power the source can deliver
+    pub max_power: Power,
+}
+
+/// Linear thermal bridge at a junction of boundaries (wall corners, floor edges,
+/// window frames, ...). Layered boundaries only conduct heat perpendicular to their
+/// surface, the extra heat flow around the junction is added as a direct conductance
+/// ψ·l between the two zones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermalBridge {
+    pub zones: [Rc<Zone>; 2],
+    /// Length of the junction
+    pub length: Length,
+    /// Linear thermal transmittance (ψ value) of the junction
+    pub psi: ThermalConductivity,
+}
+
+impl ThermalBridge {
+    pub fn conductance(&self) -> ThermalConductance {
+        self.psi * self.length
+    }
+
+    /// Check if the thermal bridge connects an interior zone to outside or ground.
+    pub fn is_exterior(&self) -> bool {
+        self.zones[0].volume.is_some() != self.zones[1].volume.is_some()
+    }
+}
+
+/// Soil between the boundaries to `ground` and the ground temperature, modeled as
+/// a single layer of the given depth. The ground temperature is taken to be known
+/// at that depth.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ground {
+    pub thermal_conductivity: ThermalConductivity,
+    pub specific_heat_capacity: SpecificHeatCapacity,
+    pub density: MassDensity,
+    /// Effective thickness of the soil layer
+    pub depth: Length,
+}
+
+impl Default for Ground {
+    /// Clay or silt, as in ISO 13370 (λ = 1.5 W/mK, ρc = 3 MJ/m³K), one meter deep
+    fn default() -> Self {
+        Ground {
+            thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(1.5),
+            specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(1500.0),
+            density: MassDensity::new::<kilogram_per_cubic_meter>(2000.0),
+            depth: Length::new::<meter>(1.0),
+        }
+    }
+}
+
+impl Ground {
+    /// Conductance of the soil layer under the given area
+    pub fn conductance(&self, area: Area) -> ThermalConductance {
+        self.thermal_conductivity * area / self.depth
+    }
+
+    /// Heat capacity of the soil layer under the given area
+    pub fn heat_capacity(&self, area: Area) -> HeatCapacity {
+        area * self.depth * self.density * self.specific_heat_capacity
+    }
+}
+
+/// Lumped thermal mass inside a zone, exchanging heat with the zone air by convection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InternalMass {
+    pub heat_capacity: HeatCapacity,
+    /// Surface area of the contents, exposed to the zone air
+    pub area: Area,
 }
 
 impl Zone {
+    /// Air contained in the zone, `model_air` unless the zone has its own.
+    pub fn air_material<'a>(&'a self, model_air: &'a Material) -> &'a Material {
+        self.air.as_deref().unwrap_or(model_air)
+    }
+
     pub fn heat_capacity(&self, content: &Material) -> HeatCapacity {
         if let Some(volume) = self.volume {
             volume * content.density * content.specific_heat_capacity
@@ -205,6 +1072,17 @@ impl Zone {
             HeatCapacity::new::<joule_per_kelvin>(f64::INFINITY)
         }
     }
+
+    /// Conductance between the zone and outside caused by air exchange,
+    /// `None` if the zone has no air exchange or no volume (outside and ground).
+    pub fn ventilation_conductance(&self, air: &Material) -> Option<ThermalConductance> {
+        let volume = self.volume?;
+        let air_changes = self.air_changes_per_hour?;
+        Some(
+            volume * air.density * air.specific_heat_capacity * air_changes
+                / Time::new::<hour>(1.0),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -213,10 +1091,26 @@ impl Arbitrary for Zone {
     type Strategy = BoxedStrategy<Zone>;
 
     fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
-        ("[a-z]*", prop::option::of(0.1f64..1000f64))
+        (
+            "[a-z]*",
+            prop::option::of(0.1f64..1000f64),
+            prop::option::of((1f64..1e7f64, 0.1f64..100f64)),
+        )
             .prop_map(|tuple| Zone {
                 name: tuple.0,
                 volume: tuple.1.map(Volume::new::<cubic_meter>),
+                // Only interior zones can have internal mass
+                internal_mass: tuple
+                    .1
+                    .and(tuple.2)
+                    .map(|(heat_capacity, area)| InternalMass {
+                        heat_capacity: HeatCapacity::new::<joule_per_kelvin>(heat_capacity),
+                        area: Area::new::<square_meter>(area),
+                    }),
+                // Air exchange needs an outside zone, which arbitrary models don't have
+                air_changes_per_hour: None,
+                air: None,
+                internal_gains: None,
             })
             .boxed()
     }
@@ -227,41 +1121,229 @@ pub struct Boundary {
     pub boundary_type: Rc<BoundaryType>,
     pub zones: [Rc<Zone>; 2],
     pub area: Area,
+    /// Fixed surface resistances, replacing the calculated surface convection
+    pub surface_resistances: SurfaceResistances,
+    /// Orientation of exterior boundaries, `None` for boundaries between interior zones
+    pub orientation: Option<Orientation>,
+    /// Boundary a sub-boundary was given in when loading (e.g. the wall around a window),
+    /// `None` for other boundaries. Doesn't affect the thermal network, it only lets
+    /// the model be saved in the form it was loaded in.
+    pub parent: Option<ParentBoundary>,
 }
 
-#[cfg(test)]
-impl Arbitrary for Boundary {
-    type Parameters = (Rc<Vec<Rc<BoundaryType>>>, Rc<Vec<Rc<Zone>>>);
-    type Strategy = BoxedStrategy<Boundary>;
+/// Boundary type and zones of the boundary a sub-boundary belongs to,
+/// see `Boundary::parent`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParentBoundary {
+    pub boundary_type: Rc<BoundaryType>,
+    pub zones: [Rc<Zone>; 2],
+}
 
-    fn arbitrary_with(params: (Rc<Vec<Rc<BoundaryType>>>, Rc<Vec<Rc<Zone>>>)) -> Self::Strategy {
-        let (boundary_types, zones) = params;
-        assert!(boundary_types.len() > 0);
-        assert!(zones.len() > 1);
-        (
-            0..boundary_types.len(),
-            0..zones.len(),
-            0..(zones.len() - 1),
-            1e-6f64..1000f64,
-        )
-            .prop_map(move |params| {
-                let z1 = params.1;
-                let z2 = if params.2 < params.1 {
-                    params.2
-                } else {
-                    params.2 + 1
-                };
-                assert_ne!(z1, z2);
-                Boundary {
+/// Orientation of the outer surface of an exterior boundary, for solar calculations
+/// (see `tools::sun::calculate_tilted_irradiance`).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Orientation {
+    /// Angle from horizontal, 0° for a flat roof, 90° for a wall
+    pub tilt: Angle,
+    /// Direction the surface faces, clockwise from north
+    pub azimuth: Angle,
+}
+
+impl Default for Orientation {
+    /// Flat surface (facing south, although that doesn't matter when flat)
+    fn default() -> Self {
+        Orientation {
+            tilt: Angle::new::<degree>(0.0),
+            azimuth: Angle::new::<degree>(180.0),
+        }
+    }
+}
+
+/// Surface resistances of a layered boundary given by the user, e.g. the conventional
+/// values of ISO 6946 (0.13 m²K/W interior, 0.04 m²K/W exterior for walls).
+/// These include both convection and radiation. Sides without a value use still air
+/// convection, like the rest of the model, except for the side facing ground, which
+/// touches the soil directly.
+/// Simple boundaries ignore them, their U value already includes the surfaces.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SurfaceResistances {
+    /// Resistance of surfaces facing interior zones
+    pub interior: Option<ThermalInsulance>,
+    /// Resistance of the surface facing outside or ground
+    pub exterior: Option<ThermalInsulance>,
+}
+
+impl Boundary {
+    /// Check if the boundary separates an interior zone from outside or ground.
+    pub fn is_exterior(&self) -> bool {
+        self.zones[0].volume.is_some() != self.zones[1].volume.is_some()
+    }
+
+    /// Surface resistances given for the sides of `zones[0]` and `zones[1]`, if any.
+    /// The exterior resistance applies to the side of outside or ground of an exterior
+    /// boundary, the interior resistance to all other sides.
+    pub fn surface_resistance_overrides(&self) -> [Option<ThermalInsulance>; 2] {
+        let side = |zone: &Zone| {
+            if self.is_exterior() && zone.volume.is_none() {
+                self.surface_resistances.exterior
+            } else {
+                self.surface_resistances.interior
+            }
+        };
+        [side(&self.zones[0]), side(&self.zones[1])]
+    }
+
+    /// Index of the side of the boundary facing the ground zone, if any.
+    pub fn ground_side(&self) -> Option<usize> {
+        self.zones
+            .iter()
+            .position(|zone| zone.name == "ground" && zone.volume.is_none())
+    }
+
+    /// Surface resistances on the sides of `zones[0]` and `zones[1]`, either given
+    /// or calculated from still air convection.
+    /// The side facing ground touches the soil directly, its resistance is zero
+    /// unless given.
+    pub fn surface_resistances(&self) -> [ThermalInsulance; 2] {
+        let convection = air_convection_conductance(Velocity::new::<meter_per_second>(0.0)).recip();
+        let overrides = self.surface_resistance_overrides();
+        [0, 1].map(|side| {
+            overrides[side].unwrap_or(if self.ground_side() == Some(side) {
+                ThermalInsulance::default()
+            } else {
+                convection
+            })
+        })
+    }
+
+    /// Thermal transmittance of the boundary, per square meter of its area.
+    /// Same as `BoundaryType::u_value`, but using the surface resistances of this boundary.
+    pub fn u_value(&self) -> HeatTransfer {
+        match self.boundary_type.as_ref() {
+            BoundaryType::Layered { layers, .. } => {
+                let [r1, r2] = self.surface_resistances();
+                layers
+                    .iter()
+                    .map(|layer| layer.r_value())
+                    .fold(r1 + r2, |a, b| a + b)
+                    .recip()
+            }
+            BoundaryType::Simple { u, .. } => *u,
+        }
+    }
+
+    /// Solar power transmitted through the boundary, if it is glazing (a simple boundary
+    /// between outside and an interior zone), with the interior zone it enters.
+    /// The power is `g * area * irradiance`, with the irradiance weighted by the angular
+    /// transmittance (`TiltedIrradiance::through_glazing`) if the boundary type has it.
+    ///
+    /// Arguments:
+    /// * `irradiance` - irradiance on the plane of the boundary
+    /// * `incidence_angle` - angle between the sun beam and the boundary normal,
+    ///   `None` when the sun doesn't shine on the boundary
+    pub fn transmitted_solar_power(
+        &self,
+        irradiance: &TiltedIrradiance,
+        incidence_angle: Option<Angle>,
+    ) -> Option<(&Rc<Zone>, Power)> {
+        let BoundaryType::Simple {
+            g,
+            angular_transmittance,
+            ..
+        } = self.boundary_type.as_ref()
+        else {
+            return None;
+        };
+        let interior_zone = match [self.zones[0].name.as_str(), self.zones[1].name.as_str()] {
+            ["outside", _] => &self.zones[1],
+            [_, "outside"] => &self.zones[0],
+            _ => return None,
+        };
+        interior_zone.volume?;
+        let irradiance = if *angular_transmittance {
+            irradiance.through_glazing(incidence_angle)
+        } else {
+            irradiance.total
+        };
+        Some((interior_zone, *g * self.area * irradiance))
+    }
+
+    /// Key identifying the boundary independently of the order of its zones.
+    /// Two boundaries with equal keys have the same boundary type name, connect the same
+    /// pair of zones and have the same area.
+    ///
+    /// Returns:
+    /// * boundary type name
+    /// * sorted names of the two zones
+    /// * bit pattern of the area in square meters (to make the key hashable)
+    pub fn canonical_key(&self) -> (String, [String; 2], u64) {
+        let mut zones = [self.zones[0].name.clone(), self.zones[1].name.clone()];
+        zones.sort();
+        (
+            self.boundary_type.name().to_string(),
+            zones,
+            self.area.get::<square_meter>().to_bits(),
+        )
+    }
+}
+
+/// Thermal resistance in m²K/W (uom doesn't have a unit for it)
+#[cfg(test)]
+fn r_value(value: f64) -> ThermalInsulance {
+    Length::new::<meter>(value) / ThermalConductivity::new::<watt_per_meter_kelvin>(1.0)
+}
+
+#[cfg(test)]
+impl Arbitrary for Boundary {
+    type Parameters = (Rc<Vec<Rc<BoundaryType>>>, Rc<Vec<Rc<Zone>>>);
+    type Strategy = BoxedStrategy<Boundary>;
+
+    fn arbitrary_with(params: (Rc<Vec<Rc<BoundaryType>>>, Rc<Vec<Rc<Zone>>>)) -> Self::Strategy {
+        let (boundary_types, zones) = params;
+        assert!(!boundary_types.is_empty());
+        assert!(zones.len() > 1);
+        (
+            0..boundary_types.len(),
+            0..zones.len(),
+            0..(zones.len() - 1),
+            1e-6f64..1000f64,
+            prop::option::of(0.01f64..1f64),
+            prop::option::of(0.01f64..1f64),
+        )
+            .prop_map(move |params| {
+                let z1 = params.1;
+                let z2 = if params.2 < params.1 {
+                    params.2
+                } else {
+                    params.2 + 1
+                };
+                assert_ne!(z1, z2);
+                Boundary {
                     boundary_type: Rc::clone(&boundary_types[params.0]),
                     zones: [Rc::clone(&zones[z1]), Rc::clone(&zones[z2])],
                     area: Area::new::<square_meter>(params.3),
+                    surface_resistances: SurfaceResistances {
+                        interior: params.4.map(r_value),
+                        exterior: params.5.map(r_value),
+                    },
+                    // Arbitrary zones are all interior
+                    orientation: None,
+                    parent: None,
                 }
             })
             .boxed()
     }
 }
 
+/// Solar absorptance of layered boundary types that don't specify it
+/// (a medium colored facade)
+pub const DEFAULT_SOLAR_ABSORPTANCE: f64 = 0.6;
+
+/// Material layers thinner than this [m] are most likely a mistake in the model data,
+/// they dominate the network by their large conductance and tiny heat capacity
+/// (see `Model::check_layer_thickness`).
+pub const DEFAULT_MIN_LAYER_THICKNESS: f64 = 0.001;
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum BoundaryType {
     Layered {
@@ -271,14 +1353,131 @@ pub enum BoundaryType {
         /// A name that can be used to address the interface between the zone and
         /// the first layer.
         initial_marker: Option<String>,
+        /// Fraction of the sunlight absorbed by the outer surface
+        /// (see `RcNetwork::absorbed_solar_gains`).
+        solar_absorptance: Ratio,
     },
     Simple {
         name: String,
         u: HeatTransfer,
         g: Ratio,
+        /// Reduce `g` at oblique incidence of the sun beam
+        /// (see `tools::sun::angular_transmittance_factor`).
+        angular_transmittance: bool,
     },
 }
 
+impl BoundaryType {
+    pub fn name(&self) -> &str {
+        match self {
+            BoundaryType::Layered { name, .. } => name,
+            BoundaryType::Simple { name, .. } => name,
+        }
+    }
+
+    /// Thermal transmittance of the boundary type, per square meter of its area.
+    /// For layered boundaries this includes the surface convection on both sides,
+    /// calculated the same way as in the RC network (still air).
+    pub fn u_value(&self) -> HeatTransfer {
+        match self {
+            BoundaryType::Layered {
+                name: _,
+                layers,
+                initial_marker: _,
+                solar_absorptance: _,
+            } => {
+                let surface_resistance =
+                    air_convection_conductance(Velocity::new::<meter_per_second>(0.0)).recip();
+                layers
+                    .iter()
+                    .map(|layer| layer.r_value())
+                    .fold(2.0 * surface_resistance, |a, b| a + b)
+                    .recip()
+            }
+            BoundaryType::Simple { u, .. } => *u,
+        }
+    }
+
+    /// Dynamic thermal characteristics of the boundary type for a sinusoidal temperature
+    /// variation with the given period, calculated using the matrix method of ISO 13786.
+    /// Surface resistances are the same as in `u_value`.
+    /// Simple boundaries are massless, their decrement factor is 1 and time lag is 0.
+    pub fn dynamic_characteristics(&self, period: Time) -> DynamicCharacteristics {
+        let period = period.get::<second>();
+        let resistance_matrix = |resistance: f64| {
+            na::Matrix2::new(
+                Complex::new(1.0, 0.0),
+                Complex::new(-resistance, 0.0),
+                Complex::new(0.0, 0.0),
+                Complex::new(1.0, 0.0),
+            )
+        };
+
+        let transfer_matrix = match self {
+            BoundaryType::Layered { layers, .. } => {
+                let surface_resistance =
+                    air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+                        .get::<watt_per_square_meter_kelvin>()
+                        .recip();
+                let unit_area = Area::new::<square_meter>(1.0);
+                let layers_matrix = layers
+                    .iter()
+                    .map(|layer| {
+                        // Value of ThermalInsulance in the base unit, m²·K/W
+                        let resistance = layer.r_value().value;
+                        let heat_capacity =
+                            layer.heat_capacity(unit_area).get::<joule_per_kelvin>();
+                        if heat_capacity == 0.0 {
+                            return resistance_matrix(resistance);
+                        }
+                        // Thickness divided by the periodic penetration depth.
+                        // Thickness itself cancels out, so layers given by R-value work too.
+                        let xi =
+                            (std::f64::consts::PI * resistance * heat_capacity / period).sqrt();
+                        let (sinh, cosh, sin, cos) = (xi.sinh(), xi.cosh(), xi.sin(), xi.cos());
+                        let z11 = Complex::new(cosh * cos, sinh * sin);
+                        let z12 = Complex::new(sinh * cos + cosh * sin, cosh * sin - sinh * cos)
+                            * (-resistance / (2.0 * xi));
+                        let z21 = Complex::new(sinh * cos - cosh * sin, sinh * cos + cosh * sin)
+                            * (-xi / resistance);
+                        na::Matrix2::new(z11, z12, z21, z11)
+                    })
+                    .fold(resistance_matrix(surface_resistance), |product, layer| {
+                        layer * product
+                    });
+                resistance_matrix(surface_resistance) * layers_matrix
+            }
+            BoundaryType::Simple { u, .. } => {
+                resistance_matrix(u.get::<watt_per_square_meter_kelvin>().recip())
+            }
+        };
+
+        let periodic_transmittance = -transfer_matrix[(0, 1)].inv();
+        // Phase of the periodic transmittance is negative (the response lags behind)
+        let lag_angle = (-periodic_transmittance.argument()).rem_euclid(2.0 * std::f64::consts::PI);
+        let periodic_transmittance =
+            HeatTransfer::new::<watt_per_square_meter_kelvin>(periodic_transmittance.modulus());
+
+        DynamicCharacteristics {
+            decrement_factor: periodic_transmittance / self.u_value(),
+            time_lag: Time::new::<second>(lag_angle / (2.0 * std::f64::consts::PI) * period),
+            periodic_thermal_transmittance: periodic_transmittance,
+        }
+    }
+}
+
+/// Response of a boundary to a periodic temperature variation on one side (ISO 13786).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicCharacteristics {
+    /// Amplitude of the periodic heat flow relative to the steady state heat flow
+    /// for the same temperature amplitude
+    pub decrement_factor: Ratio,
+    /// Delay of the heat flow peak after the temperature peak
+    pub time_lag: Time,
+    /// Amplitude of the heat flow on one side per amplitude of temperature on the other side
+    pub periodic_thermal_transmittance: HeatTransfer,
+}
+
 #[cfg(test)]
 impl Arbitrary for BoundaryType {
     type Parameters = Rc<Vec<Rc<Material>>>;
@@ -290,41 +1489,125 @@ impl Arbitrary for BoundaryType {
                 name: tuple.0,
                 u: HeatTransfer::new::<watt_per_square_meter_kelvin>(tuple.1),
                 g: Ratio::new::<percent>(tuple.2),
+                angular_transmittance: false,
             }),
             (
                 "[a-z]*",
                 prop::collection::vec(BoundaryLayer::arbitrary_with(materials), 1..10),
                 prop::option::of("[a-z]*"),
+                0f64..100f64,
             )
                 .prop_map(|tuple| BoundaryType::Layered {
                     name: tuple.0,
                     layers: tuple.1,
-                    initial_marker: tuple.2
+                    initial_marker: tuple.2,
+                    solar_absorptance: Ratio::new::<percent>(tuple.3),
                 }),
         ]
         .boxed()
     }
 }
 
+/// Thermal resistance of a unit area (R-value) [m²·K/W]
+pub type ThermalInsulance = <Length as std::ops::Div<ThermalConductivity>>::Output;
+/// Heat capacity of a unit area [J/(m²·K)]
+pub type ArealHeatCapacity = <HeatCapacity as std::ops::Div<Area>>::Output;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct BoundaryLayer {
-    pub material: Rc<Material>,
-    pub thickness: Length,
+    pub content: LayerContent,
     /// A name that can be used to address the interface following this layer.
     /// (between this layer and the next, or between this layer and the zone, if this is the last
     /// layer)
     pub following_marker: Option<String>,
 }
 
+/// What a boundary layer is made of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayerContent {
+    Material {
+        material: Rc<Material>,
+        thickness: Length,
+    },
+    /// Layer given directly by its thermal resistance, the way insulation
+    /// products are often specified.
+    Resistance {
+        r_value: ThermalInsulance,
+        heat_capacity_per_area: ArealHeatCapacity,
+    },
+}
+
 impl BoundaryLayer {
+    /// Check that a material layer of a boundary type is at least `min_thickness` thick,
+    /// layers given by R-value have no thickness.
+    pub fn check_thickness(
+        &self,
+        boundary_type: &str,
+        min_thickness: Length,
+    ) -> Result<(), ModelError> {
+        match &self.content {
+            LayerContent::Material { thickness, .. } if *thickness < min_thickness => {
+                Err(ModelError::ThinLayer {
+                    boundary_type: boundary_type.to_string(),
+                    thickness: *thickness,
+                    min_thickness,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn heat_capacity(&self, area: Area) -> HeatCapacity {
-        let volume = area * self.thickness;
-        let material_mass = volume * self.material.density;
-        material_mass * self.material.specific_heat_capacity
+        match &self.content {
+            LayerContent::Material {
+                material,
+                thickness,
+            } => {
+                let volume = area * *thickness;
+                let material_mass = volume * material.density;
+                material_mass * material.specific_heat_capacity
+            }
+            LayerContent::Resistance {
+                r_value: _,
+                heat_capacity_per_area,
+            } => *heat_capacity_per_area * area,
+        }
     }
 
     pub fn conductance(&self, area: Area) -> ThermalConductance {
-        self.material.thermal_conductivity * area / self.thickness
+        match &self.content {
+            LayerContent::Material {
+                material,
+                thickness,
+            } => material.thermal_conductivity * area / *thickness,
+            LayerContent::Resistance {
+                r_value,
+                heat_capacity_per_area: _,
+            } => area / *r_value,
+        }
+    }
+
+    /// Emissivity of the layer surface.
+    /// Layers given by R-value don't have a material, they use `DEFAULT_EMISSIVITY`.
+    pub fn emissivity(&self) -> Ratio {
+        match &self.content {
+            LayerContent::Material { material, .. } => material.emissivity,
+            LayerContent::Resistance { .. } => Ratio::new::<ratio>(DEFAULT_EMISSIVITY),
+        }
+    }
+
+    /// Thermal resistance of a unit area of the layer.
+    pub fn r_value(&self) -> ThermalInsulance {
+        match &self.content {
+            LayerContent::Material {
+                material,
+                thickness,
+            } => *thickness / material.thermal_conductivity,
+            LayerContent::Resistance {
+                r_value,
+                heat_capacity_per_area: _,
+            } => *r_value,
+        }
     }
 }
 
@@ -334,15 +1617,17 @@ impl Arbitrary for BoundaryLayer {
     type Strategy = BoxedStrategy<BoundaryLayer>;
 
     fn arbitrary_with(materials: Rc<Vec<Rc<Material>>>) -> Self::Strategy {
-        assert!(materials.len() > 0);
+        assert!(!materials.is_empty());
         (
             0..materials.len(),
             1e-6f64..5f64,
             prop::option::of("[a-z]*"),
         )
             .prop_map(move |tuple| BoundaryLayer {
-                material: Rc::clone(&materials[tuple.0]),
-                thickness: Length::new::<meter>(tuple.1),
+                content: LayerContent::Material {
+                    material: Rc::clone(&materials[tuple.0]),
+                    thickness: Length::new::<meter>(tuple.1),
+                },
                 following_marker: tuple.2,
             })
             .boxed()
@@ -355,9 +1640,52 @@ pub struct Material {
     pub thermal_conductivity: ThermalConductivity,
     pub specific_heat_capacity: SpecificHeatCapacity,
     pub density: MassDensity,
+    /// Longwave emissivity of the material surface
+    pub emissivity: Ratio,
 }
 
+/// Emissivity of materials that don't specify it (most building materials are close to it)
+pub const DEFAULT_EMISSIVITY: f64 = 0.9;
+
+/// Built-in materials, usable in models with `from_preset`.
+/// Name, thermal conductivity [W/(m.K)], specific heat capacity [J/(kg.K)],
+/// density [kg/m³] and emissivity.
+/// Design values from EN ISO 10456 (table 3), emissivities from EN ISO 6946 (annex C).
+const MATERIAL_PRESETS: [(&str, f64, f64, f64, f64); 9] = [
+    ("brick", 0.77, 1000.0, 1800.0, 0.9),
+    ("concrete", 2.3, 1000.0, 2300.0, 0.9),
+    ("reinforced_concrete", 2.5, 1000.0, 2400.0, 0.9),
+    ("mineral_wool", 0.04, 1030.0, 40.0, 0.9),
+    ("expanded_polystyrene", 0.035, 1450.0, 20.0, 0.9),
+    ("gypsum_board", 0.25, 1000.0, 900.0, 0.9),
+    ("lime_sand_plaster", 0.8, 1000.0, 1600.0, 0.9),
+    ("softwood", 0.13, 1600.0, 500.0, 0.9),
+    ("glass", 1.0, 750.0, 2500.0, 0.837),
+];
+
 impl Material {
+    /// Material from the built-in catalog, named after the preset.
+    pub fn preset(name: &str) -> Option<Material> {
+        MATERIAL_PRESETS.iter().find(|preset| preset.0 == name).map(
+            |&(name, thermal_conductivity, specific_heat_capacity, density, emissivity)| Material {
+                name: name.into(),
+                thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(
+                    thermal_conductivity,
+                ),
+                specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
+                    specific_heat_capacity,
+                ),
+                density: MassDensity::new::<kilogram_per_cubic_meter>(density),
+                emissivity: Ratio::new::<ratio>(emissivity),
+            },
+        )
+    }
+
+    /// Names of all materials available through `Material::preset`.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        MATERIAL_PRESETS.iter().map(|preset| preset.0)
+    }
+
     /// Return a default implementation of air material, used if air is not
     /// explicitly defined in the model
     fn default_air() -> Material {
@@ -366,6 +1694,7 @@ impl Material {
             thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.026),
             specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(1012.0),
             density: MassDensity::new::<kilogram_per_cubic_meter>(1.199),
+            emissivity: Ratio::new::<ratio>(DEFAULT_EMISSIVITY),
         }
     }
 }
@@ -381,6 +1710,7 @@ impl Arbitrary for Material {
             1e-6f64..100f64,
             1e-6f64..100f64,
             1e-6f64..10000f64,
+            0f64..1f64,
         )
             .prop_map(|tuple| Material {
                 name: tuple.0,
@@ -389,788 +1719,4801 @@ impl Arbitrary for Material {
                     tuple.2,
                 ),
                 density: MassDensity::new::<kilogram_per_cubic_meter>(tuple.3),
+                emissivity: Ratio::new::<ratio>(tuple.4),
             })
             .boxed()
     }
 }
 
-fn get<K, V, Q>(h: &HashMap<K, Rc<V>>, key: &Q, label: &str) -> anyhow::Result<Rc<V>>
-where
-    K: std::borrow::Borrow<Q>,
-    K: std::hash::Hash + std::cmp::Eq,
-    Q: std::hash::Hash + std::cmp::Eq + std::fmt::Debug + ?Sized,
-{
-    Ok(Rc::clone(h.get(key).ok_or_else(|| {
-        anyhow::anyhow!("Could not find {} {:?}", label, key)
-    })?))
+/// Hashing of model contents for `Model::content_hash`.
+/// Floats can't implement `Hash`, so this is a separate trait.
+trait ContentHash {
+    fn content_hash<H: Hasher>(&self, state: &mut H);
 }
 
-mod as_loaded {
-    use std::collections::HashMap;
-    use std::rc::Rc;
-
-    use serde::Deserialize;
-    use uom::si::f64::{
-        Area, HeatTransfer, Length, MassDensity, Ratio, SpecificHeatCapacity, ThermalConductivity,
-        Volume,
-    };
-
-    use super::get;
-
-    #[derive(Clone, Debug, Deserialize)]
-    pub struct Model {
-        pub zones: HashMap<String, Zone>,
-        pub boundaries: Vec<Boundary>,
-        pub materials: HashMap<String, Material>,
-        pub boundary_types: HashMap<String, BoundaryType>,
+impl ContentHash for f64 {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.to_bits().hash(state);
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    pub struct Zone {
-        pub volume: Volume,
+impl<D, U> ContentHash for uom::si::Quantity<D, U, f64>
+where
+    D: uom::si::Dimension + ?Sized,
+    U: uom::si::Units<f64> + ?Sized,
+{
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.value.content_hash(state);
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    pub struct AdjacentZone {
-        pub suffix: String,
-        pub boundary_type: String,
-        pub area: Area,
+impl<T: ContentHash> ContentHash for Option<T> {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.is_some().hash(state);
+        if let Some(value) = self {
+            value.content_hash(state);
+        }
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    pub struct Boundary {
-        pub boundary_type: String,
-        pub zones: [String; 2],
-        pub area: Area,
-        #[serde(default)]
-        pub sub_boundaries: Vec<SubBoundary>,
+impl<T: ContentHash> ContentHash for Rc<T> {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().content_hash(state);
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    pub struct SubBoundary {
-        pub boundary_type: String,
-        pub area: Area,
+impl<A: ContentHash, B: ContentHash> ContentHash for (A, B) {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.0.content_hash(state);
+        self.1.content_hash(state);
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    #[serde(untagged)]
-    pub enum BoundaryType {
-        Layered {
-            layers: Vec<BoundaryLayer>,
-        },
-        /// Simple boundaries don't have any mass!
-        Simple {
-            u: HeatTransfer,
-            g: Ratio,
-        },
+impl ContentHash for Material {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.thermal_conductivity.content_hash(state);
+        self.specific_heat_capacity.content_hash(state);
+        self.density.content_hash(state);
+        self.emissivity.content_hash(state);
     }
+}
 
-    impl BoundaryType {
-        pub fn convert(
-            self,
-            name: String,
-            materials: &HashMap<String, Rc<super::Material>>,
-        ) -> anyhow::Result<super::BoundaryType> {
-            Ok(match self {
-                BoundaryType::Layered { layers } => {
-                    // Verify that the input looks OK:
-                    let mut prev_is_marker = false;
-                    let mut have_non_marker = false;
-                    for layer in layers.iter() {
-                        let is_marker = layer.is_marker();
-                        if is_marker && prev_is_marker {
-                            anyhow::bail!("Boundary type {:?} has two consecutive markers", name);
-                        }
-                        have_non_marker |= !is_marker;
-                        prev_is_marker = is_marker;
-                    }
-                    if !have_non_marker {
-                        anyhow::bail!(
-                            "Boundary type {:?} does not have at least non-marker layer",
-                            name
-                        );
-                    };
-
-                    let mut out_layers: Vec<super::BoundaryLayer> =
-                        Vec::with_capacity(layers.len());
-
-                    // This construction kind of peeks the first element and consumes it
-                    // from the iterator if it matches
-                    let first_is_marker = layers.first().unwrap().is_marker();
-                    let mut it = layers.into_iter();
-                    let initial_marker = if first_is_marker {
-                        match it.next() {
-                            Some(BoundaryLayer::Marker { marker }) => Some(marker),
-                            _ => panic!(), // IMPOSIBIRU!
-                        }
-                    } else {
-                        None
-                    };
-
-                    // Convert the individual layers and assign markers
-                    for layer in it {
-                        if let BoundaryLayer::Marker { marker } = layer {
-                            let following_marker =
-                                &mut out_layers.last_mut().unwrap().following_marker;
-                            assert!(following_marker.is_none());
-                            *following_marker = Some(marker);
-                        } else {
-                            out_layers.push(layer.convert(materials)?);
-                        }
-                    }
+impl ContentHash for Zone {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.volume.content_hash(state);
+        self.internal_mass.content_hash(state);
+        self.air_changes_per_hour.content_hash(state);
+        self.air.content_hash(state);
+        self.internal_gains.content_hash(state);
+    }
+}
 
-                    super::BoundaryType::Layered {
-                        name,
-                        layers: out_layers,
-                        initial_marker,
-                    }
+impl ContentHash for InternalGains {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            InternalGains::Constant(power) => {
+                0u8.hash(state);
+                power.content_hash(state);
+            }
+            InternalGains::Hourly { values, timezone } => {
+                1u8.hash(state);
+                for value in values {
+                    value.content_hash(state);
                 }
-                BoundaryType::Simple { u, g } => super::BoundaryType::Simple { name, u, g },
-            })
+                timezone.name().hash(state);
+            }
         }
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    #[serde(untagged)]
-    pub enum BoundaryLayer {
-        Layer { material: String, thickness: Length },
-        Marker { marker: String },
+impl ContentHash for InternalMass {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.heat_capacity.content_hash(state);
+        self.area.content_hash(state);
     }
+}
 
-    impl BoundaryLayer {
-        pub fn convert(
-            self,
-            materials: &HashMap<String, Rc<super::Material>>,
-        ) -> anyhow::Result<super::BoundaryLayer> {
-            Ok(match self {
-                BoundaryLayer::Layer {
-                    material,
-                    thickness,
-                } => super::BoundaryLayer {
-                    material: get(materials, &material, "material")?,
-                    thickness,
-                    following_marker: None,
-                },
-                BoundaryLayer::Marker { marker: _ } => panic!("Can't convert a marker"),
-            })
+impl ContentHash for BoundaryType {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            BoundaryType::Layered {
+                name,
+                layers,
+                initial_marker,
+                solar_absorptance,
+            } => {
+                0u8.hash(state);
+                name.hash(state);
+                layers.len().hash(state);
+                for layer in layers {
+                    layer.content_hash(state);
+                }
+                initial_marker.hash(state);
+                solar_absorptance.content_hash(state);
+            }
+            BoundaryType::Simple {
+                name,
+                u,
+                g,
+                angular_transmittance,
+            } => {
+                1u8.hash(state);
+                name.hash(state);
+                u.content_hash(state);
+                g.content_hash(state);
+                angular_transmittance.hash(state);
+            }
         }
+    }
+}
 
-        pub fn is_marker(&self) -> bool {
-            match self {
-                Self::Layer {
-                    material: _,
-                    thickness: _,
-                } => false,
-                Self::Marker { marker: _ } => true,
+impl ContentHash for BoundaryLayer {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        match &self.content {
+            LayerContent::Material {
+                material,
+                thickness,
+            } => {
+                0u8.hash(state);
+                material.content_hash(state);
+                thickness.content_hash(state);
+            }
+            LayerContent::Resistance {
+                r_value,
+                heat_capacity_per_area,
+            } => {
+                1u8.hash(state);
+                r_value.content_hash(state);
+                heat_capacity_per_area.content_hash(state);
             }
         }
+        self.following_marker.hash(state);
     }
+}
 
-    #[derive(Clone, Debug, Deserialize, PartialEq)]
-    pub struct Material {
-        pub thermal_conductivity: ThermalConductivity,
-        pub specific_heat_capacity: SpecificHeatCapacity,
-        pub density: MassDensity,
+impl ContentHash for Boundary {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.boundary_type.content_hash(state);
+        self.zones[0].name.hash(state);
+        self.zones[1].name.hash(state);
+        self.area.content_hash(state);
+        self.surface_resistances.interior.content_hash(state);
+        self.surface_resistances.exterior.content_hash(state);
+        self.orientation
+            .map(|orientation| (orientation.tilt, orientation.azimuth))
+            .content_hash(state);
     }
+}
 
-    impl Material {
-        pub fn convert(self, name: String) -> super::Material {
-            super::Material {
-                name,
-                thermal_conductivity: self.thermal_conductivity,
-                specific_heat_capacity: self.specific_heat_capacity,
-                density: self.density,
-            }
-        }
+impl ContentHash for HeatSource {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.zone.name.hash(state);
+        self.max_power.content_hash(state);
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use approx::assert_abs_diff_eq;
-    use assert_matches::assert_matches;
-    use test_case::test_case;
-    use test_strategy::proptest;
-    use uom::si::{
-        area::square_meter, heat_transfer::watt_per_square_meter_kelvin, length::meter,
-        mass_density::kilogram_per_cubic_meter, ratio::percent,
-        specific_heat_capacity::joule_per_kilogram_kelvin,
-        thermal_conductivity::watt_per_meter_kelvin, volume::cubic_meter,
-    };
-
-    #[test]
-    fn convert_material() {
-        let input = as_loaded::Material {
-            thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(123.0),
-            specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(456.0),
-            density: MassDensity::new::<kilogram_per_cubic_meter>(789.0),
-        };
-
-        let output = input.convert("qwertyuiop".into());
-
-        assert_eq!(output.name, "qwertyuiop");
-        assert_eq!(
-            output.thermal_conductivity,
-            ThermalConductivity::new::<watt_per_meter_kelvin>(123.0)
-        );
-        assert_eq!(
-            output.specific_heat_capacity,
-            SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(456.0)
-        );
-        assert_eq!(
-            output.density,
-            MassDensity::new::<kilogram_per_cubic_meter>(789.0)
-        );
+impl ContentHash for ThermalBridge {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.zones[0].name.hash(state);
+        self.zones[1].name.hash(state);
+        self.length.content_hash(state);
+        self.psi.content_hash(state);
     }
+}
 
-    #[test]
-    fn convert_boundary_layer() {
-        let input = as_loaded::BoundaryLayer::Layer {
-            material: "mat1".into(),
-            thickness: Length::new::<meter>(0.2),
-        };
-        let materials = converted_materials_hashmap();
-        let output = input.convert(&materials).unwrap();
-        assert_eq!(
-            output,
-            BoundaryLayer {
-                thickness: Length::new::<meter>(0.2),
-                material: Rc::clone(&materials["mat1"]),
-                following_marker: None
-            }
-        );
+impl ContentHash for Ground {
+    fn content_hash<H: Hasher>(&self, state: &mut H) {
+        self.thermal_conductivity.content_hash(state);
+        self.specific_heat_capacity.content_hash(state);
+        self.density.content_hash(state);
+        self.depth.content_hash(state);
     }
+}
 
-    #[test]
-    fn convert_boundary_type_layered_intial_marker() {
-        let input = as_loaded::BoundaryType::Layered {
-            layers: vec![
-                as_loaded::BoundaryLayer::Marker {
-                    marker: "A DUCK!".into(),
-                },
-                as_loaded::BoundaryLayer::Layer {
-                    material: "mat1".into(),
-                    thickness: Length::new::<meter>(1.0),
-                },
-                as_loaded::BoundaryLayer::Layer {
-                    material: "mat2".into(),
-                    thickness: Length::new::<meter>(2.0),
-                },
-            ],
-        };
-        let materials = converted_materials_hashmap();
-        let output = input.convert("somename".to_string(), &materials).unwrap();
-        assert_eq!(
-            output,
-            BoundaryType::Layered {
-                name: "somename".into(),
-                layers: vec![
-                    BoundaryLayer {
-                        thickness: Length::new::<meter>(1.0),
-                        material: Rc::clone(&materials["mat1"]),
-                        following_marker: None,
-                    },
-                    BoundaryLayer {
-                        thickness: Length::new::<meter>(2.0),
-                        material: Rc::clone(&materials["mat2"]),
-                        following_marker: None,
-                    },
-                ],
-                initial_marker: Some("A DUCK!".into()),
-            }
-        );
-    }
+/// Hash items independently of their order, by hashing the sorted hashes of the items.
+fn hash_unordered<'a, T: ContentHash + 'a, H: Hasher>(
+    items: impl Iterator<Item = &'a T>,
+    state: &mut H,
+) {
+    let mut hashes: Vec<u64> = items
+        .map(|item| {
+            let mut item_state = DefaultHasher::new();
+            item.content_hash(&mut item_state);
+            item_state.finish()
+        })
+        .collect();
+    hashes.sort_unstable();
+    hashes.hash(state);
+}
 
-    #[proptest]
-    fn convert_boundary_type_layered_marker_inside(#[strategy(1usize..4usize)] i: usize) {
-        let mut layers = vec![
-            as_loaded::BoundaryLayer::Layer {
-                material: "mat1".into(),
-                thickness: Length::new::<meter>(1.0),
-            },
-            as_loaded::BoundaryLayer::Layer {
-                material: "mat2".into(),
-                thickness: Length::new::<meter>(2.0),
-            },
-            as_loaded::BoundaryLayer::Layer {
-                material: "mat2".into(),
-                thickness: Length::new::<meter>(3.0),
-            },
-        ];
-        layers.insert(
-            i,
-            as_loaded::BoundaryLayer::Marker {
-                marker: "asdf".into(),
-            },
-        );
-        let input = as_loaded::BoundaryType::Layered { layers };
-        let materials = converted_materials_hashmap();
-        let output = input.convert("somename".to_string(), &materials).unwrap();
+/// Check if the area left of a boundary after subtracting its sub-boundaries is
+/// only a rounding error (e.g. of sub-boundary areas given with a few decimals).
+fn is_negligible_area(remaining_area: Area, boundary_area: Area) -> bool {
+    remaining_area.value.abs() <= 1e-9 * boundary_area.value.abs()
+}
 
-        assert_matches!(output, BoundaryType::Layered { name: _, layers, initial_marker } => {
-            assert!(initial_marker.is_none());
-            assert_eq!(layers.len(), 3);
-            assert!(layers.iter().enumerate().all(|(j, l)| (j == (i - 1)) || l.following_marker.is_none()));
-            assert_eq!(layers[i - 1].following_marker, Some("asdf".into()));
-        });
-    }
-    #[test]
-    fn convert_boundary_type_simple() {
-        let input = as_loaded::BoundaryType::Simple {
-            u: HeatTransfer::new::<watt_per_square_meter_kelvin>(123.0),
-            g: Ratio::new::<percent>(90.0),
-        };
-        let materials = HashMap::new();
-        let output = input.convert("somename".to_string(), &materials).unwrap();
-        assert_eq!(
-            output,
-            BoundaryType::Simple {
-                name: "somename".into(),
-                u: HeatTransfer::new::<watt_per_square_meter_kelvin>(123.0),
-                g: Ratio::new::<percent>(90.0)
-            }
-        );
+fn get<V>(
+    h: &HashMap<String, Rc<V>>,
+    key: &str,
+    missing: fn(String) -> ModelError,
+) -> Result<Rc<V>, ModelError> {
+    h.get(key)
+        .map(Rc::clone)
+        .ok_or_else(|| missing(key.to_string()))
+}
+
+/// Read the contents of a file included by a model.
+/// URLs (`http://` or `https://`) are only supported with the `remote-includes` feature,
+/// other includes are paths, relative to `base_dir`.
+fn fetch_include(include: &str, base_dir: &Path) -> anyhow::Result<String> {
+    if include.starts_with("http://") || include.starts_with("https://") {
+        fetch_remote_include(include)
+    } else {
+        fs::read_to_string(base_dir.join(include))
+            .map_err(|e| anyhow::anyhow!("Can't read include {:?}: {}", include, e))
     }
+}
 
-    #[test]
-    fn convert_boundary_type_layered_missing_material() {
-        let input = as_loaded::BoundaryType::Layered {
-            layers: vec![
-                as_loaded::BoundaryLayer::Layer {
-                    material: "matX".into(),
-                    thickness: Length::new::<meter>(1.0),
-                },
-                as_loaded::BoundaryLayer::Layer {
-                    material: "mat2".into(),
-                    thickness: Length::new::<meter>(2.0),
-                },
-            ],
-        };
-        let materials = converted_materials_hashmap();
+/// Download an included file, remembering the result for the rest of the process
+/// so that models sharing a library only fetch it once.
+#[cfg(feature = "remote-includes")]
+fn fetch_remote_include(url: &str) -> anyhow::Result<String> {
+    use std::sync::{Mutex, OnceLock};
 
-        let message = format!(
-            "{}",
-            input
-                .convert("somename".to_string(), &materials)
-                .unwrap_err()
-        );
+    static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
 
-        message
-            .find("material")
-            .expect("Error message should contain what type of object was missing");
-        message
-            .find("matX")
-            .expect("Error message should contain the name of the object");
+    if let Some(text) = cache.lock().unwrap().get(url) {
+        return Ok(text.clone());
     }
+    let text = ureq::get(url)
+        .call()
+        .map_err(|e| anyhow::anyhow!("Can't fetch include {:?}: {}", url, e))?
+        .into_string()?;
+    cache.lock().unwrap().insert(url.to_string(), text.clone());
+    Ok(text)
+}
 
-    #[test]
-    fn convert_boundary_type_no_layers() {
-        let input = as_loaded::BoundaryType::Layered { layers: vec![] };
-        let materials = converted_materials_hashmap();
-
-        let message = format!(
-            "{}",
-            input
-                .convert("somename".to_string(), &materials)
-                .unwrap_err()
-        );
+#[cfg(not(feature = "remote-includes"))]
+fn fetch_remote_include(url: &str) -> anyhow::Result<String> {
+    anyhow::bail!(
+        "Can't fetch include {:?}: remote includes need the `remote-includes` feature",
+        url
+    )
+}
 
-        message
-            .find("somename")
-            .expect("Error message should contain the name of the bad boundary type");
-    }
+mod as_loaded {
+    use std::collections::{HashMap, HashSet};
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::str::FromStr;
 
-    #[test]
-    fn convert_boundary_type_only_marker() {
-        let input = as_loaded::BoundaryType::Layered {
-            layers: vec![as_loaded::BoundaryLayer::Marker { marker: "X".into() }],
-        };
-        let materials = converted_materials_hashmap();
+    use chrono_tz::Tz;
+    use itertools::Itertools;
+    use serde::{de, Deserialize, Deserializer, Serialize};
+    use uom::si::f64::{
+        Angle, Area, HeatCapacity, HeatFluxDensity, HeatTransfer, Length, MassDensity, Power,
+        Ratio, SpecificHeatCapacity, ThermalConductivity, Volume,
+    };
+    use uom::str::ParseQuantityError;
 
-        let message = format!(
-            "{}",
-            input
-                .convert("somename".to_string(), &materials)
-                .unwrap_err()
-        );
+    use super::{
+        degree, get, is_negligible_area, meter, ratio, ClimateConfig, HorizonProfile, ModelError,
+        ValidationReport,
+    };
 
-        message
-            .find("somename")
-            .expect("Error message should contain the name of the bad boundary type");
+    /// Quantity given either as a number in the base SI unit (e.g. `0.1` for length
+    /// in meters), or as a string with a unit known to uom, e.g. `"10 cm"` or `"0.3 m"`.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum QuantityInput<Q> {
+        BaseUnit(Q),
+        WithUnit(String),
     }
 
-    #[test]
-    fn convert_boundary_type_successive_markers() {
-        let input = as_loaded::BoundaryType::Layered {
-            layers: vec![
-                as_loaded::BoundaryLayer::Layer {
-                    material: "mat1".into(),
-                    thickness: Length::new::<meter>(1.0),
-                },
-                as_loaded::BoundaryLayer::Marker {
-                    marker: "ONE DUCK!".into(),
-                },
-                as_loaded::BoundaryLayer::Marker {
-                    marker: "TWO DUCK!".into(),
-                },
-                as_loaded::BoundaryLayer::Layer {
-                    material: "mat2".into(),
-                    thickness: Length::new::<meter>(2.0),
-                },
-            ],
-        };
-        let materials = converted_materials_hashmap();
+    impl<Q: FromStr<Err = ParseQuantityError>> QuantityInput<Q> {
+        fn parse<E: de::Error>(self) -> Result<Q, E> {
+            match self {
+                QuantityInput::BaseUnit(quantity) => Ok(quantity),
+                QuantityInput::WithUnit(text) => text
+                    .parse()
+                    .map_err(|e| E::custom(format!("Invalid quantity {:?}: {}", text, e))),
+            }
+        }
+    }
 
-        let message = format!(
-            "{}",
-            input
-                .convert("somename".to_string(), &materials)
-                .unwrap_err()
-        );
+    /// Deserialize a quantity given by `QuantityInput`.
+    fn deserialize_quantity<'de, D, Q>(deserializer: D) -> Result<Q, D::Error>
+    where
+        D: Deserializer<'de>,
+        Q: Deserialize<'de> + FromStr<Err = ParseQuantityError>,
+    {
+        QuantityInput::deserialize(deserializer)?.parse()
+    }
 
-        println!("{}", message);
+    /// Deserialize an optional quantity given by `QuantityInput`.
+    fn deserialize_optional_quantity<'de, D, Q>(deserializer: D) -> Result<Option<Q>, D::Error>
+    where
+        D: Deserializer<'de>,
+        Q: Deserialize<'de> + FromStr<Err = ParseQuantityError>,
+    {
+        Option::<QuantityInput<Q>>::deserialize(deserializer)?
+            .map(QuantityInput::parse)
+            .transpose()
+    }
 
-        message
-            .find("somename")
-            .expect("Error message should contain the name of the bad boundary type");
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct Model {
+        pub zones: HashMap<String, Zone>,
+        pub boundaries: Vec<Boundary>,
+        pub materials: HashMap<String, Material>,
+        pub boundary_types: HashMap<String, BoundaryType>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub location: Option<Location>,
+        /// Files or URLs with additional materials and boundary types
+        /// (see `Library`).
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub include: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub heat_sources: Vec<HeatSource>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub ground: Option<Ground>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub thermal_bridges: Vec<ThermalBridge>,
+        /// Surfaces between a zone and several other zones, expanded to boundaries
+        /// when converting (see `SharedSurface`).
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub shared_surfaces: Vec<SharedSurface>,
     }
 
-    /// Tests the conversion of a minimal valid model
-    #[test]
-    fn convert_model_minimal() {
-        let input = as_loaded::Model {
-            zones: HashMap::new(),
-            boundaries: vec![],
-            materials: HashMap::new(),
-            boundary_types: HashMap::new(),
-        };
+    impl Model {
+        /// Convert a model back to the loaded form, for saving.
+        /// Sub-boundaries are put back into their parent boundaries (see
+        /// `super::Boundary::parent`), with the area of the parent restored.
+        /// Shared surfaces are saved as separate boundaries.
+        /// Fails if the model can't be represented, e.g. if two different materials
+        /// have the same name.
+        pub fn from_model(model: &super::Model) -> anyhow::Result<Self> {
+            let mut materials = HashMap::new();
+            if model.air.name != "air" {
+                anyhow::bail!(
+                    "Air material must be named \"air\", got {:?}",
+                    model.air.name
+                );
+            }
+            insert_unique(
+                &mut materials,
+                "air",
+                Material::from_model(&model.air),
+                "material",
+            )?;
 
-        let output: Model = input.try_into().unwrap();
+            let mut boundary_types = HashMap::new();
+            for boundary_type in model.used_boundary_types() {
+                let (name, converted) = BoundaryType::from_model(boundary_type, &mut materials)?;
+                insert_unique(&mut boundary_types, name, converted, "boundary type")?;
+            }
 
-        assert_eq!(output.zones.len(), 2); // Outside and ground are always there
-        assert!(output.boundaries.is_empty());
-    }
+            let mut zones = HashMap::new();
+            for (name, zone) in model.zones.iter() {
+                let is_reserved = name == "outside" || name == "ground";
+                match zone.volume {
+                    Some(volume) if !is_reserved => {
+                        let air = zone
+                            .air
+                            .as_ref()
+                            .map(|air| {
+                                insert_unique(
+                                    &mut materials,
+                                    &air.name,
+                                    Material::from_model(air),
+                                    "material",
+                                )?;
+                                Ok::<_, anyhow::Error>(air.name.clone())
+                            })
+                            .transpose()?;
+                        zones.insert(
+                            name.clone(),
+                            Zone {
+                                volume: Some(volume),
+                                floor_area: None,
+                                height: None,
+                                internal_mass: zone.internal_mass.as_ref().map(|internal_mass| {
+                                    InternalMass::Direct {
+                                        heat_capacity: internal_mass.heat_capacity,
+                                        area: internal_mass.area,
+                                    }
+                                }),
+                                air_changes_per_hour: zone.air_changes_per_hour,
+                                air,
+                                internal_gains: zone
+                                    .internal_gains
+                                    .as_ref()
+                                    .map(InternalGains::from_model),
+                            },
+                        );
+                    }
+                    None if is_reserved => {}
+                    _ => anyhow::bail!(
+                        "Zone {:?} can't be saved, only outside and ground can be without volume",
+                        name
+                    ),
+                }
+            }
+
+            // Sub-boundaries are put before their parent when loading, each of them
+            // waits for the next boundary it fits into
+            let mut boundaries = Vec::new();
+            let mut pending: Vec<&super::Boundary> = Vec::new();
+            for boundary in model.boundaries.iter() {
+                if let Some(parent) = boundary.parent.as_ref() {
+                    for boundary_type in [&boundary.boundary_type, &parent.boundary_type] {
+                        let (name, converted) =
+                            BoundaryType::from_model(boundary_type, &mut materials)?;
+                        insert_unique(&mut boundary_types, name, converted, "boundary type")?;
+                    }
+                    pending.push(boundary);
+                    continue;
+                }
+                let (sub_boundaries, rest) = pending
+                    .into_iter()
+                    .partition(|sub_boundary| Boundary::is_sub_boundary(sub_boundary, boundary));
+                pending = rest;
+                boundaries.push(Boundary::from_model(boundary, &sub_boundaries));
+            }
+            // Parents fully covered by their sub-boundaries were left out when loading
+            while let Some(&first) = pending.first() {
+                let parent = first.parent.as_ref().unwrap();
+                let (sub_boundaries, rest): (Vec<_>, _) =
+                    pending.into_iter().partition(|sub_boundary| {
+                        sub_boundary.parent.as_ref().is_some_and(|other| {
+                            other.boundary_type.name() == parent.boundary_type.name()
+                                && other.zones[0].name == parent.zones[0].name
+                                && other.zones[1].name == parent.zones[1].name
+                        }) && sub_boundary.surface_resistances == first.surface_resistances
+                    });
+                pending = rest;
+                let covered = super::Boundary {
+                    boundary_type: Rc::clone(&parent.boundary_type),
+                    zones: parent.zones.clone(),
+                    area: Area::default(),
+                    surface_resistances: first.surface_resistances.clone(),
+                    orientation: (parent.zones[0].volume.is_some()
+                        != parent.zones[1].volume.is_some())
+                    .then(|| {
+                        sub_boundaries
+                            .iter()
+                            .find_map(|sub_boundary| sub_boundary.orientation)
+                            .unwrap_or_default()
+                    }),
+                    parent: None,
+                };
+                boundaries.push(Boundary::from_model(&covered, &sub_boundaries));
+            }
+
+            Ok(Model {
+                zones,
+                boundaries,
+                materials,
+                boundary_types,
+                location: model.location.as_ref().map(Location::from_model),
+                include: Vec::new(),
+                heat_sources: model
+                    .heat_sources
+                    .iter()
+                    .map(|heat_source| HeatSource {
+                        name: heat_source.name.clone(),
+                        zone: heat_source.zone.name.clone(),
+                        max_power: heat_source.max_power,
+                    })
+                    .collect(),
+                ground: (model.ground != super::Ground::default())
+                    .then(|| Ground::from_model(&model.ground)),
+                thermal_bridges: model
+                    .thermal_bridges
+                    .iter()
+                    .map(|thermal_bridge| ThermalBridge {
+                        zones: [
+                            thermal_bridge.zones[0].name.clone(),
+                            thermal_bridge.zones[1].name.clone(),
+                        ],
+                        length: thermal_bridge.length,
+                        psi: thermal_bridge.psi,
+                    })
+                    .collect(),
+                shared_surfaces: Vec::new(),
+            })
+        }
+
+        /// Add everything from another model, see `super::Model::merge`.
+        pub fn merge(&mut self, other: Model) -> anyhow::Result<()> {
+            for (name, zone) in other.zones {
+                insert_unique(&mut self.zones, &name, zone, "zone")?;
+            }
+            for (name, material) in other.materials {
+                insert_unique(&mut self.materials, &name, material, "material")?;
+            }
+            for (name, boundary_type) in other.boundary_types {
+                insert_unique(
+                    &mut self.boundary_types,
+                    &name,
+                    boundary_type,
+                    "boundary type",
+                )?;
+            }
+            for heat_source in other.heat_sources {
+                match self
+                    .heat_sources
+                    .iter()
+                    .find(|existing| existing.name == heat_source.name)
+                {
+                    Some(existing) if *existing != heat_source => anyhow::bail!(
+                        "Model has two different heat sources named {:?}",
+                        heat_source.name
+                    ),
+                    Some(_) => {}
+                    None => self.heat_sources.push(heat_source),
+                }
+            }
+            merge_optional(&mut self.location, other.location, "locations")?;
+            merge_optional(&mut self.ground, other.ground, "grounds")?;
+            self.boundaries.extend(other.boundaries);
+            self.thermal_bridges.extend(other.thermal_bridges);
+            self.shared_surfaces.extend(other.shared_surfaces);
+            self.include.extend(other.include);
+            Ok(())
+        }
+    }
+
+    /// Take a setting of another model if this one doesn't have it,
+    /// failing if both have different values.
+    fn merge_optional<T: PartialEq>(
+        value: &mut Option<T>,
+        other: Option<T>,
+        label: &str,
+    ) -> anyhow::Result<()> {
+        match (value.as_ref(), other) {
+            (Some(existing), Some(other)) if *existing != other => {
+                anyhow::bail!("Models have different {}", label)
+            }
+            (None, other) => *value = other,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Insert a named item, failing if a different item with the same name is already there.
+    fn insert_unique<T: PartialEq>(
+        map: &mut HashMap<String, T>,
+        name: &str,
+        value: T,
+        label: &str,
+    ) -> anyhow::Result<()> {
+        match map.get(name) {
+            Some(existing) if *existing != value => {
+                anyhow::bail!("Model has two different {}s named {:?}", label, name)
+            }
+            Some(_) => {}
+            None => {
+                map.insert(name.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Contents of an included file: shared materials and boundary types.
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Library {
+        #[serde(default)]
+        pub materials: HashMap<String, Material>,
+        #[serde(default)]
+        pub boundary_types: HashMap<String, BoundaryType>,
+    }
+
+    impl Model {
+        /// Load all included files and merge their materials and boundary types
+        /// into the model. Definitions in the model itself take precedence over the
+        /// included ones, later includes take precedence over earlier ones.
+        pub fn resolve_includes(&mut self, base_dir: &Path) -> anyhow::Result<()> {
+            for include in std::mem::take(&mut self.include).into_iter().rev() {
+                let text = super::fetch_include(&include, base_dir)?;
+                let library: Library = json5::from_str(&text)
+                    .map_err(|e| anyhow::anyhow!("Can't parse include {:?}: {}", include, e))?;
+                for (name, material) in library.materials {
+                    self.materials.entry(name).or_insert(material);
+                }
+                for (name, boundary_type) in library.boundary_types {
+                    self.boundary_types.entry(name).or_insert(boundary_type);
+                }
+            }
+            Ok(())
+        }
+
+        /// Collect all problems of the model, see `super::Model::validate_all`.
+        pub fn validate(&self) -> ValidationReport {
+            let mut report = ValidationReport::default();
+            let reserved_outer_zones = ["outside", "ground"];
+
+            for z in reserved_outer_zones.iter() {
+                if self.zones.contains_key(*z) {
+                    report.errors.push(format!(
+                        "'{}' is a reserved zone name and must not be defined in model",
+                        z
+                    ));
+                }
+            }
+            for (name, zone) in self.zones.iter().sorted_by_key(|(name, _)| *name) {
+                match zone.volume(name) {
+                    Ok(volume) if !is_positive(volume.value) => {
+                        report
+                            .errors
+                            .push(format!("Zone {:?} has non-positive volume", name));
+                    }
+                    Ok(_) => {}
+                    Err(error) => report.errors.push(error.to_string()),
+                }
+                if let Some(air_changes) = zone.air_changes_per_hour {
+                    if air_changes.is_nan() || air_changes < 0.0 {
+                        report
+                            .errors
+                            .push(format!("Zone {:?} has negative air changes per hour", name));
+                    }
+                }
+                if let Some(internal_gains) = &zone.internal_gains {
+                    if let Err(error) = internal_gains.clone().convert(name) {
+                        report.errors.push(error.to_string());
+                    }
+                }
+            }
+
+            let mut materials: HashMap<_, _> = HashMap::new();
+            for (name, material) in self.materials.iter().sorted_by_key(|(name, _)| *name) {
+                let material = match material.clone().resolve(name.clone()) {
+                    Ok(material) => {
+                        if let Err(e) = check_material(&material) {
+                            report.errors.push(e.to_string());
+                        }
+                        if !(0.0..=1.0).contains(&material.emissivity.value) {
+                            report.errors.push(format!(
+                                "Material {:?} has emissivity outside of range 0 to 1",
+                                name
+                            ));
+                        }
+                        material
+                    }
+                    Err(e) => {
+                        report.errors.push(e.to_string());
+                        // Stand-in, so that the uses of the material are not reported
+                        // as missing
+                        super::Material {
+                            name: name.clone(),
+                            ..super::Material::default_air()
+                        }
+                    }
+                };
+                materials.insert(name.clone(), Rc::new(material));
+            }
+            let default_air = super::Material::default_air();
+            materials
+                .entry(default_air.name.clone())
+                .or_insert_with(|| Rc::new(default_air));
+
+            let mut used_materials = HashSet::from(["air"]);
+            for (name, boundary_type) in self.boundary_types.iter().sorted_by_key(|(name, _)| *name)
+            {
+                match boundary_type {
+                    BoundaryType::Layered {
+                        layers,
+                        solar_absorptance,
+                    } => {
+                        if solar_absorptance.is_some_and(|a| !(0.0..=1.0).contains(&a.value)) {
+                            report.errors.push(format!(
+                                "Boundary type {:?} has solar absorptance outside of range 0 to 1",
+                                name
+                            ));
+                        }
+                        for layer in layers.iter() {
+                            match layer {
+                                BoundaryLayer::Layer {
+                                    material,
+                                    thickness,
+                                } => {
+                                    used_materials.insert(material.as_str());
+                                    let min_thickness =
+                                        Length::new::<meter>(super::DEFAULT_MIN_LAYER_THICKNESS);
+                                    if !is_positive(thickness.value) {
+                                        report.errors.push(format!(
+                                            "Boundary type {:?} has a layer with non-positive thickness",
+                                            name
+                                        ));
+                                    } else if *thickness < min_thickness {
+                                        report.warnings.push(
+                                            ModelError::ThinLayer {
+                                                boundary_type: name.clone(),
+                                                thickness: *thickness,
+                                                min_thickness,
+                                            }
+                                            .to_string(),
+                                        );
+                                    }
+                                }
+                                BoundaryLayer::Resistance {
+                                    r_value,
+                                    heat_capacity_per_area,
+                                } => {
+                                    if !is_positive(r_value.value) {
+                                        report.errors.push(format!(
+                                            "Boundary type {:?} has a layer with non-positive R value",
+                                            name
+                                        ));
+                                    }
+                                    if heat_capacity_per_area.value < 0.0 {
+                                        report.errors.push(format!(
+                                            "Boundary type {:?} has a layer with negative heat capacity",
+                                            name
+                                        ));
+                                    }
+                                }
+                                BoundaryLayer::Marker { marker: _ } => {}
+                            }
+                        }
+                    }
+                    BoundaryType::Simple { u, g, .. } => {
+                        if !is_positive(u.value) {
+                            report
+                                .errors
+                                .push(format!("Boundary type {:?} has non-positive U value", name));
+                        }
+                        if !(0.0..=1.0).contains(&g.value) {
+                            report.errors.push(format!(
+                                "Boundary type {:?} has g value outside of range 0 to 1",
+                                name
+                            ));
+                        }
+                    }
+                }
+                if let Err(e) = boundary_type.clone().convert(name.clone(), &materials) {
+                    report.errors.push(e.to_string());
+                }
+            }
+            for (name, zone) in self.zones.iter().sorted_by_key(|(name, _)| *name) {
+                if let Some(air) = &zone.air {
+                    used_materials.insert(air.as_str());
+                    if !materials.contains_key(air) {
+                        report
+                            .errors
+                            .push(ModelError::MissingMaterial(air.clone()).to_string());
+                    }
+                }
+                let Some(internal_mass) = &zone.internal_mass else {
+                    continue;
+                };
+                let area = match internal_mass {
+                    InternalMass::Material {
+                        material,
+                        volume,
+                        area,
+                    } => {
+                        used_materials.insert(material.as_str());
+                        if !is_positive(volume.value) {
+                            report.errors.push(format!(
+                                "Internal mass of zone {:?} has non-positive volume",
+                                name
+                            ));
+                        }
+                        area
+                    }
+                    InternalMass::Direct {
+                        heat_capacity,
+                        area,
+                    } => {
+                        if !is_positive(heat_capacity.value) {
+                            report.errors.push(format!(
+                                "Internal mass of zone {:?} has non-positive heat capacity",
+                                name
+                            ));
+                        }
+                        area
+                    }
+                };
+                if !is_positive(area.value) {
+                    report.errors.push(format!(
+                        "Internal mass of zone {:?} has non-positive area",
+                        name
+                    ));
+                }
+                if let Err(e) = internal_mass.clone().convert(&materials) {
+                    report.errors.push(e.to_string());
+                }
+            }
+            for name in self.materials.keys().sorted() {
+                if !used_materials.contains(name.as_str()) {
+                    report
+                        .warnings
+                        .push(format!("Material {:?} is not used", name));
+                }
+            }
+
+            let mut shared_boundaries = Vec::new();
+            for shared_surface in self.shared_surfaces.iter() {
+                match shared_surface.expand() {
+                    Ok(boundaries) => shared_boundaries.extend(boundaries),
+                    Err(e) => report.errors.push(e.to_string()),
+                }
+            }
+
+            let mut used_boundary_types = HashSet::new();
+            let mut adjacency: HashMap<&str, Vec<&str>> = HashMap::new();
+            for boundary in self.boundaries.iter().chain(shared_boundaries.iter()) {
+                for zone in boundary.zones.iter() {
+                    if !self.zones.contains_key(zone)
+                        && !reserved_outer_zones.contains(&zone.as_str())
+                    {
+                        report
+                            .errors
+                            .push(format!("Could not find zone {:?}", zone));
+                    }
+                }
+                if boundary.zones[0] == boundary.zones[1] {
+                    report.errors.push(format!(
+                        "Boundary connects zone {:?} to itself",
+                        boundary.zones[0]
+                    ));
+                }
+                adjacency
+                    .entry(&boundary.zones[0])
+                    .or_default()
+                    .push(&boundary.zones[1]);
+                adjacency
+                    .entry(&boundary.zones[1])
+                    .or_default()
+                    .push(&boundary.zones[0]);
+
+                let mut remaining_area = boundary.area;
+                for boundary_type in std::iter::once(&boundary.boundary_type)
+                    .chain(boundary.sub_boundaries.iter().map(|sb| &sb.boundary_type))
+                {
+                    used_boundary_types.insert(boundary_type.as_str());
+                    if !self.boundary_types.contains_key(boundary_type) {
+                        report
+                            .errors
+                            .push(format!("Could not find boundary type {:?}", boundary_type));
+                    }
+                }
+                if !is_positive(boundary.area.value) {
+                    report.errors.push(format!(
+                        "Boundary {:?} has non-positive area",
+                        boundary.zones
+                    ));
+                }
+                let is_exterior = reserved_outer_zones.contains(&boundary.zones[0].as_str())
+                    != reserved_outer_zones.contains(&boundary.zones[1].as_str());
+                if !is_exterior && (boundary.tilt.is_some() || boundary.azimuth.is_some()) {
+                    report.errors.push(
+                        ModelError::InteriorOrientation {
+                            zones: boundary.zones.clone(),
+                        }
+                        .to_string(),
+                    );
+                }
+                if boundary
+                    .tilt
+                    .is_some_and(|tilt| !(0.0..=180.0).contains(&tilt))
+                {
+                    report.errors.push(format!(
+                        "Boundary {:?} has tilt outside of range 0 to 180 degrees",
+                        boundary.zones
+                    ));
+                }
+                for resistance in [
+                    boundary.interior_surface_resistance,
+                    boundary.exterior_surface_resistance,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    if !is_positive(resistance.value) {
+                        report.errors.push(format!(
+                            "Boundary {:?} has non-positive surface resistance",
+                            boundary.zones
+                        ));
+                    }
+                }
+                for sub_boundary in boundary.sub_boundaries.iter() {
+                    if !is_positive(sub_boundary.area.value) {
+                        report.errors.push(format!(
+                            "Sub-boundary of boundary {:?} has non-positive area",
+                            boundary.zones
+                        ));
+                    }
+                    remaining_area -= sub_boundary.area;
+
+                    if let Some(zones) = &sub_boundary.zones {
+                        if !zones.iter().any(|z| boundary.zones.contains(z)) {
+                            report.errors.push(format!(
+                                "Sub-boundary zones {:?} don't share any zone with boundary {:?}",
+                                zones, boundary.zones
+                            ));
+                        }
+                        for zone in zones.iter() {
+                            if !self.zones.contains_key(zone)
+                                && !reserved_outer_zones.contains(&zone.as_str())
+                            {
+                                report
+                                    .errors
+                                    .push(format!("Could not find zone {:?}", zone));
+                            }
+                        }
+                        if zones[0] == zones[1] {
+                            report.errors.push(format!(
+                                "Sub-boundary connects zone {:?} to itself",
+                                zones[0]
+                            ));
+                        }
+                        adjacency.entry(&zones[0]).or_default().push(&zones[1]);
+                        adjacency.entry(&zones[1]).or_default().push(&zones[0]);
+                    }
+                }
+                if !boundary.sub_boundaries.is_empty()
+                    && is_negligible_area(remaining_area, boundary.area)
+                {
+                    report.warnings.push(format!(
+                        "Boundary {:?} is completely covered by its sub-boundaries",
+                        boundary.zones
+                    ));
+                } else if remaining_area.value < 0.0 {
+                    report.errors.push(format!(
+                        "Boundary {:?} has less area than the sum of its sub-boundaries",
+                        boundary.zones
+                    ));
+                }
+            }
+            for name in self.boundary_types.keys().sorted() {
+                if !used_boundary_types.contains(name.as_str()) {
+                    report
+                        .warnings
+                        .push(format!("Boundary type {:?} is not used", name));
+                }
+            }
+
+            // Connectivity: every zone must be able to exchange heat with outside or ground
+            let mut reachable: HashSet<&str> = HashSet::new();
+            let mut stack: Vec<&str> = reserved_outer_zones.to_vec();
+            while let Some(zone) = stack.pop() {
+                if reachable.insert(zone) {
+                    if let Some(neighbors) = adjacency.get(zone) {
+                        stack.extend(neighbors.iter().copied());
+                    }
+                }
+            }
+            for name in self.zones.keys().sorted() {
+                if !adjacency.contains_key(name.as_str()) {
+                    report
+                        .errors
+                        .push(format!("Zone {:?} is not enclosed by any boundary", name));
+                } else if !reachable.contains(name.as_str()) {
+                    report.errors.push(format!(
+                        "Zone {:?} is not connected to outside or ground",
+                        name
+                    ));
+                } else if self.zones.len() > 1
+                    && adjacency[name.as_str()]
+                        .iter()
+                        .all(|neighbor| reserved_outer_zones.contains(neighbor))
+                {
+                    // Probably a misspelled zone name in the interior boundaries
+                    report.warnings.push(format!(
+                        "Zone {:?} only borders outside or ground, not any other zone",
+                        name
+                    ));
+                }
+            }
+
+            let mut heat_source_names = HashSet::new();
+            for heat_source in self.heat_sources.iter() {
+                if !heat_source_names.insert(heat_source.name.as_str()) {
+                    report.errors.push(format!(
+                        "Model has two heat sources named {:?}",
+                        heat_source.name
+                    ));
+                }
+                if reserved_outer_zones.contains(&heat_source.zone.as_str()) {
+                    report.errors.push(format!(
+                        "Heat source {:?} is not in an interior zone",
+                        heat_source.name
+                    ));
+                } else if !self.zones.contains_key(&heat_source.zone) {
+                    report
+                        .errors
+                        .push(format!("Could not find zone {:?}", heat_source.zone));
+                }
+                if !is_positive_or_zero(heat_source.max_power.value) {
+                    report.errors.push(
+                        ModelError::InvalidHeatSourcePower(heat_source.name.clone()).to_string(),
+                    );
+                }
+            }
+
+            for thermal_bridge in self.thermal_bridges.iter() {
+                for zone in thermal_bridge.zones.iter() {
+                    if !self.zones.contains_key(zone)
+                        && !reserved_outer_zones.contains(&zone.as_str())
+                    {
+                        report
+                            .errors
+                            .push(format!("Could not find zone {:?}", zone));
+                    }
+                }
+                if let Err(e) = thermal_bridge.check() {
+                    report.errors.push(e.to_string());
+                }
+            }
+
+            if let Some(ground) = &self.ground {
+                let ground = ground.clone().convert();
+                for (value, label) in [
+                    (ground.thermal_conductivity.value, "thermal conductivity"),
+                    (ground.density.value, "density"),
+                    (
+                        ground.specific_heat_capacity.value,
+                        "specific heat capacity",
+                    ),
+                    (ground.depth.value, "depth"),
+                ] {
+                    if !is_positive(value) {
+                        report
+                            .errors
+                            .push(format!("Ground has non-positive {}", label));
+                    }
+                }
+            }
+
+            if let Some(location) = &self.location {
+                let climate = location.climate.clone().convert();
+                if !is_positive(climate.solar_constant.value) {
+                    report
+                        .errors
+                        .push("Climate has non-positive solar constant".to_string());
+                }
+                for (albedo, label) in [
+                    (climate.albedo, "albedo"),
+                    (climate.snow_albedo, "snow albedo"),
+                ] {
+                    if !(0.0..=1.0).contains(&albedo.value) {
+                        report
+                            .errors
+                            .push(format!("Climate has {} outside of range 0 to 1", label));
+                    }
+                }
+                if climate.extinction_coefficient.is_nan() || climate.extinction_coefficient < 0.0 {
+                    report
+                        .errors
+                        .push("Climate has negative extinction coefficient".to_string());
+                }
+                if climate
+                    .horizon
+                    .points()
+                    .iter()
+                    .any(|(_, elevation)| !(0.0..90.0).contains(&elevation.get::<degree>()))
+                {
+                    report.errors.push(
+                        "Climate has horizon elevation outside of range 0 to 90 degrees"
+                            .to_string(),
+                    );
+                }
+            }
+
+            report
+        }
+    }
+
+    /// Check that a value is strictly positive (NaN is not)
+    pub(super) fn is_positive(value: f64) -> bool {
+        value > 0.0
+    }
+
+    /// Check that a value is positive or zero (NaN is not)
+    pub(super) fn is_positive_or_zero(value: f64) -> bool {
+        value >= 0.0
+    }
+
+    /// Location is given in degrees, because radians (the base unit of `Angle`)
+    /// are not what anyone copies from a map.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct Location {
+        pub latitude: f64,
+        pub longitude: f64,
+        #[serde(default)]
+        pub climate: Climate,
+    }
+
+    impl Location {
+        pub fn from_model(location: &super::Location) -> Self {
+            let climate = &location.climate;
+            Location {
+                latitude: location.latitude.get::<degree>(),
+                longitude: location.longitude.get::<degree>(),
+                climate: Climate {
+                    solar_constant: Some(climate.solar_constant),
+                    cloud_coefficients: Some(climate.cloud_coefficients),
+                    albedo: Some(climate.albedo),
+                    snow_albedo: Some(climate.snow_albedo),
+                    extinction_coefficient: Some(climate.extinction_coefficient),
+                    elevation: Some(climate.elevation),
+                    horizon: (!climate.horizon.points().is_empty()).then(|| {
+                        climate
+                            .horizon
+                            .points()
+                            .iter()
+                            .map(|(azimuth, elevation)| {
+                                [azimuth.get::<degree>(), elevation.get::<degree>()]
+                            })
+                            .collect()
+                    }),
+                },
+            }
+        }
+
+        pub fn convert(self) -> super::Location {
+            super::Location {
+                latitude: Angle::new::<degree>(self.latitude),
+                longitude: Angle::new::<degree>(self.longitude),
+                climate: self.climate.convert(),
+            }
+        }
+    }
+
+    /// Overrides of the default solar model calibration, all optional.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct Climate {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub solar_constant: Option<HeatFluxDensity>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub cloud_coefficients: Option<[f64; 3]>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub albedo: Option<Ratio>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub snow_albedo: Option<Ratio>,
+        /// Magnitudes per air mass
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub extinction_coefficient: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default, deserialize_with = "deserialize_optional_quantity")]
+        pub elevation: Option<Length>,
+        /// `[azimuth, elevation]` points of the horizon profile, in degrees
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub horizon: Option<Vec<[f64; 2]>>,
+    }
+
+    impl Climate {
+        pub fn convert(self) -> ClimateConfig {
+            let default = ClimateConfig::default();
+            ClimateConfig {
+                solar_constant: self.solar_constant.unwrap_or(default.solar_constant),
+                cloud_coefficients: self
+                    .cloud_coefficients
+                    .unwrap_or(default.cloud_coefficients),
+                albedo: self.albedo.unwrap_or(default.albedo),
+                snow_albedo: self.snow_albedo.unwrap_or(default.snow_albedo),
+                extinction_coefficient: self
+                    .extinction_coefficient
+                    .unwrap_or(default.extinction_coefficient),
+                elevation: self.elevation.unwrap_or(default.elevation),
+                horizon: self
+                    .horizon
+                    .map(|points| {
+                        HorizonProfile::new(points.into_iter().map(|[azimuth, elevation]| {
+                            (
+                                Angle::new::<degree>(azimuth),
+                                Angle::new::<degree>(elevation),
+                            )
+                        }))
+                    })
+                    .unwrap_or(default.horizon),
+            }
+        }
+    }
+
+    /// Soil under the building, properties that are not given use the defaults
+    /// of `super::Ground`.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct Ground {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub thermal_conductivity: Option<ThermalConductivity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub specific_heat_capacity: Option<SpecificHeatCapacity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub density: Option<MassDensity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_quantity")]
+        pub depth: Option<Length>,
+    }
+
+    impl Ground {
+        pub fn convert(self) -> super::Ground {
+            let default = super::Ground::default();
+            super::Ground {
+                thermal_conductivity: self
+                    .thermal_conductivity
+                    .unwrap_or(default.thermal_conductivity),
+                specific_heat_capacity: self
+                    .specific_heat_capacity
+                    .unwrap_or(default.specific_heat_capacity),
+                density: self.density.unwrap_or(default.density),
+                depth: self.depth.unwrap_or(default.depth),
+            }
+        }
+
+        pub fn from_model(ground: &super::Ground) -> Self {
+            Ground {
+                thermal_conductivity: Some(ground.thermal_conductivity),
+                specific_heat_capacity: Some(ground.specific_heat_capacity),
+                density: Some(ground.density),
+                depth: Some(ground.depth),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct HeatSource {
+        pub name: String,
+        pub zone: String,
+        pub max_power: Power,
+    }
+
+    impl HeatSource {
+        pub fn convert(
+            self,
+            zones: &HashMap<String, Rc<super::Zone>>,
+        ) -> Result<super::HeatSource, ModelError> {
+            let zone = get(zones, &self.zone, ModelError::MissingZone)?;
+            if zone.volume.is_none() {
+                return Err(ModelError::ExteriorHeatSource(self.name));
+            }
+            if !is_positive_or_zero(self.max_power.value) {
+                return Err(ModelError::InvalidHeatSourcePower(self.name));
+            }
+            Ok(super::HeatSource {
+                name: self.name,
+                zone,
+                max_power: self.max_power,
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct ThermalBridge {
+        pub zones: [String; 2],
+        #[serde(deserialize_with = "deserialize_quantity")]
+        pub length: Length,
+        pub psi: ThermalConductivity,
+    }
+
+    impl ThermalBridge {
+        pub fn convert(
+            self,
+            zones: &HashMap<String, Rc<super::Zone>>,
+        ) -> Result<super::ThermalBridge, ModelError> {
+            self.check()?;
+            Ok(super::ThermalBridge {
+                zones: [
+                    get(zones, &self.zones[0], ModelError::MissingZone)?,
+                    get(zones, &self.zones[1], ModelError::MissingZone)?,
+                ],
+                length: self.length,
+                psi: self.psi,
+            })
+        }
+
+        /// Check the values that don't depend on the rest of the model.
+        pub(super) fn check(&self) -> Result<(), ModelError> {
+            if self.zones[0] == self.zones[1] {
+                return Err(ModelError::SelfThermalBridge(self.zones[0].clone()));
+            }
+            if !is_positive_or_zero(self.length.value) || !is_positive_or_zero(self.psi.value) {
+                return Err(ModelError::InvalidThermalBridge {
+                    zones: self.zones.clone(),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct Zone {
+        /// Volume of the zone, alternatively given as `floor_area` and `height`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_quantity")]
+        pub volume: Option<Volume>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_quantity")]
+        pub floor_area: Option<Area>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_quantity")]
+        pub height: Option<Length>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub internal_mass: Option<InternalMass>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub air_changes_per_hour: Option<f64>,
+        /// Material of the air in the zone, defaults to the material `air`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub air: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub internal_gains: Option<InternalGains>,
+    }
+
+    /// Internal gains of a zone, either a constant power, or 24 hourly values
+    /// (see `super::InternalGains::Hourly`) in a time zone, UTC if not given.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum InternalGains {
+        Constant(Power),
+        Hourly {
+            hourly: Vec<Power>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            timezone: Option<String>,
+        },
+    }
+
+    impl InternalGains {
+        pub fn from_model(internal_gains: &super::InternalGains) -> Self {
+            match internal_gains {
+                super::InternalGains::Constant(power) => InternalGains::Constant(*power),
+                super::InternalGains::Hourly { values, timezone } => InternalGains::Hourly {
+                    hourly: values.to_vec(),
+                    timezone: Some(timezone.name().to_string()),
+                },
+            }
+        }
+
+        pub fn convert(self, zone: &str) -> Result<super::InternalGains, ModelError> {
+            let invalid = || ModelError::InvalidInternalGains(zone.to_string());
+            Ok(match self {
+                InternalGains::Constant(power) => super::InternalGains::Constant(power),
+                InternalGains::Hourly { hourly, timezone } => super::InternalGains::Hourly {
+                    values: hourly.try_into().map_err(|_| invalid())?,
+                    timezone: match timezone {
+                        Some(timezone) => timezone.parse().map_err(|_| invalid())?,
+                        None => Tz::UTC,
+                    },
+                },
+            })
+        }
+    }
+
+    impl Zone {
+        /// Volume of the zone, given directly or as floor area times height.
+        pub fn volume(&self, name: &str) -> Result<Volume, ModelError> {
+            match (self.volume, self.floor_area, self.height) {
+                (Some(volume), None, None) => Ok(volume),
+                (None, Some(floor_area), Some(height)) => Ok(floor_area * height),
+                (Some(_), _, _) => Err(ModelError::AmbiguousZoneVolume(name.to_string())),
+                _ => Err(ModelError::MissingZoneVolume(name.to_string())),
+            }
+        }
+    }
+
+    /// Zone contents, given either as a volume of material or directly as a heat capacity.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum InternalMass {
+        Material {
+            material: String,
+            #[serde(deserialize_with = "deserialize_quantity")]
+            volume: Volume,
+            #[serde(deserialize_with = "deserialize_quantity")]
+            area: Area,
+        },
+        Direct {
+            heat_capacity: HeatCapacity,
+            #[serde(deserialize_with = "deserialize_quantity")]
+            area: Area,
+        },
+    }
+
+    impl InternalMass {
+        pub fn convert(
+            self,
+            materials: &HashMap<String, Rc<super::Material>>,
+        ) -> Result<super::InternalMass, ModelError> {
+            Ok(match self {
+                InternalMass::Material {
+                    material,
+                    volume,
+                    area,
+                } => {
+                    let material = get(materials, &material, ModelError::MissingMaterial)?;
+                    super::InternalMass {
+                        heat_capacity: volume * material.density * material.specific_heat_capacity,
+                        area,
+                    }
+                }
+                InternalMass::Direct {
+                    heat_capacity,
+                    area,
+                } => super::InternalMass {
+                    heat_capacity,
+                    area,
+                },
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct Boundary {
+        pub boundary_type: String,
+        pub zones: [String; 2],
+        #[serde(deserialize_with = "deserialize_quantity")]
+        pub area: Area,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub sub_boundaries: Vec<SubBoundary>,
+        /// Surface resistances replacing the calculated convection,
+        /// see `super::SurfaceResistances`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub interior_surface_resistance: Option<super::ThermalInsulance>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub exterior_surface_resistance: Option<super::ThermalInsulance>,
+        /// Orientation of an exterior boundary in degrees (see `super::Orientation`),
+        /// flat when not given. Not allowed between interior zones.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tilt: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub azimuth: Option<f64>,
+    }
+
+    impl Boundary {
+        /// Convert a boundary back to the loaded form, with the sub-boundaries that were
+        /// split from it when loading. Their area is added back to the parent.
+        fn from_model(boundary: &super::Boundary, sub_boundaries: &[&super::Boundary]) -> Self {
+            let zone_names =
+                |zones: &[Rc<super::Zone>; 2]| [zones[0].name.clone(), zones[1].name.clone()];
+            let zones = zone_names(&boundary.zones);
+            Boundary {
+                boundary_type: boundary.boundary_type.name().to_string(),
+                area: boundary.area
+                    + sub_boundaries
+                        .iter()
+                        .map(|sub_boundary| sub_boundary.area)
+                        .sum::<Area>(),
+                sub_boundaries: sub_boundaries
+                    .iter()
+                    .map(|sub_boundary| {
+                        let sub_zones = zone_names(&sub_boundary.zones);
+                        SubBoundary {
+                            boundary_type: sub_boundary.boundary_type.name().to_string(),
+                            area: sub_boundary.area,
+                            zones: (sub_zones != zones).then_some(sub_zones),
+                        }
+                    })
+                    .collect(),
+                zones,
+                interior_surface_resistance: boundary.surface_resistances.interior,
+                exterior_surface_resistance: boundary.surface_resistances.exterior,
+                tilt: boundary
+                    .orientation
+                    .map(|orientation| orientation.tilt.get::<degree>()),
+                azimuth: boundary
+                    .orientation
+                    .map(|orientation| orientation.azimuth.get::<degree>()),
+            }
+        }
+
+        /// Whether `sub_boundary` was split from `parent` when loading. The sub-boundary
+        /// inherits the surface resistances and orientation of the parent, so it stays
+        /// the same when saved inside it.
+        fn is_sub_boundary(sub_boundary: &super::Boundary, parent: &super::Boundary) -> bool {
+            let Some(sub_parent) = sub_boundary.parent.as_ref() else {
+                return false;
+            };
+            sub_parent.boundary_type.name() == parent.boundary_type.name()
+                && sub_parent.zones[0].name == parent.zones[0].name
+                && sub_parent.zones[1].name == parent.zones[1].name
+                && sub_boundary.surface_resistances == parent.surface_resistances
+                && sub_boundary
+                    .orientation
+                    .is_none_or(|orientation| orientation == parent.orientation.unwrap_or_default())
+        }
+    }
+
+    /// Surface with a zone on one side and several zones on the other side, e.g. a party
+    /// wall along a corridor or a slab under several rooms.
+    /// Converted to a boundary between `zone` and each of the neighbors, like sub-boundaries
+    /// are. Neighbors with `area` get that part of the surface, the rest is split equally
+    /// between the neighbors without it, so the boundaries always add up to `area`.
+    /// Surface resistances and orientation apply to all of the boundaries.
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct SharedSurface {
+        pub boundary_type: String,
+        pub zone: String,
+        #[serde(deserialize_with = "deserialize_quantity")]
+        pub area: Area,
+        pub neighbors: Vec<SharedSurfaceNeighbor>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub interior_surface_resistance: Option<super::ThermalInsulance>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub exterior_surface_resistance: Option<super::ThermalInsulance>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tilt: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub azimuth: Option<f64>,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct SharedSurfaceNeighbor {
+        pub zone: String,
+        /// Part of the area of the shared surface, the rest is split between the neighbors
+        /// without area when not given
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_quantity")]
+        pub area: Option<Area>,
+    }
+
+    impl SharedSurface {
+        /// Boundaries between the zone and each neighbor, in the order of the neighbors.
+        pub fn expand(&self) -> Result<Vec<Boundary>, ModelError> {
+            let given_area: Area = self
+                .neighbors
+                .iter()
+                .filter_map(|neighbor| neighbor.area)
+                .sum();
+            let remaining_area = self.area - given_area;
+            let split_count = self
+                .neighbors
+                .iter()
+                .filter(|neighbor| neighbor.area.is_none())
+                .count();
+            let is_valid = if split_count == 0 {
+                is_negligible_area(remaining_area, self.area)
+            } else {
+                remaining_area.value > 0.0 && !is_negligible_area(remaining_area, self.area)
+            };
+            if !is_valid {
+                return Err(ModelError::SharedSurfaceArea(self.zone.clone()));
+            }
+
+            Ok(self
+                .neighbors
+                .iter()
+                .map(|neighbor| Boundary {
+                    boundary_type: self.boundary_type.clone(),
+                    zones: [self.zone.clone(), neighbor.zone.clone()],
+                    area: neighbor.area.unwrap_or(remaining_area / split_count as f64),
+                    sub_boundaries: Vec::new(),
+                    interior_surface_resistance: self.interior_surface_resistance,
+                    exterior_surface_resistance: self.exterior_surface_resistance,
+                    tilt: self.tilt,
+                    azimuth: self.azimuth,
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    pub struct SubBoundary {
+        pub boundary_type: String,
+        #[serde(deserialize_with = "deserialize_quantity")]
+        pub area: Area,
+        /// Zones separated by the sub-boundary, if different from the parent boundary
+        /// (e.g. interior window in an exterior wall). Must share at least one zone
+        /// with the parent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub zones: Option<[String; 2]>,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum BoundaryType {
+        Layered {
+            layers: Vec<BoundaryLayer>,
+            /// See `super::DEFAULT_SOLAR_ABSORPTANCE` when not given
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            solar_absorptance: Option<Ratio>,
+        },
+        /// Simple boundaries don't have any mass!
+        Simple {
+            u: HeatTransfer,
+            g: Ratio,
+            #[serde(default)]
+            angular_transmittance: bool,
+        },
+    }
+
+    impl BoundaryType {
+        /// Convert a boundary type back to the loaded form, collecting the materials
+        /// of its layers.
+        /// Returns the name of the boundary type and the converted value.
+        pub fn from_model<'a>(
+            boundary_type: &'a super::BoundaryType,
+            materials: &mut HashMap<String, Material>,
+        ) -> anyhow::Result<(&'a str, Self)> {
+            Ok(match boundary_type {
+                super::BoundaryType::Layered {
+                    name,
+                    layers,
+                    initial_marker,
+                    solar_absorptance,
+                } => {
+                    let mut out_layers = Vec::new();
+                    if let Some(marker) = initial_marker {
+                        out_layers.push(BoundaryLayer::Marker {
+                            marker: marker.clone(),
+                        });
+                    }
+                    for layer in layers.iter() {
+                        out_layers.push(match &layer.content {
+                            super::LayerContent::Material {
+                                material,
+                                thickness,
+                            } => {
+                                insert_unique(
+                                    materials,
+                                    &material.name,
+                                    Material::from_model(material),
+                                    "material",
+                                )?;
+                                BoundaryLayer::Layer {
+                                    material: material.name.clone(),
+                                    thickness: *thickness,
+                                }
+                            }
+                            super::LayerContent::Resistance {
+                                r_value,
+                                heat_capacity_per_area,
+                            } => BoundaryLayer::Resistance {
+                                r_value: *r_value,
+                                heat_capacity_per_area: *heat_capacity_per_area,
+                            },
+                        });
+                        if let Some(marker) = &layer.following_marker {
+                            out_layers.push(BoundaryLayer::Marker {
+                                marker: marker.clone(),
+                            });
+                        }
+                    }
+                    (
+                        name,
+                        BoundaryType::Layered {
+                            layers: out_layers,
+                            solar_absorptance: Some(*solar_absorptance),
+                        },
+                    )
+                }
+                super::BoundaryType::Simple {
+                    name,
+                    u,
+                    g,
+                    angular_transmittance,
+                } => (
+                    name,
+                    BoundaryType::Simple {
+                        u: *u,
+                        g: *g,
+                        angular_transmittance: *angular_transmittance,
+                    },
+                ),
+            })
+        }
+
+        pub fn convert(
+            self,
+            name: String,
+            materials: &HashMap<String, Rc<super::Material>>,
+        ) -> Result<super::BoundaryType, ModelError> {
+            Ok(match self {
+                BoundaryType::Layered {
+                    layers,
+                    solar_absorptance,
+                } => {
+                    // Verify that the input looks OK:
+                    let mut prev_is_marker = false;
+                    let mut have_non_marker = false;
+                    for layer in layers.iter() {
+                        let is_marker = layer.is_marker();
+                        if is_marker && prev_is_marker {
+                            return Err(ModelError::ConsecutiveMarkers(name));
+                        }
+                        have_non_marker |= !is_marker;
+                        prev_is_marker = is_marker;
+                    }
+                    if !have_non_marker {
+                        return Err(ModelError::NoNonMarkerLayer(name));
+                    };
+
+                    let mut out_layers: Vec<super::BoundaryLayer> =
+                        Vec::with_capacity(layers.len());
+
+                    // This construction kind of peeks the first element and consumes it
+                    // from the iterator if it matches
+                    let first_is_marker = layers.first().unwrap().is_marker();
+                    let mut it = layers.into_iter();
+                    let initial_marker = if first_is_marker {
+                        match it.next() {
+                            Some(BoundaryLayer::Marker { marker }) => Some(marker),
+                            _ => panic!(), // IMPOSIBIRU!
+                        }
+                    } else {
+                        None
+                    };
+
+                    // Convert the individual layers and assign markers
+                    for layer in it {
+                        if let BoundaryLayer::Marker { marker } = layer {
+                            let following_marker =
+                                &mut out_layers.last_mut().unwrap().following_marker;
+                            assert!(following_marker.is_none());
+                            *following_marker = Some(marker);
+                        } else {
+                            out_layers.push(layer.convert(materials)?);
+                        }
+                    }
+
+                    super::BoundaryType::Layered {
+                        name,
+                        layers: out_layers,
+                        initial_marker,
+                        solar_absorptance: solar_absorptance.unwrap_or_else(|| {
+                            Ratio::new::<ratio>(super::DEFAULT_SOLAR_ABSORPTANCE)
+                        }),
+                    }
+                }
+                BoundaryType::Simple {
+                    u,
+                    g,
+                    angular_transmittance,
+                } => super::BoundaryType::Simple {
+                    name,
+                    u,
+                    g,
+                    angular_transmittance,
+                },
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum BoundaryLayer {
+        Layer {
+            material: String,
+            #[serde(deserialize_with = "deserialize_quantity")]
+            thickness: Length,
+        },
+        /// Layer given by R-value and optionally heat capacity per area
+        /// (massless if missing).
+        Resistance {
+            r_value: super::ThermalInsulance,
+            #[serde(default)]
+            heat_capacity_per_area: super::ArealHeatCapacity,
+        },
+        Marker {
+            marker: String,
+        },
+    }
+
+    impl BoundaryLayer {
+        pub fn convert(
+            self,
+            materials: &HashMap<String, Rc<super::Material>>,
+        ) -> Result<super::BoundaryLayer, ModelError> {
+            Ok(match self {
+                BoundaryLayer::Layer {
+                    material,
+                    thickness,
+                } => super::BoundaryLayer {
+                    content: super::LayerContent::Material {
+                        material: get(materials, &material, ModelError::MissingMaterial)?,
+                        thickness,
+                    },
+                    following_marker: None,
+                },
+                BoundaryLayer::Resistance {
+                    r_value,
+                    heat_capacity_per_area,
+                } => super::BoundaryLayer {
+                    content: super::LayerContent::Resistance {
+                        r_value,
+                        heat_capacity_per_area,
+                    },
+                    following_marker: None,
+                },
+                BoundaryLayer::Marker { marker: _ } => panic!("Can't convert a marker"),
+            })
+        }
+
+        pub fn is_marker(&self) -> bool {
+            matches!(self, Self::Marker { .. })
+        }
+    }
+
+    /// Material as written in the model file.
+    /// Properties that are not given are taken from the preset named by `from_preset`
+    /// when the model is converted.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+    pub struct Material {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub from_preset: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub thermal_conductivity: Option<ThermalConductivity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub specific_heat_capacity: Option<SpecificHeatCapacity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub density: Option<MassDensity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub emissivity: Option<Ratio>,
+    }
+
+    impl Material {
+        pub fn from_model(material: &super::Material) -> Self {
+            Material {
+                from_preset: None,
+                thermal_conductivity: Some(material.thermal_conductivity),
+                specific_heat_capacity: Some(material.specific_heat_capacity),
+                density: Some(material.density),
+                emissivity: Some(material.emissivity),
+            }
+        }
+
+        /// Convert the material, see `resolve`.
+        /// The physical properties must be finite and positive.
+        /// Emissivity is checked only by validation, zero is a valid value.
+        pub fn convert(self, name: String) -> Result<super::Material, ModelError> {
+            let material = self.resolve(name)?;
+            check_material(&material)?;
+            Ok(material)
+        }
+
+        /// Material with the properties that are not given taken from its preset,
+        /// the values are not checked.
+        pub fn resolve(self, name: String) -> Result<super::Material, ModelError> {
+            let preset = self
+                .from_preset
+                .map(|preset| {
+                    super::Material::preset(&preset).ok_or_else(|| {
+                        ModelError::MissingMaterialPreset {
+                            material: name.clone(),
+                            preset,
+                        }
+                    })
+                })
+                .transpose()?;
+            let thermal_conductivity = property(
+                self.thermal_conductivity,
+                preset.as_ref().map(|preset| preset.thermal_conductivity),
+                &name,
+                "thermal_conductivity",
+            )?;
+            let specific_heat_capacity = property(
+                self.specific_heat_capacity,
+                preset.as_ref().map(|preset| preset.specific_heat_capacity),
+                &name,
+                "specific_heat_capacity",
+            )?;
+            let density = property(
+                self.density,
+                preset.as_ref().map(|preset| preset.density),
+                &name,
+                "density",
+            )?;
+            Ok(super::Material {
+                name,
+                thermal_conductivity,
+                specific_heat_capacity,
+                density,
+                emissivity: self
+                    .emissivity
+                    .or(preset.map(|preset| preset.emissivity))
+                    .unwrap_or_else(|| Ratio::new::<ratio>(super::DEFAULT_EMISSIVITY)),
+            })
+        }
+    }
+
+    /// Check that the physical properties of a material are finite and positive.
+    fn check_material(material: &super::Material) -> Result<(), ModelError> {
+        for (value, property) in [
+            (material.thermal_conductivity.value, "thermal conductivity"),
+            (
+                material.specific_heat_capacity.value,
+                "specific heat capacity",
+            ),
+            (material.density.value, "density"),
+        ] {
+            if !(is_positive(value) && value.is_finite()) {
+                return Err(ModelError::InvalidMaterialProperty {
+                    material: material.name.clone(),
+                    property,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Material property given in the model, or the one of the preset.
+    fn property<T>(
+        value: Option<T>,
+        preset_value: Option<T>,
+        material: &str,
+        property: &'static str,
+    ) -> Result<T, ModelError> {
+        value
+            .or(preset_value)
+            .ok_or_else(|| ModelError::MissingMaterialProperty {
+                material: material.to_string(),
+                property,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::{assert_abs_diff_eq, assert_ulps_eq};
+    use assert_matches::assert_matches;
+    use std::collections::HashSet;
+    use test_case::test_case;
+    use test_strategy::proptest;
+    use uom::si::{
+        area::square_meter,
+        f64::HeatFluxDensity,
+        heat_flux_density::watt_per_square_meter,
+        heat_transfer::watt_per_square_meter_kelvin,
+        length::meter,
+        mass_density::kilogram_per_cubic_meter,
+        power::watt,
+        ratio::{percent, ratio},
+        specific_heat_capacity::joule_per_kilogram_kelvin,
+        thermal_conductivity::watt_per_meter_kelvin,
+        time::hour,
+        volume::cubic_meter,
+    };
+
+    #[test]
+    fn convert_material() {
+        let input = as_loaded::Material {
+            from_preset: None,
+            thermal_conductivity: Some(ThermalConductivity::new::<watt_per_meter_kelvin>(123.0)),
+            specific_heat_capacity: Some(SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
+                456.0,
+            )),
+            density: Some(MassDensity::new::<kilogram_per_cubic_meter>(789.0)),
+            emissivity: Some(Ratio::new::<ratio>(0.9)),
+        };
+
+        let output = input.convert("qwertyuiop".into()).unwrap();
+
+        assert_eq!(output.name, "qwertyuiop");
+        assert_eq!(
+            output.thermal_conductivity,
+            ThermalConductivity::new::<watt_per_meter_kelvin>(123.0)
+        );
+        assert_eq!(
+            output.specific_heat_capacity,
+            SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(456.0)
+        );
+        assert_eq!(
+            output.density,
+            MassDensity::new::<kilogram_per_cubic_meter>(789.0)
+        );
+    }
+
+    #[test]
+    fn material_presets_are_valid() {
+        assert!(Material::preset("unobtainium").is_none());
+        for name in Material::preset_names() {
+            let material = Material::preset(name).unwrap();
+            assert_eq!(material.name, name);
+            as_loaded::Material::from_model(&material)
+                .convert(name.to_string())
+                .unwrap();
+            assert!(material.emissivity.get::<ratio>() > 0.0);
+            assert!(material.emissivity.get::<ratio>() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn load_material_preset() {
+        let model = Model::from_json(
+            r#"{
+                materials: {
+                    wool: { from_preset: "mineral_wool" },
+                    dense_wool: { from_preset: "mineral_wool", density: 100 },
+                },
+                boundary_types: {
+                    wall: {
+                        layers: [
+                            { material: "wool", thickness: 0.1 },
+                            { material: "dense_wool", thickness: 0.1 },
+                        ],
+                    },
+                },
+                zones: { a: { volume: 1 } },
+                boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }],
+            }"#,
+        )
+        .unwrap();
+
+        let BoundaryType::Layered { layers, .. } = model.boundaries[0].boundary_type.as_ref()
+        else {
+            panic!("Wall should be layered");
+        };
+        let preset = Material::preset("mineral_wool").unwrap();
+        let [wool, dense_wool] = [0, 1].map(|i| match &layers[i].content {
+            LayerContent::Material { material, .. } => Rc::clone(material),
+            content => panic!("Unexpected layer {:?}", content),
+        });
+        assert_eq!(wool.name, "wool");
+        assert_eq!(wool.thermal_conductivity, preset.thermal_conductivity);
+        assert_eq!(wool.density, preset.density);
+        assert_eq!(dense_wool.thermal_conductivity, preset.thermal_conductivity);
+        assert_eq!(
+            dense_wool.specific_heat_capacity,
+            preset.specific_heat_capacity
+        );
+        assert_eq!(
+            dense_wool.density,
+            MassDensity::new::<kilogram_per_cubic_meter>(100.0)
+        );
+    }
+
+    #[test_case(
+        r#"{ from_preset: "unobtainium" }"#,
+        ModelError::MissingMaterialPreset {
+            material: "m".into(),
+            preset: "unobtainium".into(),
+        }
+        ; "unknown preset"
+    )]
+    #[test_case(
+        r#"{ thermal_conductivity: 1, density: 1 }"#,
+        ModelError::MissingMaterialProperty {
+            material: "m".into(),
+            property: "specific_heat_capacity",
+        }
+        ; "missing property"
+    )]
+    fn load_material_preset_invalid(material: &str, expected: ModelError) {
+        let error = Model::from_json(&format!(
+            r#"{{
+                materials: {{ m: {} }},
+                boundary_types: {{}},
+                zones: {{}},
+                boundaries: [],
+            }}"#,
+            material
+        ))
+        .unwrap_err();
+        assert_eq!(error.downcast_ref::<ModelError>(), Some(&expected));
+        format!("{}", error)
+            .find("\"m\"")
+            .expect("Error message should name the material");
+    }
+
+    #[test]
+    fn load_quantities_with_units() {
+        let model = Model::from_json(
+            r#"{
+                materials: {
+                    brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+                },
+                boundary_types: {
+                    wall: {
+                        layers: [
+                            { material: "brick", thickness: 0.1 },
+                            { material: "brick", thickness: "10 cm" },
+                            { material: "brick", thickness: "0.3 m" },
+                        ],
+                    },
+                },
+                zones: { a: { volume: "50 m³" } },
+                boundaries: [
+                    { boundary_type: "wall", zones: ["a", "outside"], area: "10 square meters" },
+                ],
+            }"#,
+        )
+        .unwrap();
+
+        let BoundaryType::Layered { layers, .. } = model.boundaries[0].boundary_type.as_ref()
+        else {
+            panic!("Wall should be layered");
+        };
+        let thicknesses: Vec<_> = layers
+            .iter()
+            .map(|layer| match layer.content {
+                LayerContent::Material { thickness, .. } => thickness,
+                ref content => panic!("Unexpected layer {:?}", content),
+            })
+            .collect();
+        assert_eq!(thicknesses[0], Length::new::<meter>(0.1));
+        assert_eq!(thicknesses[1], thicknesses[0]);
+        assert_eq!(thicknesses[2], Length::new::<meter>(0.3));
+        assert_eq!(model.boundaries[0].area, Area::new::<square_meter>(10.0));
+        assert_eq!(
+            model.zones["a"].volume,
+            Some(Volume::new::<cubic_meter>(50.0))
+        );
+    }
+
+    #[test_case(r#""10 ducks""# ; "unknown unit")]
+    #[test_case(r#""10m³""# ; "no separator")]
+    #[test_case(r#""ten m³""# ; "invalid value")]
+    fn load_quantity_invalid(volume: &str) {
+        let message = format!(
+            "{}",
+            Model::from_json(&format!(
+                r#"{{
+                    materials: {{}},
+                    boundary_types: {{}},
+                    zones: {{ a: {{ volume: {} }} }},
+                    boundaries: [],
+                }}"#,
+                volume
+            ))
+            .unwrap_err()
+        );
+        message
+            .find(&format!("Invalid quantity {}", volume))
+            .expect("Error message should contain the quantity");
+    }
+
+    #[test]
+    fn convert_boundary_layer() {
+        let input = as_loaded::BoundaryLayer::Layer {
+            material: "mat1".into(),
+            thickness: Length::new::<meter>(0.2),
+        };
+        let materials = converted_materials_hashmap();
+        let output = input.convert(&materials).unwrap();
+        assert_eq!(
+            output,
+            BoundaryLayer {
+                content: LayerContent::Material {
+                    material: Rc::clone(&materials["mat1"]),
+                    thickness: Length::new::<meter>(0.2),
+                },
+                following_marker: None
+            }
+        );
+    }
+
+    #[test]
+    fn convert_boundary_type_layered_intial_marker() {
+        let input = as_loaded::BoundaryType::Layered {
+            layers: vec![
+                as_loaded::BoundaryLayer::Marker {
+                    marker: "A DUCK!".into(),
+                },
+                as_loaded::BoundaryLayer::Layer {
+                    material: "mat1".into(),
+                    thickness: Length::new::<meter>(1.0),
+                },
+                as_loaded::BoundaryLayer::Layer {
+                    material: "mat2".into(),
+                    thickness: Length::new::<meter>(2.0),
+                },
+            ],
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+        let output = input.convert("somename".to_string(), &materials).unwrap();
+        assert_eq!(
+            output,
+            BoundaryType::Layered {
+                name: "somename".into(),
+                layers: vec![
+                    BoundaryLayer {
+                        content: LayerContent::Material {
+                            material: Rc::clone(&materials["mat1"]),
+                            thickness: Length::new::<meter>(1.0),
+                        },
+                        following_marker: None,
+                    },
+                    BoundaryLayer {
+                        content: LayerContent::Material {
+                            material: Rc::clone(&materials["mat2"]),
+                            thickness: Length::new::<meter>(2.0),
+                        },
+                        following_marker: None,
+                    },
+                ],
+                initial_marker: Some("A DUCK!".into()),
+                solar_absorptance: Ratio::new::<ratio>(DEFAULT_SOLAR_ABSORPTANCE),
+            }
+        );
+    }
+
+    #[proptest]
+    fn convert_boundary_type_layered_marker_inside(#[strategy(1usize..4usize)] i: usize) {
+        let mut layers = vec![
+            as_loaded::BoundaryLayer::Layer {
+                material: "mat1".into(),
+                thickness: Length::new::<meter>(1.0),
+            },
+            as_loaded::BoundaryLayer::Layer {
+                material: "mat2".into(),
+                thickness: Length::new::<meter>(2.0),
+            },
+            as_loaded::BoundaryLayer::Layer {
+                material: "mat2".into(),
+                thickness: Length::new::<meter>(3.0),
+            },
+        ];
+        layers.insert(
+            i,
+            as_loaded::BoundaryLayer::Marker {
+                marker: "asdf".into(),
+            },
+        );
+        let input = as_loaded::BoundaryType::Layered {
+            layers,
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+        let output = input.convert("somename".to_string(), &materials).unwrap();
+
+        assert_matches!(output, BoundaryType::Layered { name: _, layers, initial_marker, .. } => {
+            assert!(initial_marker.is_none());
+            assert_eq!(layers.len(), 3);
+            assert!(layers.iter().enumerate().all(|(j, l)| (j == (i - 1)) || l.following_marker.is_none()));
+            assert_eq!(layers[i - 1].following_marker, Some("asdf".into()));
+        });
+    }
+    #[test]
+    fn convert_boundary_type_simple() {
+        let input = as_loaded::BoundaryType::Simple {
+            u: HeatTransfer::new::<watt_per_square_meter_kelvin>(123.0),
+            g: Ratio::new::<percent>(90.0),
+            angular_transmittance: false,
+        };
+        let materials = HashMap::new();
+        let output = input.convert("somename".to_string(), &materials).unwrap();
+        assert_eq!(
+            output,
+            BoundaryType::Simple {
+                name: "somename".into(),
+                u: HeatTransfer::new::<watt_per_square_meter_kelvin>(123.0),
+                g: Ratio::new::<percent>(90.0),
+                angular_transmittance: false,
+            }
+        );
+    }
+
+    #[test]
+    fn convert_boundary_type_layered_missing_material() {
+        let input = as_loaded::BoundaryType::Layered {
+            layers: vec![
+                as_loaded::BoundaryLayer::Layer {
+                    material: "matX".into(),
+                    thickness: Length::new::<meter>(1.0),
+                },
+                as_loaded::BoundaryLayer::Layer {
+                    material: "mat2".into(),
+                    thickness: Length::new::<meter>(2.0),
+                },
+            ],
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+
+        assert_eq!(
+            input
+                .convert("somename".to_string(), &materials)
+                .unwrap_err(),
+            ModelError::MissingMaterial("matX".into())
+        );
+    }
+
+    #[test]
+    fn convert_boundary_type_no_layers() {
+        let input = as_loaded::BoundaryType::Layered {
+            layers: vec![],
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+
+        assert_eq!(
+            input
+                .convert("somename".to_string(), &materials)
+                .unwrap_err(),
+            ModelError::NoNonMarkerLayer("somename".into())
+        );
+    }
+
+    #[test]
+    fn convert_boundary_type_only_marker() {
+        let input = as_loaded::BoundaryType::Layered {
+            layers: vec![as_loaded::BoundaryLayer::Marker { marker: "X".into() }],
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+
+        assert_eq!(
+            input
+                .convert("somename".to_string(), &materials)
+                .unwrap_err(),
+            ModelError::NoNonMarkerLayer("somename".into())
+        );
+    }
+
+    #[test]
+    fn convert_boundary_type_successive_markers() {
+        let input = as_loaded::BoundaryType::Layered {
+            layers: vec![
+                as_loaded::BoundaryLayer::Layer {
+                    material: "mat1".into(),
+                    thickness: Length::new::<meter>(1.0),
+                },
+                as_loaded::BoundaryLayer::Marker {
+                    marker: "ONE DUCK!".into(),
+                },
+                as_loaded::BoundaryLayer::Marker {
+                    marker: "TWO DUCK!".into(),
+                },
+                as_loaded::BoundaryLayer::Layer {
+                    material: "mat2".into(),
+                    thickness: Length::new::<meter>(2.0),
+                },
+            ],
+            solar_absorptance: None,
+        };
+        let materials = converted_materials_hashmap();
+
+        assert_eq!(
+            input
+                .convert("somename".to_string(), &materials)
+                .unwrap_err(),
+            ModelError::ConsecutiveMarkers("somename".into())
+        );
+    }
+
+    /// Tests the conversion of a minimal valid model
+    #[test]
+    fn convert_model_minimal() {
+        let input = as_loaded::Model {
+            zones: HashMap::new(),
+            boundaries: vec![],
+            materials: HashMap::new(),
+            boundary_types: HashMap::new(),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        let output: Model = input.try_into().unwrap();
+
+        assert_eq!(output.zones.len(), 2); // Outside and ground are always there
+        assert!(output.boundaries.is_empty());
+    }
+
+    #[test]
+    fn convert_model_zones() {
+        let input = as_loaded::Model {
+            zones: HashMap::from([
+                (
+                    "z1".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(1.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+                (
+                    "z2".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(2.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+            ]),
+            boundaries: ["z1", "z2"]
+                .into_iter()
+                .map(|zone| as_loaded::Boundary {
+                    boundary_type: "window".into(),
+                    zones: [zone.into(), "outside".into()],
+                    area: Area::new::<square_meter>(1.0),
+                    interior_surface_resistance: None,
+                    exterior_surface_resistance: None,
+                    tilt: None,
+                    azimuth: None,
+                    sub_boundaries: Vec::new(),
+                })
+                .collect(),
+            materials: HashMap::new(),
+            boundary_types: HashMap::from([(
+                "window".into(),
+                as_loaded::BoundaryType::Simple {
+                    u: HeatTransfer::new::<watt_per_square_meter_kelvin>(1.0),
+                    g: Ratio::new::<ratio>(0.5),
+                    angular_transmittance: false,
+                },
+            )]),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        let output: Model = input.try_into().unwrap();
+
+        assert_eq!(
+            output.zones,
+            HashMap::from([
+                (
+                    "outside".into(),
+                    Rc::new(Zone {
+                        name: "outside".into(),
+                        volume: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    })
+                ),
+                (
+                    "ground".into(),
+                    Rc::new(Zone {
+                        name: "ground".into(),
+                        volume: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    })
+                ),
+                (
+                    "z1".into(),
+                    Rc::new(Zone {
+                        name: "z1".into(),
+                        volume: Some(Volume::new::<cubic_meter>(1.0)),
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    })
+                ),
+                (
+                    "z2".into(),
+                    Rc::new(Zone {
+                        name: "z2".into(),
+                        volume: Some(Volume::new::<cubic_meter>(2.0)),
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    })
+                ),
+            ])
+        );
+    }
+
+    #[test_case("outside")]
+    #[test_case("ground")]
+    fn convert_model_override_builtin_zone(defined_zone: &str) {
+        let input = as_loaded::Model {
+            zones: HashMap::from([(
+                defined_zone.into(),
+                as_loaded::Zone {
+                    volume: Some(Volume::new::<cubic_meter>(1.0)),
+                    floor_area: None,
+                    height: None,
+                    internal_mass: None,
+                    air_changes_per_hour: None,
+                    air: None,
+                    internal_gains: None,
+                },
+            )]),
+            boundaries: vec![],
+            materials: HashMap::new(),
+            boundary_types: HashMap::new(),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        assert_eq!(
+            Model::try_from(input).unwrap_err(),
+            ModelError::ReservedZone(defined_zone.into())
+        );
+    }
+
+    #[test]
+    fn convert_model_boundaries() {
+        let input = as_loaded::Model {
+            zones: HashMap::from([
+                (
+                    "z1".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(1.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+                (
+                    "z2".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(2.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+            ]),
+            boundaries: vec![as_loaded::Boundary {
+                boundary_type: "bt1".into(),
+                zones: ["z1".into(), "z2".into()],
+                area: Area::new::<square_meter>(123.0),
+                interior_surface_resistance: None,
+                exterior_surface_resistance: None,
+                tilt: None,
+                azimuth: None,
+                sub_boundaries: vec![
+                    as_loaded::SubBoundary {
+                        boundary_type: "bt2".into(),
+                        area: Area::new::<square_meter>(1.0),
+                        zones: None,
+                    },
+                    as_loaded::SubBoundary {
+                        boundary_type: "bt3".into(),
+                        area: Area::new::<square_meter>(2.0),
+                        zones: None,
+                    },
+                ],
+            }],
+            materials: HashMap::new(),
+            boundary_types: HashMap::from([
+                (
+                    "bt1".into(),
+                    as_loaded::BoundaryType::Simple {
+                        u: Default::default(),
+                        g: Default::default(),
+                        angular_transmittance: false,
+                    },
+                ),
+                (
+                    "bt2".into(),
+                    as_loaded::BoundaryType::Simple {
+                        u: Default::default(),
+                        g: Default::default(),
+                        angular_transmittance: false,
+                    },
+                ),
+                (
+                    "bt3".into(),
+                    as_loaded::BoundaryType::Simple {
+                        u: Default::default(),
+                        g: Default::default(),
+                        angular_transmittance: false,
+                    },
+                ),
+            ]),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        let output: Model = input.try_into().unwrap();
+
+        let z1 = Rc::new(Zone {
+            name: "z1".into(),
+            volume: Some(Volume::new::<cubic_meter>(1.0)),
+            internal_mass: None,
+            air_changes_per_hour: None,
+            air: None,
+            internal_gains: None,
+        });
+        let z2 = Rc::new(Zone {
+            name: "z2".into(),
+            volume: Some(Volume::new::<cubic_meter>(2.0)),
+            internal_mass: None,
+            air_changes_per_hour: None,
+            air: None,
+            internal_gains: None,
+        });
+        let bt1 = Rc::new(BoundaryType::Simple {
+            name: "bt1".into(),
+            u: Default::default(),
+            g: Default::default(),
+            angular_transmittance: false,
+        });
+        let bt2 = Rc::new(BoundaryType::Simple {
+            name: "bt2".into(),
+            u: Default::default(),
+            g: Default::default(),
+            angular_transmittance: false,
+        });
+        let bt3 = Rc::new(BoundaryType::Simple {
+            name: "bt3".into(),
+            u: Default::default(),
+            g: Default::default(),
+            angular_transmittance: false,
+        });
+
+        // This is fragile wrt. ordering of boundaries. Any order is valid, but the comparison only accepts one.
+        assert_eq!(
+            output.boundaries,
+            vec![
+                Boundary {
+                    boundary_type: Rc::clone(&bt2),
+                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
+                    area: Area::new::<square_meter>(1.0),
+                    surface_resistances: Default::default(),
+                    orientation: None,
+                    parent: Some(ParentBoundary {
+                        boundary_type: Rc::clone(&bt1),
+                        zones: [Rc::clone(&z1), Rc::clone(&z2)],
+                    }),
+                },
+                Boundary {
+                    boundary_type: Rc::clone(&bt3),
+                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
+                    area: Area::new::<square_meter>(2.0),
+                    surface_resistances: Default::default(),
+                    orientation: None,
+                    parent: Some(ParentBoundary {
+                        boundary_type: Rc::clone(&bt1),
+                        zones: [Rc::clone(&z1), Rc::clone(&z2)],
+                    }),
+                },
+                Boundary {
+                    boundary_type: Rc::clone(&bt1),
+                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
+                    area: Area::new::<square_meter>(120.0),
+                    surface_resistances: Default::default(),
+                    orientation: None,
+                    parent: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn used_boundary_types_skips_orphans() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {
+                wall: { u: 1, g: 0 },
+                window: { u: 2, g: 0.5 },
+                door: { u: 1.5, g: 0 },
+                orphan: { u: 3, g: 0 },
+            },
+            zones: {
+                a: { volume: 1 },
+                b: { volume: 2 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 10,
+                    sub_boundaries: [{ boundary_type: "window", area: 2 }],
+                },
+                { boundary_type: "wall", zones: ["a", "b"], area: 5 },
+                { boundary_type: "door", zones: ["b", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let names: Vec<_> = model
+            .used_boundary_types()
+            .iter()
+            .map(|bt| bt.name())
+            .collect();
+        assert_eq!(names, ["door", "wall", "window"]);
+    }
+
+    #[test]
+    fn canonical_key_order_independent() {
+        let json = |boundaries: &str| {
+            format!(
+                r#"{{
+                materials: {{}},
+                boundary_types: {{
+                    wall: {{ u: 1, g: 0 }},
+                    window: {{ u: 2, g: 0.5 }},
+                }},
+                zones: {{
+                    a: {{ volume: 1 }},
+                    b: {{ volume: 2 }},
+                }},
+                boundaries: [{}],
+            }}"#,
+                boundaries
+            )
+        };
+        let keys = |model: &Model| -> HashSet<_> {
+            model
+                .boundaries
+                .iter()
+                .map(Boundary::canonical_key)
+                .collect()
+        };
+
+        let model1 = Model::from_json(&json(
+            r#"
+            { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+            { boundary_type: "window", zones: ["a", "b"], area: 2 },
+            "#,
+        ))
+        .unwrap();
+        let model2 = Model::from_json(&json(
+            r#"
+            { boundary_type: "window", zones: ["b", "a"], area: 2 },
+            { boundary_type: "wall", zones: ["outside", "a"], area: 10 },
+            "#,
+        ))
+        .unwrap();
+        let model3 = Model::from_json(&json(
+            r#"
+            { boundary_type: "window", zones: ["b", "a"], area: 3 },
+            { boundary_type: "wall", zones: ["outside", "a"], area: 10 },
+            "#,
+        ))
+        .unwrap();
+
+        assert_eq!(keys(&model1), keys(&model2));
+        assert_ne!(keys(&model1), keys(&model3));
+    }
+
+    #[test]
+    fn convert_model_sub_boundary_zones() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {
+                wall: { u: 1, g: 0 },
+                window: { u: 2, g: 0.5 },
+            },
+            zones: {
+                room: { volume: 1 },
+                stairwell: { volume: 2 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["room", "outside"],
+                    area: 10,
+                    sub_boundaries: [
+                        { boundary_type: "window", area: 1 },
+                        { boundary_type: "window", area: 2, zones: ["room", "stairwell"] },
+                    ]
+                }
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let key = |boundary_type: &str, zones: [&str; 2], area: f64| {
+            (
+                boundary_type.to_string(),
+                zones.map(String::from),
+                area.to_bits(),
+            )
+        };
+        let keys: HashSet<_> = model
+            .boundaries
+            .iter()
+            .map(Boundary::canonical_key)
+            .collect();
+        assert_eq!(model.boundaries.len(), 3);
+        assert_eq!(
+            keys,
+            HashSet::from([
+                key("window", ["outside", "room"], 1.0),
+                key("window", ["room", "stairwell"], 2.0),
+                key("wall", ["outside", "room"], 7.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn convert_model_shared_surface() {
+        let model = Model::from_json(
+            r#"{
+                materials: {},
+                boundary_types: { wall: { u: 1, g: 0 } },
+                zones: {
+                    corridor: { volume: 1 },
+                    a: { volume: 1 },
+                    b: { volume: 1 },
+                    c: { volume: 1 },
+                },
+                boundaries: [{ boundary_type: "wall", zones: ["corridor", "outside"], area: 5 }],
+                shared_surfaces: [
+                    {
+                        boundary_type: "wall",
+                        zone: "corridor",
+                        area: 10,
+                        neighbors: [{ zone: "a", area: 4 }, { zone: "b" }, { zone: "c" }],
+                    },
+                ],
+            }"#,
+        )
+        .unwrap();
+
+        let boundaries: Vec<_> = model
+            .boundaries
+            .iter()
+            .map(|boundary| {
+                (
+                    boundary.zones[0].name.as_str(),
+                    boundary.zones[1].name.as_str(),
+                    boundary.area.get::<square_meter>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            boundaries,
+            [
+                ("corridor", "outside", 5.0),
+                ("corridor", "a", 4.0),
+                ("corridor", "b", 3.0),
+                ("corridor", "c", 3.0),
+            ]
+        );
+        let shared_area: f64 = boundaries[1..].iter().map(|boundary| boundary.2).sum();
+        assert_ulps_eq!(shared_area, 10.0);
+        assert_eq!(model.neighbors("corridor"), ["a", "b", "c", "outside"]);
+    }
+
+    #[test_case(r#"[{ zone: "a", area: 6 }, { zone: "b", area: 5 }]"# ; "neighbors too large")]
+    #[test_case(r#"[{ zone: "a", area: 6 }, { zone: "b", area: 2 }]"# ; "neighbors too small")]
+    #[test_case(r#"[{ zone: "a", area: 10 }, { zone: "b" }]"# ; "nothing left to split")]
+    #[test_case(r#"[]"# ; "no neighbors")]
+    fn convert_model_shared_surface_invalid_area(neighbors: &str) {
+        let json = format!(
+            r#"{{
+                materials: {{}},
+                boundary_types: {{ wall: {{ u: 1, g: 0 }} }},
+                zones: {{ corridor: {{ volume: 1 }}, a: {{ volume: 1 }}, b: {{ volume: 1 }} }},
+                boundaries: [
+                    {{ boundary_type: "wall", zones: ["corridor", "a"], area: 1 }},
+                    {{ boundary_type: "wall", zones: ["corridor", "b"], area: 1 }},
+                ],
+                shared_surfaces: [
+                    {{ boundary_type: "wall", zone: "corridor", area: 10, neighbors: {} }},
+                ],
+            }}"#,
+            neighbors
+        );
+
+        let error = Model::from_json(&json).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::SharedSurfaceArea("corridor".into()))
+        );
+        let report = Model::validate_all(&json);
+        assert!(report
+            .errors
+            .contains(&ModelError::SharedSurfaceArea("corridor".into()).to_string()));
+    }
+
+    #[test]
+    fn convert_model_sub_boundary_unrelated_zones() {
+        let error = Model::from_json(
+            r#"{
+                materials: {},
+                boundary_types: {
+                    wall: { u: 1, g: 0 },
+                },
+                zones: {
+                    a: { volume: 1 },
+                    b: { volume: 1 },
+                    c: { volume: 1 },
+                },
+                boundaries: [
+                    {
+                        boundary_type: "wall",
+                        zones: ["a", "outside"],
+                        area: 10,
+                        sub_boundaries: [
+                            { boundary_type: "wall", area: 1, zones: ["b", "c"] },
+                        ]
+                    }
+                ],
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::UnrelatedSubBoundaryZones {
+                zones: ["b".into(), "c".into()],
+                boundary_zones: ["a".into(), "outside".into()],
+            })
+        );
+    }
+
+    #[test_case(r#"{ boundary_type: "wall", zones: ["a", "a"], area: 10 }"#
+        ; "boundary")]
+    #[test_case(r#"{
+            boundary_type: "wall",
+            zones: ["a", "outside"],
+            area: 10,
+            sub_boundaries: [{ boundary_type: "wall", area: 1, zones: ["a", "a"] }],
+        }"#
+        ; "sub-boundary")]
+    fn convert_model_self_boundary(boundary: &str) {
+        let error = Model::from_json(&format!(
+            r#"{{
+                materials: {{}},
+                boundary_types: {{ wall: {{ u: 1, g: 0 }} }},
+                zones: {{ a: {{ volume: 1 }} }},
+                boundaries: [{}],
+            }}"#,
+            boundary
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::SelfBoundary("a".into()))
+        );
+        error
+            .to_string()
+            .find("zone \"a\"")
+            .expect("Error message should contain the zone name");
+    }
+
+    #[test]
+    fn ua_value_sample_model() {
+        let model = Model::from_json(sample_model_json()).unwrap();
+        let network = crate::rc_network::RcNetwork::try_from(&model).unwrap();
+
+        // Only an interior wall with a simple window, no envelope
+        assert_eq!(model.ua_value().get::<watt_per_kelvin>(), 0.0);
+        assert_abs_diff_eq!(
+            network
+                .heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            0.0,
+            epsilon = 1e-12
+        );
+        // Conductances of the wall and the window are the ones the network uses
+        assert_ulps_eq!(
+            network
+                .combined_conductance("a", "b")
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            model
+                .boundaries
+                .iter()
+                .map(|boundary| model.boundary_conductance(boundary))
+                .sum::<ThermalConductance>()
+                .get::<watt_per_kelvin>(),
+            max_ulps = 1000
+        );
+    }
+
+    #[test]
+    fn total_heat_capacity_sample_model() {
+        let model = Model::from_json(sample_model_json()).unwrap();
+        let network = crate::rc_network::RcNetwork::try_from(&model).unwrap();
+
+        // Air of both zones and the brick wall without the window
+        let expected = (123.0 + 234.0) * 1.199 * 1012.0 + 9.0 * 0.1 * 3.0 * 2.0;
+        assert_ulps_eq!(
+            model
+                .total_heat_capacity(&model.air)
+                .get::<joule_per_kelvin>(),
+            expected,
+            max_ulps = 4
+        );
+        assert_ulps_eq!(
+            network.total_heat_capacity().get::<joule_per_kelvin>(),
+            expected,
+            max_ulps = 4
+        );
+    }
+
+    #[test_case("0.5 mm", false; "sub-millimeter layer")]
+    #[test_case("5 mm", true; "thick layer")]
+    fn check_layer_thickness(thickness: &str, accepted: bool) {
+        let json = format!(
+            r#"{{
+                materials: {{
+                    paint: {{
+                        thermal_conductivity: 0.2,
+                        specific_heat_capacity: 1500,
+                        density: 1200,
+                    }},
+                }},
+                boundary_types: {{
+                    wall: {{ layers: [{{ material: "paint", thickness: "{}" }}] }},
+                }},
+                zones: {{ a: {{ volume: 10 }} }},
+                boundaries: [
+                    {{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }},
+                ],
+            }}"#,
+            thickness
+        );
+        // Loading is not strict
+        let model = Model::from_json(&json).unwrap();
+        let min_thickness = Length::new::<meter>(DEFAULT_MIN_LAYER_THICKNESS);
+        let result = model.check_layer_thickness(min_thickness);
+        let strict = Model::from_json_strict(&json);
+        let report = Model::validate_all(&json);
+        assert!(report.is_ok(), "{}", report);
+        if accepted {
+            assert_eq!(result, Ok(()));
+            assert!(strict.is_ok());
+            assert!(report.warnings.is_empty(), "{}", report);
+        } else {
+            assert_matches!(
+                strict.unwrap_err().downcast_ref::<ModelError>(),
+                Some(ModelError::ThinLayer { boundary_type, .. }) if boundary_type == "wall"
+            );
+            assert_matches!(
+                result,
+                Err(ModelError::ThinLayer { boundary_type, .. }) if boundary_type == "wall"
+            );
+            assert_eq!(report.warnings.len(), 1);
+            report.warnings[0]
+                .find("\"wall\"")
+                .expect("Warning should contain the boundary type name");
+        }
+    }
+
+    #[test]
+    fn edit_boundary_type() {
+        let mut model = Model::from_json(
+            r#"{
+                materials: {
+                    brick: {
+                        thermal_conductivity: 1,
+                        specific_heat_capacity: 2,
+                        density: 3,
+                    },
+                },
+                boundary_types: {
+                    wall: { layers: [{ material: "brick", thickness: 0.1 }] },
+                    window: { u: 1, g: 0.5 },
+                },
+                zones: { a: { volume: 10 }, b: { volume: 10 } },
+                boundaries: [
+                    { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+                    { boundary_type: "wall", zones: ["b", "outside"], area: 10 },
+                    { boundary_type: "window", zones: ["a", "b"], area: 1 },
+                ],
+            }"#,
+        )
+        .unwrap();
+        let original = model.clone();
+        let thickness = |model: &Model, boundary: usize| match model.boundaries[boundary]
+            .boundary_type
+            .as_ref()
+        {
+            BoundaryType::Layered { layers, .. } => match &layers[0].content {
+                LayerContent::Material { thickness, .. } => thickness.get::<meter>(),
+                LayerContent::Resistance { .. } => panic!("Expected a material layer"),
+            },
+            BoundaryType::Simple { .. } => panic!("Expected a layered boundary type"),
+        };
+
+        assert_eq!(model.boundary_type("window").unwrap().name(), "window");
+        assert!(model.boundary_type("door").is_none());
+
+        model
+            .set_layer_thickness("wall", "brick", Length::new::<meter>(0.2))
+            .unwrap();
+        assert_eq!(thickness(&model, 0), 0.2);
+        assert_eq!(thickness(&model, 1), 0.2);
+        assert!(Rc::ptr_eq(
+            &model.boundaries[0].boundary_type,
+            &model.boundaries[1].boundary_type
+        ));
+        assert_eq!(thickness(&original, 0), 0.1);
+        assert_eq!(
+            model.boundary_type_mut("window", |window| window.name().to_string()),
+            Some("window".to_string())
+        );
+
+        let error = model
+            .set_layer_thickness("door", "brick", Length::new::<meter>(0.2))
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::MissingBoundaryType("door".into()))
+        );
+        let error = model
+            .set_layer_thickness("wall", "wool", Length::new::<meter>(0.2))
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::MissingMaterial("wool".into()))
+        );
+        let message = model
+            .set_layer_thickness("window", "brick", Length::new::<meter>(0.2))
+            .unwrap_err()
+            .to_string();
+        message
+            .find("window")
+            .expect("Error message should contain the boundary type name");
+        assert!(model
+            .set_layer_thickness("wall", "brick", Length::new::<meter>(0.0))
+            .is_err());
+        assert_eq!(thickness(&model, 0), 0.2);
+    }
+
+    #[test]
+    fn load_internal_gains() {
+        let model = Model::from_json(
+            r#"{
+                materials: {},
+                boundary_types: { window: { u: 1, g: 0.5 } },
+                zones: {
+                    office: { volume: 50, internal_gains: 100 },
+                    flat: {
+                        volume: 50,
+                        internal_gains: {
+                            hourly: [
+                                0, 0, 0, 0, 0, 0, 100, 200, 0, 0, 0, 0,
+                                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 300,
+                            ],
+                            timezone: "Europe/Prague",
+                        },
+                    },
+                    storage: { volume: 50 },
+                },
+                boundaries: [
+                    { boundary_type: "window", zones: ["office", "outside"], area: 1 },
+                    { boundary_type: "window", zones: ["flat", "outside"], area: 1 },
+                    { boundary_type: "window", zones: ["storage", "outside"], area: 1 },
+                ],
+            }"#,
+        )
+        .unwrap();
+        let gains_at = |time: &str| {
+            model.internal_gains(
+                &DateTime::parse_from_rfc3339(time)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            )
+        };
+
+        // 6:30 and 23:30 in Prague in winter
+        let gains = gains_at("2023-01-10T05:30:00Z");
+        assert_eq!(gains.len(), 2);
+        assert_eq!(gains["office"], Power::new::<watt>(100.0));
+        assert_ulps_eq!(gains["flat"].get::<watt>(), 150.0);
+        assert_ulps_eq!(
+            gains_at("2023-01-10T22:30:00Z")["flat"].get::<watt>(),
+            150.0
+        );
+        // Local time follows daylight saving time
+        assert_ulps_eq!(
+            gains_at("2023-07-10T05:00:00Z")["flat"].get::<watt>(),
+            200.0
+        );
+
+        let saved = Model::from_json(&model.to_json5().unwrap()).unwrap();
+        assert_eq!(
+            saved.zones["flat"].internal_gains,
+            model.zones["flat"].internal_gains
+        );
+    }
+
+    #[test_case("internal_gains: { hourly: [1, 2, 3] }" ; "too few values")]
+    #[test_case(
+        "internal_gains: { hourly: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], timezone: \"Mars/Olympus\" }"
+        ; "unknown time zone"
+    )]
+    fn load_internal_gains_invalid(internal_gains: &str) {
+        let error = Model::from_json(&format!(
+            r#"{{
+                materials: {{}},
+                boundary_types: {{ window: {{ u: 1, g: 0.5 }} }},
+                zones: {{ a: {{ volume: 50, {} }} }},
+                boundaries: [{{ boundary_type: "window", zones: ["a", "outside"], area: 1 }}],
+            }}"#,
+            internal_gains
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::InvalidInternalGains("a".into()))
+        );
+    }
+
+    #[test]
+    fn convert_model_too_large_sub_boundaries() {
+        let input = as_loaded::Model {
+            zones: HashMap::from([
+                (
+                    "z1".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(1.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+                (
+                    "z2".into(),
+                    as_loaded::Zone {
+                        volume: Some(Volume::new::<cubic_meter>(2.0)),
+                        floor_area: None,
+                        height: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
+                    },
+                ),
+            ]),
+            boundaries: vec![as_loaded::Boundary {
+                boundary_type: "bt".into(),
+                zones: ["z1".into(), "z2".into()],
+                area: Area::new::<square_meter>(1.0),
+                interior_surface_resistance: None,
+                exterior_surface_resistance: None,
+                tilt: None,
+                azimuth: None,
+                sub_boundaries: vec![as_loaded::SubBoundary {
+                    boundary_type: "bt".into(),
+                    area: Area::new::<square_meter>(2.0),
+                    zones: None,
+                }],
+            }],
+            materials: HashMap::new(),
+            boundary_types: HashMap::from([(
+                "bt".into(),
+                as_loaded::BoundaryType::Simple {
+                    u: Default::default(),
+                    g: Default::default(),
+                    angular_transmittance: false,
+                },
+            )]),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        assert_eq!(
+            Model::try_from(input).unwrap_err(),
+            ModelError::SubBoundaryTooLarge {
+                zones: ["z1".into(), "z2".into()]
+            }
+        );
+    }
+
+    #[test]
+    fn convert_model_bad_zone_link() {
+        let input = as_loaded::Model {
+            zones: HashMap::from([(
+                "goodzone".into(),
+                as_loaded::Zone {
+                    volume: Some(Volume::new::<cubic_meter>(1.0)),
+                    floor_area: None,
+                    height: None,
+                    internal_mass: None,
+                    air_changes_per_hour: None,
+                    air: None,
+                    internal_gains: None,
+                },
+            )]),
+            boundaries: vec![as_loaded::Boundary {
+                boundary_type: "bt".into(),
+                zones: ["goodzone".into(), "badzone".into()],
+                area: Area::new::<square_meter>(1.0),
+                interior_surface_resistance: None,
+                exterior_surface_resistance: None,
+                tilt: None,
+                azimuth: None,
+                sub_boundaries: Vec::new(),
+            }],
+            materials: HashMap::new(),
+            boundary_types: HashMap::from([(
+                "bt".into(),
+                as_loaded::BoundaryType::Simple {
+                    u: Default::default(),
+                    g: Default::default(),
+                    angular_transmittance: false,
+                },
+            )]),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
+        };
+
+        assert_eq!(
+            Model::try_from(input).unwrap_err(),
+            ModelError::MissingZone("badzone".into())
+        );
+    }
+
+    #[test]
+    fn convert_model_internal_mass() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                wood: {
+                    thermal_conductivity: 0.15,
+                    specific_heat_capacity: 1600,
+                    density: 500,
+                },
+            },
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                a: { volume: 1, internal_mass: { material: "wood", volume: 2, area: 3 } },
+                b: { volume: 1, internal_mass: { heat_capacity: 1000, area: 4 } },
+                c: { volume: 1 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "outside"], area: 1 },
+                { boundary_type: "window", zones: ["b", "outside"], area: 1 },
+                { boundary_type: "window", zones: ["c", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            model.zones["a"].internal_mass,
+            Some(InternalMass {
+                heat_capacity: HeatCapacity::new::<joule_per_kelvin>(2.0 * 500.0 * 1600.0),
+                area: Area::new::<square_meter>(3.0),
+            })
+        );
+        assert_eq!(
+            model.zones["b"].internal_mass,
+            Some(InternalMass {
+                heat_capacity: HeatCapacity::new::<joule_per_kelvin>(1000.0),
+                area: Area::new::<square_meter>(4.0),
+            })
+        );
+        assert_eq!(model.zones["c"].internal_mass, None);
+    }
+
+    #[test]
+    fn convert_model_internal_mass_missing_material() {
+        let error = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {},
+            zones: {
+                a: { volume: 1, internal_mass: { material: "unobtainium", volume: 2, area: 3 } },
+            },
+            boundaries: [],
+        }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ModelError>(),
+            Some(&ModelError::MissingMaterial("unobtainium".into()))
+        );
+    }
+
+    #[test]
+    fn validate_file_with_local_include() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("library.json5"),
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.5, specific_heat_capacity: 1000, density: 1500 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+        }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("model.json5"),
+            r#"{
+            include: ["library.json5"],
+            materials: {},
+            boundary_types: {},
+            zones: { a: { volume: 1 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let report = Model::validate_file(dir.path().join("model.json5"));
+        assert!(report.is_ok(), "{}", report);
+
+        let report = Model::validate_file(dir.path().join("missing.json5"));
+        report.errors[0]
+            .find("Can't read model")
+            .expect("Error should say the model can't be read");
+    }
+
+    #[test]
+    fn load_with_local_include() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("library.json5"),
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.5, specific_heat_capacity: 1000, density: 1500 },
+                air: { thermal_conductivity: 1, specific_heat_capacity: 1, density: 1 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+        }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("model.json5"),
+            r#"{
+            include: ["library.json5"],
+            materials: {
+                air: { thermal_conductivity: 2, specific_heat_capacity: 2, density: 2 },
+            },
+            boundary_types: {},
+            zones: { a: { volume: 1 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let model = Model::load(dir.path().join("model.json5")).unwrap();
+
+        assert_eq!(model.boundaries[0].boundary_type.name(), "wall");
+        // Definitions in the model override the included ones
+        assert_eq!(
+            model.air.density,
+            MassDensity::new::<kilogram_per_cubic_meter>(2.0)
+        );
+    }
+
+    #[test]
+    fn load_include_with_zones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("library.json5"),
+            r#"{ zones: { b: { volume: 1 } } }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("model.json5"),
+            r#"{
+            include: ["library.json5"],
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        )
+        .unwrap();
+
+        let message = format!(
+            "{}",
+            Model::load(dir.path().join("model.json5")).unwrap_err()
+        );
+        message
+            .find("library.json5")
+            .expect("Error message should contain the include name");
+    }
+
+    #[cfg(not(feature = "remote-includes"))]
+    #[test]
+    fn remote_include_without_feature() {
+        let message = format!(
+            "{}",
+            Model::from_json(
+                r#"{
+                include: ["https://example.com/materials.json5"],
+                materials: {},
+                boundary_types: {},
+                zones: {},
+                boundaries: [],
+            }"#,
+            )
+            .unwrap_err()
+        );
+        message
+            .find("remote-includes")
+            .expect("Error message should mention the feature");
+    }
+
+    #[test]
+    fn convert_model_defined_air() {
+        let test_air = as_loaded::Material {
+            from_preset: None,
+            thermal_conductivity: Some(ThermalConductivity::new::<watt_per_meter_kelvin>(999.0)),
+            specific_heat_capacity: Some(SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
+                999.0,
+            )),
+            density: Some(MassDensity::new::<kilogram_per_cubic_meter>(999.0)),
+            emissivity: Some(Ratio::new::<ratio>(0.9)),
+        };
 
-    #[test]
-    fn convert_model_zones() {
         let input = as_loaded::Model {
-            zones: HashMap::from([
-                (
-                    "z1".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(1.0),
-                    },
-                ),
-                (
-                    "z2".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(2.0),
-                    },
-                ),
-            ]),
+            zones: HashMap::new(),
             boundaries: vec![],
-            materials: HashMap::new(),
+            materials: HashMap::from([("air".into(), test_air.clone())]),
             boundary_types: HashMap::new(),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
         };
-
         let output: Model = input.try_into().unwrap();
-
         assert_eq!(
-            output.zones,
-            HashMap::from([
-                (
-                    "outside".into(),
-                    Rc::new(Zone {
-                        name: "outside".into(),
-                        volume: None
-                    })
-                ),
-                (
-                    "ground".into(),
-                    Rc::new(Zone {
-                        name: "ground".into(),
-                        volume: None
-                    })
-                ),
-                (
-                    "z1".into(),
-                    Rc::new(Zone {
-                        name: "z1".into(),
-                        volume: Some(Volume::new::<cubic_meter>(1.0))
-                    })
-                ),
-                (
-                    "z2".into(),
-                    Rc::new(Zone {
-                        name: "z2".into(),
-                        volume: Some(Volume::new::<cubic_meter>(2.0))
-                    })
-                ),
-            ])
+            output.air.as_ref(),
+            &test_air.convert("air".into()).unwrap()
         );
     }
 
-    #[test_case("outside")]
-    #[test_case("ground")]
-    fn convert_model_override_builtin_zone(defined_zone: &str) {
+    #[test]
+    fn convert_model_default_air() {
         let input = as_loaded::Model {
-            zones: HashMap::from([(
-                defined_zone.into(),
-                as_loaded::Zone {
-                    volume: Volume::new::<cubic_meter>(1.0),
-                },
-            )]),
+            zones: HashMap::new(),
             boundaries: vec![],
             materials: HashMap::new(),
             boundary_types: HashMap::new(),
+            location: None,
+            include: Vec::new(),
+            heat_sources: Vec::new(),
+            ground: None,
+            thermal_bridges: Vec::new(),
+            shared_surfaces: Vec::new(),
         };
+        let output: Model = input.try_into().unwrap();
+        assert_eq!(output.air.as_ref(), &Material::default_air());
+    }
+
+    #[test]
+    fn validate_all_valid() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 2,
+                    density: 3,
+                }
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 }
+                    ]
+                },
+                window: {
+                    u: 1,
+                    g: 0.5,
+                }
+            },
+            zones: {
+                a: { volume: 123 },
+                b: { volume: 234 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "b"],
+                    area: 10,
+                },
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 10,
+                    sub_boundaries: [
+                        { boundary_type: "window", area: 1 }
+                    ]
+                }
+            ],
+        }"#,
+        );
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.warnings.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn convert_model_climate() {
+        let model = Model::from_json(
+            r#"{
+            location: {
+                latitude: 49.5,
+                longitude: 17.4,
+                climate: { solar_constant: 1367, albedo: 0.2 },
+            },
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        )
+        .unwrap();
+        let climate = model.location.unwrap().climate;
+        let default = ClimateConfig::default();
+        assert_eq!(
+            climate,
+            ClimateConfig {
+                solar_constant: HeatFluxDensity::new::<watt_per_square_meter>(1367.0),
+                albedo: Ratio::new::<ratio>(0.2),
+                ..default.clone()
+            }
+        );
+
+        let model = Model::from_json(
+            r#"{
+            location: { latitude: 49.5, longitude: 17.4 },
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(model.location.unwrap().climate, default);
+    }
+
+    #[test]
+    fn convert_model_horizon() {
+        let model = Model::from_json(
+            r#"{
+            location: {
+                latitude: 49.5,
+                longitude: 17.4,
+                climate: { horizon: [[270, 25], [90, 15]] },
+            },
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        )
+        .unwrap();
+        let horizon = model.location.unwrap().climate.horizon;
+        assert_abs_diff_eq!(
+            horizon
+                .elevation_at(Angle::new::<degree>(180.0))
+                .get::<degree>(),
+            20.0,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn validate_climate_horizon() {
+        let report = Model::validate_all(
+            r#"{
+            location: { latitude: 49.5, longitude: 17.4, climate: { horizon: [[0, 95]] } },
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            ["Climate has horizon elevation outside of range 0 to 90 degrees"]
+        );
+    }
+
+    #[test]
+    fn validate_climate_albedo() {
+        let report = Model::validate_all(
+            r#"{
+            location: { latitude: 49.5, longitude: 17.4, climate: { snow_albedo: 1.5 } },
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            ["Climate has snow albedo outside of range 0 to 1"]
+        );
+    }
+
+    #[test]
+    fn convert_model_ground() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+            ground: { thermal_conductivity: 2, depth: 3 },
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            model.ground,
+            Ground {
+                thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(2.0),
+                depth: Length::new::<meter>(3.0),
+                ..Ground::default()
+            }
+        );
+    }
+
+    #[test]
+    fn validate_ground() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: {},
+            zones: {},
+            boundaries: [],
+            ground: { depth: 0 },
+        }"#,
+        );
+        assert_eq!(report.errors, ["Ground has non-positive depth"]);
+    }
+
+    #[test]
+    fn convert_model_heat_source_outside() {
+        let result = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 1 }],
+            heat_sources: [{ name: "heat pump", zone: "outside", max_power: 3000 }],
+        }"#,
+        );
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&ModelError::ExteriorHeatSource("heat pump".into()))
+        );
+    }
+
+    #[test_case("-100"; "negative")]
+    #[test_case("NaN"; "nan")]
+    fn convert_model_heat_source_invalid_power(max_power: &str) {
+        let result = Model::from_json(&format!(
+            r#"{{
+            materials: {{}},
+            boundary_types: {{ window: {{ u: 1, g: 0.5 }} }},
+            zones: {{ a: {{ volume: 10 }} }},
+            boundaries: [{{ boundary_type: "window", zones: ["a", "outside"], area: 1 }}],
+            heat_sources: [{{ name: "radiator", zone: "a", max_power: {} }}],
+        }}"#,
+            max_power
+        ));
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&ModelError::InvalidHeatSourcePower("radiator".into()))
+        );
+    }
+
+    #[test]
+    fn validate_heat_sources() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 1 }],
+            heat_sources: [
+                { name: "radiator", zone: "a", max_power: -100 },
+                { name: "radiator", zone: "b", max_power: 100 },
+                { name: "heat pump", zone: "ground", max_power: 3000 },
+            ],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Heat source \"radiator\" has max power that is not zero or a positive number",
+                "Model has two heat sources named \"radiator\"",
+                "Could not find zone \"b\"",
+                "Heat source \"heat pump\" is not in an interior zone",
+            ]
+        );
+    }
+
+    #[test_case("10", "4, 6"; "exactly consumed")]
+    #[test_case("10", "3.3, 3.3, 3.4"; "nearly consumed, rounding up")]
+    #[test_case("0.3", "0.1, 0.2"; "nearly consumed, rounding down")]
+    fn validate_consumed_parent_boundary(area: &str, sub_areas: &str) {
+        let sub_boundaries = sub_areas
+            .split(", ")
+            .map(|sub_area| format!(r#"{{ boundary_type: "window", area: {} }}"#, sub_area))
+            .join(", ");
+        let report = Model::validate_all(&format!(
+            r#"{{
+            materials: {{}},
+            boundary_types: {{ wall: {{ u: 0.3, g: 0 }}, window: {{ u: 1, g: 0.5 }} }},
+            zones: {{ a: {{ volume: 10 }} }},
+            boundaries: [
+                {{
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: {},
+                    sub_boundaries: [{}],
+                }},
+            ],
+        }}"#,
+            area, sub_boundaries
+        ));
+        assert!(report.errors.is_empty(), "{}", report);
+        assert_eq!(
+            report.warnings,
+            ["Boundary [\"a\", \"outside\"] is completely covered by its sub-boundaries"]
+        );
+    }
+
+    #[test_case("volume: 50"; "volume")]
+    #[test_case("floor_area: 20, height: 2.5"; "floor area and height")]
+    fn zone_volume(size: &str) {
+        let model = Model::from_json(&format!(
+            r#"{{
+            materials: {{}},
+            boundary_types: {{ window: {{ u: 1, g: 0.5 }} }},
+            zones: {{ a: {{ {} }} }},
+            boundaries: [{{ boundary_type: "window", zones: ["a", "outside"], area: 1 }}],
+        }}"#,
+            size
+        ))
+        .unwrap();
+        assert_eq!(
+            model.zones["a"].volume,
+            Some(Volume::new::<cubic_meter>(50.0))
+        );
+    }
+
+    #[test_case("volume: 50, floor_area: 20, height: 2.5", ModelError::AmbiguousZoneVolume("a".into()); "both")]
+    #[test_case("volume: 50, height: 2.5", ModelError::AmbiguousZoneVolume("a".into()); "volume and height")]
+    #[test_case("floor_area: 20", ModelError::MissingZoneVolume("a".into()); "no height")]
+    #[test_case("", ModelError::MissingZoneVolume("a".into()); "nothing")]
+    fn zone_volume_invalid(size: &str, expected: ModelError) {
+        let json = format!(
+            r#"{{
+            materials: {{}},
+            boundary_types: {{ window: {{ u: 1, g: 0.5 }} }},
+            zones: {{ a: {{ {} }} }},
+            boundaries: [{{ boundary_type: "window", zones: ["a", "outside"], area: 1 }}],
+        }}"#,
+            size
+        );
+        assert_eq!(
+            Model::from_json(&json)
+                .unwrap_err()
+                .downcast_ref::<ModelError>(),
+            Some(&expected)
+        );
+        assert_eq!(Model::validate_all(&json).errors, [expected.to_string()]);
+    }
+
+    #[test]
+    fn convert_model_orientation() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { wall: { u: 0.3, g: 0 }, window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 }, b: { volume: 10 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 10,
+                    sub_boundaries: [{ boundary_type: "window", area: 2 }],
+                    tilt: 90,
+                    azimuth: 135,
+                },
+                { boundary_type: "window", zones: ["b", "outside"], area: 1 },
+                { boundary_type: "wall", zones: ["a", "b"], area: 10 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let orientations: Vec<_> = model
+            .boundaries
+            .iter()
+            .map(|boundary| {
+                boundary.orientation.map(|orientation| {
+                    (
+                        orientation.tilt.get::<degree>(),
+                        orientation.azimuth.get::<degree>(),
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(orientations.len(), 4);
+        for (orientation, expected) in orientations.iter().zip([
+            Some((90.0, 135.0)), // window inherits the orientation of its wall
+            Some((90.0, 135.0)),
+            Some((0.0, 180.0)),
+            None,
+        ]) {
+            match (orientation, expected) {
+                (Some((tilt, azimuth)), Some((expected_tilt, expected_azimuth))) => {
+                    assert_ulps_eq!(*tilt, expected_tilt);
+                    assert_ulps_eq!(*azimuth, expected_azimuth);
+                }
+                _ => assert_eq!(*orientation, expected),
+            }
+        }
+
+        let reloaded = Model::from_json(&model.to_json5().unwrap()).unwrap();
+        for (reloaded, original) in reloaded.boundaries.iter().zip(model.boundaries.iter()) {
+            match (reloaded.orientation, original.orientation) {
+                (Some(reloaded), Some(original)) => {
+                    assert_ulps_eq!(reloaded.tilt.value, original.tilt.value);
+                    assert_ulps_eq!(reloaded.azimuth.value, original.azimuth.value);
+                }
+                (reloaded, original) => assert_eq!(reloaded, original),
+            }
+        }
+    }
+
+    #[test]
+    fn interior_boundary_orientation() {
+        let json = r#"{
+            materials: {},
+            boundary_types: { wall: { u: 0.3, g: 0 } },
+            zones: { a: { volume: 10 }, b: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10, tilt: 200 },
+                { boundary_type: "wall", zones: ["a", "b"], area: 10, azimuth: 90 },
+            ],
+        }"#;
+        assert_eq!(
+            Model::from_json(json)
+                .unwrap_err()
+                .downcast_ref::<ModelError>(),
+            Some(&ModelError::InteriorOrientation {
+                zones: ["a".into(), "b".into()]
+            })
+        );
+        assert_eq!(
+            Model::validate_all(json).errors,
+            [
+                "Boundary [\"a\", \"outside\"] has tilt outside of range 0 to 180 degrees",
+                "Boundary [\"a\", \"b\"] between interior zones can't have tilt or azimuth",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_thermal_bridges() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 1 }],
+            thermal_bridges: [
+                { zones: ["a", "outside"], length: 4, psi: 0.05 },
+                { zones: ["a", "b"], length: 4, psi: 0.05 },
+                { zones: ["a", "a"], length: 4, psi: 0.05 },
+                { zones: ["a", "ground"], length: -4, psi: 0.05 },
+            ],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Could not find zone \"b\"",
+                "Thermal bridge connects zone \"a\" to itself",
+                "Thermal bridge between [\"a\", \"ground\"] has length or psi that is not zero or a positive number",
+            ]
+        );
+
+        let result = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 1 }],
+            thermal_bridges: [{ zones: ["a", "b"], length: 4, psi: 0.05 }],
+        }"#,
+        );
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&ModelError::MissingZone("b".into()))
+        );
+    }
+
+    #[test_case(r#"["a", "a"], length: 4, psi: 0.05"#,
+        ModelError::SelfThermalBridge("a".into()); "same zone")]
+    #[test_case(r#"["a", "outside"], length: -4, psi: 0.05"#,
+        ModelError::InvalidThermalBridge { zones: ["a".into(), "outside".into()] }; "negative length")]
+    #[test_case(r#"["a", "outside"], length: 4, psi: NaN"#,
+        ModelError::InvalidThermalBridge { zones: ["a".into(), "outside".into()] }; "nan psi")]
+    fn convert_model_invalid_thermal_bridge(thermal_bridge: &str, expected: ModelError) {
+        let result = Model::from_json(&format!(
+            r#"{{
+            materials: {{}},
+            boundary_types: {{ window: {{ u: 1, g: 0.5 }} }},
+            zones: {{ a: {{ volume: 10 }} }},
+            boundaries: [{{ boundary_type: "window", zones: ["a", "outside"], area: 1 }}],
+            thermal_bridges: [{{ zones: {} }}],
+        }}"#,
+            thermal_bridge
+        ));
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&expected)
+        );
+    }
+
+    #[test]
+    fn convert_model_orphaned_zones() {
+        let result = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: {
+                kitchen: { volume: 10 },
+                bedroom: { volume: 10 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["kitchen", "outside"], area: 1 },
+            ],
+            // A thermal bridge alone doesn't enclose a zone
+            thermal_bridges: [{ zones: ["kitchen", "bedroom"], length: 4, psi: 0.05 }],
+        }"#,
+        );
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&ModelError::OrphanedZones(vec!["bedroom".into()]))
+        );
+
+        let result = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: {
+                kitchen: { volume: 10 },
+                bedroom: { volume: 10 },
+                attic: { volume: 10 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["kitchen", "outside"], area: 1 },
+            ],
+        }"#,
+        );
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ModelError>(),
+            Some(&ModelError::OrphanedZones(vec![
+                "attic".into(),
+                "bedroom".into()
+            ]))
+        );
+    }
+
+    #[test_case("thermal_conductivity", "0", "thermal conductivity")]
+    #[test_case("specific_heat_capacity", "-1", "specific heat capacity")]
+    #[test_case("density", "NaN", "density")]
+    #[test_case("density", "Infinity", "density")]
+    fn convert_model_invalid_material_property(field: &str, value: &str, property: &str) {
+        let mut properties = HashMap::from([
+            ("thermal_conductivity", "1"),
+            ("specific_heat_capacity", "1"),
+            ("density", "1"),
+        ]);
+        properties.insert(field, value);
+        let json = format!(
+            r#"{{
+            materials: {{
+                brick: {{
+                    thermal_conductivity: {},
+                    specific_heat_capacity: {},
+                    density: {},
+                }},
+            }},
+            boundary_types: {{ wall: {{ layers: [{{ material: "brick", thickness: 0.1 }}] }} }},
+            zones: {{ a: {{ volume: 10 }} }},
+            boundaries: [{{ boundary_type: "wall", zones: ["a", "outside"], area: 1 }}],
+        }}"#,
+            properties["thermal_conductivity"],
+            properties["specific_heat_capacity"],
+            properties["density"]
+        );
 
-        let message = format!("{}", Model::try_from(input).unwrap_err());
-        println!("{}", message);
+        let message = format!("{}", Model::from_json(&json).unwrap_err());
         message
-            .find("reserved zone")
-            .expect("Error message should say that there's a problem with a reserved zone");
+            .find("brick")
+            .expect("Error message should contain the material name");
         message
-            .find(defined_zone)
-            .expect("Error message should contain the name of the problematic zone");
+            .find(property)
+            .expect("Error message should contain the bad property");
+
+        let report = Model::validate_all(&json);
+        assert_eq!(report.errors, [message]);
     }
 
     #[test]
-    fn convert_model_boundaries() {
-        let input = as_loaded::Model {
-            zones: HashMap::from([
-                (
-                    "z1".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(1.0),
-                    },
-                ),
-                (
-                    "z2".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(2.0),
-                    },
-                ),
-            ]),
-            boundaries: vec![as_loaded::Boundary {
-                boundary_type: "bt1".into(),
-                zones: ["z1".into(), "z2".into()],
-                area: Area::new::<square_meter>(123.0),
-                sub_boundaries: vec![
-                    as_loaded::SubBoundary {
-                        boundary_type: "bt2".into(),
-                        area: Area::new::<square_meter>(1.0),
-                    },
-                    as_loaded::SubBoundary {
-                        boundary_type: "bt3".into(),
-                        area: Area::new::<square_meter>(2.0),
-                    },
-                ],
-            }],
-            materials: HashMap::new(),
-            boundary_types: HashMap::from([
-                (
-                    "bt1".into(),
-                    as_loaded::BoundaryType::Simple {
-                        u: Default::default(),
-                        g: Default::default(),
-                    },
-                ),
-                (
-                    "bt2".into(),
-                    as_loaded::BoundaryType::Simple {
-                        u: Default::default(),
-                        g: Default::default(),
-                    },
-                ),
-                (
-                    "bt3".into(),
-                    as_loaded::BoundaryType::Simple {
-                        u: Default::default(),
-                        g: Default::default(),
-                    },
-                ),
-            ]),
-        };
-
-        let output: Model = input.try_into().unwrap();
+    fn validate_zone_air_material() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { bathroom: { volume: 10, air: "steam" } },
+            boundaries: [{ boundary_type: "window", zones: ["bathroom", "outside"], area: 1 }],
+        }"#,
+        );
+        assert_eq!(report.errors, ["Could not find material \"steam\""]);
+    }
 
-        let z1 = Rc::new(Zone {
-            name: "z1".into(),
-            volume: Some(Volume::new::<cubic_meter>(1.0)),
-        });
-        let z2 = Rc::new(Zone {
-            name: "z2".into(),
-            volume: Some(Volume::new::<cubic_meter>(2.0)),
-        });
-        let bt1 = Rc::new(BoundaryType::Simple {
-            name: "bt1".into(),
-            u: Default::default(),
-            g: Default::default(),
-        });
-        let bt2 = Rc::new(BoundaryType::Simple {
-            name: "bt2".into(),
-            u: Default::default(),
-            g: Default::default(),
-        });
-        let bt3 = Rc::new(BoundaryType::Simple {
-            name: "bt3".into(),
-            u: Default::default(),
-            g: Default::default(),
-        });
+    #[test]
+    fn validate_material_emissivity() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {
+                shiny: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 1,
+                    density: 1,
+                    emissivity: 1.5,
+                },
+            },
+            boundary_types: { wall: { layers: [{ material: "shiny", thickness: 0.1 }] } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 1 }],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            ["Material \"shiny\" has emissivity outside of range 0 to 1"]
+        );
+    }
 
-        // This is fragile wrt. ordering of boundaries. Any order is valid, but the comparison only accepts one.
+    #[test]
+    fn validate_zone_without_interior_coupling() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: {
+                kitchen: { volume: 10 },
+                bedroom: { volume: 10 },
+                attic: { volume: 10 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["kitchen", "outside"], area: 1 },
+                { boundary_type: "window", zones: ["kitchen", "bedroom"], area: 1 },
+                { boundary_type: "window", zones: ["attic", "ground"], area: 1 },
+            ],
+        }"#,
+        );
+        assert!(report.is_ok(), "{}", report);
         assert_eq!(
-            output.boundaries,
-            vec![
-                Boundary {
-                    boundary_type: Rc::clone(&bt2),
-                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
-                    area: Area::new::<square_meter>(1.0),
+            report.warnings,
+            ["Zone \"attic\" only borders outside or ground, not any other zone"]
+        );
+    }
+
+    #[test]
+    fn validate_all_parse_error() {
+        let report = Model::validate_all("{ this is not a model");
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    #[test]
+    fn validate_all_collects_everything() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 2,
+                    density: 3,
                 },
-                Boundary {
-                    boundary_type: Rc::clone(&bt3),
-                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
-                    area: Area::new::<square_meter>(2.0),
+                unused_material: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 2,
+                    density: 3,
                 },
-                Boundary {
-                    boundary_type: Rc::clone(&bt1),
-                    zones: [Rc::clone(&z1), Rc::clone(&z2)],
-                    area: Area::new::<square_meter>(120.0),
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 },
+                        { material: "missing_material", thickness: 0.1 },
+                    ]
                 },
-            ]
+                window: {
+                    u: -1,
+                    g: 2,
+                },
+                unused_type: {
+                    u: 1,
+                    g: 0.5,
+                },
+            },
+            zones: {
+                outside: { volume: 1 },
+                a: { volume: 123 },
+                b: { volume: 0 },
+                lonely: { volume: 1 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 10,
+                    sub_boundaries: [
+                        { boundary_type: "window", area: 11 }
+                    ]
+                },
+                {
+                    boundary_type: "wall",
+                    zones: ["b", "b"],
+                    area: 0,
+                },
+            ],
+        }"#,
         );
+
+        let expected_errors = [
+            "reserved zone",
+            "non-positive volume",
+            "missing_material",
+            "non-positive U",
+            "g value",
+            "itself",
+            "non-positive area",
+            "sub-boundaries",
+            "\"lonely\" is not enclosed",
+            "\"b\" is not connected",
+        ];
+        for expected in expected_errors {
+            assert!(
+                report.errors.iter().any(|e| e.contains(expected)),
+                "Missing error containing {:?}",
+                expected
+            );
+        }
+        let expected_warnings = ["unused_material", "unused_type"];
+        for expected in expected_warnings {
+            assert!(
+                report.warnings.iter().any(|w| w.contains(expected)),
+                "Missing warning containing {:?}",
+                expected
+            );
+        }
     }
 
     #[test]
-    fn convert_model_too_large_sub_boundaries() {
-        let input = as_loaded::Model {
-            zones: HashMap::from([
-                (
-                    "z1".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(1.0),
-                    },
-                ),
+    fn load_model() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+
+        use std::io::Write;
+        write!(f, "{}", sample_model_json()).unwrap();
+
+        let model = Model::load(f.path()).unwrap();
+
+        check_sample_model(model);
+    }
+
+    #[test]
+    fn model_from_json() {
+        let model = Model::from_json(sample_model_json()).unwrap();
+        check_sample_model(model);
+    }
+
+    #[test]
+    fn boundaries_and_neighbors_of_zone() {
+        let mut model = Model::from_json(sample_model_json()).unwrap();
+        model.boundaries.push(Boundary {
+            zones: [
+                Rc::clone(&model.zones["outside"]),
+                Rc::clone(&model.zones["a"]),
+            ],
+            ..model.boundaries[0].clone()
+        });
+
+        let areas: Vec<_> = model
+            .boundaries_of("a")
+            .map(|boundary| boundary.area.get::<square_meter>())
+            .collect();
+        assert_eq!(areas, [1.0, 9.0, 1.0]);
+        assert_eq!(model.boundaries_of("b").count(), 2);
+        assert_eq!(model.boundaries_of("ground").count(), 0);
+
+        assert_eq!(model.neighbors("a"), ["b", "outside"]);
+        assert_eq!(model.neighbors("b"), ["a"]);
+        assert_eq!(model.neighbors("outside"), ["a"]);
+        assert!(model.neighbors("nonexistent").is_empty());
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let model = Model::from_json(sample_model_json()).unwrap();
+        let f = tempfile::NamedTempFile::new().unwrap();
+        model.save(f.path()).unwrap();
+
+        let loaded = Model::load(f.path()).unwrap();
+
+        assert_eq!(loaded.zones, model.zones);
+        assert_eq!(loaded.boundaries, model.boundaries);
+        assert_eq!(loaded.air, model.air);
+        assert_eq!(loaded.location, model.location);
+    }
+
+    #[test]
+    fn to_json5_sub_boundaries() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 2, density: 3 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+                window: { u: 1, g: 0.5 },
+                glass_wall: { u: 2, g: 0.6 },
+            },
+            zones: { a: { volume: 30 }, b: { volume: 40 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 20,
+                    tilt: 90,
+                    azimuth: 180,
+                    sub_boundaries: [
+                        { boundary_type: "window", area: 2 },
+                        { boundary_type: "window", area: 1, zones: ["b", "outside"] },
+                    ],
+                },
+                { boundary_type: "wall", zones: ["a", "b"], area: 8 },
+                {
+                    boundary_type: "wall",
+                    zones: ["b", "outside"],
+                    area: 4,
+                    sub_boundaries: [{ boundary_type: "glass_wall", area: 4 }],
+                },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        let saved = as_loaded::Model::from_model(&model).unwrap();
+
+        fn sub_boundaries(boundary: &as_loaded::Boundary) -> Vec<(&str, f64, Option<[String; 2]>)> {
+            boundary
+                .sub_boundaries
+                .iter()
+                .map(|sub_boundary| {
+                    (
+                        sub_boundary.boundary_type.as_str(),
+                        sub_boundary.area.get::<square_meter>(),
+                        sub_boundary.zones.clone(),
+                    )
+                })
+                .collect()
+        }
+        assert_eq!(saved.boundaries.len(), 3);
+        assert_eq!(saved.boundaries[0].area.get::<square_meter>(), 20.0);
+        assert_eq!(saved.boundaries[0].tilt, Some(90.0));
+        assert_eq!(
+            sub_boundaries(&saved.boundaries[0]),
+            [
+                ("window", 2.0, None),
                 (
-                    "z2".into(),
-                    as_loaded::Zone {
-                        volume: Volume::new::<cubic_meter>(2.0),
-                    },
+                    "window",
+                    1.0,
+                    Some(["b".to_string(), "outside".to_string()])
                 ),
-            ]),
-            boundaries: vec![as_loaded::Boundary {
-                boundary_type: "bt".into(),
-                zones: ["z1".into(), "z2".into()],
-                area: Area::new::<square_meter>(1.0),
-                sub_boundaries: vec![as_loaded::SubBoundary {
-                    boundary_type: "bt".into(),
-                    area: Area::new::<square_meter>(2.0),
-                }],
-            }],
-            materials: HashMap::new(),
-            boundary_types: HashMap::from([(
-                "bt".into(),
-                as_loaded::BoundaryType::Simple {
-                    u: Default::default(),
-                    g: Default::default(),
-                },
-            )]),
+            ]
+        );
+        assert!(saved.boundaries[1].sub_boundaries.is_empty());
+        // The parent fully covered by its sub-boundary is restored
+        assert_eq!(saved.boundaries[2].boundary_type, "wall");
+        assert_eq!(saved.boundaries[2].area.get::<square_meter>(), 4.0);
+        assert_eq!(
+            sub_boundaries(&saved.boundaries[2]),
+            [("glass_wall", 4.0, None)]
+        );
+
+        let reloaded = Model::from_json(&model.to_json5().unwrap()).unwrap();
+        assert_eq!(reloaded.zones, model.zones);
+        assert_eq!(reloaded.boundaries, model.boundaries);
+
+        // Merging goes through the saved form as well
+        let mut merged = model.clone();
+        merged
+            .merge(
+                Model::from_json(
+                    r#"{
+                    materials: {},
+                    boundary_types: { window: { u: 1, g: 0.5 } },
+                    zones: { c: { volume: 10 } },
+                    boundaries: [{ boundary_type: "window", zones: ["c", "outside"], area: 1 }],
+                }"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            merged.boundaries[..model.boundaries.len()],
+            model.boundaries
+        );
+    }
+
+    const HOUSE_JSON: &str = r#"{
+        materials: {
+            brick: { thermal_conductivity: 1, specific_heat_capacity: 2, density: 3 },
+        },
+        boundary_types: {
+            wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+        },
+        zones: { hall: { volume: 30 }, kitchen: { volume: 40 } },
+        boundaries: [
+            { boundary_type: "wall", zones: ["hall", "outside"], area: 20 },
+            { boundary_type: "wall", zones: ["hall", "kitchen"], area: 10 },
+            { boundary_type: "wall", zones: ["kitchen", "ground"], area: 15 },
+        ],
+    }"#;
+
+    #[test]
+    fn content_hash_order_independent() {
+        let model = |thickness: f64, reversed: bool| {
+            let mut materials = [
+                r#"brick: { thermal_conductivity: 1, specific_heat_capacity: 2, density: 3 }"#,
+                r#"wood: { thermal_conductivity: 0.1, specific_heat_capacity: 1600, density: 500 }"#,
+            ];
+            let mut zones = ["a: { volume: 10 }", "b: { volume: 20 }"];
+            let mut boundaries = [
+                r#"{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }"#,
+                r#"{ boundary_type: "wall", zones: ["a", "b"], area: 5 }"#,
+                r#"{ boundary_type: "door", zones: ["b", "outside"], area: 2 }"#,
+            ];
+            if reversed {
+                materials.reverse();
+                zones.reverse();
+                boundaries.reverse();
+            }
+            Model::from_json(&format!(
+                r#"{{
+                    materials: {{ {} }},
+                    boundary_types: {{
+                        wall: {{ layers: [{{ material: "brick", thickness: {} }}] }},
+                        door: {{ layers: [{{ material: "wood", thickness: 0.05 }}] }},
+                    }},
+                    zones: {{ {} }},
+                    boundaries: [{}],
+                }}"#,
+                materials.join(", "),
+                thickness,
+                zones.join(", "),
+                boundaries.join(", ")
+            ))
+            .unwrap()
         };
 
-        let message = format!("{}", Model::try_from(input).unwrap_err());
-        message
-            .find("sub-boundaries")
-            .expect("Error message should say that there's a problem with sub boundary");
-        message
-            .find("z1")
-            .expect("Error message should contain the name of the problematic zones");
-        message
-            .find("z2")
-            .expect("Error message should contain the name of the problematic zones");
+        let hash = model(0.3, false).content_hash();
+        assert_eq!(model(0.3, false).content_hash(), hash);
+        assert_eq!(model(0.3, true).content_hash(), hash);
+        assert_ne!(model(0.31, false).content_hash(), hash);
     }
 
     #[test]
-    fn convert_model_bad_zone_link() {
-        let input = as_loaded::Model {
-            zones: HashMap::from([(
-                "goodzone".into(),
-                as_loaded::Zone {
-                    volume: Volume::new::<cubic_meter>(1.0),
-                },
-            )]),
-            boundaries: vec![as_loaded::Boundary {
-                boundary_type: "bt".into(),
-                zones: ["goodzone".into(), "badzone".into()],
-                area: Area::new::<square_meter>(1.0),
-                sub_boundaries: Vec::new(),
-            }],
-            materials: HashMap::new(),
-            boundary_types: HashMap::from([(
-                "bt".into(),
-                as_loaded::BoundaryType::Simple {
-                    u: Default::default(),
-                    g: Default::default(),
-                },
-            )]),
-        };
+    fn merge_models() {
+        let mut house = Model::from_json(HOUSE_JSON).unwrap();
+        let garage = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 2, density: 3 },
+                wood: { thermal_conductivity: 0.1, specific_heat_capacity: 1600, density: 500 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+                door: { layers: [{ material: "wood", thickness: 0.05 }] },
+            },
+            zones: { hall: { volume: 30 }, garage: { volume: 60 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["garage", "outside"], area: 40 },
+                { boundary_type: "door", zones: ["hall", "garage"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        house.merge(garage).unwrap();
+
+        assert_eq!(
+            house.zones.keys().sorted().collect::<Vec<_>>(),
+            ["garage", "ground", "hall", "kitchen", "outside"]
+        );
+        assert_eq!(house.boundaries.len(), 5);
+        assert_eq!(house.neighbors("hall"), ["garage", "kitchen", "outside"]);
+        assert_eq!(house.neighbors("garage"), ["hall", "outside"]);
+        // Merged boundaries refer to the zones of the merged model
+        for boundary in house.boundaries.iter() {
+            for zone in boundary.zones.iter() {
+                assert!(Rc::ptr_eq(zone, &house.zones[&zone.name]));
+            }
+        }
+    }
+
+    #[test]
+    fn merge_conflicting_material() {
+        let mut house = Model::from_json(HOUSE_JSON).unwrap();
+        let original_boundaries = house.boundaries.clone();
+        let garage = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.5, specific_heat_capacity: 2, density: 3 },
+            },
+            boundary_types: {
+                garage_wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: { garage: { volume: 60 } },
+            boundaries: [{ boundary_type: "garage_wall", zones: ["garage", "outside"], area: 40 }],
+        }"#,
+        )
+        .unwrap();
+
+        let message = format!("{}", house.merge(garage).unwrap_err());
 
-        let message = format!("{}", Model::try_from(input).unwrap_err());
         message
-            .find("zone")
-            .expect("Error message should say that there's a problem with a zone");
+            .find("material")
+            .expect("Error message should mention a material");
         message
-            .find("badzone")
-            .expect("Error message should contain the name of the problematic zone");
+            .find("\"brick\"")
+            .expect("Error message should contain the material name");
+        assert!(!house.zones.contains_key("garage"));
+        assert_eq!(house.boundaries, original_boundaries);
     }
 
     #[test]
-    fn convert_model_defined_air() {
-        let test_air = as_loaded::Material {
-            thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(999.0),
-            specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(999.0),
-            density: MassDensity::new::<kilogram_per_cubic_meter>(999.0),
-        };
+    fn to_json5_keeps_markers_and_location() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 2, density: 3 },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { marker: "inner" },
+                        { material: "brick", thickness: 0.1 },
+                        { marker: "middle" },
+                        { r_value: 2, heat_capacity_per_area: 1000 },
+                    ]
+                },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }],
+            location: { latitude: 50.08, longitude: 14.42, climate: { albedo: 0.2 } },
+        }"#,
+        )
+        .unwrap();
 
-        let input = as_loaded::Model {
-            zones: HashMap::new(),
-            boundaries: vec![],
-            materials: HashMap::from([("air".into(), test_air.clone())]),
-            boundary_types: HashMap::new(),
-        };
-        let output: Model = input.try_into().unwrap();
-        assert_eq!(output.air.as_ref(), &test_air.convert("air".into()));
+        let reloaded = Model::from_json(&model.to_json5().unwrap()).unwrap();
+
+        assert_eq!(reloaded.boundaries, model.boundaries);
+        let (reloaded, original) = (reloaded.location.unwrap(), model.location.unwrap());
+        // Angles are written in degrees, converting back to radians may round
+        assert_ulps_eq!(reloaded.latitude.value, original.latitude.value);
+        assert_ulps_eq!(reloaded.longitude.value, original.longitude.value);
+        assert_eq!(reloaded.climate, original.climate);
     }
 
     #[test]
-    fn convert_model_default_air() {
-        let input = as_loaded::Model {
-            zones: HashMap::new(),
-            boundaries: vec![],
-            materials: HashMap::new(),
-            boundary_types: HashMap::new(),
-        };
-        let output: Model = input.try_into().unwrap();
-        assert_eq!(output.air.as_ref(), &Material::default_air());
+    fn to_json5_zone_without_volume() {
+        let mut model = Model::from_json(sample_model_json()).unwrap();
+        model.zones.insert(
+            "attic".into(),
+            Rc::new(Zone {
+                name: "attic".into(),
+                volume: None,
+                internal_mass: None,
+                air_changes_per_hour: None,
+                air: None,
+                internal_gains: None,
+            }),
+        );
+        let message = format!("{}", model.to_json5().unwrap_err());
+        message
+            .find("attic")
+            .expect("Error message should contain the zone name");
     }
 
     #[test]
-    fn load_model() {
-        let mut f = tempfile::NamedTempFile::new().unwrap();
+    fn to_json5_duplicate_material_names() {
+        let mut model = Model::from_json(sample_model_json()).unwrap();
+        let mut other_brick = model.air.as_ref().clone();
+        other_brick.name = "brick".into();
+        model.boundaries[0].boundary_type = Rc::new(BoundaryType::Layered {
+            name: "other_wall".into(),
+            layers: vec![BoundaryLayer {
+                content: LayerContent::Material {
+                    material: Rc::new(other_brick),
+                    thickness: Length::new::<meter>(0.1),
+                },
+                following_marker: None,
+            }],
+            initial_marker: None,
+            solar_absorptance: Ratio::new::<ratio>(DEFAULT_SOLAR_ABSORPTANCE),
+        });
+        let message = format!("{}", model.to_json5().unwrap_err());
+        message
+            .find("brick")
+            .expect("Error message should contain the material name");
+    }
 
-        use std::io::Write;
-        write!(f, "{}", sample_model_json()).unwrap();
+    /// Copy of the model that can be saved: air named "air", other materials,
+    /// boundary types and interior zones with unique names and zones without volume
+    /// replaced by the reserved outer zones.
+    fn saveable(model: &Model) -> Model {
+        let mut materials: HashMap<*const Material, Rc<Material>> = HashMap::new();
+        // The first renamed material is air
+        let mut rename_material = |material: &Rc<Material>| {
+            let name = match materials.len() {
+                0 => "air".to_string(),
+                index => format!("m{}", index),
+            };
+            Rc::clone(materials.entry(Rc::as_ptr(material)).or_insert_with(|| {
+                Rc::new(Material {
+                    name,
+                    ..material.as_ref().clone()
+                })
+            }))
+        };
+        let air = rename_material(&model.air);
 
-        let model = Model::load(f.path()).unwrap();
+        let mut boundary_types: HashMap<*const BoundaryType, Rc<BoundaryType>> = HashMap::new();
+        let mut zones: HashMap<*const Zone, Rc<Zone>> = HashMap::new();
+        let mut outer_zones = ["outside", "ground"].into_iter();
+        let mut boundaries = Vec::new();
+        for boundary in model.boundaries.iter() {
+            let index = boundary_types.len();
+            let boundary_type = boundary_types
+                .entry(Rc::as_ptr(&boundary.boundary_type))
+                .or_insert_with(|| {
+                    Rc::new(match boundary.boundary_type.as_ref() {
+                        BoundaryType::Layered {
+                            layers,
+                            initial_marker,
+                            solar_absorptance,
+                            ..
+                        } => BoundaryType::Layered {
+                            name: format!("b{}", index),
+                            layers: layers
+                                .iter()
+                                .map(|layer| BoundaryLayer {
+                                    content: match &layer.content {
+                                        LayerContent::Material {
+                                            material,
+                                            thickness,
+                                        } => LayerContent::Material {
+                                            material: rename_material(material),
+                                            thickness: *thickness,
+                                        },
+                                        content => content.clone(),
+                                    },
+                                    following_marker: layer.following_marker.clone(),
+                                })
+                                .collect(),
+                            initial_marker: initial_marker.clone(),
+                            solar_absorptance: *solar_absorptance,
+                        },
+                        BoundaryType::Simple {
+                            u,
+                            g,
+                            angular_transmittance,
+                            ..
+                        } => BoundaryType::Simple {
+                            name: format!("b{}", index),
+                            u: *u,
+                            g: *g,
+                            angular_transmittance: *angular_transmittance,
+                        },
+                    })
+                });
+            let boundary_type = Rc::clone(boundary_type);
 
-        check_sample_model(model);
+            let mut rename_zone = |zone: &Rc<Zone>| {
+                let index = zones.len();
+                Rc::clone(zones.entry(Rc::as_ptr(zone)).or_insert_with(|| {
+                    let mut renamed = zone.as_ref().clone();
+                    renamed.name = format!("z{}", index);
+                    if renamed.volume.is_none() {
+                        match outer_zones.next() {
+                            Some(name) => renamed.name = name.into(),
+                            None => renamed.volume = Some(Volume::new::<cubic_meter>(1.0)),
+                        }
+                    }
+                    Rc::new(renamed)
+                }))
+            };
+            let zones = [
+                rename_zone(&boundary.zones[0]),
+                rename_zone(&boundary.zones[1]),
+            ];
+            // Renamed zones may have turned the boundary exterior
+            let is_exterior = zones[0].volume.is_some() != zones[1].volume.is_some();
+            boundaries.push(Boundary {
+                boundary_type,
+                zones,
+                area: boundary.area,
+                surface_resistances: boundary.surface_resistances.clone(),
+                orientation: is_exterior.then(Orientation::default),
+                parent: None,
+            });
+        }
+
+        let mut zones: HashMap<String, Rc<Zone>> = zones
+            .into_values()
+            .map(|zone| (zone.name.clone(), zone))
+            .collect();
+        for name in ["outside", "ground"] {
+            zones.entry(name.into()).or_insert_with(|| {
+                Rc::new(Zone {
+                    name: name.into(),
+                    volume: None,
+                    internal_mass: None,
+                    air_changes_per_hour: None,
+                    air: None,
+                    internal_gains: None,
+                })
+            });
+        }
+
+        Model {
+            zones,
+            boundaries,
+            air,
+            location: model.location.clone(),
+            heat_sources: model.heat_sources.clone(),
+            ground: model.ground.clone(),
+            thermal_bridges: model.thermal_bridges.clone(),
+        }
     }
 
-    #[test]
-    fn model_from_json() {
-        let model = Model::from_json(sample_model_json()).unwrap();
-        check_sample_model(model);
+    #[proptest]
+    fn to_json5_round_trip(
+        #[strategy(Model::arbitrary().prop_map(|model| saveable(&model)))] model: Model,
+    ) {
+        let reloaded = Model::from_json(&model.to_json5().unwrap()).unwrap();
+
+        assert_eq!(reloaded.zones, model.zones);
+        assert_eq!(reloaded.boundaries, model.boundaries);
+        assert_eq!(reloaded.air, model.air);
     }
 
     #[test_case(Some(1.0), 12.0; "finite")]
@@ -1179,12 +6522,17 @@ mod tests {
         let z = Zone {
             name: Default::default(),
             volume: v.map(Volume::new::<cubic_meter>),
+            internal_mass: None,
+            air_changes_per_hour: None,
+            air: None,
+            internal_gains: None,
         };
         let m = Material {
             name: Default::default(),
             thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(2.0),
             specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(3.0),
             density: MassDensity::new::<kilogram_per_cubic_meter>(4.0),
+            emissivity: Ratio::new::<ratio>(0.9),
         };
         assert_eq!(
             z.heat_capacity(&m),
@@ -1197,12 +6545,17 @@ mod tests {
         let z = Zone {
             name: Default::default(),
             volume: None,
+            internal_mass: None,
+            air_changes_per_hour: None,
+            air: None,
+            internal_gains: None,
         };
         let m = Material {
             name: Default::default(),
             thermal_conductivity: Default::default(),
             specific_heat_capacity: Default::default(),
             density: Default::default(),
+            emissivity: Default::default(),
         };
         assert_eq!(
             z.heat_capacity(&m),
@@ -1210,18 +6563,348 @@ mod tests {
         );
     }
 
+    #[test]
+    fn convert_boundary_layer_r_value() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {
+                insulated: {
+                    layers: [
+                        { r_value: 2.5 },
+                        { r_value: 0.5, heat_capacity_per_area: 10000 },
+                    ]
+                },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "insulated", zones: ["a", "outside"], area: 4 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let BoundaryType::Layered { layers, .. } = model.boundaries[0].boundary_type.as_ref()
+        else {
+            panic!("Expected a layered boundary");
+        };
+        let area = Area::new::<square_meter>(4.0);
+
+        assert_ulps_eq!(layers[0].conductance(area).get::<watt_per_kelvin>(), 1.6);
+        assert_eq!(layers[0].heat_capacity(area).get::<joule_per_kelvin>(), 0.0);
+        assert_ulps_eq!(layers[1].conductance(area).get::<watt_per_kelvin>(), 8.0);
+        assert_ulps_eq!(
+            layers[1].heat_capacity(area).get::<joule_per_kelvin>(),
+            40000.0
+        );
+
+        let surface_resistance = air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+            .get::<watt_per_square_meter_kelvin>()
+            .recip();
+        assert_ulps_eq!(
+            model.boundaries[0]
+                .boundary_type
+                .u_value()
+                .get::<watt_per_square_meter_kelvin>(),
+            1.0 / (2.0 * surface_resistance + 3.0)
+        );
+    }
+
+    #[test]
+    fn u_value_single_brick_wall() {
+        let brick = Rc::new(Material {
+            name: "brick".into(),
+            thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.8),
+            specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(840.0),
+            density: MassDensity::new::<kilogram_per_cubic_meter>(1800.0),
+            emissivity: Ratio::new::<ratio>(0.9),
+        });
+        let wall = BoundaryType::Layered {
+            name: "wall".into(),
+            layers: vec![BoundaryLayer {
+                content: LayerContent::Material {
+                    material: brick,
+                    thickness: Length::new::<meter>(0.3),
+                },
+                following_marker: None,
+            }],
+            initial_marker: None,
+            solar_absorptance: Ratio::new::<ratio>(DEFAULT_SOLAR_ABSORPTANCE),
+        };
+
+        // R = 0.3 / 0.8 + 2 / 12.12 = 0.5400 m²K/W
+        assert_abs_diff_eq!(
+            wall.u_value().get::<watt_per_square_meter_kelvin>(),
+            1.8518,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn boundary_u_value_iso_6946() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                plaster: { thermal_conductivity: 0.7, specific_heat_capacity: 1000, density: 1400 },
+                brick: { thermal_conductivity: 0.77, specific_heat_capacity: 900, density: 1700 },
+                eps: { thermal_conductivity: 0.04, specific_heat_capacity: 1270, density: 20 },
+                render: { thermal_conductivity: 0.87, specific_heat_capacity: 1000, density: 1800 },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "render", thickness: 0.01 },
+                        { material: "eps", thickness: 0.1 },
+                        { material: "brick", thickness: 0.24 },
+                        { material: "plaster", thickness: 0.015 },
+                    ]
+                },
+            },
+            zones: { room: { volume: 50 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["outside", "room"],
+                    area: 10,
+                    interior_surface_resistance: 0.13,
+                    exterior_surface_resistance: 0.04,
+                },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let wall = &model.boundaries[0];
+
+        assert_eq!(
+            wall.surface_resistance_overrides(),
+            [Some(r_value(0.04)), Some(r_value(0.13))]
+        );
+        // Layers: 0.01 / 0.87 + 0.1 / 0.04 + 0.24 / 0.77 + 0.015 / 0.7 = 2.8446 m²K/W,
+        // R = 0.04 + 2.8446 + 0.13 = 3.0146 m²K/W, U = 0.3317 W/m²K
+        // (0.33 W/m²K after rounding to two significant digits as ISO 6946 requires)
+        assert_abs_diff_eq!(
+            wall.u_value().get::<watt_per_square_meter_kelvin>(),
+            0.3317,
+            epsilon = 1e-4
+        );
+        // The boundary type alone has still air convection on both sides,
+        // R = 2 / 12.12 + 2.8446 = 3.0096 m²K/W
+        assert_abs_diff_eq!(
+            wall.boundary_type
+                .u_value()
+                .get::<watt_per_square_meter_kelvin>(),
+            0.3323,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn boundary_interior_surface_resistance() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { wall: { layers: [{ r_value: 1 }] } },
+            zones: { a: { volume: 50 }, b: { volume: 50 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "b"],
+                    area: 10,
+                    interior_surface_resistance: 0.13,
+                    exterior_surface_resistance: 0.04,
+                },
+            ],
+        }"#,
+        )
+        .unwrap();
+
+        // Interior boundary has no exterior side, R = 0.13 + 1 + 0.13 = 1.26 m²K/W
+        assert_eq!(
+            model.boundaries[1].surface_resistance_overrides(),
+            [Some(r_value(0.13)), Some(r_value(0.13))]
+        );
+        assert_abs_diff_eq!(
+            model.boundaries[1]
+                .u_value()
+                .get::<watt_per_square_meter_kelvin>(),
+            0.7937,
+            epsilon = 1e-4
+        );
+        // Boundary without given resistances uses still air convection on both sides,
+        // R = 1 / 12.12 + 1 + 1 / 12.12 = 1.1650 m²K/W
+        let [r1, r2] = model.boundaries[0].surface_resistances();
+        assert_eq!(r1, r2);
+        assert_abs_diff_eq!(
+            model.boundaries[0]
+                .u_value()
+                .get::<watt_per_square_meter_kelvin>(),
+            0.8584,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn validate_surface_resistance() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: { wall: { layers: [{ r_value: 1 }] } },
+            zones: { a: { volume: 10 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 1,
+                    exterior_surface_resistance: 0,
+                },
+            ],
+        }"#,
+        );
+        assert_eq!(
+            report.errors,
+            ["Boundary [\"a\", \"outside\"] has non-positive surface resistance"]
+        );
+    }
+
+    #[test]
+    fn u_value_simple() {
+        let window = BoundaryType::Simple {
+            name: "window".into(),
+            u: HeatTransfer::new::<watt_per_square_meter_kelvin>(1.1),
+            g: Ratio::new::<percent>(50.0),
+            angular_transmittance: false,
+        };
+        assert_eq!(
+            window.u_value(),
+            HeatTransfer::new::<watt_per_square_meter_kelvin>(1.1)
+        );
+    }
+
+    #[test]
+    fn validate_boundary_layer_r_value() {
+        let report = Model::validate_all(
+            r#"{
+            materials: {},
+            boundary_types: {
+                bad: { layers: [{ r_value: 0 }, { r_value: 1, heat_capacity_per_area: -5 }] },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "bad", zones: ["a", "outside"], area: 4 },
+            ],
+        }"#,
+        );
+        let message = report.errors.join("\n");
+        message
+            .find("non-positive R value")
+            .expect("Error message should mention the R value");
+        message
+            .find("negative heat capacity")
+            .expect("Error message should mention the heat capacity");
+    }
+
+    /// 200 mm of dense concrete (λ = 1.8 W/(m·K), ρ = 2400 kg/m³, c = 1000 J/(kg·K)),
+    /// 24 h period, derived by hand following ISO 13786:2017 (heat transfer matrix
+    /// of a homogeneous layer), with the surface resistance used by this crate,
+    /// Rs = 1 / 12.12 = 0.08251 m²·K/W on both sides:
+    ///
+    /// * periodic penetration depth δ = √(λ·T / (π·ρ·c)) = 0.143619 m, ξ = d / δ = 1.392571
+    /// * Z11 = cosh ξ cos ξ + i sinh ξ sin ξ = 0.378820 + 1.858463i
+    /// * Z12 = -δ / 2λ · (sinh ξ cos ξ + cosh ξ sin ξ + i (cosh ξ sin ξ - sinh ξ cos ξ))
+    ///   = -0.097252 - 0.070540i
+    /// * Z21 = -λ / δ · (sinh ξ cos ξ - cosh ξ sin ξ + i (sinh ξ cos ξ + cosh ξ sin ξ))
+    ///   = 22.160937 - 30.552550i
+    /// * with the surface resistances Z12' = Z12 - 2·Rs·Z11 + Rs²·Z21 = -0.008900 - 0.585207i
+    /// * |Y12| = 1 / |Z12'| = 1.70860 W/(m²·K), U = 1 / (2·Rs + d / λ) = 3.62151 W/(m²·K)
+    /// * decrement factor f = |Y12| / U = 0.47179,
+    ///   time lag = -arg(-1 / Z12') / 2π · T = 5.9419 h
+    ///
+    /// These agree with the usual figures quoted for such a wall (decrement factor
+    /// around 0.5, time lag around 6 hours).
+    #[test]
+    fn dynamic_characteristics_concrete_wall() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                concrete: {
+                    thermal_conductivity: 1.8,
+                    specific_heat_capacity: 1000,
+                    density: 2400,
+                },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "concrete", thickness: 0.2 }] },
+                split_wall: {
+                    layers: [
+                        { material: "concrete", thickness: 0.1 },
+                        { r_value: 0.0555555555555556, heat_capacity_per_area: 240000 },
+                    ]
+                },
+                window: { u: 1.2, g: 0.5 },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 1 },
+                { boundary_type: "split_wall", zones: ["a", "outside"], area: 1 },
+                { boundary_type: "window", zones: ["a", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let day = Time::new::<hour>(24.0);
+        let characteristics = |index: usize| {
+            model.boundaries[index]
+                .boundary_type
+                .dynamic_characteristics(day)
+        };
+
+        let wall = characteristics(0);
+        assert_abs_diff_eq!(
+            wall.decrement_factor.get::<ratio>(),
+            0.47179,
+            epsilon = 1e-5
+        );
+        assert_abs_diff_eq!(wall.time_lag.get::<hour>(), 5.9419, epsilon = 1e-4);
+        assert_abs_diff_eq!(
+            wall.periodic_thermal_transmittance
+                .get::<watt_per_square_meter_kelvin>(),
+            1.70860,
+            epsilon = 1e-5
+        );
+
+        // The same wall split into two layers, one given by R-value
+        let split_wall = characteristics(1);
+        assert_abs_diff_eq!(
+            split_wall.decrement_factor.get::<ratio>(),
+            wall.decrement_factor.get::<ratio>(),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            split_wall.time_lag.get::<second>(),
+            wall.time_lag.get::<second>(),
+            epsilon = 1e-3
+        );
+
+        let window = characteristics(2);
+        assert_abs_diff_eq!(window.decrement_factor.get::<ratio>(), 1.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(window.time_lag.get::<second>(), 0.0, epsilon = 1e-9);
+    }
+
     #[test]
     fn boundary_layer_heat_capacity() {
         let bl = BoundaryLayer {
-            material: Rc::new(Material {
-                name: "water".into(),
-                thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.598),
-                specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
-                    4180.0,
-                ),
-                density: MassDensity::new::<kilogram_per_cubic_meter>(997.0),
-            }),
-            thickness: Length::new::<meter>(1.0),
+            content: LayerContent::Material {
+                material: Rc::new(Material {
+                    name: "water".into(),
+                    thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.598),
+                    specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
+                        4180.0,
+                    ),
+                    density: MassDensity::new::<kilogram_per_cubic_meter>(997.0),
+                    emissivity: Ratio::new::<ratio>(0.9),
+                }),
+                thickness: Length::new::<meter>(1.0),
+            },
             following_marker: None,
         };
         assert_abs_diff_eq!(
@@ -1235,15 +6918,18 @@ mod tests {
     #[test]
     fn boundary_layer_conductance() {
         let bl = BoundaryLayer {
-            material: Rc::new(Material {
-                name: "water".into(),
-                thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.598),
-                specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
-                    4180.0,
-                ),
-                density: MassDensity::new::<kilogram_per_cubic_meter>(997.0),
-            }),
-            thickness: Length::new::<meter>(2.0),
+            content: LayerContent::Material {
+                material: Rc::new(Material {
+                    name: "water".into(),
+                    thermal_conductivity: ThermalConductivity::new::<watt_per_meter_kelvin>(0.598),
+                    specific_heat_capacity: SpecificHeatCapacity::new::<joule_per_kilogram_kelvin>(
+                        4180.0,
+                    ),
+                    density: MassDensity::new::<kilogram_per_cubic_meter>(997.0),
+                    emissivity: Ratio::new::<ratio>(0.9),
+                }),
+                thickness: Length::new::<meter>(2.0),
+            },
             following_marker: None,
         };
         assert_eq!(
@@ -1257,9 +6943,9 @@ mod tests {
         r#"{
             materials: {
                 air: {
-                    thermal_conductivity: 0,
-                    specific_heat_capacity: 0,
-                    density: 0,
+                    thermal_conductivity: 0.026,
+                    specific_heat_capacity: 1012,
+                    density: 1.199,
                 },
                 brick: {
                     thermal_conductivity: 1,
@@ -1309,35 +6995,51 @@ mod tests {
                     "a".into(),
                     Rc::new(Zone {
                         name: "a".into(),
-                        volume: Some(Volume::new::<cubic_meter>(123.0))
+                        volume: Some(Volume::new::<cubic_meter>(123.0)),
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
                     })
                 ),
                 (
                     "b".into(),
                     Rc::new(Zone {
                         name: "b".into(),
-                        volume: Some(Volume::new::<cubic_meter>(234.0))
+                        volume: Some(Volume::new::<cubic_meter>(234.0)),
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
                     })
                 ),
                 (
                     "outside".into(),
                     Rc::new(Zone {
                         name: "outside".into(),
-                        volume: None
+                        volume: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
                     })
                 ),
                 (
                     "ground".into(),
                     Rc::new(Zone {
                         name: "ground".into(),
-                        volume: None
+                        volume: None,
+                        internal_mass: None,
+                        air_changes_per_hour: None,
+                        air: None,
+                        internal_gains: None,
                     })
                 ),
             ])
         );
 
         assert_eq!(model.boundaries.len(), 2);
-        assert_matches!(&model.boundaries[1].boundary_type.as_ref(), &BoundaryType::Layered{ name, layers: _, initial_marker: _ } => {
+        assert_matches!(&model.boundaries[1].boundary_type.as_ref(), &BoundaryType::Layered{ name, .. } => {
             assert_eq!(name, "wall");
         });
     }
@@ -1354,6 +7056,7 @@ mod tests {
                         456.0,
                     ),
                     density: MassDensity::new::<kilogram_per_cubic_meter>(789.0),
+                    emissivity: Ratio::new::<ratio>(0.9),
                 }),
             ),
             (
@@ -1365,6 +7068,7 @@ mod tests {
                         56.0,
                     ),
                     density: MassDensity::new::<kilogram_per_cubic_meter>(89.0),
+                    emissivity: Ratio::new::<ratio>(0.9),
                 }),
             ),
         ])
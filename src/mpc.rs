@@ -0,0 +1,383 @@
+use na::{DMatrix, DVector};
+use petgraph::graph::NodeIndex;
+use uom::si::{
+    f64::{Power, ThermodynamicTemperature, Time},
+    power::watt,
+    thermodynamic_temperature::kelvin,
+};
+
+use crate::state_space::{DiscreteStateSpace, StateSpace, StateSpaceInput};
+
+/// Settings of a `Controller`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControllerConfig {
+    /// Length of one control step, the power is constant during a step
+    pub step: Time,
+    /// Number of steps the controller plans ahead
+    pub horizon: usize,
+    /// Desired temperature of the zone
+    pub setpoint: ThermodynamicTemperature,
+    /// Lowest comfortable temperature of the zone
+    pub comfort_min: ThermodynamicTemperature,
+    /// Highest comfortable temperature of the zone
+    pub comfort_max: ThermodynamicTemperature,
+    /// Power of the heater, the controller chooses between zero and this value
+    pub max_power: Power,
+    /// Cost of one kelvin squared of distance from the setpoint, per step
+    pub setpoint_weight: f64,
+    /// Cost of one kelvin squared outside of the comfort bounds, per step
+    pub comfort_weight: f64,
+    /// Cost of running the heater at full power for one step
+    pub energy_weight: f64,
+}
+
+/// Model predictive controller of the heating power of a single zone.
+///
+/// Each call plans the power for the whole horizon by solving a quadratic program
+/// over the discretized state space model of the building and the first step
+/// of the plan is applied. The comfort bounds are soft (violating them is expensive,
+/// not impossible), so the problem has a solution even when the zone can't be heated
+/// to the comfortable temperature in time.
+#[derive(Clone, Debug)]
+pub struct Controller {
+    config: ControllerConfig,
+    system: DiscreteStateSpace,
+    /// Column of the input matrix with the heat flow into the zone
+    heating_input: usize,
+    /// Zone temperature at each step of the horizon caused by heating at full power
+    /// during each step (horizon x horizon, lower triangular)
+    heating_response: DMatrix<f64>,
+}
+
+impl Controller {
+    /// Create a controller of a zone of the system of `RcNetwork::state_space`,
+    /// `zone` is the node index of the zone in the network.
+    pub fn new(
+        state_space: &StateSpace,
+        zone: NodeIndex,
+        config: ControllerConfig,
+    ) -> anyhow::Result<Self> {
+        if config.horizon == 0 {
+            anyhow::bail!("Horizon must have at least one step");
+        }
+        if config.max_power.get::<watt>() <= 0.0 {
+            anyhow::bail!("Heater power must be positive, got {:?}", config.max_power);
+        }
+        if config.comfort_min > config.comfort_max {
+            anyhow::bail!(
+                "Comfort minimum {:?} is above the maximum {:?}",
+                config.comfort_min,
+                config.comfort_max
+            );
+        }
+        let zone_state = *state_space
+            .state_indices
+            .get(&zone)
+            .ok_or_else(|| anyhow::anyhow!("Node {:?} is not a state of the system", zone))?;
+        let heating_input = state_space
+            .inputs
+            .iter()
+            .position(|input| *input == StateSpaceInput::HeatFlow(zone))
+            .ok_or_else(|| anyhow::anyhow!("Node {:?} has no heat flow input", zone))?;
+
+        let mut c = DMatrix::zeros(1, state_space.state_count());
+        c[(0, zone_state)] = 1.0;
+        let system = state_space.discretize(config.step, c)?;
+
+        let horizon = config.horizon;
+        let mut heating_response = DMatrix::zeros(horizon, horizon);
+        let mut state = system.b.column(heating_input) * config.max_power.get::<watt>();
+        for delay in 0..horizon {
+            let temperature = (&system.c * &state)[0];
+            for step in 0..horizon - delay {
+                heating_response[(step + delay, step)] = temperature;
+            }
+            state = &system.a * state;
+        }
+
+        Ok(Controller {
+            config,
+            system,
+            heating_input,
+            heating_response,
+        })
+    }
+
+    /// Plan the heating power for each step of the horizon.
+    ///
+    /// Arguments:
+    /// * `state` - current temperatures of the states of the system [K]
+    /// * `inputs` - inputs of the system without the heating, assumed constant
+    ///   over the horizon (outside temperature [K], solar gains [W], ...)
+    pub fn plan(&self, state: &DVector<f64>, inputs: &DVector<f64>) -> anyhow::Result<Vec<Power>> {
+        if state.len() != self.system.state_count() {
+            anyhow::bail!(
+                "State has {} elements, expected {}",
+                state.len(),
+                self.system.state_count()
+            );
+        }
+        if inputs.len() != self.system.input_count() {
+            anyhow::bail!(
+                "Inputs have {} elements, expected {}",
+                inputs.len(),
+                self.system.input_count()
+            );
+        }
+        let config = &self.config;
+        let horizon = config.horizon;
+
+        // Zone temperature at each step without heating
+        let forced = &self.system.b * inputs;
+        let mut free_state = state.clone();
+        let free_response = DVector::from_fn(horizon, |_, _| {
+            free_state = &self.system.a * &free_state + &forced;
+            (&self.system.c * &free_state)[0]
+        });
+
+        // Variables are the heating power as a fraction of the maximum and the comfort
+        // bound violation for each step, cost is 1/2 x' P x + q' x
+        let g = &self.heating_response;
+        let identity = DMatrix::<f64>::identity(horizon, horizon);
+        let mut p = DMatrix::zeros(2 * horizon, 2 * horizon);
+        p.view_mut((0, 0), (horizon, horizon))
+            .copy_from(&(g.transpose() * g * (2.0 * config.setpoint_weight)));
+        p.view_mut((horizon, horizon), (horizon, horizon))
+            .copy_from(&(&identity * (2.0 * config.comfort_weight)));
+        let setpoint_error = free_response.add_scalar(-config.setpoint.get::<kelvin>());
+        let mut q = DVector::zeros(2 * horizon);
+        q.rows_mut(0, horizon).copy_from(
+            &(g.transpose() * setpoint_error * (2.0 * config.setpoint_weight))
+                .add_scalar(config.energy_weight),
+        );
+
+        // 0 <= power <= 1, violation >= 0,
+        // comfort_min <= temperature + violation, temperature - violation <= comfort_max
+        let mut a = DMatrix::zeros(4 * horizon, 2 * horizon);
+        a.view_mut((0, 0), (horizon, horizon)).copy_from(&identity);
+        a.view_mut((horizon, horizon), (horizon, horizon))
+            .copy_from(&identity);
+        a.view_mut((2 * horizon, 0), (horizon, horizon))
+            .copy_from(g);
+        a.view_mut((2 * horizon, horizon), (horizon, horizon))
+            .copy_from(&identity);
+        a.view_mut((3 * horizon, 0), (horizon, horizon))
+            .copy_from(g);
+        a.view_mut((3 * horizon, horizon), (horizon, horizon))
+            .copy_from(&-&identity);
+        let mut lower = DVector::from_element(4 * horizon, f64::NEG_INFINITY);
+        let mut upper = DVector::from_element(4 * horizon, f64::INFINITY);
+        upper.rows_mut(0, horizon).fill(1.0);
+        lower.rows_mut(0, 2 * horizon).fill(0.0);
+        lower
+            .rows_mut(2 * horizon, horizon)
+            .copy_from(&(-&free_response).add_scalar(config.comfort_min.get::<kelvin>()));
+        upper
+            .rows_mut(3 * horizon, horizon)
+            .copy_from(&(-&free_response).add_scalar(config.comfort_max.get::<kelvin>()));
+
+        let solution = solve_qp(&p, &q, &a, &lower, &upper)?;
+        Ok(solution
+            .rows(0, horizon)
+            .iter()
+            .map(|fraction| config.max_power * fraction.clamp(0.0, 1.0))
+            .collect())
+    }
+
+    /// Heating power for the next step, the first step of `plan`.
+    pub fn next_power(&self, state: &DVector<f64>, inputs: &DVector<f64>) -> anyhow::Result<Power> {
+        Ok(self.plan(state, inputs)?[0])
+    }
+
+    /// Column of the system inputs with the heat flow into the controlled zone.
+    pub fn heating_input(&self) -> usize {
+        self.heating_input
+    }
+}
+
+/// Minimize `1/2 x' P x + q' x` subject to `lower <= A x <= upper`, with `P` positive
+/// semidefinite, using the alternating direction method of multipliers
+/// with the step size adaptation and termination criteria of OSQP.
+fn solve_qp(
+    p: &DMatrix<f64>,
+    q: &DVector<f64>,
+    a: &DMatrix<f64>,
+    lower: &DVector<f64>,
+    upper: &DVector<f64>,
+) -> anyhow::Result<DVector<f64>> {
+    const SIGMA: f64 = 1e-6;
+    const ALPHA: f64 = 1.6;
+    const ABSOLUTE_TOLERANCE: f64 = 1e-6;
+    const RELATIVE_TOLERANCE: f64 = 1e-6;
+    const MAX_ITERATIONS: usize = 20000;
+    /// How often the step size is adapted to balance the residuals
+    const ADAPTATION_INTERVAL: usize = 25;
+
+    let n = p.nrows();
+    let project = |z: DVector<f64>| z.zip_zip_map(lower, upper, |z, l, u| z.max(l).min(u));
+    let factorize = |rho: f64| {
+        (p + DMatrix::identity(n, n) * SIGMA + a.transpose() * a * rho)
+            .cholesky()
+            .ok_or_else(|| anyhow::anyhow!("QP matrix is not positive definite"))
+    };
+
+    let mut rho = 0.1;
+    let mut kkt = factorize(rho)?;
+    let mut x = DVector::zeros(n);
+    let mut z = project(DVector::zeros(a.nrows()));
+    let mut y = DVector::zeros(a.nrows());
+    for iteration in 1..=MAX_ITERATIONS {
+        let x_tilde = kkt.solve(&(&x * SIGMA - q + a.transpose() * (&z * rho - &y)));
+        let z_tilde = a * &x_tilde;
+        x = &x_tilde * ALPHA + &x * (1.0 - ALPHA);
+        let z_relaxed = &z_tilde * ALPHA + &z * (1.0 - ALPHA);
+        let z_next = project(&z_relaxed + &y / rho);
+        y += (z_relaxed - &z_next) * rho;
+        z = z_next;
+
+        let ax = a * &x;
+        let px = p * &x;
+        let aty = a.transpose() * &y;
+        let primal_residual = (&ax - &z).amax();
+        let dual_residual = (&px + q + &aty).amax();
+        let primal_scale = ax.amax().max(z.amax());
+        let dual_scale = px.amax().max(aty.amax()).max(q.amax());
+        if primal_residual <= ABSOLUTE_TOLERANCE + RELATIVE_TOLERANCE * primal_scale
+            && dual_residual <= ABSOLUTE_TOLERANCE + RELATIVE_TOLERANCE * dual_scale
+        {
+            return Ok(x);
+        }
+
+        if iteration % ADAPTATION_INTERVAL == 0 {
+            // The variables and constraints are of the order of one (fractions of power,
+            // kelvins), scales near zero would blow up the step size
+            let ratio = (primal_residual / primal_scale.max(1.0))
+                / (dual_residual / dual_scale.max(1.0)).max(f64::MIN_POSITIVE);
+            let new_rho = (rho * ratio.sqrt()).clamp(1e-6, 1e6);
+            if !(0.2..=5.0).contains(&(new_rho / rho)) {
+                rho = new_rho;
+                kkt = factorize(rho)?;
+            }
+        }
+    }
+    anyhow::bail!(
+        "QP solver did not converge in {} iterations",
+        MAX_ITERATIONS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::rc_network::RcNetwork;
+    use approx::assert_abs_diff_eq;
+    use uom::si::{thermodynamic_temperature::degree_celsius, time::hour};
+
+    fn celsius(value: f64) -> ThermodynamicTemperature {
+        ThermodynamicTemperature::new::<degree_celsius>(value)
+    }
+
+    /// Single zone with a wall to outside, all states at `temperature`,
+    /// outside at 10 °C.
+    fn controller(setpoint: f64, temperature: f64) -> (Controller, DVector<f64>, DVector<f64>) {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let network = RcNetwork::try_from(&model).unwrap();
+        let state_space = network.state_space();
+        let controller = Controller::new(
+            &state_space,
+            network.zone_indices["a"],
+            ControllerConfig {
+                step: Time::new::<hour>(1.0),
+                horizon: 12,
+                setpoint: celsius(setpoint),
+                comfort_min: celsius(setpoint - 1.0),
+                comfort_max: celsius(setpoint + 4.0),
+                max_power: Power::new::<watt>(2000.0),
+                setpoint_weight: 1.0,
+                comfort_weight: 100.0,
+                energy_weight: 0.1,
+            },
+        )
+        .unwrap();
+        let state = DVector::from_element(
+            state_space.state_count(),
+            celsius(temperature).get::<kelvin>(),
+        );
+        let inputs = DVector::from_iterator(
+            state_space.input_count(),
+            state_space.inputs.iter().map(|input| match input {
+                StateSpaceInput::Temperature(_) => celsius(10.0).get::<kelvin>(),
+                StateSpaceInput::HeatFlow(_) => 0.0,
+            }),
+        );
+        (controller, state, inputs)
+    }
+
+    #[test]
+    fn no_heating_above_setpoint() {
+        let (controller, state, inputs) = controller(18.0, 22.0);
+        let plan = controller.plan(&state, &inputs).unwrap();
+        for power in plan {
+            assert_abs_diff_eq!(power.get::<watt>(), 0.0, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn heats_cold_zone() {
+        let (controller, state, inputs) = controller(21.0, 15.0);
+        let plan = controller.plan(&state, &inputs).unwrap();
+        assert!(plan[0].get::<watt>() > 1000.0, "{:?}", plan[0]);
+        for power in plan {
+            assert!(power.get::<watt>() > -1e-3);
+            assert!(power.get::<watt>() < 2000.0 + 1e-3);
+        }
+    }
+
+    #[test]
+    fn qp_bound_active() {
+        // Minimize (x0 - 2)^2 + (x1 + 1)^2 with x0 <= 1, x1 >= 0 and x0 + x1 >= 0.5
+        let solution = solve_qp(
+            &DMatrix::identity(2, 2),
+            &DVector::from_vec(vec![-2.0, 1.0]),
+            &DMatrix::from_row_slice(3, 2, &[1.0, 0.0, 0.0, 1.0, 1.0, 1.0]),
+            &DVector::from_vec(vec![f64::NEG_INFINITY, 0.0, 0.5]),
+            &DVector::from_vec(vec![1.0, f64::INFINITY, f64::INFINITY]),
+        )
+        .unwrap();
+        assert_abs_diff_eq!(solution[0], 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(solution[1], 0.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn wrong_dimensions() {
+        let (controller, state, _) = controller(21.0, 15.0);
+        let message = format!(
+            "{}",
+            controller
+                .next_power(&state, &DVector::zeros(1))
+                .unwrap_err()
+        );
+        message
+            .find("Inputs")
+            .expect("Error message should name the wrong vector");
+    }
+}
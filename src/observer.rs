@@ -0,0 +1,201 @@
+use na::{DMatrix, DVector};
+
+use crate::state_space::DiscreteStateSpace;
+
+/// Kalman filter estimating the full state of a discrete state-space system
+/// (e.g. including wall temperatures) from measurements of its outputs
+/// (e.g. zone temperatures).
+#[derive(Clone, Debug)]
+pub struct KalmanObserver {
+    system: DiscreteStateSpace,
+    /// Covariance of the process noise (states x states)
+    process_noise: DMatrix<f64>,
+    /// Covariance of the measurement noise (outputs x outputs)
+    measurement_noise: DMatrix<f64>,
+    state: DVector<f64>,
+    covariance: DMatrix<f64>,
+}
+
+impl KalmanObserver {
+    /// Create an observer starting from an initial estimate.
+    ///
+    /// Larger `process_noise` makes the estimate follow the measurements more closely,
+    /// larger `measurement_noise` makes it trust the model more.
+    pub fn new(
+        system: DiscreteStateSpace,
+        initial_state: DVector<f64>,
+        initial_covariance: DMatrix<f64>,
+        process_noise: DMatrix<f64>,
+        measurement_noise: DMatrix<f64>,
+    ) -> anyhow::Result<Self> {
+        let n = system.state_count();
+        let m = system.output_count();
+        if initial_state.len() != n {
+            anyhow::bail!(
+                "Initial state has {} elements, expected {}",
+                initial_state.len(),
+                n
+            );
+        }
+        for (matrix, size, label) in [
+            (&initial_covariance, n, "Initial covariance"),
+            (&process_noise, n, "Process noise covariance"),
+            (&measurement_noise, m, "Measurement noise covariance"),
+        ] {
+            if matrix.shape() != (size, size) {
+                anyhow::bail!(
+                    "{} is {}x{}, expected {}x{}",
+                    label,
+                    matrix.nrows(),
+                    matrix.ncols(),
+                    size,
+                    size
+                );
+            }
+        }
+
+        Ok(KalmanObserver {
+            system,
+            process_noise,
+            measurement_noise,
+            state: initial_state,
+            covariance: initial_covariance,
+        })
+    }
+
+    /// Propagate the estimate through the model by one step with the given inputs.
+    pub fn predict(&mut self, input: &DVector<f64>) -> anyhow::Result<()> {
+        if input.len() != self.system.input_count() {
+            anyhow::bail!(
+                "Input has {} elements, expected {}",
+                input.len(),
+                self.system.input_count()
+            );
+        }
+        let a = &self.system.a;
+        self.state = a * &self.state + &self.system.b * input;
+        self.covariance = a * &self.covariance * a.transpose() + &self.process_noise;
+        Ok(())
+    }
+
+    /// Correct the estimate using a measurement of the outputs.
+    pub fn update(&mut self, measurement: &DVector<f64>) -> anyhow::Result<()> {
+        if measurement.len() != self.system.output_count() {
+            anyhow::bail!(
+                "Measurement has {} elements, expected {}",
+                measurement.len(),
+                self.system.output_count()
+            );
+        }
+        let c = &self.system.c;
+        let innovation = measurement - c * &self.state;
+        let innovation_covariance = c * &self.covariance * c.transpose() + &self.measurement_noise;
+        let inverse = innovation_covariance
+            .try_inverse()
+            .ok_or_else(|| anyhow::anyhow!("Innovation covariance is singular"))?;
+        let gain = &self.covariance * c.transpose() * inverse;
+
+        self.state += &gain * innovation;
+        // Joseph form, keeps the covariance symmetric and positive definite
+        let i_kc =
+            DMatrix::identity(self.system.state_count(), self.system.state_count()) - &gain * c;
+        self.covariance = &i_kc * &self.covariance * i_kc.transpose()
+            + &gain * &self.measurement_noise * gain.transpose();
+        Ok(())
+    }
+
+    /// Predict with the inputs of the previous step, then correct with the new measurement.
+    pub fn step(&mut self, input: &DVector<f64>, measurement: &DVector<f64>) -> anyhow::Result<()> {
+        self.predict(input)?;
+        self.update(measurement)
+    }
+
+    /// Current estimate of the state.
+    pub fn state(&self) -> &DVector<f64> {
+        &self.state
+    }
+
+    /// Covariance of the current state estimate.
+    pub fn covariance(&self) -> &DMatrix<f64> {
+        &self.covariance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    /// Two thermal nodes coupled together, heat input into the first one,
+    /// only the first one is measured.
+    fn two_state_system() -> DiscreteStateSpace {
+        DiscreteStateSpace::new(
+            DMatrix::from_row_slice(2, 2, &[0.9, 0.1, 0.05, 0.93]),
+            DMatrix::from_row_slice(2, 1, &[0.5, 0.0]),
+            DMatrix::from_row_slice(1, 2, &[1.0, 0.0]),
+        )
+        .unwrap()
+    }
+
+    fn observer(measurement_noise: f64) -> KalmanObserver {
+        KalmanObserver::new(
+            two_state_system(),
+            DVector::from_vec(vec![15.0, 15.0]),
+            DMatrix::identity(2, 2) * 100.0,
+            DMatrix::identity(2, 2) * 0.01,
+            DMatrix::from_element(1, 1, measurement_noise),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn converges_to_hidden_state() {
+        let system = two_state_system();
+        let mut observer = observer(0.1);
+        let mut true_state = DVector::from_vec(vec![20.0, 5.0]);
+        let input = DVector::from_vec(vec![1.0]);
+
+        for _ in 0..200 {
+            true_state = &system.a * &true_state + &system.b * &input;
+            let measurement = &system.c * &true_state;
+            observer.step(&input, &measurement).unwrap();
+        }
+
+        assert_abs_diff_eq!(observer.state()[0], true_state[0], epsilon = 1e-3);
+        // The second state is never measured directly
+        assert_abs_diff_eq!(observer.state()[1], true_state[1], epsilon = 1e-3);
+    }
+
+    #[test]
+    fn noisy_measurement_ignored() {
+        let mut trusting = observer(0.01);
+        let mut distrusting = observer(1e12);
+        let input = DVector::from_vec(vec![0.0]);
+        let measurement = DVector::from_vec(vec![100.0]);
+
+        trusting.step(&input, &measurement).unwrap();
+        distrusting.step(&input, &measurement).unwrap();
+
+        // Pure model prediction
+        assert_abs_diff_eq!(distrusting.state()[0], 15.0, epsilon = 1e-6);
+        assert!(trusting.state()[0] > 99.0);
+    }
+
+    #[test]
+    fn wrong_dimensions() {
+        let message = format!(
+            "{}",
+            KalmanObserver::new(
+                two_state_system(),
+                DVector::from_vec(vec![15.0, 15.0]),
+                DMatrix::identity(2, 2),
+                DMatrix::identity(2, 2),
+                DMatrix::identity(2, 2),
+            )
+            .unwrap_err()
+        );
+        message
+            .find("Measurement noise")
+            .expect("Error message should name the wrong matrix");
+    }
+}
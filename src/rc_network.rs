@@ -1,24 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
 
 use itertools::Itertools;
 use multimap::MultiMap;
+use na::{DMatrix, DVector};
 use petgraph::{
     graph::{NodeIndex, UnGraph},
+    unionfind::UnionFind,
     visit::{EdgeRef, IntoNodeReferences, NodeIndexable},
 };
+use serde::{Deserialize, Serialize};
 use uom::si::{
-    f64::{Area, HeatCapacity, HeatTransfer, ThermalConductance, Velocity},
+    f64::{
+        Angle, Area, HeatCapacity, HeatFluxDensity, HeatTransfer, Power, Ratio, ThermalConductance,
+        ThermodynamicTemperature, Time, Velocity,
+    },
     heat_capacity::joule_per_kelvin,
     heat_transfer::watt_per_square_meter_kelvin,
+    power::watt,
+    ratio::ratio,
     thermal_conductance::watt_per_kelvin,
+    thermodynamic_temperature::kelvin,
+    time::second,
     velocity::meter_per_second,
 };
 
-use crate::model::{BoundaryLayer, BoundaryType, Model};
+use crate::model::{Boundary, BoundaryLayer, BoundaryType, Model};
+use crate::simulation::{Inputs, LinearSystem, Simulation};
+use crate::state_space::{StateSpace, StateSpaceInput};
+use crate::tools::psychrometrics::dew_point;
 use crate::tools::reciprocal_sum;
+use crate::tools::sun::TiltedIrradiance;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Node {
     pub zone_name: Option<String>,
     pub marker: Option<(String, String)>,
@@ -26,12 +43,19 @@ pub struct Node {
     pub boundary_group_index: Option<usize>, // Groups edges belonging to the same boundary, only for display
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Edge {
     pub conductance: ThermalConductance,
 }
 
-#[derive(Clone, Debug)]
+/// Version of the binary format written by `RcNetwork::save`.
+/// Must be incremented whenever the serialized structures change.
+const BINARY_FORMAT_VERSION: u32 = 4;
+
+/// Stefan-Boltzmann constant [W/m²K⁴]
+const STEFAN_BOLTZMANN: f64 = 5.670374419e-8;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RcNetwork {
     pub graph: UnGraph<Node, Edge>,
 
@@ -41,11 +65,81 @@ pub struct RcNetwork {
 
     /// Mapping of (zone name, marker) pairs to node indices
     pub marker_indices: MultiMap<(String, String), NodeIndex>,
+
+    /// Mapping of heat source names to the nodes they inject power into
+    pub source_indices: HashMap<String, NodeIndex>,
+
+    /// Labels of node groups, indexed by `Node::boundary_group_index`.
+    /// Boundary type name and the zone pair, only for display
+    pub boundary_groups: Vec<String>,
+
+    /// Boundary group index of each boundary of the model the network was built from,
+    /// in the order of `Model::boundaries`. `None` for simple boundaries, which don't
+    /// have nodes of their own.
+    pub boundary_group_indices: Vec<Option<usize>>,
+}
+
+/// Boundary between two zones, as it appears in the network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundaryRef {
+    /// Boundary group index of the nodes of a layered boundary,
+    /// `None` for simple boundaries, which are a single edge between the zones.
+    pub boundary_group_index: Option<usize>,
+    /// Names of the two zones, sorted
+    pub zones: [String; 2],
+    /// Steady state conductance between the two zones through this boundary
+    pub conductance: ThermalConductance,
+}
+
+/// Heat flows injected into nodes (e.g. by heat sources) during one time step
+/// of `RcNetwork::simulate`. Nodes that are not listed get no heat.
+pub type ControlStep = HashMap<NodeIndex, Power>;
+
+/// Node of a boundary colder than the dew point of the air of a zone next to the boundary,
+/// where water vapor from the zone may condense.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CondensationRisk {
+    pub node: NodeIndex,
+    /// Zone whose air would condense
+    pub zone: String,
+    pub temperature: ThermodynamicTemperature,
+    /// Dew point of the zone air
+    pub dew_point: ThermodynamicTemperature,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct DotDisplayer<'a> {
     rc_network: &'a RcNetwork,
+    /// Fill exterior nodes (with infinite heat capacity) with a different color
+    highlight_exterior: bool,
+    /// Number of decimal places of conductances, `None` to print them in full
+    precision: Option<usize>,
+}
+
+impl<'a> DotDisplayer<'a> {
+    /// Color exterior nodes (outside, ground, ...) differently from the zones.
+    pub fn highlight_exterior(self) -> Self {
+        DotDisplayer {
+            highlight_exterior: true,
+            ..self
+        }
+    }
+
+    /// Print conductances with a fixed number of decimal places.
+    pub fn precision(self, digits: usize) -> Self {
+        DotDisplayer {
+            precision: Some(digits),
+            ..self
+        }
+    }
+
+    fn node_attributes(&self, node: &Node) -> &'static str {
+        if self.highlight_exterior && !node.heat_capacity.is_finite() {
+            ", style = filled, fillcolor = lightblue"
+        } else {
+            ""
+        }
+    }
 }
 
 impl<'a> fmt::Display for DotDisplayer<'a> {
@@ -69,25 +163,49 @@ impl<'a> fmt::Display for DotDisplayer<'a> {
 
         writeln!(f, "graph {{")?;
         for (index, node) in ungrouped_nodes {
-            writeln!(f, "    node_{} [ label = \"{}\" ]", index, node)?;
+            writeln!(
+                f,
+                "    node_{} [ label = \"{}\"{} ]",
+                index,
+                node,
+                self.node_attributes(node)
+            )?;
         }
 
         for (index, group) in grouped_nodes.iter().enumerate() {
             writeln!(f, "    subgraph cluster_{} {{", index)?;
+            if let Some(label) = self.rc_network.boundary_groups.get(index) {
+                writeln!(f, "        label = \"{}\"", label)?;
+            }
             for (index, node) in group {
-                writeln!(f, "        node_{} [ label = \"{}\" ]", index, node)?;
+                writeln!(
+                    f,
+                    "        node_{} [ label = \"{}\"{} ]",
+                    index,
+                    node,
+                    self.node_attributes(node)
+                )?;
             }
             writeln!(f, "    }}")?;
         }
 
         for edge in g.edge_references() {
-            writeln!(
+            write!(
                 f,
-                "    node_{} -- node_{} [ label = \"{}\" ]",
+                "    node_{} -- node_{} [ label = \"",
                 g.to_index(edge.source()),
                 g.to_index(edge.target()),
-                edge.weight()
-            )?
+            )?;
+            match self.precision {
+                Some(digits) => write!(
+                    f,
+                    "{:.*} W/K",
+                    digits,
+                    edge.weight().conductance.get::<watt_per_kelvin>()
+                )?,
+                None => write!(f, "{}", edge.weight())?,
+            }
+            writeln!(f, "\" ]")?
         }
 
         writeln!(f, "}}")
@@ -114,300 +232,3478 @@ impl fmt::Display for Edge {
 
 impl<'a> RcNetwork {
     pub fn to_dot(&'a self) -> DotDisplayer<'a> {
-        DotDisplayer { rc_network: self }
+        DotDisplayer {
+            rc_network: self,
+            highlight_exterior: false,
+            precision: None,
+        }
     }
 }
 
-impl From<&Model> for RcNetwork {
-    fn from(model: &Model) -> Self {
-        let mut graph = UnGraph::default();
-        let zone_indices: HashMap<_, _> = model
-            .zones
-            .iter()
-            .map(|(name, zone)| {
-                (
-                    name.clone(),
-                    graph.add_node(Node {
-                        zone_name: Some(name.clone()),
-                        marker: None,
-                        heat_capacity: zone.heat_capacity(&model.air),
-                        boundary_group_index: None,
-                    }),
-                )
-            })
-            .collect();
-        let mut marker_indices: MultiMap<_, _> = MultiMap::new();
+impl RcNetwork {
+    /// Write the graph in the dot format (see `to_dot`) to a file, replacing its contents.
+    pub fn write_dot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "{}", self.to_dot())?;
+        writer.flush()
+    }
+}
 
-        let mut boundary_group_index = 0;
-        for boundary in model.boundaries.iter() {
-            let z1 = zone_indices[&boundary.zones[0].name];
-            let z2 = zone_indices[&boundary.zones[1].name];
-            let convection_conductance =
-                air_convection_conductance(Velocity::new::<meter_per_second>(0.0)) * boundary.area;
+impl RcNetwork {
+    /// Export the network as GraphML, for tools like Gephi or NetworkX.
+    /// Nodes have `zone_name`, `marker` (as `zone/marker`) and `heat_capacity` [J/K]
+    /// attributes, edges have `conductance` [W/K].
+    pub fn to_graphml(&self) -> String {
+        let g = &self.graph;
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        for (id, domain, name, attribute_type) in [
+            ("zone_name", "node", "zone_name", "string"),
+            ("marker", "node", "marker", "string"),
+            ("heat_capacity", "node", "heat_capacity", "double"),
+            ("conductance", "edge", "conductance", "double"),
+        ] {
+            out.push_str(&format!(
+                "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>\n",
+                id, domain, name, attribute_type
+            ));
+        }
+        out.push_str("  <graph id=\"rc_network\" edgedefault=\"undirected\">\n");
 
-            match boundary.boundary_type.as_ref() {
-                BoundaryType::Layered {
-                    name: _,
-                    layers,
-                    initial_marker,
-                } => {
-                    let builder = LayeredBoundaryBuilder {
-                        zone1_node: z1,
-                        zone2_node: z2,
-                        zone1_name: &boundary.zones[0].name,
-                        layers,
-                        initial_marker,
-                        area: boundary.area,
-                        convection_conductance,
-                        group_index: boundary_group_index,
-                    };
-                    builder.add_layered_boundary_nodes(&mut graph, &mut marker_indices);
-                    boundary_group_index += 1;
-                }
-                BoundaryType::Simple { name: _, u, g: _ } => {
-                    graph.add_edge(
-                        z1,
-                        z2,
-                        Edge {
-                            conductance: reciprocal_sum!(
-                                convection_conductance,
-                                *u * boundary.area,
-                                convection_conductance
-                            ),
-                        },
-                    );
-                }
+        for (index, node) in g.node_references() {
+            out.push_str(&format!("    <node id=\"n{}\">\n", g.to_index(index)));
+            if let Some(name) = &node.zone_name {
+                out.push_str(&format!(
+                    "      <data key=\"zone_name\">{}</data>\n",
+                    xml_escape(name)
+                ));
             }
+            if let Some((zone, marker)) = &node.marker {
+                out.push_str(&format!(
+                    "      <data key=\"marker\">{}/{}</data>\n",
+                    xml_escape(zone),
+                    xml_escape(marker)
+                ));
+            }
+            out.push_str(&format!(
+                "      <data key=\"heat_capacity\">{}</data>\n",
+                xml_double(node.heat_capacity.get::<joule_per_kelvin>())
+            ));
+            out.push_str("    </node>\n");
         }
 
-        RcNetwork {
-            graph,
-            zone_indices,
-            marker_indices,
+        for edge in g.edge_references() {
+            out.push_str(&format!(
+                "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">\n",
+                edge.id().index(),
+                g.to_index(edge.source()),
+                g.to_index(edge.target())
+            ));
+            out.push_str(&format!(
+                "      <data key=\"conductance\">{}</data>\n",
+                xml_double(edge.weight().conductance.get::<watt_per_kelvin>())
+            ));
+            out.push_str("    </edge>\n");
+        }
+
+        out.push_str("  </graph>\n");
+        out.push_str("</graphml>\n");
+        out
+    }
+
+    /// Export the network as two CSV tables, for spreadsheets or MATLAB.
+    /// Returns nodes (index, zone name, marker as `zone/marker`, heat capacity [J/K],
+    /// boundary group) and edges (source and target node index, conductance [W/K]),
+    /// both with a header row. Missing values are empty, infinite heat capacity is `inf`.
+    pub fn to_csv(&self) -> (String, String) {
+        let g = &self.graph;
+        let mut nodes =
+            String::from("index,zone_name,marker,heat_capacity_J_per_K,boundary_group\n");
+        for (index, node) in g.node_references() {
+            nodes.push_str(&format!(
+                "{},{},{},{},{}\n",
+                g.to_index(index),
+                csv_escape(node.zone_name.as_deref().unwrap_or("")),
+                csv_escape(
+                    &node
+                        .marker
+                        .as_ref()
+                        .map(|(zone, marker)| format!("{}/{}", zone, marker))
+                        .unwrap_or_default()
+                ),
+                node.heat_capacity.get::<joule_per_kelvin>(),
+                node.boundary_group_index
+                    .map(|group| group.to_string())
+                    .unwrap_or_default()
+            ));
+        }
+
+        let mut edges = String::from("source_index,target_index,conductance_W_per_K\n");
+        for edge in g.edge_references() {
+            edges.push_str(&format!(
+                "{},{},{}\n",
+                g.to_index(edge.source()),
+                g.to_index(edge.target()),
+                edge.weight().conductance.get::<watt_per_kelvin>()
+            ));
         }
+        (nodes, edges)
     }
 }
 
-/// Helper for adding nodes and edges of a layered boundary.
-/// This exists only to hold the arguments in a slightly organized fashion
-/// (and avoid Clippy complaints about too many arguments being passed to a function).
-struct LayeredBoundaryBuilder<'a> {
-    zone1_node: NodeIndex,
-    zone2_node: NodeIndex,
-    zone1_name: &'a str,
-    layers: &'a [BoundaryLayer],
-    initial_marker: &'a Option<String>,
-    area: Area,
-    convection_conductance: ThermalConductance,
-    group_index: usize,
+/// Quote a CSV field if it contains separators, quotes or line breaks.
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
 }
 
-impl<'a> LayeredBoundaryBuilder<'a> {
-    /// Add nodes corresponding to the boundary layers to the graph, including connections,
-    /// collects marked nodes.
-    fn add_layered_boundary_nodes(
-        &self,
-        graph: &mut UnGraph<Node, Edge>,
-        marker_indices: &mut MultiMap<(String, String), NodeIndex>,
-    ) {
-        let mut current_node = self.add_boundary_node(
-            self.layers.first().unwrap().heat_capacity(self.area) / 2.0,
-            self.zone1_node,
-            self.convection_conductance,
-            self.initial_marker,
-            graph,
-            marker_indices,
-        );
+/// Format a number as XML Schema double, which spells infinity as `INF`.
+fn xml_double(value: f64) -> String {
+    if value.is_infinite() {
+        if value > 0.0 { "INF" } else { "-INF" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
 
-        for (layer1, layer2) in self.layers.iter().tuple_windows() {
-            current_node = self.add_boundary_node(
-                (layer1.heat_capacity(self.area) + layer2.heat_capacity(self.area)) / 2.0,
-                current_node,
-                layer1.conductance(self.area),
-                &layer1.following_marker,
-                graph,
-                marker_indices,
-            );
+/// Escape characters with special meaning in XML text and attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl RcNetwork {
+    /// Groups of nodes connected by edges, each sorted by node index.
+    /// Components are ordered by their first node.
+    pub fn connected_components(&self) -> Vec<Vec<NodeIndex>> {
+        let mut union_find = UnionFind::new(self.graph.node_count());
+        for edge in self.graph.edge_references() {
+            union_find.union(edge.source().index(), edge.target().index());
         }
 
-        let last_layer = self.layers.last().unwrap();
+        let mut components: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+        for index in self.graph.node_indices() {
+            components
+                .entry(union_find.find(index.index()))
+                .or_default()
+                .push(index);
+        }
+        components
+            .into_values()
+            .sorted_by_key(|component| component[0])
+            .collect()
+    }
 
-        current_node = self.add_boundary_node(
-            last_layer.heat_capacity(self.area) / 2.0,
-            current_node,
-            last_layer.conductance(self.area),
-            &last_layer.following_marker,
-            graph,
-            marker_indices,
-        );
+    /// Check that every node is connected to an exterior node (with infinite heat
+    /// capacity, like outside or ground).
+    /// Otherwise the temperatures of the disconnected nodes have no steady state
+    /// and solving for it fails.
+    pub fn assert_reaches_exterior(&self) -> anyhow::Result<()> {
+        let isolated: Vec<_> = self
+            .connected_components()
+            .into_iter()
+            .filter(|component| {
+                component
+                    .iter()
+                    .all(|index| self.graph[*index].heat_capacity.is_finite())
+            })
+            .map(|component| {
+                component
+                    .iter()
+                    .map(|index| self.node_description(*index))
+                    .sorted()
+                    .join(", ")
+            })
+            .collect();
+        if !isolated.is_empty() {
+            anyhow::bail!(
+                "Nodes not connected to outside or ground: [{}]",
+                isolated.join("], [")
+            );
+        }
+        Ok(())
+    }
 
-        graph.add_edge(
-            current_node,
-            self.zone2_node,
-            Edge {
-                conductance: self.convection_conductance,
-            },
-        );
+    /// Zone name or marker of a node for messages, node index if it has neither.
+    fn node_description(&self, index: NodeIndex) -> String {
+        let node = &self.graph[index];
+        match (&node.zone_name, &node.marker) {
+            (Some(zone), _) => zone.clone(),
+            (None, Some((zone, marker))) => format!("{}/{}", zone, marker),
+            (None, None) => format!("node_{}", index.index()),
+        }
     }
 
-    /// Add a new node on a boundary between two nodes, process its markers and connect
-    /// it to the graph.
-    /// This is used both for the nodes within the boundary and for nodes between the
-    /// boundary and the zone.
-    fn add_boundary_node(
+    /// Equilibrium temperatures of all nodes, with the given nodes held at fixed
+    /// temperatures and no heat gains.
+    /// Nodes with infinite heat capacity that are not fixed are treated as free nodes.
+    /// See `Simulation::steady_state` for a version with zone names and heat gains.
+    pub fn solve_steady_state(
         &self,
-        heat_capacity: HeatCapacity,
-        prev_node: NodeIndex,
-        thermal_conductance: ThermalConductance,
-        marker: &Option<String>,
-        graph: &mut UnGraph<Node, Edge>,
-        marker_indices: &mut MultiMap<(String, String), NodeIndex>,
-    ) -> NodeIndex {
-        let marker = marker
-            .as_ref()
-            .map(|marker| (self.zone1_name.into(), marker.clone()));
+        fixed: &HashMap<NodeIndex, ThermodynamicTemperature>,
+    ) -> anyhow::Result<HashMap<NodeIndex, ThermodynamicTemperature>> {
+        let mut fixed_temperatures = vec![None; self.graph.node_count()];
+        for (index, temperature) in fixed.iter() {
+            let Some(fixed_temperature) = fixed_temperatures.get_mut(index.index()) else {
+                anyhow::bail!("Node {} is not in the network", index.index());
+            };
+            *fixed_temperature = Some(temperature.get::<kelvin>());
+        }
 
-        let node = graph.add_node(Node {
-            zone_name: None,
-            marker: marker.clone(),
-            heat_capacity,
-            boundary_group_index: Some(self.group_index),
-        });
+        let system = LinearSystem::assemble(self, &fixed_temperatures);
+        let solution = system.solve()?;
+        Ok(self
+            .graph
+            .node_indices()
+            .zip(system.temperatures(&fixed_temperatures, &solution))
+            .collect())
+    }
 
-        if let Some(marker) = marker {
-            marker_indices.insert(marker, node);
+    /// Linear continuous time model of the network for model predictive control.
+    /// Every node with finite heat capacity is a state, inputs are temperatures of
+    /// the nodes with infinite heat capacity followed by heat flows into zone and marker
+    /// nodes, both in node index order.
+    pub fn state_space(&self) -> StateSpace {
+        let mut state_indices = HashMap::new();
+        let mut temperature_inputs = Vec::new();
+        let mut heat_flow_inputs = Vec::new();
+        for (index, node) in self.graph.node_references() {
+            if node.heat_capacity.is_finite() {
+                state_indices.insert(index, state_indices.len());
+                if node.zone_name.is_some() || node.marker.is_some() {
+                    heat_flow_inputs.push(StateSpaceInput::HeatFlow(index));
+                }
+            } else {
+                temperature_inputs.push(StateSpaceInput::Temperature(index));
+            }
         }
+        let mut inputs = temperature_inputs;
+        inputs.extend(heat_flow_inputs);
+        let input_indices: HashMap<_, _> = inputs
+            .iter()
+            .enumerate()
+            .map(|(column, input)| (*input, column))
+            .collect();
 
-        graph.add_edge(
-            prev_node,
-            node,
-            Edge {
-                conductance: thermal_conductance,
-            },
-        );
+        let state_count = state_indices.len();
+        let mut a = DMatrix::zeros(state_count, state_count);
+        let mut b = DMatrix::zeros(state_count, inputs.len());
+        let heat_capacity =
+            |index: NodeIndex| self.graph[index].heat_capacity.get::<joule_per_kelvin>();
+        for edge in self.graph.edge_references() {
+            let g = edge.weight().conductance.get::<watt_per_kelvin>();
+            for (this, other) in [
+                (edge.source(), edge.target()),
+                (edge.target(), edge.source()),
+            ] {
+                let Some(&row) = state_indices.get(&this) else {
+                    continue;
+                };
+                let coefficient = g / heat_capacity(this);
+                a[(row, row)] -= coefficient;
+                match state_indices.get(&other) {
+                    Some(&column) => a[(row, column)] += coefficient,
+                    None => {
+                        b[(row, input_indices[&StateSpaceInput::Temperature(other)])] += coefficient
+                    }
+                }
+            }
+        }
+        for (input, column) in input_indices.iter() {
+            if let StateSpaceInput::HeatFlow(index) = input {
+                b[(state_indices[index], *column)] = heat_capacity(*index).recip();
+            }
+        }
 
-        node
+        StateSpace {
+            a,
+            b,
+            state_indices,
+            inputs,
+        }
     }
-}
 
-/// Return thermal conductance of a surface in air.
-/// Based on https://www.engineeringtoolbox.com/convective-heat-transfer-d_430.html
-pub fn air_convection_conductance(wind_speed: Velocity) -> HeatTransfer {
-    // The calculation is done outside of UOM, because the coefficient units would be awkward
-    let wind_speed = wind_speed.get::<meter_per_second>();
-    HeatTransfer::new::<watt_per_square_meter_kelvin>(
-        12.12 - 1.16 * wind_speed + 11.6 * wind_speed.sqrt(),
-    )
-}
+    /// Time constants of the network (`-1/λ` for the negative eigenvalues λ of the
+    /// state matrix, see `state_space`), sorted from the fastest.
+    /// Explicit stepping is only stable for time steps shorter than twice the first one.
+    /// Modes that never decay (isolated parts of the network) are left out.
+    /// All nodes with finite heat capacity must have positive heat capacity.
+    pub fn time_constants(&self) -> Vec<Time> {
+        let state_space = self.state_space();
+        let mut sqrt_heat_capacities = DVector::zeros(state_space.state_count());
+        for (index, state) in state_space.state_indices.iter() {
+            sqrt_heat_capacities[*state] = self.graph[*index]
+                .heat_capacity
+                .get::<joule_per_kelvin>()
+                .sqrt();
+        }
+        // A = -C⁻¹ G, with diagonal heat capacities C and symmetric conductances G,
+        // is similar to the symmetric matrix C^½ A C^-½ = -C^-½ G C^-½
+        let symmetric = DMatrix::from_fn(
+            state_space.state_count(),
+            state_space.state_count(),
+            |row, column| {
+                state_space.a[(row, column)] * sqrt_heat_capacities[row]
+                    / sqrt_heat_capacities[column]
+            },
+        );
+        let eigenvalues = symmetric.symmetric_eigen().eigenvalues;
+        // Zero eigenvalues may come out slightly negative due to rounding
+        let tolerance = 1e-12 * eigenvalues.amax();
+        eigenvalues
+            .iter()
+            .filter(|eigenvalue| **eigenvalue < -tolerance)
+            .map(|eigenvalue| Time::new::<second>(-eigenvalue.recip()))
+            .sorted_by(|a, b| a.value.total_cmp(&b.value))
+            .collect()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use approx::{assert_abs_diff_eq, assert_ulps_eq};
-    use test_case::test_case;
-    use test_strategy::proptest;
+    /// Solar heat gains through glazing, as heat injected into the zone nodes.
+    /// Each window is given with the solar irradiance on its plane, calculated by
+    /// `tools::sun::calculate_tilted_irradiance_components` for the tilt and azimuth
+    /// of the window, and the incidence angle of the sun beam (`tools::sun::incidence_angle_at`).
+    /// The transmitted power is given by `Boundary::transmitted_solar_power`, summed
+    /// over windows of each zone.
+    /// Only simple boundaries between outside and an interior zone transmit sunlight,
+    /// other boundaries (layered, between two interior zones, to ground) contribute nothing.
+    pub fn solar_heat_gains<'b, I>(&self, windows: I) -> anyhow::Result<HashMap<NodeIndex, Power>>
+    where
+        I: IntoIterator<Item = (&'b Boundary, TiltedIrradiance, Option<Angle>)>,
+    {
+        let mut gains = HashMap::new();
+        for (boundary, irradiance, incidence_angle) in windows {
+            let Some((zone, power)) =
+                boundary.transmitted_solar_power(&irradiance, incidence_angle)
+            else {
+                continue;
+            };
+            let index = self
+                .zone_indices
+                .get(&zone.name)
+                .ok_or_else(|| anyhow::anyhow!("Zone {:?} is not in the network", zone.name))?;
+            *gains
+                .entry(*index)
+                .or_insert_with(|| Power::new::<watt>(0.0)) += power;
+        }
+        Ok(gains)
+    }
 
-    // The test values are taken from the illustration graph in the source articles,
-    // converted to pairs using web plot digitizer. The plot appears to be very imprecise,
-    // forcing this test to have very error high tolerance.
-    #[test_case( 3.0, 27.4; "example1")]
-    #[test_case( 8.0, 35.2; "example2")]
-    #[test_case(13.0, 39.3; "example3")]
-    #[test_case(18.0, 41.6; "example4")]
-    fn air_convection_conductance_example(air_velocity: f64, expected_heat_transfer: f64) {
-        let conductance =
-            air_convection_conductance(Velocity::new::<meter_per_second>(air_velocity));
-        assert_abs_diff_eq!(
-            conductance.get::<watt_per_square_meter_kelvin>(),
-            expected_heat_transfer,
-            epsilon = 1.5
-        );
+    /// Sunlight absorbed by opaque exterior walls (the sol-air effect), as heat injected
+    /// into the outermost layer node of the walls.
+    /// Each wall is given by its index in `Model::boundaries` of the model the network
+    /// was built from, with the solar irradiance on its plane (see `solar_heat_gains`).
+    /// The absorbed power is `solar_absorptance * area * irradiance`.
+    /// Only layered boundaries between outside and an interior zone absorb sunlight.
+    pub fn absorbed_solar_gains<I>(
+        &self,
+        model: &Model,
+        walls: I,
+    ) -> anyhow::Result<HashMap<NodeIndex, Power>>
+    where
+        I: IntoIterator<Item = (usize, HeatFluxDensity)>,
+    {
+        let mut gains = HashMap::new();
+        for (boundary_index, irradiance) in walls {
+            let boundary = model.boundaries.get(boundary_index).ok_or_else(|| {
+                anyhow::anyhow!("Boundary {} is not in the model", boundary_index)
+            })?;
+            let BoundaryType::Layered {
+                solar_absorptance, ..
+            } = boundary.boundary_type.as_ref()
+            else {
+                continue;
+            };
+            let interior_zone = match [
+                boundary.zones[0].name.as_str(),
+                boundary.zones[1].name.as_str(),
+            ] {
+                ["outside", _] => &boundary.zones[1],
+                [_, "outside"] => &boundary.zones[0],
+                _ => continue,
+            };
+            if interior_zone.volume.is_none() {
+                continue;
+            }
+            let group = self
+                .boundary_group_indices
+                .get(boundary_index)
+                .copied()
+                .flatten()
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Boundary {} has no nodes in the network", boundary_index)
+                })?;
+            let outside = self.zone_indices["outside"];
+            let surface = self
+                .graph
+                .neighbors(outside)
+                .find(|index| self.graph[*index].boundary_group_index == Some(group))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Boundary {} doesn't touch outside", boundary_index)
+                })?;
+            *gains
+                .entry(surface)
+                .or_insert_with(|| Power::new::<watt>(0.0)) +=
+                *solar_absorptance * boundary.area * irradiance;
+        }
+        Ok(gains)
     }
 
-    #[proptest]
-    fn graph_node_count(model: Model) {
-        let mut expected_node_count = model.zones.len();
-        let mut expected_edge_count = 0;
-        for boundary in model.boundaries.iter() {
-            match boundary.boundary_type.as_ref() {
-                BoundaryType::Simple {
-                    name: _,
-                    u: _,
-                    g: _,
-                } => expected_edge_count += 1,
-                BoundaryType::Layered {
-                    name: _,
-                    layers,
-                    initial_marker: _,
-                } => {
-                    expected_node_count += layers.len() + 1;
-                    expected_edge_count += layers.len() + 2;
+    /// Replace parallel edges (several edges between the same pair of nodes, e.g. multiple
+    /// simple boundaries between the same two zones) by a single edge with the sum of
+    /// their conductances. Layered boundaries are never parallel, because each of them
+    /// has its own nodes.
+    /// Edges keep the order of their first occurrence, but edge indices change.
+    pub fn merge_parallel_edges(&mut self) {
+        let mut merged: Vec<(NodeIndex, NodeIndex, Edge)> = Vec::new();
+        let mut positions: HashMap<(NodeIndex, NodeIndex), usize> = HashMap::new();
+        for edge in self.graph.edge_references() {
+            let (source, target) = (edge.source(), edge.target());
+            let key = if source <= target {
+                (source, target)
+            } else {
+                (target, source)
+            };
+            match positions.get(&key) {
+                Some(&position) => merged[position].2.conductance += edge.weight().conductance,
+                None => {
+                    positions.insert(key, merged.len());
+                    merged.push((source, target, *edge.weight()));
                 }
             }
         }
 
-        let net: RcNetwork = (&model).into();
+        self.graph.clear_edges();
+        for (source, target, edge) in merged {
+            self.graph.add_edge(source, target, edge);
+        }
+    }
+
+    /// Reduce the number of nodes of layered boundaries to at most `max_nodes_per_boundary`,
+    /// by repeatedly merging the pair of adjacent boundary nodes with the smallest resistance
+    /// between them. Merged node has the sum of the heat capacities and the resistance
+    /// between the pair is split between its two remaining edges, so the total heat
+    /// capacity and the end to end conductance of each boundary stay the same.
+    /// Two marked nodes are never merged together, a merged node keeps the marker of its
+    /// marked part, so boundaries with many markers may keep more nodes.
+    ///
+    /// Node and edge indices change, the zone and marker mappings are updated.
+    /// Fails if `max_nodes_per_boundary` is zero, a boundary needs at least one node.
+    pub fn reduce(&self, max_nodes_per_boundary: usize) -> anyhow::Result<RcNetwork> {
+        if max_nodes_per_boundary == 0 {
+            anyhow::bail!("Boundary needs at least one node");
+        }
+
+        let mut groups: BTreeMap<usize, Vec<NodeIndex>> = BTreeMap::new();
+        for (index, node) in self.graph.node_references() {
+            if let Some(group) = node.boundary_group_index {
+                groups.entry(group).or_default().push(index);
+            }
+        }
+        let chains: Vec<_> = groups
+            .into_values()
+            .filter(|nodes| nodes.len() > max_nodes_per_boundary)
+            .filter_map(|nodes| self.boundary_chain(nodes))
+            .collect();
+        let chain_nodes: HashSet<_> = chains
+            .iter()
+            .flat_map(|chain| chain.nodes.iter().copied())
+            .collect();
+
+        let mut graph = UnGraph::default();
+        let mut new_indices = HashMap::new();
+        for (index, node) in self.graph.node_references() {
+            if !chain_nodes.contains(&index) {
+                new_indices.insert(index, graph.add_node(node.clone()));
+            }
+        }
+        for edge in self.graph.edge_references() {
+            if let (Some(&source), Some(&target)) = (
+                new_indices.get(&edge.source()),
+                new_indices.get(&edge.target()),
+            ) {
+                graph.add_edge(source, target, *edge.weight());
+            }
+        }
+
+        let mut marker_indices = MultiMap::new();
+        for chain in chains {
+            let (nodes, resistances) = chain.reduced(&self.graph, max_nodes_per_boundary);
+            let mut previous = new_indices[&chain.ends[0]];
+            for (node, resistance) in nodes.into_iter().zip(resistances.iter()) {
+                let marker = node.marker.clone();
+                let index = graph.add_node(node);
+                if let Some(marker) = marker {
+                    marker_indices.insert(marker, index);
+                }
+                graph.add_edge(
+                    previous,
+                    index,
+                    Edge {
+                        conductance: ThermalConductance::new::<watt_per_kelvin>(resistance.recip()),
+                    },
+                );
+                previous = index;
+            }
+            graph.add_edge(
+                previous,
+                new_indices[&chain.ends[1]],
+                Edge {
+                    conductance: ThermalConductance::new::<watt_per_kelvin>(
+                        resistances.last().unwrap().recip(),
+                    ),
+                },
+            );
+        }
+        for (marker, indices) in self.marker_indices.iter_all() {
+            for index in indices {
+                if let Some(&index) = new_indices.get(index) {
+                    marker_indices.insert(marker.clone(), index);
+                }
+            }
+        }
+
+        Ok(RcNetwork {
+            graph,
+            zone_indices: self
+                .zone_indices
+                .iter()
+                .map(|(name, index)| (name.clone(), new_indices[index]))
+                .collect(),
+            marker_indices,
+            source_indices: self
+                .source_indices
+                .iter()
+                .map(|(name, index)| (name.clone(), new_indices[index]))
+                .collect(),
+            boundary_groups: self.boundary_groups.clone(),
+            boundary_group_indices: self.boundary_group_indices.clone(),
+        })
+    }
+
+    /// Find the path through nodes of a layered boundary by following its edges, starting
+    /// from the lowest node index that touches a node outside of the boundary. The order
+    /// of creation doesn't matter (soil nodes are created before the layers).
+    /// Returns `None` if the nodes don't form a simple path between two other nodes
+    /// (e.g. internal mass, which only has one edge).
+    fn boundary_chain(&self, nodes: Vec<NodeIndex>) -> Option<BoundaryChain> {
+        let members: HashSet<_> = nodes.iter().copied().collect();
+        if nodes
+            .iter()
+            .any(|index| self.graph.edges(*index).count() != 2)
+        {
+            return None;
+        }
+        let first_edge = nodes.iter().sorted().find_map(|index| {
+            self.graph
+                .edges(*index)
+                .find(|edge| !members.contains(&edge.target()))
+        })?;
+
+        let mut path = Vec::with_capacity(nodes.len());
+        let mut edges = vec![first_edge.id()];
+        let mut current = first_edge.source();
+        let last_end = loop {
+            path.push(current);
+            let next = self
+                .graph
+                .edges(current)
+                .find(|edge| edge.id() != *edges.last().unwrap())?;
+            edges.push(next.id());
+            if !members.contains(&next.target()) {
+                break next.target();
+            }
+            current = next.target();
+            if path.contains(&current) {
+                return None;
+            }
+        };
+        if path.len() != nodes.len() {
+            return None;
+        }
+        let ends = [first_edge.target(), last_end];
+        Some(BoundaryChain {
+            nodes: path,
+            ends,
+            edges,
+        })
+    }
+
+    /// Advance node temperatures by a single time step, using explicit (forward) Euler
+    /// integration. `temperatures` must contain every node, indexed by the node index
+    /// of the graph.
+    /// Nodes in `fixed` and nodes with infinite heat capacity keep their temperature.
+    ///
+    /// The integration is only stable if `dt` is shorter than the smallest time constant
+    /// of the free nodes, `heat_capacity / (sum of conductances of the node's edges)`,
+    /// otherwise the temperatures oscillate and diverge.
+    /// Thin boundary layers have short time constants, `Simulation::step` uses implicit
+    /// integration that is stable for any time step.
+    ///
+    /// Fails if `temperatures` doesn't have one value per node.
+    pub fn step(
+        &self,
+        temperatures: &mut [ThermodynamicTemperature],
+        dt: Time,
+        fixed: &HashSet<NodeIndex>,
+    ) -> anyhow::Result<()> {
+        self.check_temperature_count(temperatures.len())?;
+        let current = kelvins(temperatures);
+        let derivatives = self.derivatives(&current, fixed, &ControlStep::new());
+        let dt = dt.get::<second>();
+        for ((temperature, current), derivative) in
+            temperatures.iter_mut().zip(current).zip(derivatives)
+        {
+            *temperature = ThermodynamicTemperature::new::<kelvin>(current + derivative * dt);
+        }
+        Ok(())
+    }
+
+    /// Advance node temperatures by a single time step, using the classic 4th order
+    /// Runge-Kutta method. Arguments are the same as for `step`.
+    ///
+    /// The stability limit is only slightly larger than for `step` (about 2.8 instead
+    /// of 2 times the smallest time constant), but the error shrinks with the 4th power
+    /// of `dt`, so much longer steps give the same accuracy.
+    pub fn step_rk4(
+        &self,
+        temperatures: &mut [ThermodynamicTemperature],
+        dt: Time,
+        fixed: &HashSet<NodeIndex>,
+    ) -> anyhow::Result<()> {
+        self.step_rk4_with_heat_flows(temperatures, dt, fixed, &ControlStep::new())
+    }
+
+    /// `step_rk4` with heat flows injected into nodes during the step.
+    fn step_rk4_with_heat_flows(
+        &self,
+        temperatures: &mut [ThermodynamicTemperature],
+        dt: Time,
+        fixed: &HashSet<NodeIndex>,
+        heat_flows: &ControlStep,
+    ) -> anyhow::Result<()> {
+        self.check_temperature_count(temperatures.len())?;
+        self.check_node_indices(heat_flows.keys())?;
+        let current = kelvins(temperatures);
+        let dt = dt.get::<second>();
+        let shifted = |derivatives: &[f64], time: f64| -> Vec<f64> {
+            current
+                .iter()
+                .zip(derivatives)
+                .map(|(temperature, derivative)| temperature + derivative * time)
+                .collect()
+        };
+
+        let k1 = self.derivatives(&current, fixed, heat_flows);
+        let k2 = self.derivatives(&shifted(&k1, dt / 2.0), fixed, heat_flows);
+        let k3 = self.derivatives(&shifted(&k2, dt / 2.0), fixed, heat_flows);
+        let k4 = self.derivatives(&shifted(&k3, dt), fixed, heat_flows);
+
+        for (i, temperature) in temperatures.iter_mut().enumerate() {
+            *temperature = ThermodynamicTemperature::new::<kelvin>(
+                current[i] + (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]) * dt / 6.0,
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail unless there is one temperature per node.
+    fn check_temperature_count(&self, count: usize) -> anyhow::Result<()> {
+        if count != self.graph.node_count() {
+            anyhow::bail!(
+                "Temperatures have {} elements, expected one per node, {}",
+                count,
+                self.graph.node_count()
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if any of the node indices is not in the graph.
+    fn check_node_indices<'a>(
+        &self,
+        indices: impl IntoIterator<Item = &'a NodeIndex>,
+    ) -> anyhow::Result<()> {
+        if let Some(index) = indices
+            .into_iter()
+            .find(|index| index.index() >= self.graph.node_count())
+        {
+            anyhow::bail!(
+                "Node {} is not in the network of {} nodes",
+                index.index(),
+                self.graph.node_count()
+            );
+        }
+        Ok(())
+    }
+
+    /// Temperatures of the nodes of a marker, picked from temperatures of all nodes
+    /// indexed by node index (as used by `step`).
+    /// There is one node per boundary that has the marker, in the order of boundaries.
+    /// Returns `None` if the zone has no such marker, or if `temperatures` don't reach
+    /// its nodes (e.g. temperatures of a reduced network).
+    pub fn marker_temperature(
+        &self,
+        temperatures: &[ThermodynamicTemperature],
+        zone: &str,
+        marker: &str,
+    ) -> Option<Vec<ThermodynamicTemperature>> {
+        let indices = self
+            .marker_indices
+            .get_vec(&(zone.to_string(), marker.to_string()))?;
+        indices
+            .iter()
+            .map(|index| temperatures.get(index.index()).copied())
+            .collect()
+    }
+
+    /// Run a simulation with a sequence of heat flows injected into nodes, one
+    /// `ControlStep` per time step of length `dt`, using `step_rk4`.
+    /// `initial` are temperatures of all nodes indexed by node index, nodes in `fixed`
+    /// are held at the given temperatures.
+    ///
+    /// Returns temperatures of all nodes at the start and after each step,
+    /// `inputs.len() + 1` entries.
+    /// Fails if `initial` doesn't have one value per node, or if `inputs` or `fixed`
+    /// refer to nodes that are not in the network.
+    pub fn simulate(
+        &self,
+        initial: &[ThermodynamicTemperature],
+        inputs: &[ControlStep],
+        dt: Time,
+        fixed: &HashMap<NodeIndex, ThermodynamicTemperature>,
+    ) -> anyhow::Result<Vec<Vec<ThermodynamicTemperature>>> {
+        self.check_temperature_count(initial.len())?;
+        self.check_node_indices(fixed.keys())?;
+        let mut temperatures = initial.to_vec();
+        for (index, temperature) in fixed.iter() {
+            temperatures[index.index()] = *temperature;
+        }
+        let fixed: HashSet<_> = fixed.keys().copied().collect();
+
+        let mut history = Vec::with_capacity(inputs.len() + 1);
+        history.push(temperatures.clone());
+        for heat_flows in inputs {
+            self.step_rk4_with_heat_flows(&mut temperatures, dt, &fixed, heat_flows)?;
+            history.push(temperatures.clone());
+        }
+        Ok(history)
+    }
+
+    /// Rates of change of node temperatures [K/s] given their temperatures [K],
+    /// from the heat flowing through the edges and injected into the nodes.
+    /// Nodes in `fixed` and nodes with infinite heat capacity don't change.
+    fn derivatives(
+        &self,
+        temperatures: &[f64],
+        fixed: &HashSet<NodeIndex>,
+        heat_flows: &ControlStep,
+    ) -> Vec<f64> {
+        let mut net_power = vec![0.0; temperatures.len()];
+        for (index, heat_flow) in heat_flows.iter() {
+            net_power[index.index()] += heat_flow.get::<watt>();
+        }
+        for edge in self.graph.edge_references() {
+            let source = edge.source().index();
+            let target = edge.target().index();
+            let flow = edge.weight().conductance.get::<watt_per_kelvin>()
+                * (temperatures[source] - temperatures[target]);
+            net_power[source] -= flow;
+            net_power[target] += flow;
+        }
+
+        for (index, node) in self.graph.node_references() {
+            let heat_capacity = node.heat_capacity.get::<joule_per_kelvin>();
+            if fixed.contains(&index) || !heat_capacity.is_finite() {
+                net_power[index.index()] = 0.0;
+            } else {
+                net_power[index.index()] /= heat_capacity;
+            }
+        }
+        net_power
+    }
+
+    /// Total heat capacity of all nodes except the ones with infinite heat capacity
+    /// (outside and ground), i.e. of the zones, their internal mass and the boundaries.
+    /// A rough measure of how slowly the building responds, see also `time_constants`.
+    pub fn total_heat_capacity(&self) -> HeatCapacity {
+        self.graph
+            .node_weights()
+            .map(|node| node.heat_capacity)
+            .filter(|heat_capacity| heat_capacity.is_finite())
+            .sum()
+    }
+
+    /// Heat loss coefficient of the building: steady state heat flow from all interior
+    /// zones to outside and ground per kelvin of temperature difference.
+    /// Fails if the steady state can't be solved, when some nodes are not connected
+    /// to any zone.
+    pub fn heat_loss_coefficient(&self) -> anyhow::Result<ThermalConductance> {
+        let is_exterior = |index: NodeIndex| !self.graph[index].heat_capacity.is_finite();
+        let heat_flows = self.unit_envelope_heat_flows()?;
+
+        let heat_flow: Power = self
+            .graph
+            .edge_references()
+            .filter_map(
+                |edge| match (is_exterior(edge.source()), is_exterior(edge.target())) {
+                    (true, false) => Some(-heat_flows[edge.id().index()]),
+                    (false, true) => Some(heat_flows[edge.id().index()]),
+                    _ => None,
+                },
+            )
+            .sum();
+        // The temperature difference is 1 K
+        Ok(ThermalConductance::new::<watt_per_kelvin>(
+            heat_flow.get::<watt>(),
+        ))
+    }
+
+    /// Heat loss coefficient of a single zone: steady state heat flow from the zone to
+    /// outside and ground per kelvin of temperature difference, through all boundaries
+    /// of the zone.
+    /// All interior zones are at the same temperature, so no heat flows between them
+    /// and their losses are separated. The sum over all interior zones is the
+    /// `heat_loss_coefficient` of the building.
+    pub fn zone_heat_loss_coefficient(&self, zone: &str) -> anyhow::Result<ThermalConductance> {
+        let index = *self
+            .zone_indices
+            .get(zone)
+            .ok_or_else(|| anyhow::anyhow!("Zone {:?} not found", zone))?;
+        if !self.graph[index].heat_capacity.is_finite() {
+            anyhow::bail!("Zone {:?} is exterior", zone);
+        }
+        let heat_flows = self.unit_envelope_heat_flows()?;
+        let heat_flow: Power = self
+            .graph
+            .edges(index)
+            .map(|edge| {
+                let flow = heat_flows[edge.id().index()];
+                if self.graph.edge_endpoints(edge.id()).unwrap().0 == index {
+                    flow
+                } else {
+                    -flow
+                }
+            })
+            .sum();
+        // The temperature difference is 1 K
+        Ok(ThermalConductance::new::<watt_per_kelvin>(
+            heat_flow.get::<watt>(),
+        ))
+    }
+
+    /// Initial temperatures of all nodes, indexed by the node index of the graph.
+    /// Zones in `overrides` get their temperature, other zones get `default`.
+    /// Temperatures of boundary nodes are interpolated between the two zones the boundary
+    /// connects, weighted by thermal resistance (the steady state profile of the boundary).
+    pub fn initial_temperatures(
+        &self,
+        default: ThermodynamicTemperature,
+        overrides: &HashMap<String, ThermodynamicTemperature>,
+    ) -> anyhow::Result<Vec<ThermodynamicTemperature>> {
+        let mut temperatures = vec![default; self.graph.node_count()];
+        for (zone, temperature) in overrides.iter() {
+            let index = self
+                .zone_indices
+                .get(zone)
+                .ok_or_else(|| anyhow::anyhow!("Zone {:?} is not in the network", zone))?;
+            temperatures[index.index()] = *temperature;
+        }
+
+        let graph = &self.graph;
+        let mut visited = vec![false; graph.node_count()];
+        for start in graph.node_indices() {
+            let Some(group) = graph[start].boundary_group_index else {
+                continue;
+            };
+            if visited[start.index()] {
+                continue;
+            }
+
+            // Walk the chain of nodes of this boundary, starting from its first node
+            // (the one adjacent to a zone node), collecting cumulative resistances.
+            let neighbor_zone = |node| {
+                graph
+                    .edges(node)
+                    .find(|edge| graph[edge.target()].zone_name.is_some())
+            };
+            let Some(first_edge) = neighbor_zone(start) else {
+                continue;
+            };
+            let zone1 = first_edge.target();
+            let mut resistance = first_edge.weight().conductance.recip();
+            let mut chain = vec![(start, resistance)];
+            visited[start.index()] = true;
+            let mut current = start;
+            while let Some(edge) = graph.edges(current).find(|edge| {
+                graph[edge.target()].boundary_group_index == Some(group)
+                    && !visited[edge.target().index()]
+            }) {
+                current = edge.target();
+                visited[current.index()] = true;
+                resistance += edge.weight().conductance.recip();
+                chain.push((current, resistance));
+            }
+            let Some(last_edge) = graph
+                .edges(current)
+                .find(|edge| graph[edge.target()].zone_name.is_some() && edge.target() != zone1)
+                .or_else(|| neighbor_zone(current))
+            else {
+                continue;
+            };
+            let zone2 = last_edge.target();
+            let total_resistance = resistance + last_edge.weight().conductance.recip();
+
+            let t1 = temperatures[zone1.index()].get::<kelvin>();
+            let t2 = temperatures[zone2.index()].get::<kelvin>();
+            for (node, resistance) in chain {
+                let fraction = (resistance / total_resistance).value;
+                temperatures[node.index()] =
+                    ThermodynamicTemperature::new::<kelvin>(t1 + (t2 - t1) * fraction);
+            }
+        }
+
+        Ok(temperatures)
+    }
+
+    /// Equivalent conductance of all boundaries, thermal bridges and air exchange
+    /// between two zones, e.g. the combined U·A of several windows to outside.
+    /// Edges along each boundary are in series, boundaries between the same zones
+    /// are in parallel. Paths through other zones are not included, nodes leading
+    /// nowhere (internal mass) carry no heat in steady state.
+    pub fn combined_conductance(
+        &self,
+        zone_a: &str,
+        zone_b: &str,
+    ) -> anyhow::Result<ThermalConductance> {
+        let zone_index = |zone: &str| {
+            self.zone_indices
+                .get(zone)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("Zone {:?} not found", zone))
+        };
+        let (a, b) = (zone_index(zone_a)?, zone_index(zone_b)?);
+
+        let mut combined = ThermalConductance::new::<watt_per_kelvin>(0.0);
+        for edge in self.graph.edges(a) {
+            let mut previous = edge.id();
+            let mut current = edge.target();
+            let mut series = edge.weight().conductance;
+            while self.graph[current].zone_name.is_none() {
+                let mut next_edges = self
+                    .graph
+                    .edges(current)
+                    .filter(|next| next.id() != previous);
+                let (Some(next), None) = (next_edges.next(), next_edges.next()) else {
+                    if self.graph.edges(current).count() > 2 {
+                        anyhow::bail!(
+                            "Boundary between zones {:?} and {:?} branches",
+                            zone_a,
+                            zone_b
+                        );
+                    }
+                    break;
+                };
+                series = reciprocal_sum!(series, next.weight().conductance);
+                previous = next.id();
+                current = next.target();
+            }
+            if current == b {
+                combined += series;
+            }
+        }
+        Ok(combined)
+    }
+
+    /// Steady state heat flows along the edges, with all interior zones at 1 K
+    /// and outside and ground at 0 K.
+    fn unit_envelope_heat_flows(&self) -> anyhow::Result<Vec<Power>> {
+        let is_exterior = |index: NodeIndex| !self.graph[index].heat_capacity.is_finite();
+        let inputs = Inputs {
+            boundary_temperatures: self
+                .zone_indices
+                .iter()
+                .map(|(name, index)| {
+                    let temperature = if is_exterior(*index) { 0.0 } else { 1.0 };
+                    (
+                        name.clone(),
+                        ThermodynamicTemperature::new::<kelvin>(temperature),
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Ok(Simulation::steady_state(self, &inputs)?.edge_heat_flows())
+    }
+
+    /// Design heat load: steady state heating power needed to keep the zones at their
+    /// setpoints when it is `design_outside_temperature` outside.
+    /// All zones with infinite heat capacity (outside and ground) are held at the design
+    /// temperature, which is conservative for the ground. Zones without a setpoint
+    /// (unheated spaces) settle at their equilibrium temperature.
+    /// `ventilation` gives ventilation heat loss coefficients of the heated zones
+    /// (see `psychrometrics::ventilation_conductance`).
+    /// Air exchange of zones with `air_changes_per_hour` is an edge of the network and
+    /// is already part of the load, `ventilation` is added on top of it. Give only the air
+    /// exchange that the model doesn't include, or the same air is counted twice.
+    ///
+    /// Returns the heating power of each zone with a setpoint and their total.
+    pub fn design_heat_load(
+        &self,
+        design_outside_temperature: ThermodynamicTemperature,
+        indoor_setpoints: &HashMap<String, ThermodynamicTemperature>,
+        ventilation: &HashMap<String, ThermalConductance>,
+    ) -> anyhow::Result<(HashMap<String, Power>, Power)> {
+        if let Some(zone) = ventilation
+            .keys()
+            .find(|zone| !indoor_setpoints.contains_key(*zone))
+        {
+            anyhow::bail!("Zone {:?} has ventilation, but no setpoint", zone);
+        }
+        let mut boundary_temperatures = indoor_setpoints.clone();
+        for (name, index) in self.zone_indices.iter() {
+            if !self.graph[*index].heat_capacity.is_finite() {
+                if indoor_setpoints.contains_key(name) {
+                    anyhow::bail!("Zone {:?} is exterior and can't have a setpoint", name);
+                }
+                boundary_temperatures.insert(name.clone(), design_outside_temperature);
+            }
+        }
+        let inputs = Inputs {
+            boundary_temperatures,
+            ..Default::default()
+        };
+        let simulation = Simulation::steady_state(self, &inputs)?;
+        let heat_flows = simulation.edge_heat_flows();
+
+        let loads: HashMap<_, _> = indoor_setpoints
+            .iter()
+            .map(|(zone, setpoint)| {
+                let index = self.zone_indices[zone];
+                let envelope: Power = self
+                    .graph
+                    .edges(index)
+                    .map(|edge| {
+                        let flow = heat_flows[edge.id().index()];
+                        if self.graph.edge_endpoints(edge.id()).unwrap().0 == index {
+                            flow
+                        } else {
+                            -flow
+                        }
+                    })
+                    .sum();
+                let temperature_difference =
+                    setpoint.get::<kelvin>() - design_outside_temperature.get::<kelvin>();
+                let ventilation = ventilation.get(zone).map_or(0.0, |conductance| {
+                    conductance.get::<watt_per_kelvin>() * temperature_difference
+                });
+                (zone.clone(), envelope + Power::new::<watt>(ventilation))
+            })
+            .collect();
+        let total = loads.values().copied().sum();
+        Ok((loads, total))
+    }
+
+    /// Find boundary nodes (surfaces and interfaces between layers, also internal mass)
+    /// colder than the dew point of a zone their boundary touches.
+    /// This assumes that the zone air reaches all layers of the boundary, vapor
+    /// barriers are not modeled.
+    /// `temperatures` are temperatures of all nodes indexed by node index (as used by
+    /// `step`), zones without relative humidity are skipped.
+    ///
+    /// Returns the risks sorted by node index.
+    pub fn condensation_risks(
+        &self,
+        temperatures: &[ThermodynamicTemperature],
+        relative_humidities: &HashMap<String, Ratio>,
+    ) -> Vec<CondensationRisk> {
+        let graph = &self.graph;
+        // Group index -> zones touching the group, with their dew points
+        let mut group_zones: BTreeMap<usize, Vec<(&String, ThermodynamicTemperature)>> =
+            BTreeMap::new();
+        for edge in graph.edge_references() {
+            for (zone, other) in [
+                (edge.source(), edge.target()),
+                (edge.target(), edge.source()),
+            ] {
+                let (Some(name), Some(group)) =
+                    (&graph[zone].zone_name, graph[other].boundary_group_index)
+                else {
+                    continue;
+                };
+                if let Some(relative_humidity) = relative_humidities.get(name) {
+                    group_zones.entry(group).or_default().push((
+                        name,
+                        dew_point(temperatures[zone.index()], *relative_humidity),
+                    ));
+                }
+            }
+        }
+
+        let mut risks = Vec::new();
+        for (index, node) in graph.node_references() {
+            let (None, Some(group)) = (&node.zone_name, node.boundary_group_index) else {
+                continue;
+            };
+            let temperature = temperatures[index.index()];
+            for (zone, dew_point) in group_zones.get(&group).into_iter().flatten() {
+                if temperature < *dew_point {
+                    risks.push(CondensationRisk {
+                        node: index,
+                        zone: zone.to_string(),
+                        temperature,
+                        dew_point: *dew_point,
+                    });
+                }
+            }
+        }
+        risks
+    }
+
+    /// Split the boundaries of the network into envelope boundaries, which touch
+    /// outside or ground (zones with infinite heat capacity) and lose heat, and internal
+    /// boundaries, which only redistribute heat between interior zones.
+    /// Internal mass is not a boundary and is not included.
+    ///
+    /// Returns envelope and internal boundaries, simple boundaries first (in edge order),
+    /// then layered boundaries ordered by group index.
+    pub fn classify_boundaries(&self) -> (Vec<BoundaryRef>, Vec<BoundaryRef>) {
+        let graph = &self.graph;
+        let mut boundaries = Vec::new();
+        // Group index -> (adjacent zones, total resistance of the chain [K/W])
+        let mut groups: BTreeMap<usize, (Vec<String>, f64)> = BTreeMap::new();
+
+        for edge in graph.edge_references() {
+            let source = &graph[edge.source()];
+            let target = &graph[edge.target()];
+            let resistance = edge.weight().conductance.get::<watt_per_kelvin>().recip();
+            match (&source.zone_name, &target.zone_name) {
+                (Some(z1), Some(z2)) => {
+                    let mut zones = [z1.clone(), z2.clone()];
+                    zones.sort();
+                    boundaries.push(BoundaryRef {
+                        boundary_group_index: None,
+                        zones,
+                        conductance: edge.weight().conductance,
+                    });
+                }
+                (zone, other) => {
+                    let (group, zone) = match (zone, other) {
+                        (Some(zone), _) => (target.boundary_group_index, Some(zone)),
+                        (_, Some(zone)) => (source.boundary_group_index, Some(zone)),
+                        _ => (source.boundary_group_index, None),
+                    };
+                    let Some(group) = group else {
+                        continue;
+                    };
+                    let entry = groups.entry(group).or_default();
+                    entry.1 += resistance;
+                    if let Some(zone) = zone {
+                        entry.0.push(zone.clone());
+                    }
+                }
+            }
+        }
+
+        for (group, (mut zones, resistance)) in groups {
+            if zones.len() != 2 {
+                continue;
+            }
+            zones.sort();
+            boundaries.push(BoundaryRef {
+                boundary_group_index: Some(group),
+                zones: [zones[0].clone(), zones[1].clone()],
+                conductance: ThermalConductance::new::<watt_per_kelvin>(resistance.recip()),
+            });
+        }
+
+        let is_outer = |zone: &String| !graph[self.zone_indices[zone]].heat_capacity.is_finite();
+        boundaries
+            .into_iter()
+            .partition(|boundary| boundary.zones.iter().any(is_outer))
+    }
+
+    /// Save the network in a compact binary format, to avoid rebuilding it
+    /// from the model on every run.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, &BINARY_FORMAT_VERSION)?;
+        bincode::serialize_into(&mut writer, self)?;
+        Ok(())
+    }
+
+    /// Load a network previously stored by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != BINARY_FORMAT_VERSION {
+            anyhow::bail!(
+                "Unsupported network format version {} (expected {})",
+                version,
+                BINARY_FORMAT_VERSION
+            );
+        }
+        Ok(bincode::deserialize_from(&mut reader)?)
+    }
+}
+
+impl TryFrom<&Model> for RcNetwork {
+    type Error = anyhow::Error;
+
+    /// Build the network with convection in still air only on the boundary surfaces,
+    /// see `RcNetwork::from_model`.
+    fn try_from(model: &Model) -> anyhow::Result<Self> {
+        RcNetwork::from_model(model, None, Velocity::new::<meter_per_second>(0.0))
+    }
+}
+
+impl RcNetwork {
+    /// Build the network of a model.
+    /// Surfaces of layered boundaries exchange heat with the zones by convection and,
+    /// if `radiation_reference_temperature` is given, also by longwave radiation,
+    /// linearized around the reference temperature (see `radiation_heat_transfer`).
+    /// Radiation is left out by default to match `Boundary::u_value`.
+    /// Convection at surfaces facing outside depends on `wind_speed` (see
+    /// `air_convection_conductance`), surfaces facing interior zones are in still air.
+    /// To follow changing wind, build the network again.
+    /// Surfaces with a fixed resistance (`Boundary::surface_resistances`) use it instead.
+    pub fn from_model(
+        model: &Model,
+        radiation_reference_temperature: Option<ThermodynamicTemperature>,
+        wind_speed: Velocity,
+    ) -> anyhow::Result<Self> {
+        let mut graph = UnGraph::default();
+        let zone_indices: HashMap<_, _> = model
+            .zones
+            .iter()
+            .map(|(name, zone)| {
+                (
+                    name.clone(),
+                    graph.add_node(Node {
+                        zone_name: Some(name.clone()),
+                        marker: None,
+                        heat_capacity: zone.heat_capacity(zone.air_material(&model.air)),
+                        boundary_group_index: None,
+                    }),
+                )
+            })
+            .collect();
+        let mut marker_indices: MultiMap<_, _> = MultiMap::new();
+
+        let mut boundary_group_index = 0;
+        let mut boundary_groups = Vec::new();
+        let mut boundary_group_indices = Vec::with_capacity(model.boundaries.len());
+        for boundary in model.boundaries.iter() {
+            let z1 = zone_indices[&boundary.zones[0].name];
+            let z2 = zone_indices[&boundary.zones[1].name];
+            // Sides without volume are outside (ground is replaced by soil below)
+            let convection_conductances = [0, 1].map(|side| {
+                let air_speed = if boundary.zones[side].volume.is_none() {
+                    wind_speed
+                } else {
+                    Velocity::new::<meter_per_second>(0.0)
+                };
+                air_convection_conductance(air_speed) * boundary.area
+            });
+            let description = format!(
+                "Boundary {:?} between zones {:?} and {:?}",
+                boundary.boundary_type.name(),
+                boundary.zones[0].name,
+                boundary.zones[1].name
+            );
+            let group_label = format!(
+                "{} ({}↔{})",
+                boundary.boundary_type.name(),
+                boundary.zones[0].name,
+                boundary.zones[1].name
+            );
+
+            // Boundaries to ground touch soil instead of air. The soil layer gets a node
+            // in its middle, which takes the place of the ground node as the end of
+            // the boundary.
+            let ground_side = boundary.ground_side();
+            let soil_conductance = model.ground.conductance(boundary.area) * 2.0;
+            let mut ends = [z1, z2];
+            if let Some(side) = ground_side {
+                let heat_capacity = model.ground.heat_capacity(boundary.area);
+                check_heat_capacity(heat_capacity, &description)?;
+                check_conductance(soil_conductance, boundary.area, &description)?;
+                let soil = graph.add_node(Node {
+                    zone_name: None,
+                    marker: None,
+                    heat_capacity,
+                    boundary_group_index: Some(boundary_group_index),
+                });
+                graph.add_edge(
+                    soil,
+                    ends[side],
+                    Edge {
+                        conductance: soil_conductance,
+                    },
+                );
+                ends[side] = soil;
+            }
+
+            match boundary.boundary_type.as_ref() {
+                BoundaryType::Layered {
+                    name: _,
+                    layers,
+                    initial_marker,
+                    solar_absorptance: _,
+                } => {
+                    // Given surface resistances already include radiation
+                    let surface_conductance =
+                        |side: usize, layer: &BoundaryLayer, resistance| match resistance {
+                            Some(resistance) => boundary.area / resistance,
+                            None => radiation_reference_temperature.map_or(
+                                convection_conductances[side],
+                                |temperature| {
+                                    convection_conductances[side]
+                                        + radiation_heat_transfer(layer.emissivity(), temperature)
+                                            * boundary.area
+                                },
+                            ),
+                        };
+                    let resistances = boundary.surface_resistance_overrides();
+                    let mut surface_conductances = [
+                        surface_conductance(0, layers.first().unwrap(), resistances[0]),
+                        surface_conductance(1, layers.last().unwrap(), resistances[1]),
+                    ];
+                    if let Some(side) = ground_side {
+                        // Soil touches the boundary directly, unless a surface resistance is given
+                        surface_conductances[side] = match resistances[side] {
+                            Some(_) => {
+                                reciprocal_sum!(surface_conductances[side], soil_conductance)
+                            }
+                            None => soil_conductance,
+                        };
+                    }
+                    let builder = LayeredBoundaryBuilder {
+                        zone1_node: ends[0],
+                        zone2_node: ends[1],
+                        zone1_name: &boundary.zones[0].name,
+                        layers,
+                        initial_marker,
+                        area: boundary.area,
+                        surface_conductances,
+                        group_index: boundary_group_index,
+                        description: &description,
+                    };
+                    builder.add_layered_boundary_nodes(&mut graph, &mut marker_indices)?;
+                    boundary_group_indices.push(Some(boundary_group_index));
+                    boundary_groups.push(group_label);
+                    boundary_group_index += 1;
+                }
+                BoundaryType::Simple { u, .. } => {
+                    let [surface1, surface2] = [0, 1].map(|side| {
+                        if ground_side == Some(side) {
+                            soil_conductance
+                        } else {
+                            convection_conductances[side]
+                        }
+                    });
+                    let conductance = reciprocal_sum!(surface1, *u * boundary.area, surface2);
+                    check_conductance(conductance, boundary.area, &description)?;
+                    graph.add_edge(ends[0], ends[1], Edge { conductance });
+                    boundary_group_indices.push(None);
+                    if ground_side.is_some() {
+                        boundary_groups.push(group_label);
+                        boundary_group_index += 1;
+                    }
+                }
+            }
+        }
+
+        for (name, zone) in model.zones.iter().sorted_by_key(|(name, _)| *name) {
+            let Some(internal_mass) = &zone.internal_mass else {
+                continue;
+            };
+            let description = format!("Internal mass of zone {:?}", name);
+            let conductance = air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+                * internal_mass.area;
+            check_heat_capacity(internal_mass.heat_capacity, &description)?;
+            check_conductance(conductance, internal_mass.area, &description)?;
+
+            let node = graph.add_node(Node {
+                zone_name: None,
+                marker: None,
+                heat_capacity: internal_mass.heat_capacity,
+                boundary_group_index: Some(boundary_group_index),
+            });
+            graph.add_edge(zone_indices[name], node, Edge { conductance });
+            boundary_groups.push(format!("internal mass ({})", name));
+            boundary_group_index += 1;
+        }
+
+        // Air exchange with outside, in parallel to the boundaries
+        for (name, zone) in model.zones.iter().sorted_by_key(|(name, _)| *name) {
+            if let Some(conductance) = zone.ventilation_conductance(zone.air_material(&model.air)) {
+                let outside = zone_indices.get("outside").ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Zone {:?} has air exchange, but the model has no outside zone",
+                        name
+                    )
+                })?;
+                graph.add_edge(zone_indices[name], *outside, Edge { conductance });
+            }
+        }
+
+        for thermal_bridge in model.thermal_bridges.iter() {
+            graph.add_edge(
+                zone_indices[&thermal_bridge.zones[0].name],
+                zone_indices[&thermal_bridge.zones[1].name],
+                Edge {
+                    conductance: thermal_bridge.conductance(),
+                },
+            );
+        }
+
+        let source_indices = model
+            .heat_sources
+            .iter()
+            .map(|heat_source| {
+                (
+                    heat_source.name.clone(),
+                    zone_indices[&heat_source.zone.name],
+                )
+            })
+            .collect();
+
+        Ok(RcNetwork {
+            graph,
+            zone_indices,
+            marker_indices,
+            source_indices,
+            boundary_groups,
+            boundary_group_indices,
+        })
+    }
+}
+
+/// Nodes of a layered boundary connected in a path between two other nodes.
+struct BoundaryChain {
+    nodes: Vec<NodeIndex>,
+    /// Nodes connected to the first and to the last node of the chain
+    ends: [NodeIndex; 2],
+    /// Edges along the path from the first end to the second one, one more than nodes
+    edges: Vec<petgraph::graph::EdgeIndex>,
+}
+
+impl BoundaryChain {
+    /// Merge adjacent nodes of the chain, see `RcNetwork::reduce`.
+    ///
+    /// Returns the merged nodes and the resistances [K/W] of the edges along the path,
+    /// one more than nodes.
+    fn reduced(&self, graph: &UnGraph<Node, Edge>, max_nodes: usize) -> (Vec<Node>, Vec<f64>) {
+        let mut nodes: Vec<Node> = self
+            .nodes
+            .iter()
+            .map(|index| graph[*index].clone())
+            .collect();
+        let mut resistances: Vec<f64> = self
+            .edges
+            .iter()
+            .map(|edge| graph[*edge].conductance.get::<watt_per_kelvin>().recip())
+            .collect();
+
+        while nodes.len() > max_nodes {
+            // Resistance between nodes i and i + 1 is resistances[i + 1]
+            let Some(i) = (0..(nodes.len() - 1))
+                .filter(|&i| nodes[i].marker.is_none() || nodes[i + 1].marker.is_none())
+                .min_by(|&a, &b| resistances[a + 1].total_cmp(&resistances[b + 1]))
+            else {
+                break;
+            };
+            let removed = nodes.remove(i + 1);
+            let node = &mut nodes[i];
+            node.heat_capacity += removed.heat_capacity;
+            if node.marker.is_none() {
+                node.marker = removed.marker;
+            }
+            let between = resistances.remove(i + 1);
+            resistances[i] += between / 2.0;
+            resistances[i + 1] += between / 2.0;
+        }
+
+        (nodes, resistances)
+    }
+}
+
+fn kelvins(temperatures: &[ThermodynamicTemperature]) -> Vec<f64> {
+    temperatures
+        .iter()
+        .map(|temperature| temperature.get::<kelvin>())
+        .collect()
+}
+
+/// Check that a conductance computed for a boundary is usable in the network.
+/// Zero conductance is only allowed for boundaries with zero area, otherwise it
+/// would disconnect the boundary nodes and make the network unsolvable.
+fn check_conductance(
+    conductance: ThermalConductance,
+    area: Area,
+    description: &str,
+) -> anyhow::Result<()> {
+    if !conductance.is_finite() {
+        anyhow::bail!("{} has non-finite conductance", description);
+    }
+    if conductance.get::<watt_per_kelvin>() <= 0.0 && area.value > 0.0 {
+        anyhow::bail!(
+            "{} has zero conductance (zero thermal conductivity or huge thickness?)",
+            description
+        );
+    }
+    Ok(())
+}
+
+/// Check that a heat capacity computed for a boundary is usable in the network.
+fn check_heat_capacity(heat_capacity: HeatCapacity, description: &str) -> anyhow::Result<()> {
+    if !heat_capacity.is_finite() {
+        anyhow::bail!("{} has non-finite heat capacity", description);
+    }
+    Ok(())
+}
+
+/// Helper for adding nodes and edges of a layered boundary.
+/// This exists only to hold the arguments in a slightly organized fashion
+/// (and avoid Clippy complaints about too many arguments being passed to a function).
+struct LayeredBoundaryBuilder<'a> {
+    zone1_node: NodeIndex,
+    zone2_node: NodeIndex,
+    zone1_name: &'a str,
+    layers: &'a [BoundaryLayer],
+    initial_marker: &'a Option<String>,
+    area: Area,
+    /// Conductances between the boundary surface and the zone, on the side of zone 1 and zone 2
+    surface_conductances: [ThermalConductance; 2],
+    group_index: usize,
+    /// Description of the boundary for error messages
+    description: &'a str,
+}
+
+impl<'a> LayeredBoundaryBuilder<'a> {
+    /// Add nodes corresponding to the boundary layers to the graph, including connections,
+    /// collects marked nodes.
+    /// Fails if any of the conductances or heat capacities is not usable.
+    fn add_layered_boundary_nodes(
+        &self,
+        graph: &mut UnGraph<Node, Edge>,
+        marker_indices: &mut MultiMap<(String, String), NodeIndex>,
+    ) -> anyhow::Result<()> {
+        let mut current_node = self.add_boundary_node(
+            self.layers.first().unwrap().heat_capacity(self.area) / 2.0,
+            self.zone1_node,
+            self.surface_conductances[0],
+            self.initial_marker,
+            graph,
+            marker_indices,
+        )?;
+
+        for (layer1, layer2) in self.layers.iter().tuple_windows() {
+            current_node = self.add_boundary_node(
+                (layer1.heat_capacity(self.area) + layer2.heat_capacity(self.area)) / 2.0,
+                current_node,
+                layer1.conductance(self.area),
+                &layer1.following_marker,
+                graph,
+                marker_indices,
+            )?;
+        }
+
+        let last_layer = self.layers.last().unwrap();
+
+        current_node = self.add_boundary_node(
+            last_layer.heat_capacity(self.area) / 2.0,
+            current_node,
+            last_layer.conductance(self.area),
+            &last_layer.following_marker,
+            graph,
+            marker_indices,
+        )?;
+
+        graph.add_edge(
+            current_node,
+            self.zone2_node,
+            Edge {
+                conductance: self.surface_conductances[1],
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Add a new node on a boundary between two nodes, process its markers and connect
+    /// it to the graph.
+    /// This is used both for the nodes within the boundary and for nodes between the
+    /// boundary and the zone.
+    fn add_boundary_node(
+        &self,
+        heat_capacity: HeatCapacity,
+        prev_node: NodeIndex,
+        thermal_conductance: ThermalConductance,
+        marker: &Option<String>,
+        graph: &mut UnGraph<Node, Edge>,
+        marker_indices: &mut MultiMap<(String, String), NodeIndex>,
+    ) -> anyhow::Result<NodeIndex> {
+        check_heat_capacity(heat_capacity, self.description)?;
+        check_conductance(thermal_conductance, self.area, self.description)?;
+
+        let marker = marker
+            .as_ref()
+            .map(|marker| (self.zone1_name.into(), marker.clone()));
+
+        let node = graph.add_node(Node {
+            zone_name: None,
+            marker: marker.clone(),
+            heat_capacity,
+            boundary_group_index: Some(self.group_index),
+        });
+
+        if let Some(marker) = marker {
+            marker_indices.insert(marker, node);
+        }
+
+        graph.add_edge(
+            prev_node,
+            node,
+            Edge {
+                conductance: thermal_conductance,
+            },
+        );
+
+        Ok(node)
+    }
+}
+
+/// Return thermal conductance of a surface in air.
+/// Based on https://www.engineeringtoolbox.com/convective-heat-transfer-d_430.html
+pub fn air_convection_conductance(wind_speed: Velocity) -> HeatTransfer {
+    // The calculation is done outside of UOM, because the coefficient units would be awkward
+    let wind_speed = wind_speed.get::<meter_per_second>();
+    HeatTransfer::new::<watt_per_square_meter_kelvin>(
+        12.12 - 1.16 * wind_speed + 11.6 * wind_speed.sqrt(),
+    )
+}
+
+/// Linearized radiative heat transfer coefficient of a surface exchanging longwave
+/// radiation with surroundings at a similar temperature, `4·ε·σ·T³`.
+///
+/// Arguments:
+/// * `emissivity` - emissivity of the surface
+/// * `reference_temperature` - mean temperature of the surface and its surroundings
+///
+/// Returns:
+/// * `HeatTransfer` - radiative heat transfer coefficient
+pub fn radiation_heat_transfer(
+    emissivity: Ratio,
+    reference_temperature: ThermodynamicTemperature,
+) -> HeatTransfer {
+    let temperature = reference_temperature.get::<kelvin>();
+    HeatTransfer::new::<watt_per_square_meter_kelvin>(
+        4.0 * emissivity.get::<ratio>() * STEFAN_BOLTZMANN * temperature.powi(3),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::LayerContent;
+    use approx::{assert_abs_diff_eq, assert_ulps_eq};
+    use test_case::test_case;
+    use test_strategy::proptest;
+    use uom::si::{
+        angle::degree, area::square_meter, f64::Length, heat_flux_density::watt_per_square_meter,
+        length::meter, mass_density::kilogram_per_cubic_meter,
+        specific_heat_capacity::joule_per_kilogram_kelvin,
+        thermodynamic_temperature::degree_celsius, volume::cubic_meter,
+    };
+
+    // The test values are taken from the illustration graph in the source articles,
+    // converted to pairs using web plot digitizer. The plot appears to be very imprecise,
+    // forcing this test to have very error high tolerance.
+    #[test_case( 3.0, 27.4; "example1")]
+    #[test_case( 8.0, 35.2; "example2")]
+    #[test_case(13.0, 39.3; "example3")]
+    #[test_case(18.0, 41.6; "example4")]
+    fn air_convection_conductance_example(air_velocity: f64, expected_heat_transfer: f64) {
+        let conductance =
+            air_convection_conductance(Velocity::new::<meter_per_second>(air_velocity));
+        assert_abs_diff_eq!(
+            conductance.get::<watt_per_square_meter_kelvin>(),
+            expected_heat_transfer,
+            epsilon = 1.5
+        );
+    }
+
+    #[proptest]
+    fn graph_node_count(model: Model) {
+        let internal_mass_count = model
+            .zones
+            .values()
+            .filter(|zone| zone.internal_mass.is_some())
+            .count();
+        let mut expected_node_count = model.zones.len() + internal_mass_count;
+        let mut expected_edge_count = internal_mass_count;
+        for boundary in model.boundaries.iter() {
+            if boundary.ground_side().is_some() {
+                // Soil node and its edge to ground
+                expected_node_count += 1;
+                expected_edge_count += 1;
+            }
+            match boundary.boundary_type.as_ref() {
+                BoundaryType::Simple { .. } => expected_edge_count += 1,
+                BoundaryType::Layered {
+                    name: _,
+                    layers,
+                    initial_marker: _,
+                    solar_absorptance: _,
+                } => {
+                    expected_node_count += layers.len() + 1;
+                    expected_edge_count += layers.len() + 2;
+                }
+            }
+        }
+
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        assert_eq!(net.graph.node_count(), expected_node_count);
+        assert_eq!(net.graph.edge_count(), expected_edge_count);
+    }
+
+    /// Test that the total heat capacity of the model excluding outside zones
+    /// is the same as the total heat capacity of the RC network excluing infinite zones
+    /// and nothing gets lost.
+    /// The expected value is summed directly from the model data (volume times density
+    /// times specific heat of every piece of matter), without going through
+    /// `Model::total_heat_capacity`.
+    #[proptest]
+    fn heat_capacity_sum(model: Model) {
+        let mut parts = Vec::new();
+        for zone in model.zones.values() {
+            let Some(volume) = zone.volume else {
+                continue;
+            };
+            let air = zone.air.as_deref().unwrap_or(&model.air);
+            parts.push(
+                volume.get::<cubic_meter>()
+                    * air.density.get::<kilogram_per_cubic_meter>()
+                    * air
+                        .specific_heat_capacity
+                        .get::<joule_per_kilogram_kelvin>(),
+            );
+            if let Some(mass) = &zone.internal_mass {
+                parts.push(mass.heat_capacity.get::<joule_per_kelvin>());
+            }
+        }
+        for boundary in model.boundaries.iter() {
+            let area = boundary.area.get::<square_meter>();
+            if let BoundaryType::Layered { layers, .. } = boundary.boundary_type.as_ref() {
+                for layer in layers {
+                    parts.push(match &layer.content {
+                        LayerContent::Material {
+                            material,
+                            thickness,
+                        } => {
+                            area * thickness.get::<meter>()
+                                * material.density.get::<kilogram_per_cubic_meter>()
+                                * material
+                                    .specific_heat_capacity
+                                    .get::<joule_per_kilogram_kelvin>()
+                        }
+                        LayerContent::Resistance {
+                            heat_capacity_per_area,
+                            ..
+                        } => heat_capacity_per_area.value * area,
+                    });
+                }
+            }
+            if boundary
+                .zones
+                .iter()
+                .any(|zone| zone.name == "ground" && zone.volume.is_none())
+            {
+                parts.push(
+                    area * model.ground.depth.get::<meter>()
+                        * model.ground.density.get::<kilogram_per_cubic_meter>()
+                        * model
+                            .ground
+                            .specific_heat_capacity
+                            .get::<joule_per_kilogram_kelvin>(),
+                );
+            }
+        }
+
+        let expected_capacity = compensated_sum(parts);
+        let actual_capacity = compensated_sum(
+            RcNetwork::try_from(&model)
+                .unwrap()
+                .graph
+                .node_weights()
+                .map(|node| node.heat_capacity.get::<joule_per_kelvin>())
+                .filter(|heat_capacity| heat_capacity.is_finite()),
+        );
+
+        assert_ulps_eq!(actual_capacity, expected_capacity);
+    }
+
+    /// Neumaier summation, so that the result doesn't depend on the order of the values.
+    fn compensated_sum(values: impl IntoIterator<Item = f64>) -> f64 {
+        let mut sum = 0.0;
+        let mut compensation = 0.0;
+        for value in values {
+            let new_sum = sum + value;
+            compensation += if f64::abs(sum) >= f64::abs(value) {
+                (sum - new_sum) + value
+            } else {
+                (value - new_sum) + sum
+            };
+            sum = new_sum;
+        }
+        sum + compensation
+    }
+
+    #[proptest]
+    fn save_load_roundtrip(model: Model) {
+        let net = RcNetwork::try_from(&model).unwrap();
+        let f = tempfile::NamedTempFile::new().unwrap();
+
+        net.save(f.path()).unwrap();
+        let loaded = RcNetwork::load(f.path()).unwrap();
+
+        assert_eq!(
+            loaded.graph.node_weights().collect::<Vec<_>>(),
+            net.graph.node_weights().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded
+                .graph
+                .edge_references()
+                .map(|edge| (edge.source(), edge.target(), *edge.weight()))
+                .collect::<Vec<_>>(),
+            net.graph
+                .edge_references()
+                .map(|edge| (edge.source(), edge.target(), *edge.weight()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.zone_indices, net.zone_indices);
+        assert_eq!(loaded.marker_indices, net.marker_indices);
+        assert_eq!(loaded.source_indices, net.source_indices);
+        assert_eq!(loaded.boundary_groups, net.boundary_groups);
+    }
+
+    #[test]
+    fn heat_loss_coefficient_matches_ua_value() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.5,
+                    specific_heat_capacity: 1000,
+                    density: 1500,
+                },
+                insulation: {
+                    thermal_conductivity: 0.04,
+                    specific_heat_capacity: 1200,
+                    density: 30,
+                }
+            },
+            boundary_types: {
+                exterior_wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                        { marker: "x" },
+                        { material: "insulation", thickness: 0.15 },
+                    ]
+                },
+                interior_wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.15 },
+                    ]
+                },
+                floor: {
+                    layers: [
+                        { material: "insulation", thickness: 0.1 },
+                        { material: "brick", thickness: 0.2 },
+                    ]
+                },
+                window: { u: 1.1, g: 0.5 },
+                slab: { u: 0.3, g: 0 },
+            },
+            zones: {
+                a: { volume: 50, air_changes_per_hour: 0.5 },
+                b: { volume: 70 },
+            },
+            boundaries: [
+                { boundary_type: "exterior_wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "exterior_wall", zones: ["outside", "b"], area: 30 },
+                { boundary_type: "interior_wall", zones: ["a", "b"], area: 10 },
+                { boundary_type: "floor", zones: ["a", "ground"], area: 15 },
+                { boundary_type: "floor", zones: ["b", "ground"], area: 25 },
+                { boundary_type: "window", zones: ["a", "outside"], area: 3 },
+                { boundary_type: "slab", zones: ["ground", "b"], area: 5 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        assert_ulps_eq!(
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            model.ua_value().get::<watt_per_kelvin>(),
+            max_ulps = 1000
+        );
+    }
+
+    #[test]
+    fn solve_steady_state_divider() {
+        let mut graph = UnGraph::new_undirected();
+        let node = |heat_capacity: f64| Node {
+            zone_name: None,
+            marker: None,
+            heat_capacity: HeatCapacity::new::<joule_per_kelvin>(heat_capacity),
+            boundary_group_index: None,
+        };
+        let edge = |conductance: f64| Edge {
+            conductance: ThermalConductance::new::<watt_per_kelvin>(conductance),
+        };
+        let a = graph.add_node(node(f64::INFINITY));
+        let middle = graph.add_node(node(1000.0));
+        let b = graph.add_node(node(f64::INFINITY));
+        graph.add_edge(a, middle, edge(1.0));
+        graph.add_edge(middle, b, edge(3.0));
+        let net = RcNetwork {
+            graph,
+            zone_indices: HashMap::new(),
+            marker_indices: MultiMap::new(),
+            source_indices: HashMap::new(),
+            boundary_groups: Vec::new(),
+            boundary_group_indices: Vec::new(),
+        };
+
+        let temperatures = net
+            .solve_steady_state(&HashMap::from([
+                (a, ThermodynamicTemperature::new::<degree_celsius>(0.0)),
+                (b, ThermodynamicTemperature::new::<degree_celsius>(20.0)),
+            ]))
+            .unwrap();
+
+        assert_eq!(temperatures.len(), 3);
+        assert_abs_diff_eq!(
+            temperatures[&middle].get::<degree_celsius>(),
+            (1.0 * 0.0 + 3.0 * 20.0) / (1.0 + 3.0),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(temperatures[&b].get::<degree_celsius>(), 20.0);
+
+        // Unfixed node with infinite heat capacity floats to the other end
+        let temperatures = net
+            .solve_steady_state(&HashMap::from([(
+                a,
+                ThermodynamicTemperature::new::<degree_celsius>(5.0),
+            )]))
+            .unwrap();
+        assert_abs_diff_eq!(
+            temperatures[&b].get::<degree_celsius>(),
+            5.0,
+            epsilon = 1e-9
+        );
+
+        assert!(net.solve_steady_state(&HashMap::new()).is_err());
+    }
+
+    #[test_case(true; "fixed")]
+    #[test_case(false; "infinite heat capacity")]
+    fn step_relaxes_exponentially(neighbor_fixed: bool) {
+        let mut graph = UnGraph::new_undirected();
+        let node = |heat_capacity: f64| Node {
+            zone_name: None,
+            marker: None,
+            heat_capacity: HeatCapacity::new::<joule_per_kelvin>(heat_capacity),
+            boundary_group_index: None,
+        };
+        // Fixed node gets finite heat capacity to check that `fixed` is respected
+        let neighbor = graph.add_node(node(if neighbor_fixed { 1.0 } else { f64::INFINITY }));
+        let free = graph.add_node(node(1000.0));
+        graph.add_edge(
+            neighbor,
+            free,
+            Edge {
+                conductance: ThermalConductance::new::<watt_per_kelvin>(10.0),
+            },
+        );
+        let net = RcNetwork {
+            graph,
+            zone_indices: HashMap::new(),
+            marker_indices: MultiMap::new(),
+            source_indices: HashMap::new(),
+            boundary_groups: Vec::new(),
+            boundary_group_indices: Vec::new(),
+        };
+        let fixed = if neighbor_fixed {
+            HashSet::from([neighbor])
+        } else {
+            HashSet::new()
+        };
+
+        let mut temperatures = [
+            ThermodynamicTemperature::new::<degree_celsius>(0.0),
+            ThermodynamicTemperature::new::<degree_celsius>(10.0),
+        ];
+        // Time constant is 1000 J/K / 10 W/K = 100 s, run for one time constant
+        for _ in 0..1000 {
+            net.step(&mut temperatures, Time::new::<second>(0.1), &fixed)
+                .unwrap();
+        }
+
+        assert_abs_diff_eq!(temperatures[0].get::<degree_celsius>(), 0.0);
+        assert_abs_diff_eq!(
+            temperatures[1].get::<degree_celsius>(),
+            10.0 * (-1.0f64).exp(),
+            epsilon = 0.01
+        );
+    }
+
+    /// Network of a single node with the given heat capacity (index 1), connected
+    /// to an outside node with infinite heat capacity (index 0).
+    fn single_node_network(
+        heat_capacity: HeatCapacity,
+        conductance: ThermalConductance,
+    ) -> RcNetwork {
+        let mut graph = UnGraph::new_undirected();
+        let node = |heat_capacity: HeatCapacity| Node {
+            zone_name: None,
+            marker: None,
+            heat_capacity,
+            boundary_group_index: None,
+        };
+        let outside = graph.add_node(node(HeatCapacity::new::<joule_per_kelvin>(f64::INFINITY)));
+        let free = graph.add_node(node(heat_capacity));
+        graph.add_edge(outside, free, Edge { conductance });
+        RcNetwork {
+            graph,
+            zone_indices: HashMap::new(),
+            marker_indices: MultiMap::new(),
+            source_indices: HashMap::new(),
+            boundary_groups: Vec::new(),
+            boundary_group_indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn step_rk4_converges_faster_than_euler() {
+        let net = single_node_network(
+            HeatCapacity::new::<joule_per_kelvin>(1000.0),
+            ThermalConductance::new::<watt_per_kelvin>(10.0),
+        );
+
+        // Time constant is 100 s, run for one time constant
+        let error = |step_count: usize, rk4: bool| {
+            let mut temperatures = [
+                ThermodynamicTemperature::new::<degree_celsius>(0.0),
+                ThermodynamicTemperature::new::<degree_celsius>(10.0),
+            ];
+            let dt = Time::new::<second>(100.0 / step_count as f64);
+            for _ in 0..step_count {
+                if rk4 {
+                    net.step_rk4(&mut temperatures, dt, &HashSet::new())
+                        .unwrap();
+                } else {
+                    net.step(&mut temperatures, dt, &HashSet::new()).unwrap();
+                }
+            }
+            assert_eq!(temperatures[0].get::<degree_celsius>(), 0.0);
+            (temperatures[1].get::<degree_celsius>() - 10.0 * (-1.0f64).exp()).abs()
+        };
+
+        // Halving the step halves the error of Euler, but divides the RK4 error by 16
+        assert_abs_diff_eq!(error(10, false) / error(20, false), 2.0, epsilon = 0.1);
+        assert_abs_diff_eq!(error(10, true) / error(20, true), 16.0, epsilon = 1.0);
+        assert!(error(10, true) < error(1000, false));
+    }
+
+    #[test]
+    fn simulate_constant_heating_reaches_steady_state() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 2 }],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let a = net.zone_indices["a"];
+        let outside = net.zone_indices["outside"];
+        let outside_temperature = ThermodynamicTemperature::new::<degree_celsius>(-5.0);
+        let power = Power::new::<watt>(100.0);
+        let inputs = vec![ControlStep::from([(a, power)]); 200];
+
+        let history = net
+            .simulate(
+                &vec![
+                    ThermodynamicTemperature::new::<degree_celsius>(20.0);
+                    net.graph.node_count()
+                ],
+                &inputs,
+                Time::new::<second>(600.0),
+                &HashMap::from([(outside, outside_temperature)]),
+            )
+            .unwrap();
+
+        assert_eq!(history.len(), inputs.len() + 1);
+        assert_eq!(
+            history[0][a.index()],
+            ThermodynamicTemperature::new::<degree_celsius>(20.0)
+        );
+        assert_eq!(history[0][outside.index()], outside_temperature);
+        assert!(history
+            .iter()
+            .all(|step| step[outside.index()] == outside_temperature));
+        let conductance = net
+            .graph
+            .edges(a)
+            .exactly_one()
+            .unwrap()
+            .weight()
+            .conductance;
+        assert_abs_diff_eq!(
+            history.last().unwrap()[a.index()].get::<degree_celsius>(),
+            -5.0 + power.get::<watt>() / conductance.get::<watt_per_kelvin>(),
+            epsilon = 1e-3
+        );
+    }
+
+    #[test]
+    fn simulate_wrong_dimensions() {
+        let net = single_node_network(
+            HeatCapacity::new::<joule_per_kelvin>(1000.0),
+            ThermalConductance::new::<watt_per_kelvin>(10.0),
+        );
+        let temperature = ThermodynamicTemperature::new::<degree_celsius>(20.0);
+        let dt = Time::new::<second>(1.0);
+
+        let message = net
+            .simulate(&[temperature], &[], dt, &HashMap::new())
+            .unwrap_err()
+            .to_string();
+        message
+            .find("expected one per node")
+            .expect("Error message should mention the node count");
+
+        let message = net
+            .simulate(
+                &[temperature; 2],
+                &[ControlStep::from([(
+                    NodeIndex::new(2),
+                    Power::new::<watt>(100.0),
+                )])],
+                dt,
+                &HashMap::new(),
+            )
+            .unwrap_err()
+            .to_string();
+        message
+            .find("Node 2 is not in the network")
+            .expect("Error message should name the missing node");
+
+        let mut temperatures = [temperature; 3];
+        assert!(net.step(&mut temperatures, dt, &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn time_constant_single_node() {
+        let net = single_node_network(
+            HeatCapacity::new::<joule_per_kelvin>(1000.0),
+            ThermalConductance::new::<watt_per_kelvin>(10.0),
+        );
+
+        let time_constants = net.time_constants();
+
+        assert_eq!(time_constants.len(), 1);
+        assert_ulps_eq!(time_constants[0].get::<second>(), 1000.0 / 10.0);
+    }
+
+    #[test]
+    fn state_space_isolated_network_conserves_heat() {
+        let mut graph = UnGraph::new_undirected();
+        let nodes: Vec<_> = [100.0, 200.0, 300.0]
+            .into_iter()
+            .map(|heat_capacity| {
+                graph.add_node(Node {
+                    zone_name: None,
+                    marker: None,
+                    heat_capacity: HeatCapacity::new::<joule_per_kelvin>(heat_capacity),
+                    boundary_group_index: None,
+                })
+            })
+            .collect();
+        for (a, b, conductance) in [(0, 1, 1.0), (1, 2, 2.0), (0, 2, 3.0)] {
+            graph.add_edge(
+                nodes[a],
+                nodes[b],
+                Edge {
+                    conductance: ThermalConductance::new::<watt_per_kelvin>(conductance),
+                },
+            );
+        }
+        let net = RcNetwork {
+            graph,
+            zone_indices: HashMap::new(),
+            marker_indices: MultiMap::new(),
+            source_indices: HashMap::new(),
+            boundary_groups: Vec::new(),
+            boundary_group_indices: Vec::new(),
+        };
+
+        let state_space = net.state_space();
+
+        assert_eq!(state_space.state_count(), 3);
+        assert_eq!(state_space.input_count(), 0);
+        for row in state_space.a.row_iter() {
+            assert_abs_diff_eq!(row.sum(), 0.0, epsilon = 1e-12);
+        }
+        // Off-diagonal elements are conductance divided by heat capacity of the row node
+        let row = state_space.state_indices[&nodes[1]];
+        let column = state_space.state_indices[&nodes[2]];
+        assert_ulps_eq!(state_space.a[(row, column)], 2.0 / 200.0);
+        // The mode of uniform temperature never decays
+        assert_eq!(net.time_constants().len(), 2);
+    }
+
+    #[test]
+    fn state_space_matches_step() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.5, specific_heat_capacity: 1000, density: 1500 },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 },
+                        { marker: "heating" },
+                        { material: "brick", thickness: 0.1 },
+                    ]
+                },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "window", zones: ["a", "ground"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let state_space = net.state_space();
+
+        let mut temperatures: Vec<_> = (0..net.graph.node_count())
+            .map(|i| ThermodynamicTemperature::new::<kelvin>(280.0 + 3.0 * i as f64))
+            .collect();
+        let mut x = na::DVector::zeros(state_space.state_count());
+        for (index, state) in state_space.state_indices.iter() {
+            x[*state] = temperatures[index.index()].get::<kelvin>();
+        }
+        let u = na::DVector::from_iterator(
+            state_space.input_count(),
+            state_space.inputs.iter().map(|input| match input {
+                StateSpaceInput::Temperature(index) => temperatures[index.index()].get::<kelvin>(),
+                StateSpaceInput::HeatFlow(_) => 0.0,
+            }),
+        );
+        let derivative = &state_space.a * &x + &state_space.b * &u;
+
+        let dt = 1e-3;
+        net.step(&mut temperatures, Time::new::<second>(dt), &HashSet::new())
+            .unwrap();
+        for (index, state) in state_space.state_indices.iter() {
+            assert_abs_diff_eq!(
+                (temperatures[index.index()].get::<kelvin>() - x[*state]) / dt,
+                derivative[*state],
+                epsilon = 1e-6
+            );
+        }
+
+        // Outside and ground are inputs, heat can be injected into the zone and the marker
+        let heat_flows: Vec<_> = state_space
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                StateSpaceInput::HeatFlow(index) => Some(*index),
+                StateSpaceInput::Temperature(_) => None,
+            })
+            .collect();
+        assert_eq!(state_space.input_count(), 2 + heat_flows.len());
+        assert_eq!(heat_flows.len(), 2);
+        assert!(heat_flows.contains(&net.zone_indices["a"]));
+        let marker = net
+            .marker_indices
+            .get(&("a".into(), "heating".into()))
+            .unwrap();
+        assert!(heat_flows.contains(marker));
+    }
+
+    #[test]
+    fn solar_heat_gains_through_windows() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+                wall: { u: 0.2, g: 0 },
+                glazing: { u: 1, g: 0.5, angular_transmittance: true },
+            },
+            zones: {
+                a: { volume: 50 },
+                b: { volume: 50 },
+                c: { volume: 50 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+                { boundary_type: "window", zones: ["outside", "a"], area: 1 },
+                { boundary_type: "wall", zones: ["b", "outside"], area: 10 },
+                { boundary_type: "window", zones: ["a", "b"], area: 4 },
+                { boundary_type: "glazing", zones: ["c", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let irradiance = |beam: f64, diffuse: f64| TiltedIrradiance {
+            beam: HeatFluxDensity::new::<watt_per_square_meter>(beam),
+            sky_diffuse: HeatFluxDensity::new::<watt_per_square_meter>(diffuse),
+            ground_reflected: HeatFluxDensity::new::<watt_per_square_meter>(0.0),
+            total: HeatFluxDensity::new::<watt_per_square_meter>(beam + diffuse),
+        };
+        let south = irradiance(500.0, 100.0);
+        let north = irradiance(0.0, 100.0);
+        let incidence_angle = Some(Angle::new::<degree>(60.0));
+
+        let gains = net
+            .solar_heat_gains([
+                (&model.boundaries[0], south, incidence_angle),
+                (&model.boundaries[1], north, None),
+                (&model.boundaries[2], south, incidence_angle),
+                (&model.boundaries[3], south, incidence_angle),
+                (&model.boundaries[4], south, incidence_angle),
+            ])
+            .unwrap();
+
+        assert_eq!(gains.len(), 3);
+        assert_ulps_eq!(
+            gains[&net.zone_indices["a"]].get::<watt>(),
+            0.5 * 2.0 * 600.0 + 0.5 * 1.0 * 100.0
+        );
+        // Opaque boundary with g = 0
+        assert_eq!(gains[&net.zone_indices["b"]].get::<watt>(), 0.0);
+        // Glazing with angular transmittance lets through less than the total irradiance
+        assert_ulps_eq!(
+            gains[&net.zone_indices["c"]].get::<watt>(),
+            0.5 * 2.0
+                * south
+                    .through_glazing(incidence_angle)
+                    .get::<watt_per_square_meter>()
+        );
+        assert!(gains[&net.zone_indices["c"]].get::<watt>() < 0.5 * 2.0 * 600.0);
+
+        // Window between two interior zones
+        let gains = net
+            .solar_heat_gains([(&model.boundaries[3], south, incidence_angle)])
+            .unwrap();
+        assert!(gains.is_empty());
+    }
+
+    #[test]
+    fn absorbed_solar_gains_dark_and_light_walls() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                dark: {
+                    layers: [{ material: "brick", thickness: 0.1 }, { material: "brick", thickness: 0.2 }],
+                    solar_absorptance: 0.9,
+                },
+                light: {
+                    layers: [{ material: "brick", thickness: 0.1 }, { material: "brick", thickness: 0.2 }],
+                    solar_absorptance: 0.3,
+                },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: { a: { volume: 10 }, b: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "dark", zones: ["a", "outside"], area: 10 },
+                { boundary_type: "light", zones: ["outside", "b"], area: 10 },
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+                { boundary_type: "dark", zones: ["a", "b"], area: 10 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let south = HeatFluxDensity::new::<watt_per_square_meter>(500.0);
+
+        let gains = net
+            .absorbed_solar_gains(&model, (0..model.boundaries.len()).map(|i| (i, south)))
+            .unwrap();
+
+        // Window and the interior wall absorb nothing
+        assert_eq!(gains.len(), 2);
+        let outside = net.zone_indices["outside"];
+        let gain = |group| {
+            let (node, gain) = gains
+                .iter()
+                .find(|(node, _)| net.graph[**node].boundary_group_index == Some(group))
+                .unwrap();
+            assert!(net.graph.contains_edge(*node, outside));
+            gain.get::<watt>()
+        };
+        let dark = gain(net.boundary_group_indices[0].unwrap());
+        let light = gain(net.boundary_group_indices[1].unwrap());
+        assert_ulps_eq!(dark, 0.9 * 10.0 * 500.0);
+        assert_ulps_eq!(light, 0.3 * 10.0 * 500.0);
+        assert!(dark > light);
+
+        let message = format!(
+            "{}",
+            net.absorbed_solar_gains(&model, [(4, south)]).unwrap_err()
+        );
+        message
+            .find("Boundary 4")
+            .expect("Error message should contain the boundary index");
+    }
+
+    #[test]
+    fn radiation_heat_transfer_black_body() {
+        // Textbook value for a black body at room temperature is about 5.7 W/m²K
+        assert_abs_diff_eq!(
+            radiation_heat_transfer(
+                Ratio::new::<ratio>(1.0),
+                ThermodynamicTemperature::new::<kelvin>(293.0)
+            )
+            .get::<watt_per_square_meter_kelvin>(),
+            5.7,
+            epsilon = 0.01
+        );
+    }
+
+    #[test]
+    fn surface_radiation() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1, density: 1 },
+                foil: {
+                    thermal_conductivity: 200,
+                    specific_heat_capacity: 1,
+                    density: 1,
+                    emissivity: 0.05,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 },
+                        { material: "foil", thickness: 0.001 },
+                    ]
+                },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 2 }],
+        }"#,
+        )
+        .unwrap();
+        let temperature = ThermodynamicTemperature::new::<degree_celsius>(20.0);
+        let convection = air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+            .get::<watt_per_square_meter_kelvin>();
+        let radiation = |emissivity| {
+            radiation_heat_transfer(Ratio::new::<ratio>(emissivity), temperature)
+                .get::<watt_per_square_meter_kelvin>()
+        };
+        let surface_conductance = |net: &RcNetwork, zone: &str| {
+            let edge = net.graph.edges(net.zone_indices[zone]).next().unwrap();
+            edge.weight().conductance.get::<watt_per_kelvin>()
+        };
+
+        let net = RcNetwork::try_from(&model).unwrap();
+        assert_ulps_eq!(surface_conductance(&net, "a"), 2.0 * convection);
+        assert_ulps_eq!(surface_conductance(&net, "outside"), 2.0 * convection);
+
+        let net = RcNetwork::from_model(
+            &model,
+            Some(temperature),
+            Velocity::new::<meter_per_second>(0.0),
+        )
+        .unwrap();
+        assert_ulps_eq!(
+            surface_conductance(&net, "a"),
+            2.0 * (convection + radiation(0.9))
+        );
+        assert_ulps_eq!(
+            surface_conductance(&net, "outside"),
+            2.0 * (convection + radiation(0.05))
+        );
+    }
+
+    #[test]
+    fn wind_increases_exterior_convection() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.1 }] },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: { a: { volume: 10 }, b: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+                { boundary_type: "wall", zones: ["a", "b"], area: 10 },
+                { boundary_type: "window", zones: ["outside", "b"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let build = |wind_speed: f64| {
+            RcNetwork::from_model(&model, None, Velocity::new::<meter_per_second>(wind_speed))
+                .unwrap()
+        };
+        let still = build(0.0);
+        let windy = build(5.0);
+        let outside = still.zone_indices["outside"];
+
+        let mut exterior_edges = 0;
+        for (still_edge, windy_edge) in still
+            .graph
+            .edge_references()
+            .zip(windy.graph.edge_references())
+        {
+            assert_eq!(
+                (still_edge.source(), still_edge.target()),
+                (windy_edge.source(), windy_edge.target())
+            );
+            let still_conductance = still_edge.weight().conductance;
+            let windy_conductance = windy_edge.weight().conductance;
+            if still_edge.source() == outside || still_edge.target() == outside {
+                exterior_edges += 1;
+                assert!(windy_conductance > still_conductance);
+            } else {
+                assert_eq!(windy_conductance, still_conductance);
+            }
+        }
+        assert_eq!(exterior_edges, 2);
+
+        let wall_surface = |net: &RcNetwork| {
+            net.graph
+                .edges(outside)
+                .find(|edge| net.graph[edge.target()].boundary_group_index.is_some())
+                .unwrap()
+                .weight()
+                .conductance
+                .get::<watt_per_kelvin>()
+        };
+        assert_ulps_eq!(
+            wall_surface(&windy),
+            10.0 * air_convection_conductance(Velocity::new::<meter_per_second>(5.0))
+                .get::<watt_per_square_meter_kelvin>()
+        );
+        assert_ulps_eq!(
+            wall_surface(&still),
+            10.0 * air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+                .get::<watt_per_square_meter_kelvin>()
+        );
+    }
+
+    #[test]
+    fn heat_loss_coefficient_fixed_surface_resistances() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.5, specific_heat_capacity: 1000, density: 1500 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["outside", "a"],
+                    area: 10,
+                    interior_surface_resistance: 0.13,
+                    exterior_surface_resistance: 0.04,
+                },
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "ground"],
+                    area: 20,
+                    exterior_surface_resistance: 0.01,
+                },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        // Default soil layer is 1 m with thermal conductivity 1.5 W/mK
+        let expected =
+            10.0 / (0.13 + 0.6 + 0.04) + 20.0 / (12.12f64.recip() + 0.6 + 0.01 + 1.0 / 1.5);
+        assert_ulps_eq!(model.ua_value().get::<watt_per_kelvin>(), expected);
+        assert_abs_diff_eq!(
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            expected,
+            epsilon = 1e-9
+        );
+    }
+
+    #[proptest]
+    fn merge_parallel_edges_keeps_steady_state(model: Model) {
+        let mut net = RcNetwork::try_from(&model).unwrap();
+        let fixed: HashMap<_, _> = net
+            .zone_indices
+            .values()
+            .map(|index| {
+                (
+                    *index,
+                    ThermodynamicTemperature::new::<kelvin>(270.0 + index.index() as f64),
+                )
+            })
+            .collect();
+        let before = net.solve_steady_state(&fixed).unwrap();
+        let edge_count = net.graph.edge_count();
+
+        net.merge_parallel_edges();
+
+        let after = net.solve_steady_state(&fixed).unwrap();
+        for (index, temperature) in before.iter() {
+            assert_abs_diff_eq!(
+                after[index].get::<kelvin>(),
+                temperature.get::<kelvin>(),
+                epsilon = 1e-6
+            );
+        }
+        assert!(net.graph.edge_count() <= edge_count);
+        let pairs: HashSet<_> = net
+            .graph
+            .edge_references()
+            .map(|edge| {
+                let mut pair = [edge.source(), edge.target()];
+                pair.sort();
+                pair
+            })
+            .collect();
+        assert_eq!(pairs.len(), net.graph.edge_count());
+    }
+
+    #[test]
+    fn merge_parallel_windows() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1, density: 1 },
+            },
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+                wall: { layers: [{ material: "brick", thickness: 0.1 }] },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: 10,
+                    sub_boundaries: [
+                        { boundary_type: "window", area: 1 },
+                        { boundary_type: "window", area: 2 },
+                    ],
+                },
+                { boundary_type: "window", zones: ["outside", "a"], area: 3 },
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let mut net = RcNetwork::try_from(&model).unwrap();
+        let window_conductance = |area: f64| reciprocal_sum!(12.12 * area, area, 12.12 * area);
+        // Three windows and two walls, each with two nodes and three edges
+        assert_eq!(net.graph.edge_count(), 3 + 2 * 3);
+
+        net.merge_parallel_edges();
+
+        assert_eq!(net.graph.edge_count(), 1 + 2 * 3);
+        let window = net
+            .graph
+            .edges_connecting(net.zone_indices["a"], net.zone_indices["outside"])
+            .exactly_one()
+            .unwrap();
+        assert_ulps_eq!(
+            window.weight().conductance.get::<watt_per_kelvin>(),
+            window_conductance(1.0) + window_conductance(2.0) + window_conductance(3.0)
+        );
+    }
+
+    #[test]
+    fn design_heat_load_sums_envelope_and_ventilation() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.5,
+                    specific_heat_capacity: 1000,
+                    density: 1500,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                    ]
+                },
+            },
+            zones: {
+                a: { volume: 50 },
+                b: { volume: 70 },
+            },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "wall", zones: ["outside", "b"], area: 30 },
+                { boundary_type: "wall", zones: ["a", "b"], area: 10 },
+                { boundary_type: "wall", zones: ["b", "ground"], area: 25 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let u = model.boundaries[0]
+            .boundary_type
+            .u_value()
+            .get::<watt_per_square_meter_kelvin>();
+        let ground = model
+            .boundary_conductance(&model.boundaries[3])
+            .get::<watt_per_kelvin>();
+
+        let setpoint = ThermodynamicTemperature::new::<degree_celsius>(20.0);
+        let (loads, total) = net
+            .design_heat_load(
+                ThermodynamicTemperature::new::<degree_celsius>(-12.0),
+                &HashMap::from([("a".to_string(), setpoint), ("b".to_string(), setpoint)]),
+                &HashMap::from([(
+                    "a".to_string(),
+                    ThermalConductance::new::<watt_per_kelvin>(10.0),
+                )]),
+            )
+            .unwrap();
+
+        // Both zones at the same temperature, no heat flows through the interior wall
+        assert_ulps_eq!(
+            loads["a"].get::<watt>(),
+            (u * 20.0 + 10.0) * 32.0,
+            max_ulps = 1000
+        );
+        assert_ulps_eq!(
+            loads["b"].get::<watt>(),
+            (u * 30.0 + ground) * 32.0,
+            max_ulps = 1000
+        );
+        assert_ulps_eq!(
+            total.get::<watt>(),
+            (u * 50.0 + ground + 10.0) * 32.0,
+            max_ulps = 1000
+        );
+
+        // Unheated zone b settles between a and outside, a has to cover the loss
+        let (loads, total) = net
+            .design_heat_load(
+                ThermodynamicTemperature::new::<degree_celsius>(-12.0),
+                &HashMap::from([("a".to_string(), setpoint)]),
+                &HashMap::new(),
+            )
+            .unwrap();
+        assert_eq!(loads.len(), 1);
+        assert!(total.get::<watt>() > u * 20.0 * 32.0);
+        assert!(total.get::<watt>() < u * 30.0 * 32.0);
+    }
+
+    #[test]
+    fn design_heat_load_air_changes_and_ventilation() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                air: { thermal_conductivity: 0.026, specific_heat_capacity: 1000, density: 1.2 },
+                brick: { thermal_conductivity: 0.8, specific_heat_capacity: 900, density: 1800 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: { a: { volume: 50, air_changes_per_hour: 0.5 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let wall = model
+            .boundary_conductance(&model.boundaries[0])
+            .get::<watt_per_kelvin>();
+        let air_changes = 50.0 * 1.2 * 1000.0 * 0.5 / 3600.0;
+        let design_heat_load = |ventilation: f64| {
+            net.design_heat_load(
+                ThermodynamicTemperature::new::<degree_celsius>(-12.0),
+                &HashMap::from([(
+                    "a".to_string(),
+                    ThermodynamicTemperature::new::<degree_celsius>(20.0),
+                )]),
+                &HashMap::from([(
+                    "a".to_string(),
+                    ThermalConductance::new::<watt_per_kelvin>(ventilation),
+                )]),
+            )
+            .unwrap()
+            .1
+            .get::<watt>()
+        };
+
+        // Air changes of the model are included without any ventilation
+        assert_ulps_eq!(
+            design_heat_load(0.0),
+            (wall + air_changes) * 32.0,
+            max_ulps = 1000
+        );
+        // Ventilation is added on top, even if it is the same air
+        assert_ulps_eq!(
+            design_heat_load(air_changes),
+            (wall + 2.0 * air_changes) * 32.0,
+            max_ulps = 1000
+        );
+    }
+
+    #[test]
+    fn design_heat_load_ventilation_without_setpoint() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let message = format!(
+            "{}",
+            net.design_heat_load(
+                ThermodynamicTemperature::new::<degree_celsius>(-12.0),
+                &HashMap::new(),
+                &HashMap::from([(
+                    "a".to_string(),
+                    ThermalConductance::new::<watt_per_kelvin>(10.0),
+                )]),
+            )
+            .unwrap_err()
+        );
+        message
+            .find("\"a\"")
+            .expect("Error message should contain the zone name");
+    }
+
+    #[test]
+    fn classify_boundaries_envelope_and_internal() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.5,
+                    specific_heat_capacity: 1000,
+                    density: 1500,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                        { material: "brick", thickness: 0.1 },
+                    ]
+                },
+                door: { u: 2, g: 0 },
+            },
+            zones: {
+                a: { volume: 50, internal_mass: { heat_capacity: 1000, area: 5 } },
+                b: { volume: 70 },
+            },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "wall", zones: ["b", "a"], area: 10 },
+                { boundary_type: "door", zones: ["a", "b"], area: 2 },
+                { boundary_type: "wall", zones: ["b", "ground"], area: 25 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let (envelope, internal) = net.classify_boundaries();
+
+        let zones = |boundaries: &[BoundaryRef]| {
+            boundaries
+                .iter()
+                .map(|b| b.zones.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(zones(&envelope), [["a", "outside"], ["b", "ground"]]);
+        assert_eq!(zones(&internal), [["a", "b"], ["a", "b"]]);
+        assert_eq!(internal[0].boundary_group_index, None);
+
+        for boundary in envelope.iter().chain(internal[1..].iter()) {
+            let model_boundary = model
+                .boundaries
+                .iter()
+                .find(|b| {
+                    let mut zones = [b.zones[0].name.as_str(), b.zones[1].name.as_str()];
+                    zones.sort();
+                    zones == [boundary.zones[0].as_str(), boundary.zones[1].as_str()]
+                        && b.boundary_type.name() == "wall"
+                })
+                .unwrap();
+            assert_ulps_eq!(
+                boundary.conductance.get::<watt_per_kelvin>(),
+                model
+                    .boundary_conductance(model_boundary)
+                    .get::<watt_per_kelvin>(),
+                max_ulps = 1000
+            );
+        }
+
+        // Only the envelope loses heat
+        let envelope_conductance: f64 = envelope
+            .iter()
+            .map(|b| b.conductance.get::<watt_per_kelvin>())
+            .sum();
+        assert_ulps_eq!(
+            envelope_conductance,
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            max_ulps = 1000
+        );
+    }
+
+    #[test_case("1e-300", "1e300", "conductance"; "vanishing conductance")]
+    #[test_case("1", "1e305", "heat capacity"; "enormous thickness")]
+    fn unusable_layer(thermal_conductivity: &str, thickness: &str, expected_problem: &str) {
+        let model = Model::from_json(&format!(
+            r#"{{
+            materials: {{
+                weird: {{
+                    thermal_conductivity: {},
+                    specific_heat_capacity: 1000,
+                    density: 1000,
+                }},
+            }},
+            boundary_types: {{
+                wall: {{
+                    layers: [
+                        {{ material: "weird", thickness: {} }},
+                    ]
+                }},
+            }},
+            zones: {{
+                a: {{ volume: 50 }},
+            }},
+            boundaries: [
+                {{ boundary_type: "wall", zones: ["a", "outside"], area: 20 }},
+            ],
+        }}"#,
+            thermal_conductivity, thickness
+        ))
+        .unwrap();
+
+        let message = format!("{}", RcNetwork::try_from(&model).unwrap_err());
+        message
+            .find("\"wall\"")
+            .expect("Error message should contain the boundary type name");
+        message
+            .find("\"a\"")
+            .expect("Error message should contain the zone name");
+        message
+            .find(expected_problem)
+            .expect("Error message should name the problem");
+    }
+
+    #[test]
+    fn internal_mass_node() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                wood: {
+                    thermal_conductivity: 0.15,
+                    specific_heat_capacity: 1600,
+                    density: 500,
+                },
+            },
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                a: {
+                    volume: 50,
+                    internal_mass: { material: "wood", volume: 0.5, area: 10 },
+                },
+                b: { volume: 50 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+                { boundary_type: "window", zones: ["b", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        let a = net.zone_indices["a"];
+        let mass_nodes: Vec<_> = net
+            .graph
+            .node_indices()
+            .filter(|index| net.graph[*index].zone_name.is_none())
+            .collect();
+        assert_eq!(mass_nodes.len(), 1);
+        let mass = mass_nodes[0];
+        assert_eq!(
+            net.graph[mass].heat_capacity,
+            HeatCapacity::new::<joule_per_kelvin>(0.5 * 500.0 * 1600.0)
+        );
+
+        let neighbors: Vec<_> = net.graph.neighbors(mass).collect();
+        assert_eq!(neighbors, [a]);
+        let edge = net.graph.find_edge(a, mass).unwrap();
+        assert_eq!(
+            net.graph[edge].conductance,
+            air_convection_conductance(Velocity::new::<meter_per_second>(0.0))
+                * Area::new::<square_meter>(10.0)
+        );
+    }
+
+    #[test]
+    fn zone_air_material() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                humid_air: {
+                    thermal_conductivity: 0.026,
+                    specific_heat_capacity: 1050,
+                    density: 1.15,
+                },
+            },
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                bathroom: { volume: 10, air: "humid_air" },
+                attic: { volume: 10 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["bathroom", "outside"], area: 1 },
+                { boundary_type: "window", zones: ["attic", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let heat_capacity = |zone: &str| {
+            net.graph[net.zone_indices[zone]]
+                .heat_capacity
+                .get::<joule_per_kelvin>()
+        };
+
+        assert_ulps_eq!(heat_capacity("bathroom"), 10.0 * 1.15 * 1050.0);
+        // Default air
+        assert_ulps_eq!(heat_capacity("attic"), 10.0 * 1.199 * 1012.0);
+        assert_ne!(heat_capacity("bathroom"), heat_capacity("attic"));
+    }
+
+    #[test]
+    fn air_change_edge() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                air: {
+                    thermal_conductivity: 0.026,
+                    specific_heat_capacity: 1000,
+                    density: 1.2,
+                },
+                brick: {
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: {
+                a: { volume: 50, air_changes_per_hour: 1 },
+                b: { volume: 50 },
+            },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "wall", zones: ["b", "outside"], area: 20 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let outside = net.zone_indices["outside"];
+
+        let conductances: Vec<_> = net
+            .graph
+            .edges_connecting(net.zone_indices["a"], outside)
+            .map(|edge| edge.weight().conductance.get::<watt_per_kelvin>())
+            .collect();
+        assert_eq!(conductances.len(), 1);
+        assert_abs_diff_eq!(
+            conductances[0],
+            50.0 * 1.2 * 1000.0 / 3600.0,
+            epsilon = 1e-9
+        );
+        assert!(net
+            .graph
+            .edges_connecting(net.zone_indices["b"], outside)
+            .next()
+            .is_none());
+        assert_abs_diff_eq!(
+            net.zone_heat_loss_coefficient("a")
+                .unwrap()
+                .get::<watt_per_kelvin>()
+                - net
+                    .zone_heat_loss_coefficient("b")
+                    .unwrap()
+                    .get::<watt_per_kelvin>(),
+            50.0 * 1.2 * 1000.0 / 3600.0,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test_case("10", "4, 6"; "exactly consumed")]
+    #[test_case("10", "3.3, 3.3, 3.4"; "nearly consumed, rounding up")]
+    #[test_case("0.3", "0.1, 0.2"; "nearly consumed, rounding down")]
+    fn consumed_parent_boundary_has_no_edge(area: &str, sub_areas: &str) {
+        let sub_areas: Vec<_> = sub_areas.split(", ").collect();
+        let sub_boundaries = sub_areas
+            .iter()
+            .map(|sub_area| format!(r#"{{ boundary_type: "window", area: {} }}"#, sub_area))
+            .join(", ");
+        let model = Model::from_json(&format!(
+            r#"{{
+            materials: {{
+                brick: {{
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                }},
+            }},
+            boundary_types: {{
+                wall: {{ layers: [{{ material: "brick", thickness: 0.3 }}] }},
+                window: {{ u: 1, g: 0.5 }},
+            }},
+            zones: {{ a: {{ volume: 50 }} }},
+            boundaries: [
+                {{
+                    boundary_type: "wall",
+                    zones: ["a", "outside"],
+                    area: {},
+                    sub_boundaries: [{}],
+                }},
+            ],
+        }}"#,
+            area, sub_boundaries
+        ))
+        .unwrap();
+        assert_eq!(model.boundaries.len(), sub_areas.len());
+        assert!(model
+            .boundaries
+            .iter()
+            .all(|boundary| boundary.boundary_type.name() == "window"));
+
+        let net = RcNetwork::try_from(&model).unwrap();
+        assert!(net
+            .graph
+            .edge_weights()
+            .all(|edge| edge.conductance.get::<watt_per_kelvin>() > 0.0));
+    }
+
+    #[test]
+    fn edited_layer_thickness_changes_edge() {
+        let mut model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                },
+                wool: {
+                    thermal_conductivity: 0.04,
+                    specific_heat_capacity: 1000,
+                    density: 30,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                        { material: "wool", thickness: 0.1 },
+                    ],
+                },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let layer_conductances = |model: &Model| {
+            let net = RcNetwork::try_from(model).unwrap();
+            net.graph
+                .edge_references()
+                .filter(|edge| {
+                    net.graph[edge.source()].zone_name.is_none()
+                        && net.graph[edge.target()].zone_name.is_none()
+                })
+                .map(|edge| edge.weight().conductance.get::<watt_per_kelvin>())
+                .sorted_by(f64::total_cmp)
+                .collect::<Vec<_>>()
+        };
+
+        let before = layer_conductances(&model);
+        assert_eq!(before.len(), 2);
+        assert_abs_diff_eq!(before[0], 0.04 * 10.0 / 0.1, epsilon = 1e-9);
+        assert_abs_diff_eq!(before[1], 0.8 * 10.0 / 0.3, epsilon = 1e-9);
+
+        model
+            .set_layer_thickness("wall", "wool", Length::new::<meter>(0.2))
+            .unwrap();
+        let after = layer_conductances(&model);
+        assert_abs_diff_eq!(after[0], 0.04 * 10.0 / 0.2, epsilon = 1e-9);
+        assert_abs_diff_eq!(after[1], before[1], epsilon = 1e-9);
+    }
+
+    #[test]
+    fn initial_temperatures_overrides() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 10 }, b: { volume: 10 } },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "b"], area: 1 },
+                { boundary_type: "window", zones: ["b", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let default = ThermodynamicTemperature::new::<degree_celsius>(15.0);
+        let warm = ThermodynamicTemperature::new::<degree_celsius>(22.0);
+        let overrides = HashMap::from([("a".to_string(), warm)]);
+
+        let temperatures = net.initial_temperatures(default, &overrides).unwrap();
+        assert_eq!(temperatures.len(), net.graph.node_count());
+        assert_eq!(temperatures[net.zone_indices["a"].index()], warm);
+        assert_eq!(temperatures[net.zone_indices["b"].index()], default);
+        assert_eq!(temperatures[net.zone_indices["outside"].index()], default);
+
+        let overrides = HashMap::from([("attic".to_string(), warm)]);
+        assert!(net.initial_temperatures(default, &overrides).is_err());
+    }
+
+    #[test]
+    fn combined_conductance_parallel_windows() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+                window_a: { u: 1.2, g: 0.5 },
+                window_b: { u: 0.8, g: 0.5 },
+            },
+            zones: { living: { volume: 50 }, bedroom: { volume: 30 } },
+            boundaries: [
+                { boundary_type: "window_a", zones: ["living", "outside"], area: 2 },
+                { boundary_type: "window_b", zones: ["living", "outside"], area: 3 },
+                { boundary_type: "wall", zones: ["living", "bedroom"], area: 10 },
+                { boundary_type: "wall", zones: ["bedroom", "outside"], area: 10 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let conductance = |a, b| {
+            net.combined_conductance(a, b)
+                .unwrap()
+                .get::<watt_per_kelvin>()
+        };
+
+        let windows: f64 = net
+            .graph
+            .edges(net.zone_indices["living"])
+            .filter(|edge| edge.target() == net.zone_indices["outside"])
+            .map(|edge| edge.weight().conductance.get::<watt_per_kelvin>())
+            .sum();
+        assert_eq!(
+            net.graph
+                .edges_connecting(net.zone_indices["living"], net.zone_indices["outside"])
+                .count(),
+            2
+        );
+        // Not through the bedroom
+        assert_abs_diff_eq!(conductance("living", "outside"), windows, epsilon = 1e-9);
+        assert_abs_diff_eq!(conductance("outside", "living"), windows, epsilon = 1e-9);
+        // Layers of the wall in series
+        assert_abs_diff_eq!(
+            conductance("bedroom", "outside"),
+            net.zone_heat_loss_coefficient("bedroom")
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            epsilon = 1e-9
+        );
+        assert!(net.combined_conductance("living", "attic").is_err());
+    }
+
+    #[test]
+    fn thermal_bridge_edge() {
+        let json = |thermal_bridges: &str| {
+            format!(
+                r#"{{
+                materials: {{
+                    brick: {{
+                        thermal_conductivity: 0.8,
+                        specific_heat_capacity: 900,
+                        density: 1800,
+                    }},
+                }},
+                boundary_types: {{
+                    wall: {{ layers: [{{ material: "brick", thickness: 0.3 }}] }},
+                }},
+                zones: {{ a: {{ volume: 50 }} }},
+                boundaries: [
+                    {{ boundary_type: "wall", zones: ["a", "outside"], area: 20 }},
+                ],
+                thermal_bridges: [{}],
+            }}"#,
+                thermal_bridges
+            )
+        };
+        let zone_hlc = |json: &str| {
+            let model = Model::from_json(json).unwrap();
+            RcNetwork::try_from(&model)
+                .unwrap()
+                .zone_heat_loss_coefficient("a")
+                .unwrap()
+                .get::<watt_per_kelvin>()
+        };
 
-        assert_eq!(net.graph.node_count(), expected_node_count);
-        assert_eq!(net.graph.edge_count(), expected_edge_count);
+        let without = zone_hlc(&json(""));
+        let with = zone_hlc(&json(
+            r#"{ zones: ["a", "outside"], length: 12, psi: 0.1 }"#,
+        ));
+        assert_abs_diff_eq!(with - without, 12.0 * 0.1, epsilon = 1e-9);
+
+        let model = Model::from_json(&json(
+            r#"{ zones: ["outside", "a"], length: 12, psi: 0.1 }"#,
+        ))
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        assert_abs_diff_eq!(
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            model.ua_value().get::<watt_per_kelvin>(),
+            epsilon = 1e-9
+        );
     }
 
-    /// Test that the total heat capacity of the model excluding outside zones
-    /// is the same as the total heat capacity of the RC network excluing infinite zones
-    /// and nothing gets lost.
-    #[proptest]
-    fn heat_capacity_sum(model: Model) {
-        let mut expected_capacity: HeatCapacity = model
-            .zones
-            .iter()
-            .filter_map(|(_, zone)| {
-                if zone.volume.is_some() {
-                    Some(zone.heat_capacity(&model.air))
-                } else {
-                    None
-                }
-            })
-            .sum();
-        expected_capacity += model
-            .boundaries
-            .iter()
-            .filter_map(|boundary| {
-                if let BoundaryType::Layered {
-                    name: _,
-                    layers,
-                    initial_marker: _,
-                } = boundary.boundary_type.as_ref()
-                {
-                    Some(
-                        layers
-                            .iter()
-                            .map(|layer| layer.heat_capacity(boundary.area))
-                            .sum(),
-                    )
-                } else {
-                    None
-                }
-            })
-            .sum();
+    #[test]
+    fn ground_coupling_through_soil() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                concrete: {
+                    thermal_conductivity: 1.4,
+                    specific_heat_capacity: 880,
+                    density: 2300,
+                },
+            },
+            boundary_types: {
+                slab: { layers: [{ material: "concrete", thickness: 0.2 }] },
+            },
+            zones: {
+                a: { volume: 50 },
+                b: { volume: 50 },
+            },
+            boundaries: [
+                { boundary_type: "slab", zones: ["a", "ground"], area: 20 },
+                { boundary_type: "slab", zones: ["b", "outside"], area: 20 },
+            ],
+            ground: { thermal_conductivity: 2, depth: 0.5 },
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
 
-        let net: RcNetwork = (&model).into();
+        let to_ground = net
+            .zone_heat_loss_coefficient("a")
+            .unwrap()
+            .get::<watt_per_kelvin>();
+        let to_air = net
+            .zone_heat_loss_coefficient("b")
+            .unwrap()
+            .get::<watt_per_kelvin>();
+        assert!(to_ground < to_air);
+        assert_abs_diff_eq!(
+            to_ground,
+            20.0 / (12.12f64.recip() + 0.2 / 1.4 + 0.5 / 2.0),
+            epsilon = 1e-9
+        );
+    }
 
-        let actual_capacity: HeatCapacity = net
-            .graph
-            .node_weights()
-            .filter_map(|node| {
-                if node.heat_capacity.is_finite() {
-                    Some(node.heat_capacity)
-                } else {
-                    None
-                }
-            })
-            .sum();
+    #[test]
+    fn load_wrong_version() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        bincode::serialize_into(&mut f, &(BINARY_FORMAT_VERSION + 1)).unwrap();
 
-        assert_ulps_eq!(
-            actual_capacity.get::<joule_per_kelvin>(),
-            expected_capacity.get::<joule_per_kelvin>()
-        );
+        let message = format!("{}", RcNetwork::load(f.path()).unwrap_err());
+        message
+            .find("version")
+            .expect("Error message should say that the version doesn't match");
     }
 
     #[test]
@@ -482,11 +3778,7 @@ mod tests {
         }"#,
         )
         .unwrap();
-        let net: RcNetwork = (&model).into();
-
-        // use std::io::Write;
-        // let mut file = std::fs::File::create("/tmp/graph.dot").unwrap();
-        // write!(file, "{}", net.to_dot()).unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
 
         let a = *net.zone_indices.get("a").unwrap();
         let b = *net.zone_indices.get("b").unwrap();
@@ -528,13 +3820,16 @@ mod tests {
 
         // Not checking conductance because I'm lazy
         assert!(net.graph.contains_edge(b, az[0]));
-        assert!(net.graph.contains_edge(ground, az[1]));
+        // The floor reaches ground through the soil layer
+        let soil = net.graph.neighbors(ground).exactly_one().unwrap();
+        assert_eq!(net.graph[soil].boundary_group_index, Some(1));
+        assert!(net.graph.contains_edge(soil, az[1]));
         assert!(net.graph.contains_edge(a, outside));
 
         // This loop is very ad-hoc, it just copies the structure of the manually
         // built test data.
         // Also it's fragile WRT ordering of items in the output.
-        // Uncomment the piece of code above to have a look at the actually generated network
+        // Print `net.to_dot()` to have a look at the actually generated network
         for i in 0..2 {
             println!("Loop index {}", i); // For easier debugging, should an assert fail in this loop
 
@@ -586,5 +3881,520 @@ mod tests {
                 }
             );
         }
+
+        let temperatures: Vec<_> = net
+            .graph
+            .node_indices()
+            .map(|index| ThermodynamicTemperature::new::<kelvin>(index.index() as f64))
+            .collect();
+        for (marker, indices) in [("x", ax), ("y", ay), ("z", az)] {
+            assert_eq!(
+                net.marker_temperature(&temperatures, "a", marker).unwrap(),
+                indices
+                    .iter()
+                    .map(|index| temperatures[index.index()])
+                    .collect::<Vec<_>>()
+            );
+        }
+        assert!(net.marker_temperature(&temperatures, "a", "w").is_none());
+        assert!(net.marker_temperature(&temperatures, "b", "x").is_none());
+        // Temperatures that don't reach the last marked node
+        let short = &temperatures[..ax.iter().max().unwrap().index()];
+        assert!(net.marker_temperature(short, "a", "x").is_none());
+    }
+
+    /// Total heat capacity of each boundary group, in J/K.
+    fn group_heat_capacities(net: &RcNetwork) -> BTreeMap<usize, f64> {
+        let mut capacities = BTreeMap::new();
+        for node in net.graph.node_weights() {
+            if let Some(group) = node.boundary_group_index {
+                *capacities.entry(group).or_default() +=
+                    node.heat_capacity.get::<joule_per_kelvin>();
+            }
+        }
+        capacities
+    }
+
+    #[proptest]
+    fn reduce_keeps_heat_capacity_and_conductance(
+        model: Model,
+        #[strategy(1usize..4)] max_nodes_per_boundary: usize,
+    ) {
+        let net = RcNetwork::try_from(&model).unwrap();
+        let reduced = net.reduce(max_nodes_per_boundary).unwrap();
+
+        let before = group_heat_capacities(&net);
+        let after = group_heat_capacities(&reduced);
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            after.keys().collect::<Vec<_>>()
+        );
+        // Marked nodes are never merged together
+        let group_nodes = |net: &RcNetwork, marked_only: bool| {
+            net.graph
+                .node_weights()
+                .filter(|node| !marked_only || node.marker.is_some())
+                .filter_map(|node| node.boundary_group_index)
+                .counts()
+        };
+        let marked = group_nodes(&net, true);
+        for (group, count) in group_nodes(&reduced, false) {
+            assert!(count <= max_nodes_per_boundary.max(marked.get(&group).copied().unwrap_or(0)));
+        }
+        for (group, capacity) in before {
+            assert_abs_diff_eq!(
+                after[&group],
+                capacity,
+                epsilon = 1e-9 * capacity.abs().max(1.0)
+            );
+        }
+
+        let conductances = |net: &RcNetwork| {
+            let (envelope, internal) = net.classify_boundaries();
+            envelope
+                .into_iter()
+                .chain(internal)
+                .map(|boundary| {
+                    (
+                        (boundary.boundary_group_index, boundary.zones),
+                        boundary.conductance.get::<watt_per_kelvin>(),
+                    )
+                })
+                .into_group_map()
+        };
+        let before = conductances(&net);
+        let after = conductances(&reduced);
+        assert_eq!(before.len(), after.len());
+        for (key, before) in before {
+            let mut before = before;
+            let mut after = after[&key].clone();
+            before.sort_by(f64::total_cmp);
+            after.sort_by(f64::total_cmp);
+            for (after, before) in after.iter().zip(before.iter()) {
+                assert_abs_diff_eq!(*after, *before, epsilon = 1e-9 * before.abs().max(1.0));
+            }
+        }
+
+        for zone in net.zone_indices.keys() {
+            assert_eq!(
+                reduced.graph[reduced.zone_indices[zone]]
+                    .zone_name
+                    .as_deref(),
+                Some(zone.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn reduce_thin_layers() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                plaster: { thermal_conductivity: 0.5, specific_heat_capacity: 1000, density: 1500 },
+                brick: { thermal_conductivity: 0.8, specific_heat_capacity: 900, density: 1800 },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "plaster", thickness: 0.01 },
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.1 },
+                        { marker: "middle" },
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.1 },
+                        { material: "plaster", thickness: 0.01 },
+                    ]
+                },
+            },
+            zones: { a: { volume: 50 } },
+            boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        // Zones a, outside and ground, 7 layers have 8 nodes
+        assert_eq!(net.graph.node_count(), 3 + 8);
+
+        assert!(net.reduce(0).is_err());
+
+        let reduced = net.reduce(3).unwrap();
+        assert_eq!(reduced.graph.node_count(), 3 + 3);
+        assert_eq!(reduced.graph.edge_count(), 4);
+        assert_abs_diff_eq!(
+            reduced
+                .heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            epsilon = 1e-12
+        );
+
+        let marker = ("a".to_string(), "middle".to_string());
+        let marked = reduced.marker_indices.get_vec(&marker).unwrap();
+        assert_eq!(marked.len(), 1);
+        assert_eq!(reduced.graph[marked[0]].marker, Some(marker));
+    }
+
+    #[test_case(r#"["a", "ground"]"# ; "zone first")]
+    #[test_case(r#"["ground", "a"]"# ; "ground first")]
+    fn reduce_ground_boundary(zones: &str) {
+        let model = Model::from_json(&format!(
+            r#"{{
+            materials: {{
+                concrete: {{ thermal_conductivity: 1.3, specific_heat_capacity: 1000, density: 2300 }},
+            }},
+            boundary_types: {{
+                floor: {{
+                    layers: [
+                        {{ material: "concrete", thickness: 0.1 }},
+                        {{ material: "concrete", thickness: 0.1 }},
+                        {{ material: "concrete", thickness: 0.1 }},
+                    ]
+                }},
+            }},
+            zones: {{ a: {{ volume: 50 }} }},
+            boundaries: [{{ boundary_type: "floor", zones: {}, area: 10 }}],
+        }}"#,
+            zones
+        ))
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        // Zones a, outside and ground, soil node and 3 layers have 4 nodes
+        assert_eq!(net.graph.node_count(), 3 + 1 + 4);
+
+        let reduced = net.reduce(2).unwrap();
+
+        assert_eq!(reduced.graph.node_count(), 3 + 2);
+        assert_abs_diff_eq!(
+            reduced.total_heat_capacity().get::<joule_per_kelvin>(),
+            net.total_heat_capacity().get::<joule_per_kelvin>(),
+            epsilon = 1e-6
+        );
+        assert_abs_diff_eq!(
+            reduced
+                .heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn zone_heat_loss_coefficient_single_wall() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.8, specific_heat_capacity: 900, density: 1800 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+                window: { u: 1.2, g: 0.5 },
+            },
+            zones: { a: { volume: 50 }, b: { volume: 30 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+                { boundary_type: "wall", zones: ["a", "b"], area: 8 },
+                { boundary_type: "window", zones: ["b", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        let wall = model
+            .boundaries
+            .iter()
+            .find(|boundary| boundary.zones[1].name == "outside")
+            .unwrap();
+        let a = net.zone_heat_loss_coefficient("a").unwrap();
+        assert_abs_diff_eq!(
+            a.get::<watt_per_kelvin>(),
+            (wall.u_value() * wall.area).get::<watt_per_kelvin>(),
+            epsilon = 1e-9
+        );
+
+        let b = net.zone_heat_loss_coefficient("b").unwrap();
+        assert_abs_diff_eq!(
+            (a + b).get::<watt_per_kelvin>(),
+            net.heat_loss_coefficient()
+                .unwrap()
+                .get::<watt_per_kelvin>(),
+            epsilon = 1e-9
+        );
+
+        let message = format!("{}", net.zone_heat_loss_coefficient("c").unwrap_err());
+        message
+            .find("\"c\"")
+            .expect("Error message should contain the zone name");
+        assert!(net.zone_heat_loss_coefficient("outside").is_err());
+    }
+
+    #[test]
+    fn to_graphml() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.1 }, { marker: "<core>" }] },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: { "a&b": { volume: 10 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a&b", "outside"], area: 10 },
+                { boundary_type: "window", zones: ["a&b", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let graphml = net.to_graphml();
+
+        assert_eq!(graphml.matches("<node ").count(), net.graph.node_count());
+        assert_eq!(graphml.matches("</node>").count(), net.graph.node_count());
+        assert_eq!(graphml.matches("<edge ").count(), net.graph.edge_count());
+        assert!(graphml.contains("<data key=\"zone_name\">a&amp;b</data>"));
+        assert!(graphml.contains("<data key=\"marker\">a&amp;b/&lt;core&gt;</data>"));
+        assert!(graphml.contains("<data key=\"heat_capacity\">500000</data>"));
+        assert!(graphml.contains("<data key=\"heat_capacity\">INF</data>"));
+        assert!(!graphml.contains("<core>"));
+        assert!(graphml.trim_end().ends_with("</graphml>"));
+    }
+
+    #[test]
+    fn to_csv() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.1 }, { marker: "core" }] },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: { "living, \"room\"": { volume: 10 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["living, \"room\"", "outside"], area: 10 },
+                { boundary_type: "window", zones: ["living, \"room\"", "outside"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let (nodes, edges) = net.to_csv();
+
+        let node_lines: Vec<_> = nodes.lines().collect();
+        assert_eq!(
+            node_lines[0],
+            "index,zone_name,marker,heat_capacity_J_per_K,boundary_group"
+        );
+        assert_eq!(node_lines.len(), net.graph.node_count() + 1);
+        let room = net.zone_indices["living, \"room\""].index();
+        let room_line = format!("{},\"living, \"\"room\"\"\",,", room);
+        assert!(node_lines.iter().any(|line| line.starts_with(&room_line)));
+        assert!(nodes.contains(",\"living, \"\"room\"\"/core\","));
+        assert!(nodes.contains(",outside,,inf,\n"));
+
+        let edge_lines: Vec<_> = edges.lines().collect();
+        assert_eq!(
+            edge_lines[0],
+            "source_index,target_index,conductance_W_per_K"
+        );
+        assert_eq!(edge_lines.len(), net.graph.edge_count() + 1);
+        for line in edge_lines[1..].iter() {
+            assert_eq!(line.split(',').count(), 3);
+        }
+    }
+
+    #[test]
+    fn condensation_risks_in_cold_wall() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 0.8, specific_heat_capacity: 1000, density: 1800 },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { marker: "inner" },
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.1 },
+                    ],
+                },
+            },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "wall", zones: ["a", "outside"], area: 10 }],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let a = net.zone_indices["a"];
+        let steady_state = net
+            .solve_steady_state(&HashMap::from([
+                (a, ThermodynamicTemperature::new::<degree_celsius>(20.0)),
+                (
+                    net.zone_indices["outside"],
+                    ThermodynamicTemperature::new::<degree_celsius>(-10.0),
+                ),
+                (
+                    net.zone_indices["ground"],
+                    ThermodynamicTemperature::new::<degree_celsius>(10.0),
+                ),
+            ]))
+            .unwrap();
+        let temperatures: Vec<_> = net
+            .graph
+            .node_indices()
+            .map(|index| steady_state[&index])
+            .collect();
+        let inner = net
+            .marker_indices
+            .get(&("a".into(), "inner".into()))
+            .unwrap();
+
+        let humid = HashMap::from([("a".to_string(), Ratio::new::<ratio>(0.6))]);
+        let risks = net.condensation_risks(&temperatures, &humid);
+        assert!(!risks.is_empty());
+        for risk in risks.iter() {
+            assert_eq!(risk.zone, "a");
+            assert_ne!(risk.node, *inner);
+            assert!(net.graph[risk.node].zone_name.is_none());
+            assert!(risk.temperature < risk.dew_point);
+            assert_eq!(risk.temperature, temperatures[risk.node.index()]);
+        }
+        assert_abs_diff_eq!(
+            risks[0].dew_point.get::<degree_celsius>(),
+            12.0,
+            epsilon = 0.1
+        );
+
+        let dry = HashMap::from([("a".to_string(), Ratio::new::<ratio>(0.1))]);
+        assert!(net.condensation_risks(&temperatures, &dry).is_empty());
+        assert!(net
+            .condensation_risks(&temperatures, &HashMap::new())
+            .is_empty());
+    }
+
+    #[test]
+    fn disconnected_zones() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { wall: { u: 1, g: 0 } },
+            zones: { a: { volume: 10 }, b: { volume: 20 }, c: { volume: 30 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10 },
+                { boundary_type: "wall", zones: ["b", "c"], area: 5 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let mut net = RcNetwork::try_from(&model).unwrap();
+
+        let components = net.connected_components();
+        assert_eq!(
+            components.iter().map(Vec::len).sum::<usize>(),
+            net.graph.node_count()
+        );
+        let component_of = |zone: &str| {
+            components
+                .iter()
+                .position(|component| component.contains(&net.zone_indices[zone]))
+                .unwrap()
+        };
+        assert_eq!(component_of("a"), component_of("outside"));
+        assert_eq!(component_of("b"), component_of("c"));
+        assert_ne!(component_of("a"), component_of("b"));
+
+        let message = format!("{}", net.assert_reaches_exterior().unwrap_err());
+        message
+            .find("[b, c]")
+            .expect("Error message should list the disconnected zones");
+        assert!(!message.contains("[a"));
+
+        net.graph.add_edge(
+            net.zone_indices["c"],
+            net.zone_indices["outside"],
+            Edge {
+                conductance: ThermalConductance::new::<watt_per_kelvin>(1.0),
+            },
+        );
+        net.assert_reaches_exterior().unwrap();
+    }
+
+    #[test]
+    fn dot_cluster_labels() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.1 }] },
+            },
+            zones: { a: { volume: 10 }, b: { volume: 20 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "b"], area: 10 },
+                { boundary_type: "wall", zones: ["b", "outside"], area: 5 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let dot = net.to_dot().to_string();
+
+        assert_eq!(net.boundary_groups, ["wall (a↔b)", "wall (b↔outside)"]);
+        assert!(dot.contains("subgraph cluster_0 {\n        label = \"wall (a↔b)\"\n"));
+        assert!(dot.contains("subgraph cluster_1 {\n        label = \"wall (b↔outside)\"\n"));
+    }
+
+    #[test]
+    fn write_dot() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1.23456, g: 0.5 } },
+            zones: { a: { volume: 10 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 2 }],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let f = tempfile::NamedTempFile::new().unwrap();
+
+        net.write_dot(f.path()).unwrap();
+        let dot = std::fs::read_to_string(f.path()).unwrap();
+
+        assert!(dot.contains("graph {"));
+        assert!(dot
+            .lines()
+            .any(|line| line.trim_start().starts_with("node_")));
+        assert!(!dot.contains("fillcolor"));
+
+        let dot = net.to_dot().highlight_exterior().precision(2).to_string();
+        let exterior_count = net
+            .graph
+            .node_weights()
+            .filter(|node| !node.heat_capacity.is_finite())
+            .count();
+        assert!(exterior_count > 0);
+        assert_eq!(dot.matches("fillcolor").count(), exterior_count);
+        let edge_labels: Vec<_> = dot
+            .lines()
+            .filter(|line| line.contains(" -- "))
+            .map(|line| line.split('"').nth(1).unwrap())
+            .collect();
+        assert!(!edge_labels.is_empty());
+        for label in edge_labels {
+            let value = label.strip_suffix(" W/K").unwrap();
+            assert_eq!(value.split('.').nth(1).unwrap().len(), 2, "{}", label);
+        }
     }
 }
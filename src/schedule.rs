@@ -0,0 +1,269 @@
+//! Repeating weekly schedules of setpoints, internal gains, ventilation rates, ...
+
+use chrono::{DateTime, Datelike, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use uom::si::{Dimension, Quantity, Units};
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+const SECONDS_PER_WEEK: u32 = 7 * SECONDS_PER_DAY;
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// How the value changes between two breakpoints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Value of a breakpoint holds until the next breakpoint
+    Step,
+    /// Value changes linearly between breakpoints
+    Linear,
+}
+
+/// Value of a schedule starting at given local time of given day of the week.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint<V> {
+    pub weekday: Weekday,
+    pub time: NaiveTime,
+    pub value: V,
+}
+
+/// Values that can be linearly interpolated.
+pub trait Interpolate: Copy {
+    /// Value at `fraction` of the way from `self` to `other`.
+    fn interpolate(self, other: Self, fraction: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(self, other: Self, fraction: f64) -> Self {
+        self + (other - self) * fraction
+    }
+}
+
+// Interpolating the base unit values directly also works for quantities like
+// `ThermodynamicTemperature`, that can't be subtracted.
+impl<D, U> Interpolate for Quantity<D, U, f64>
+where
+    D: Dimension + ?Sized,
+    U: Units<f64> + ?Sized,
+{
+    fn interpolate(self, other: Self, fraction: f64) -> Self {
+        Quantity {
+            dimension: self.dimension,
+            units: self.units,
+            value: self.value.interpolate(other.value, fraction),
+        }
+    }
+}
+
+/// Weekly repeating schedule, defined by breakpoints in local time of a time zone.
+/// Because the breakpoints are in local time, the schedule follows daylight saving
+/// time changes (a setpoint change at 6:00 happens at 6:00 both in winter and summer).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule<V> {
+    /// Breakpoints sorted by their time within the week
+    breakpoints: Vec<Breakpoint<V>>,
+    interpolation: Interpolation,
+    timezone: Tz,
+}
+
+impl<V: Interpolate> Schedule<V> {
+    /// Create a schedule from breakpoints in any order.
+    /// Fails if there are no breakpoints or if two breakpoints are at the same time.
+    pub fn new(
+        mut breakpoints: Vec<Breakpoint<V>>,
+        interpolation: Interpolation,
+        timezone: Tz,
+    ) -> anyhow::Result<Self> {
+        if breakpoints.is_empty() {
+            anyhow::bail!("Schedule must have at least one breakpoint");
+        }
+        breakpoints.sort_by_key(week_seconds);
+        for (a, b) in breakpoints.iter().zip(breakpoints.iter().skip(1)) {
+            if week_seconds(a) == week_seconds(b) {
+                anyhow::bail!(
+                    "Schedule has multiple breakpoints on {} at {}",
+                    a.weekday,
+                    a.time
+                );
+            }
+        }
+        Ok(Schedule {
+            breakpoints,
+            interpolation,
+            timezone,
+        })
+    }
+
+    /// Create a schedule that is the same every day of the week.
+    pub fn daily(
+        breakpoints: &[(NaiveTime, V)],
+        interpolation: Interpolation,
+        timezone: Tz,
+    ) -> anyhow::Result<Self> {
+        Self::new(
+            WEEKDAYS
+                .iter()
+                .flat_map(|&weekday| {
+                    breakpoints.iter().map(move |&(time, value)| Breakpoint {
+                        weekday,
+                        time,
+                        value,
+                    })
+                })
+                .collect(),
+            interpolation,
+            timezone,
+        )
+    }
+
+    /// Evaluate the schedule at the given time.
+    pub fn value_at(&self, time: DateTime<Utc>) -> V {
+        let local = time.with_timezone(&self.timezone);
+        let now = local.weekday().num_days_from_monday() * SECONDS_PER_DAY
+            + local.num_seconds_from_midnight();
+
+        // Last breakpoint at or before now, wrapping around to the previous week
+        let next_index = self
+            .breakpoints
+            .partition_point(|breakpoint| week_seconds(breakpoint) <= now);
+        let previous_index = next_index
+            .checked_sub(1)
+            .unwrap_or(self.breakpoints.len() - 1);
+        let next_index = next_index % self.breakpoints.len();
+        let previous = &self.breakpoints[previous_index];
+
+        match self.interpolation {
+            Interpolation::Step => previous.value,
+            Interpolation::Linear => {
+                let next = &self.breakpoints[next_index];
+                let since_previous =
+                    (now + SECONDS_PER_WEEK - week_seconds(previous)) % SECONDS_PER_WEEK;
+                let mut span = (week_seconds(next) + SECONDS_PER_WEEK - week_seconds(previous))
+                    % SECONDS_PER_WEEK;
+                if span == 0 {
+                    // Single breakpoint
+                    span = SECONDS_PER_WEEK;
+                }
+                previous
+                    .value
+                    .interpolate(next.value, f64::from(since_previous) / f64::from(span))
+            }
+        }
+    }
+}
+
+/// Seconds since Monday midnight of the breakpoint.
+fn week_seconds<V>(breakpoint: &Breakpoint<V>) -> u32 {
+    breakpoint.weekday.num_days_from_monday() * SECONDS_PER_DAY
+        + breakpoint.time.num_seconds_from_midnight()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use test_case::test_case;
+    use uom::si::{f64::ThermodynamicTemperature, thermodynamic_temperature::degree_celsius};
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn hm(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    /// Heating setpoint: 21 °C from 6:00 to 22:00 on workdays, from 8:00 to 23:00 on weekends,
+    /// 18 °C otherwise.
+    fn setpoint_schedule() -> Schedule<ThermodynamicTemperature> {
+        let celsius = ThermodynamicTemperature::new::<degree_celsius>;
+        let mut breakpoints = Vec::new();
+        for weekday in WEEKDAYS {
+            let (start, end) = if matches!(weekday, Weekday::Sat | Weekday::Sun) {
+                (hm(8, 0), hm(23, 0))
+            } else {
+                (hm(6, 0), hm(22, 0))
+            };
+            breakpoints.push(Breakpoint {
+                weekday,
+                time: start,
+                value: celsius(21.0),
+            });
+            breakpoints.push(Breakpoint {
+                weekday,
+                time: end,
+                value: celsius(18.0),
+            });
+        }
+        Schedule::new(breakpoints, Interpolation::Step, chrono_tz::Europe::Prague).unwrap()
+    }
+
+    // 2023-01-09 is Monday, 2023-01-14 is Saturday, Prague is UTC+1 in winter
+    #[test_case("2023-01-09T05:30:00Z", 21.0; "workday morning")]
+    #[test_case("2023-01-14T05:30:00Z", 18.0; "weekend morning")]
+    #[test_case("2023-01-14T21:30:00Z", 21.0; "weekend evening")]
+    #[test_case("2023-01-09T02:00:00Z", 18.0; "monday night wraps from sunday")]
+    fn weekday_and_weekend(utc: &str, expected: f64) {
+        assert_abs_diff_eq!(
+            setpoint_schedule()
+                .value_at(time(utc))
+                .get::<degree_celsius>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn follows_daylight_saving_time() {
+        let schedule = setpoint_schedule();
+        // 4:30 UTC is 5:30 local time in winter (UTC+1), but 6:30 in summer (UTC+2)
+        assert_abs_diff_eq!(
+            schedule
+                .value_at(time("2023-01-10T04:30:00Z"))
+                .get::<degree_celsius>(),
+            18.0
+        );
+        assert_abs_diff_eq!(
+            schedule
+                .value_at(time("2023-07-11T04:30:00Z"))
+                .get::<degree_celsius>(),
+            21.0
+        );
+    }
+
+    #[test]
+    fn linear_interpolation_across_midnight() {
+        let schedule = Schedule::daily(
+            &[(hm(6, 0), 100.0), (hm(18, 0), 0.0)],
+            Interpolation::Linear,
+            chrono_tz::UTC,
+        )
+        .unwrap();
+        assert_abs_diff_eq!(schedule.value_at(time("2023-03-01T12:00:00Z")), 50.0);
+        assert_abs_diff_eq!(schedule.value_at(time("2023-03-01T00:00:00Z")), 50.0);
+        assert_abs_diff_eq!(schedule.value_at(time("2023-03-01T03:00:00Z")), 75.0);
+        // Sunday evening to Monday morning
+        assert_abs_diff_eq!(schedule.value_at(time("2023-03-06T03:00:00Z")), 75.0);
+    }
+
+    #[test]
+    fn duplicate_breakpoints() {
+        let message = format!(
+            "{}",
+            Schedule::daily(
+                &[(hm(6, 0), 1.0), (hm(6, 0), 2.0)],
+                Interpolation::Step,
+                chrono_tz::UTC,
+            )
+            .unwrap_err()
+        );
+        message
+            .find("06:00")
+            .expect("Error message should contain the time of the breakpoint");
+    }
+}
@@ -0,0 +1,1173 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use na::{DMatrix, DVector};
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+use uom::si::{
+    f64::{Power, Ratio, ThermodynamicTemperature, Time, Velocity},
+    heat_capacity::joule_per_kelvin,
+    power::watt,
+    ratio::ratio,
+    thermal_conductance::watt_per_kelvin,
+    thermodynamic_temperature::kelvin,
+    time::second,
+    velocity::meter_per_second,
+};
+
+use crate::model::{BoundaryType, Model};
+use crate::rc_network::RcNetwork;
+use crate::tools::sun::{
+    calculate_tilted_irradiance, calculate_tilted_irradiance_components, incidence_angle_at,
+};
+
+/// Boundary conditions and heat sources for solving the RC network.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Inputs {
+    /// Temperatures of zones that are held fixed during the solve
+    /// (typically `outside` and `ground`).
+    pub boundary_temperatures: HashMap<String, ThermodynamicTemperature>,
+    /// Temperatures of the fixed zones at the end of a time step, if they change
+    /// during the step. Zones missing here keep their temperature from
+    /// `boundary_temperatures` over the whole step. Not used by the steady state solve.
+    pub boundary_temperatures_end: HashMap<String, ThermodynamicTemperature>,
+    /// Heat flow injected into zone nodes (solar gains, heating, ...).
+    pub heat_gains: HashMap<String, Power>,
+    /// Power of the heat sources of the model, by name (negative for cooling).
+    /// Sources that are not listed are off.
+    pub heat_sources: HashMap<String, Power>,
+    /// Wind speed at the building, if known.
+    pub wind_speed: Option<Velocity>,
+}
+
+/// Temperatures of all nodes of an RC network, evolving in time.
+#[derive(Clone, Debug)]
+pub struct Simulation<'a> {
+    network: &'a RcNetwork,
+    /// Node temperatures, indexed by the node index of the network graph.
+    temperatures: Vec<ThermodynamicTemperature>,
+}
+
+impl<'a> Simulation<'a> {
+    /// Start a simulation with all nodes at the same temperature.
+    pub fn new(network: &'a RcNetwork, initial_temperature: ThermodynamicTemperature) -> Self {
+        Simulation {
+            network,
+            temperatures: vec![initial_temperature; network.graph.node_count()],
+        }
+    }
+
+    /// Start a simulation with zone nodes at the given temperatures,
+    /// see `RcNetwork::initial_temperatures`.
+    pub fn with_initial_temperatures(
+        network: &'a RcNetwork,
+        zone_temperatures: &HashMap<String, ThermodynamicTemperature>,
+        default: ThermodynamicTemperature,
+    ) -> anyhow::Result<Self> {
+        let temperatures = network.initial_temperatures(default, zone_temperatures)?;
+        Ok(Simulation {
+            network,
+            temperatures,
+        })
+    }
+
+    /// Calculate the equilibrium temperatures of the network for the given inputs.
+    /// Zones with infinite heat capacity that don't have a boundary temperature
+    /// are treated as free nodes.
+    pub fn steady_state(network: &'a RcNetwork, inputs: &Inputs) -> anyhow::Result<Self> {
+        let mut fixed = vec![None; network.graph.node_count()];
+        set_boundary_temperatures(network, inputs, &mut fixed)?;
+
+        let mut system = LinearSystem::assemble(network, &fixed);
+        system.add_heat_gains(network, inputs)?;
+        let solution = system.solve()?;
+
+        Ok(Simulation {
+            network,
+            temperatures: system.temperatures(&fixed, &solution),
+        })
+    }
+
+    /// Advance the simulation by a single time step, using backward Euler integration.
+    /// Nodes with infinite heat capacity that don't have a boundary temperature
+    /// keep their current temperature.
+    /// Boundary temperatures that change during the step (`Inputs::boundary_temperatures_end`)
+    /// are replaced by their average over the step (trapezoidal rule), which reduces
+    /// the error of long time steps.
+    pub fn step(&mut self, inputs: &Inputs, dt: Time) -> anyhow::Result<()> {
+        let mut fixed: Vec<_> = self
+            .network
+            .graph
+            .node_weights()
+            .zip(self.temperatures.iter())
+            .map(|(node, temperature)| {
+                if node.heat_capacity.is_finite() {
+                    None
+                } else {
+                    Some(temperature.get::<kelvin>())
+                }
+            })
+            .collect();
+        set_boundary_temperatures(self.network, inputs, &mut fixed)?;
+
+        let mut fixed_end = fixed.clone();
+        for (zone, temperature) in inputs.boundary_temperatures_end.iter() {
+            let index = zone_index(self.network, zone)?;
+            if !inputs.boundary_temperatures.contains_key(zone) {
+                anyhow::bail!(
+                    "Zone {:?} has end temperature, but no start temperature",
+                    zone
+                );
+            }
+            fixed_end[index] = Some(temperature.get::<kelvin>());
+        }
+        let fixed_average: Vec<_> = fixed
+            .iter()
+            .zip(fixed_end.iter())
+            .map(|(start, end)| Some((start.as_ref()? + end.as_ref()?) / 2.0))
+            .collect();
+
+        let mut system = LinearSystem::assemble(self.network, &fixed_average);
+        system.add_heat_gains(self.network, inputs)?;
+
+        let dt = dt.get::<second>();
+        for (node_index, free_index) in system.free_indices.iter().enumerate() {
+            if let Some(free_index) = *free_index {
+                let c = self.network.graph.raw_nodes()[node_index]
+                    .weight
+                    .heat_capacity
+                    .get::<joule_per_kelvin>()
+                    / dt;
+                system.matrix[(free_index, free_index)] += c;
+                system.rhs[free_index] += c * self.temperatures[node_index].get::<kelvin>();
+            }
+        }
+
+        let solution = system.solve()?;
+        self.temperatures = system.temperatures(&fixed_end, &solution);
+        Ok(())
+    }
+
+    /// Run the simulation over a series of time steps, calling `callback` after each
+    /// step with the index of the step and the current state of the simulation.
+    /// The callback can inspect temperatures or `edge_heat_flows` without the whole
+    /// history having to be kept in memory.
+    pub fn run<'i, I, F>(&mut self, inputs: I, dt: Time, mut callback: F) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = &'i Inputs>,
+        F: FnMut(usize, &Self),
+    {
+        for (step, inputs) in inputs.into_iter().enumerate() {
+            self.step(inputs, dt)?;
+            callback(step, self);
+        }
+        Ok(())
+    }
+
+    /// Heat flow through every edge of the network, indexed by the edge index of
+    /// the network graph. Positive values flow from the source node of the edge
+    /// to its target.
+    pub fn edge_heat_flows(&self) -> Vec<Power> {
+        let graph = &self.network.graph;
+        graph
+            .edge_references()
+            .map(|edge| {
+                let t_source = self.temperatures[edge.source().index()].get::<kelvin>();
+                let t_target = self.temperatures[edge.target().index()].get::<kelvin>();
+                Power::new::<watt>(
+                    edge.weight().conductance.get::<watt_per_kelvin>() * (t_source - t_target),
+                )
+            })
+            .collect()
+    }
+
+    /// Temperatures of all nodes, indexed by the node index of the network graph.
+    pub fn temperatures(&self) -> &[ThermodynamicTemperature] {
+        &self.temperatures
+    }
+
+    pub fn zone_temperature(&self, zone: &str) -> Option<ThermodynamicTemperature> {
+        self.network
+            .zone_indices
+            .get(zone)
+            .map(|index| self.temperatures[index.index()])
+    }
+
+    /// Mean radiant temperature of a zone, approximated as the area weighted average
+    /// of the temperatures of surfaces facing the zone (first nodes of layered boundaries
+    /// and internal mass).
+    /// All surfaces have the same convection coefficient, so the conductance of the edge
+    /// between the zone and the surface is used as the weight.
+    /// Simple boundaries (windows, doors) don't have a surface node and are not included.
+    /// If the zone has no surfaces, its air temperature is returned.
+    pub fn mean_radiant_temperature(&self, zone: &str) -> Option<ThermodynamicTemperature> {
+        let graph = &self.network.graph;
+        let zone_index = *self.network.zone_indices.get(zone)?;
+
+        let (weighted_sum, weight_sum) = graph
+            .edges(zone_index)
+            .filter_map(|edge| {
+                let other = if edge.source() == zone_index {
+                    edge.target()
+                } else {
+                    edge.source()
+                };
+                if graph[other].zone_name.is_some() {
+                    return None;
+                }
+                let weight = edge.weight().conductance.get::<watt_per_kelvin>();
+                Some((self.temperatures[other.index()].get::<kelvin>(), weight))
+            })
+            .fold((0.0, 0.0), |(sum, weights), (t, weight)| {
+                (sum + t * weight, weights + weight)
+            });
+
+        if weight_sum > 0.0 {
+            Some(ThermodynamicTemperature::new::<kelvin>(
+                weighted_sum / weight_sum,
+            ))
+        } else {
+            self.zone_temperature(zone)
+        }
+    }
+
+    /// Operative temperature of a zone (the temperature occupants feel),
+    /// as a simple average of air and mean radiant temperature.
+    /// This corresponds to still air, see `operative_temperature_weighted`.
+    pub fn operative_temperature(&self, zone: &str) -> Option<ThermodynamicTemperature> {
+        self.operative_temperature_weighted(zone, Ratio::new::<ratio>(DEFAULT_AIR_WEIGHT))
+    }
+
+    /// Operative temperature of a zone as a weighted blend of air temperature and mean
+    /// radiant temperature: `air_weight * t_air + (1 - air_weight) * t_mrt`.
+    /// The weight can be derived from air speed using `operative_temperature_air_weight`.
+    pub fn operative_temperature_weighted(
+        &self,
+        zone: &str,
+        air_weight: Ratio,
+    ) -> Option<ThermodynamicTemperature> {
+        let air = self.zone_temperature(zone)?.get::<kelvin>();
+        let radiant = self.mean_radiant_temperature(zone)?.get::<kelvin>();
+        let air_weight = air_weight.get::<ratio>();
+        Some(ThermodynamicTemperature::new::<kelvin>(
+            air_weight * air + (1.0 - air_weight) * radiant,
+        ))
+    }
+}
+
+/// Weight of air temperature in the operative temperature for still air.
+pub const DEFAULT_AIR_WEIGHT: f64 = 0.5;
+
+/// Weight of air temperature in the operative temperature depending on the air speed
+/// around occupants, according to ASHRAE 55 (0.5 below 0.2 m/s, 0.6 up to 0.6 m/s,
+/// 0.7 above).
+pub fn operative_temperature_air_weight(air_speed: Velocity) -> Ratio {
+    let air_speed = air_speed.get::<meter_per_second>();
+    Ratio::new::<ratio>(if air_speed < 0.2 {
+        DEFAULT_AIR_WEIGHT
+    } else if air_speed < 0.6 {
+        0.6
+    } else {
+        0.7
+    })
+}
+
+/// Calculate solar heat gains through glazing (simple boundaries facing outside)
+/// for each zone.
+/// The irradiance is calculated for the orientation of each window, windows without
+/// one are treated as horizontal.
+pub fn solar_gains(
+    model: &Model,
+    datetime: &DateTime<Utc>,
+    cloud_cover: Ratio,
+) -> anyhow::Result<HashMap<String, Power>> {
+    let location = model
+        .location
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Model has no location, can't calculate solar gains"))?;
+
+    let mut gains = HashMap::new();
+    for boundary in model.boundaries.iter() {
+        if !matches!(boundary.boundary_type.as_ref(), BoundaryType::Simple { .. }) {
+            continue;
+        }
+        let orientation = boundary.orientation.unwrap_or_default();
+        let irradiance = calculate_tilted_irradiance_components(
+            location.latitude,
+            location.longitude,
+            datetime,
+            cloud_cover,
+            orientation.tilt,
+            orientation.azimuth,
+            &location.climate,
+        );
+        let incidence_angle = incidence_angle_at(
+            location.latitude,
+            location.longitude,
+            datetime,
+            orientation.tilt,
+            orientation.azimuth,
+        );
+        if let Some((zone, power)) = boundary.transmitted_solar_power(&irradiance, incidence_angle)
+        {
+            *gains
+                .entry(zone.name.clone())
+                .or_insert_with(|| Power::new::<watt>(0.0)) += power;
+        }
+    }
+
+    Ok(gains)
+}
+
+/// Calculate sunlight absorbed by opaque exterior walls, as heat injected into their
+/// outer surface nodes (see `RcNetwork::absorbed_solar_gains`).
+/// The irradiance is calculated for the orientation of each wall.
+pub fn absorbed_solar_gains(
+    network: &RcNetwork,
+    model: &Model,
+    datetime: &DateTime<Utc>,
+    cloud_cover: Ratio,
+) -> anyhow::Result<HashMap<NodeIndex, Power>> {
+    let location = model
+        .location
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Model has no location, can't calculate solar gains"))?;
+    let walls = model
+        .boundaries
+        .iter()
+        .enumerate()
+        .filter(|(_, boundary)| {
+            matches!(
+                boundary.boundary_type.as_ref(),
+                BoundaryType::Layered { .. }
+            )
+        })
+        .map(|(index, boundary)| {
+            let orientation = boundary.orientation.unwrap_or_default();
+            let irradiance = calculate_tilted_irradiance(
+                location.latitude,
+                location.longitude,
+                datetime,
+                cloud_cover,
+                orientation.tilt,
+                orientation.azimuth,
+                &location.climate,
+            );
+            (index, irradiance)
+        });
+    network.absorbed_solar_gains(model, walls)
+}
+
+/// Set temperatures of nodes fixed by the inputs, in kelvins.
+fn set_boundary_temperatures(
+    network: &RcNetwork,
+    inputs: &Inputs,
+    fixed: &mut [Option<f64>],
+) -> anyhow::Result<()> {
+    for (zone, temperature) in inputs.boundary_temperatures.iter() {
+        let index = zone_index(network, zone)?;
+        fixed[index] = Some(temperature.get::<kelvin>());
+    }
+    Ok(())
+}
+
+fn zone_index(network: &RcNetwork, zone: &str) -> anyhow::Result<usize> {
+    Ok(network
+        .zone_indices
+        .get(zone)
+        .ok_or_else(|| anyhow::anyhow!("Zone {:?} is not in the network", zone))?
+        .index())
+}
+
+/// Linear system for temperatures of the free (not fixed) nodes of the network.
+/// Rows contain the heat balance of each free node.
+pub(crate) struct LinearSystem {
+    /// Mapping of node index to the index in the linear system
+    free_indices: Vec<Option<usize>>,
+    matrix: DMatrix<f64>,
+    rhs: DVector<f64>,
+}
+
+impl LinearSystem {
+    /// Build the steady-state conductance matrix of the free nodes,
+    /// with fixed nodes moved to the right-hand side.
+    pub(crate) fn assemble(network: &RcNetwork, fixed: &[Option<f64>]) -> Self {
+        let mut free_count = 0;
+        let free_indices: Vec<_> = fixed
+            .iter()
+            .map(|temperature| {
+                if temperature.is_some() {
+                    None
+                } else {
+                    free_count += 1;
+                    Some(free_count - 1)
+                }
+            })
+            .collect();
+
+        let mut matrix = DMatrix::zeros(free_count, free_count);
+        let mut rhs = DVector::zeros(free_count);
+
+        for edge in network.graph.edge_references() {
+            let g = edge.weight().conductance.get::<watt_per_kelvin>();
+            let a = edge.source().index();
+            let b = edge.target().index();
+            for (this, other) in [(a, b), (b, a)] {
+                if let Some(row) = free_indices[this] {
+                    matrix[(row, row)] += g;
+                    match free_indices[other] {
+                        Some(column) => matrix[(row, column)] -= g,
+                        None => rhs[row] += g * fixed[other].unwrap(),
+                    }
+                }
+            }
+        }
+
+        LinearSystem {
+            free_indices,
+            matrix,
+            rhs,
+        }
+    }
+
+    /// Add heat gains and heat source power to the free nodes.
+    /// Gains into fixed nodes have no effect.
+    fn add_heat_gains(&mut self, network: &RcNetwork, inputs: &Inputs) -> anyhow::Result<()> {
+        for (zone, gain) in inputs.heat_gains.iter() {
+            if let Some(row) = self.free_indices[zone_index(network, zone)?] {
+                self.rhs[row] += gain.get::<watt>();
+            }
+        }
+        for (source, power) in inputs.heat_sources.iter() {
+            let index = network
+                .source_indices
+                .get(source)
+                .ok_or_else(|| anyhow::anyhow!("Heat source {:?} is not in the network", source))?;
+            if let Some(row) = self.free_indices[index.index()] {
+                self.rhs[row] += power.get::<watt>();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn solve(&self) -> anyhow::Result<DVector<f64>> {
+        // nalgebra's LU solve doesn't handle empty matrices (all nodes fixed)
+        if self.rhs.is_empty() {
+            return Ok(DVector::zeros(0));
+        }
+        self.matrix.clone().lu().solve(&self.rhs).ok_or_else(|| {
+            anyhow::anyhow!("Network can't be solved, some nodes are not connected to a fixed node")
+        })
+    }
+
+    /// Combine the fixed temperatures and the solution to the full list of node temperatures
+    pub(crate) fn temperatures(
+        &self,
+        fixed: &[Option<f64>],
+        solution: &DVector<f64>,
+    ) -> Vec<ThermodynamicTemperature> {
+        fixed
+            .iter()
+            .zip(self.free_indices.iter())
+            .map(|(fixed, free_index)| {
+                ThermodynamicTemperature::new::<kelvin>(match free_index {
+                    Some(i) => solution[*i],
+                    None => fixed.unwrap(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_network::air_convection_conductance;
+    use crate::tools::sun::{angular_transmittance_factor, DIFFUSE_TRANSMITTANCE_FACTOR};
+    use approx::assert_abs_diff_eq;
+    use test_case::test_case;
+    use uom::si::{
+        angle::degree,
+        f64::{Angle, HeatFluxDensity},
+        heat_flux_density::watt_per_square_meter,
+        thermodynamic_temperature::degree_celsius,
+        time::hour,
+    };
+
+    /// Single zone with heat capacity 1000 J/K, connected to outside through a window
+    /// with conductance 10 W/K.
+    fn single_zone_model() -> Model {
+        Model::from_json(
+            r#"{
+            materials: {
+                air: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 1,
+                    density: 1,
+                },
+            },
+            boundary_types: {
+                window: {
+                    u: 1,
+                    g: 0.5,
+                }
+            },
+            zones: {
+                a: { volume: 1000 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "window",
+                    zones: ["a", "outside"],
+                    area: 10,
+                }
+            ],
+        }"#,
+        )
+        .unwrap()
+    }
+
+    fn inputs(outside: f64, gain: f64) -> Inputs {
+        Inputs {
+            boundary_temperatures: HashMap::from([
+                (
+                    "outside".into(),
+                    ThermodynamicTemperature::new::<degree_celsius>(outside),
+                ),
+                (
+                    "ground".into(),
+                    ThermodynamicTemperature::new::<degree_celsius>(10.0),
+                ),
+            ]),
+            heat_gains: HashMap::from([("a".into(), Power::new::<watt>(gain))]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn initial_temperatures_interpolated() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 1,
+                    specific_heat_capacity: 1,
+                    density: 1,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.1 },
+                    ]
+                },
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                a: { volume: 10 },
+                b: { volume: 10 },
+                c: { volume: 10 },
+            },
+            boundaries: [
+                {
+                    boundary_type: "wall",
+                    zones: ["a", "b"],
+                    area: 10,
+                },
+                { boundary_type: "window", zones: ["c", "outside"], area: 1 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let default = ThermodynamicTemperature::new::<degree_celsius>(15.0);
+
+        let sim = Simulation::with_initial_temperatures(
+            &net,
+            &HashMap::from([
+                (
+                    "a".into(),
+                    ThermodynamicTemperature::new::<degree_celsius>(10.0),
+                ),
+                (
+                    "b".into(),
+                    ThermodynamicTemperature::new::<degree_celsius>(30.0),
+                ),
+            ]),
+            default,
+        )
+        .unwrap();
+
+        assert_eq!(sim.zone_temperature("c").unwrap(), default);
+        assert_eq!(sim.zone_temperature("outside").unwrap(), default);
+
+        let mut wall_temperatures: Vec<_> = net
+            .graph
+            .node_indices()
+            .filter(|index| net.graph[*index].boundary_group_index.is_some())
+            .map(|index| sim.temperatures()[index.index()].get::<degree_celsius>())
+            .collect();
+        assert_eq!(wall_temperatures.len(), 3);
+
+        // The wall is symmetric, middle node must be exactly in the middle
+        assert_abs_diff_eq!(wall_temperatures[1], 20.0, epsilon = 1e-9);
+        wall_temperatures.sort_by(f64::total_cmp);
+        assert!(wall_temperatures[0] > 10.0);
+        assert!(wall_temperatures[2] < 30.0);
+    }
+
+    #[test]
+    fn initial_temperatures_unknown_zone() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+
+        let result = Simulation::with_initial_temperatures(
+            &net,
+            &HashMap::from([(
+                "nowhere".into(),
+                ThermodynamicTemperature::new::<degree_celsius>(10.0),
+            )]),
+            ThermodynamicTemperature::new::<degree_celsius>(10.0),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn steady_state_single_zone() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let g = net.graph.raw_edges()[0]
+            .weight
+            .conductance
+            .get::<watt_per_kelvin>();
+
+        let sim = Simulation::steady_state(&net, &inputs(-10.0, 100.0)).unwrap();
+
+        assert_abs_diff_eq!(
+            sim.zone_temperature("a").unwrap().get::<degree_celsius>(),
+            -10.0 + 100.0 / g,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            sim.zone_temperature("outside")
+                .unwrap()
+                .get::<degree_celsius>(),
+            -10.0,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn internal_gains_raise_steady_state_by_power_over_hlc() {
+        let model = Model::from_json(
+            r#"{
+            materials: {},
+            boundary_types: { window: { u: 1, g: 0.5 } },
+            zones: { a: { volume: 100, internal_gains: 100 } },
+            boundaries: [{ boundary_type: "window", zones: ["a", "outside"], area: 10 }],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let time = DateTime::parse_from_rfc3339("2023-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let without_gains = inputs(-10.0, 0.0);
+        let with_gains = Inputs {
+            heat_gains: model.internal_gains(&time),
+            ..without_gains.clone()
+        };
+
+        let temperature = |inputs| {
+            Simulation::steady_state(&net, inputs)
+                .unwrap()
+                .zone_temperature("a")
+                .unwrap()
+                .get::<degree_celsius>()
+        };
+        assert_abs_diff_eq!(
+            temperature(&with_gains) - temperature(&without_gains),
+            100.0
+                / net
+                    .heat_loss_coefficient()
+                    .unwrap()
+                    .get::<watt_per_kelvin>(),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn steady_state_unknown_zone() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let mut inputs = inputs(0.0, 0.0);
+        inputs
+            .heat_gains
+            .insert("nowhere".into(), Power::new::<watt>(1.0));
+
+        let message = format!("{}", Simulation::steady_state(&net, &inputs).unwrap_err());
+        message
+            .find("nowhere")
+            .expect("Error message should contain the name of the unknown zone");
+    }
+
+    #[test]
+    fn heat_source_raises_steady_state_by_power_over_hlc() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.8,
+                    specific_heat_capacity: 900,
+                    density: 1800,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.1 },
+                        { material: "brick", thickness: 0.2 },
+                    ]
+                },
+                window: { u: 1.2, g: 0.5 },
+            },
+            zones: {
+                a: { volume: 50 },
+            },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+            ],
+            heat_sources: [
+                { name: "radiator", zone: "a", max_power: 2000 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let hlc = net
+            .zone_heat_loss_coefficient("a")
+            .unwrap()
+            .get::<watt_per_kelvin>();
+        assert_eq!(net.source_indices["radiator"], net.zone_indices["a"]);
+
+        let off = inputs(-5.0, 0.0);
+        let mut on = off.clone();
+        on.heat_sources
+            .insert("radiator".into(), Power::new::<watt>(1500.0));
+
+        let t_off = Simulation::steady_state(&net, &off)
+            .unwrap()
+            .zone_temperature("a")
+            .unwrap();
+        let t_on = Simulation::steady_state(&net, &on)
+            .unwrap()
+            .zone_temperature("a")
+            .unwrap();
+
+        assert_abs_diff_eq!(
+            t_on.get::<kelvin>() - t_off.get::<kelvin>(),
+            1500.0 / hlc,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn steady_state_unknown_heat_source() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let mut inputs = inputs(0.0, 0.0);
+        inputs
+            .heat_sources
+            .insert("radiator".into(), Power::new::<watt>(1.0));
+
+        let message = format!("{}", Simulation::steady_state(&net, &inputs).unwrap_err());
+        message
+            .find("radiator")
+            .expect("Error message should contain the name of the unknown heat source");
+    }
+
+    #[test]
+    fn step_converges_to_steady_state() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let inputs = inputs(5.0, 50.0);
+
+        let expected = Simulation::steady_state(&net, &inputs)
+            .unwrap()
+            .zone_temperature("a")
+            .unwrap();
+
+        let mut sim = Simulation::new(&net, ThermodynamicTemperature::new::<degree_celsius>(20.0));
+        for _ in 0..100 {
+            sim.step(&inputs, Time::new::<hour>(1.0)).unwrap();
+        }
+
+        assert_abs_diff_eq!(
+            sim.zone_temperature("a").unwrap().get::<kelvin>(),
+            expected.get::<kelvin>(),
+            epsilon = 1e-6
+        );
+    }
+
+    /// Outside temperature ramps during a long step, averaging the boundary temperature
+    /// over the step must get closer to a finely stepped reference than holding
+    /// the start temperature.
+    #[test]
+    fn step_ramped_boundary() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let initial = ThermodynamicTemperature::new::<degree_celsius>(0.0);
+        let outside = |t: f64| ThermodynamicTemperature::new::<degree_celsius>(10.0 * t);
+        let ramp = |start: f64, end: f64| Inputs {
+            boundary_temperatures: HashMap::from([
+                ("outside".into(), outside(start)),
+                ("ground".into(), initial),
+            ]),
+            boundary_temperatures_end: HashMap::from([("outside".into(), outside(end))]),
+            ..Default::default()
+        };
+        let total_time = Time::new::<second>(500.0);
+
+        let substeps = 1000;
+        let mut reference = Simulation::new(&net, initial);
+        for i in 0..substeps {
+            reference
+                .step(
+                    &ramp(i as f64 / substeps as f64, (i + 1) as f64 / substeps as f64),
+                    total_time / substeps as f64,
+                )
+                .unwrap();
+        }
+        let reference = reference.zone_temperature("a").unwrap().get::<kelvin>();
+
+        let mut constant = Simulation::new(&net, initial);
+        let mut inputs = ramp(0.0, 1.0);
+        inputs.boundary_temperatures_end.clear();
+        constant.step(&inputs, total_time).unwrap();
+        let constant = constant.zone_temperature("a").unwrap().get::<kelvin>();
+
+        let mut averaged = Simulation::new(&net, initial);
+        averaged.step(&ramp(0.0, 1.0), total_time).unwrap();
+        assert_eq!(averaged.zone_temperature("outside").unwrap(), outside(1.0));
+        let averaged = averaged.zone_temperature("a").unwrap().get::<kelvin>();
+
+        assert!((averaged - reference).abs() < (constant - reference).abs());
+    }
+
+    #[test]
+    fn step_end_temperature_without_start() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let mut sim = Simulation::new(&net, ThermodynamicTemperature::new::<degree_celsius>(0.0));
+
+        let result = sim.step(
+            &Inputs {
+                boundary_temperatures_end: HashMap::from([(
+                    "outside".into(),
+                    ThermodynamicTemperature::new::<degree_celsius>(1.0),
+                )]),
+                ..Default::default()
+            },
+            Time::new::<second>(1.0),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn step_keeps_unfixed_infinite_nodes() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let initial = ThermodynamicTemperature::new::<degree_celsius>(20.0);
+
+        let mut sim = Simulation::new(&net, initial);
+        sim.step(
+            &Inputs {
+                heat_gains: HashMap::from([("a".into(), Power::new::<watt>(1000.0))]),
+                ..Default::default()
+            },
+            Time::new::<second>(1.0),
+        )
+        .unwrap();
+
+        assert_eq!(sim.zone_temperature("outside").unwrap(), initial);
+        assert!(sim.zone_temperature("a").unwrap() > initial);
+    }
+
+    #[test]
+    fn operative_temperature_wall() {
+        let model = Model::from_json(
+            r#"{
+            materials: {
+                brick: {
+                    thermal_conductivity: 0.5,
+                    specific_heat_capacity: 1000,
+                    density: 1500,
+                },
+            },
+            boundary_types: {
+                wall: {
+                    layers: [
+                        { material: "brick", thickness: 0.3 },
+                    ]
+                },
+            },
+            zones: {
+                a: { volume: 100 },
+            },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 20 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let mut inputs = inputs(0.0, 0.0);
+        inputs.boundary_temperatures.insert(
+            "a".into(),
+            ThermodynamicTemperature::new::<degree_celsius>(20.0),
+        );
+        let sim = Simulation::steady_state(&net, &inputs).unwrap();
+
+        // Surface temperature from the heat flux through the wall
+        let u = model.boundaries[0].boundary_type.u_value();
+        let h = air_convection_conductance(Velocity::new::<meter_per_second>(0.0));
+        let surface = 20.0 - (u / h).value * 20.0;
+
+        assert_abs_diff_eq!(
+            sim.mean_radiant_temperature("a")
+                .unwrap()
+                .get::<degree_celsius>(),
+            surface,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            sim.operative_temperature("a")
+                .unwrap()
+                .get::<degree_celsius>(),
+            (20.0 + surface) / 2.0,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            sim.operative_temperature_weighted("a", Ratio::new::<ratio>(0.7))
+                .unwrap()
+                .get::<degree_celsius>(),
+            0.7 * 20.0 + 0.3 * surface,
+            epsilon = 1e-9
+        );
+        assert_eq!(sim.operative_temperature("nowhere"), None);
+    }
+
+    #[test]
+    fn operative_temperature_no_surfaces() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let sim = Simulation::steady_state(&net, &inputs(0.0, 1000.0)).unwrap();
+
+        assert_eq!(sim.operative_temperature("a"), sim.zone_temperature("a"));
+    }
+
+    #[test_case(0.0, 0.5; "still")]
+    #[test_case(0.3, 0.6; "moderate")]
+    #[test_case(0.8, 0.7; "fast")]
+    fn operative_temperature_air_weight_example(air_speed: f64, expected: f64) {
+        assert_eq!(
+            operative_temperature_air_weight(Velocity::new::<meter_per_second>(air_speed)),
+            Ratio::new::<ratio>(expected)
+        );
+    }
+
+    #[test]
+    fn run_records_edge_heat_flows() {
+        let model = single_zone_model();
+        let net = RcNetwork::try_from(&model).unwrap();
+        let mut sim = Simulation::new(&net, ThermodynamicTemperature::new::<degree_celsius>(0.0));
+        let inputs = vec![inputs(0.0, 1000.0); 200];
+
+        let mut history = Vec::new();
+        sim.run(&inputs, Time::new::<hour>(1.0), |step, sim| {
+            history.push((step, sim.edge_heat_flows()));
+        })
+        .unwrap();
+
+        assert_eq!(history.len(), inputs.len());
+        assert_eq!(history[0].0, 0);
+        assert_eq!(history[199].0, 199);
+
+        // The only edge is the window, at the end all of the heat gain flows through it
+        let edge = net.graph.edge_indices().next().unwrap();
+        let (source, _) = net.graph.edge_endpoints(edge).unwrap();
+        let sign = if source == net.zone_indices["a"] {
+            1.0
+        } else {
+            -1.0
+        };
+        assert!(sign * history[0].1[edge.index()].get::<watt>() < 1000.0);
+        assert_abs_diff_eq!(
+            sign * history[199].1[edge.index()].get::<watt>(),
+            1000.0,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn solar_gains_angular_transmittance() {
+        let model = Model::from_json(
+            r#"{
+            location: { latitude: 49.4949522, longitude: 17.4302361 },
+            materials: {},
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+                corrected_window: { u: 1, g: 0.5, angular_transmittance: true },
+            },
+            zones: {
+                a: { volume: 100 },
+                b: { volume: 100 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["a", "outside"], area: 2 },
+                { boundary_type: "corrected_window", zones: ["outside", "b"], area: 2 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let location = model.location.as_ref().unwrap();
+        // Low sun in the morning, the beam hits the horizontal glazing at grazing incidence
+        let datetime = DateTime::parse_from_rfc3339("2023-06-29T03:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let cloud_cover = Ratio::new::<ratio>(0.5);
+
+        let gains = solar_gains(&model, &datetime, cloud_cover).unwrap();
+
+        let incidence = incidence_angle_at(
+            location.latitude,
+            location.longitude,
+            &datetime,
+            Angle::new::<degree>(0.0),
+            Angle::new::<degree>(0.0),
+        )
+        .unwrap();
+        assert!(incidence > Angle::new::<degree>(80.0));
+        let irradiance = calculate_tilted_irradiance_components(
+            location.latitude,
+            location.longitude,
+            &datetime,
+            cloud_cover,
+            Angle::new::<degree>(0.0),
+            Angle::new::<degree>(0.0),
+            &location.climate,
+        );
+        // g * area of the windows
+        let window =
+            |irradiance: HeatFluxDensity| 0.5 * 2.0 * irradiance.get::<watt_per_square_meter>();
+        assert!(gains["a"].get::<watt>() > 0.0);
+        assert_abs_diff_eq!(
+            gains["a"].get::<watt>(),
+            window(irradiance.total),
+            epsilon = 1e-9
+        );
+        // Only the beam is reduced by the incidence angle, the diffuse light
+        // gets the hemispherical average
+        assert_abs_diff_eq!(
+            gains["b"].get::<watt>(),
+            window(irradiance.beam) * angular_transmittance_factor(incidence).get::<ratio>()
+                + window(irradiance.sky_diffuse) * DIFFUSE_TRANSMITTANCE_FACTOR,
+            epsilon = 1e-9
+        );
+        assert!(
+            (gains["b"] / gains["a"]).get::<ratio>()
+                > angular_transmittance_factor(incidence).get::<ratio>()
+        );
+        assert!(gains["b"] < gains["a"]);
+    }
+
+    #[test]
+    fn solar_gains_oriented_windows() {
+        let model = Model::from_json(
+            r#"{
+            location: { latitude: 49.4949522, longitude: 17.4302361 },
+            materials: {},
+            boundary_types: {
+                window: { u: 1, g: 0.5 },
+            },
+            zones: {
+                south: { volume: 100 },
+                north: { volume: 100 },
+            },
+            boundaries: [
+                { boundary_type: "window", zones: ["south", "outside"], area: 2, tilt: 90, azimuth: 180 },
+                { boundary_type: "window", zones: ["outside", "north"], area: 2, tilt: 90, azimuth: 0 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let location = model.location.as_ref().unwrap();
+        let noon = DateTime::parse_from_rfc3339("2023-06-29T10:53:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let gains = solar_gains(&model, &noon, Ratio::new::<ratio>(0.0)).unwrap();
+
+        let expected = |azimuth: f64| {
+            0.5 * 2.0
+                * calculate_tilted_irradiance(
+                    location.latitude,
+                    location.longitude,
+                    &noon,
+                    Ratio::new::<ratio>(0.0),
+                    Angle::new::<degree>(90.0),
+                    Angle::new::<degree>(azimuth),
+                    &location.climate,
+                )
+                .get::<watt_per_square_meter>()
+        };
+        assert_abs_diff_eq!(
+            gains["south"].get::<watt>(),
+            expected(180.0),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(gains["north"].get::<watt>(), expected(0.0), epsilon = 1e-9);
+        // The sun is in the south at noon, the north window only gets reflected light
+        assert!(gains["south"] > gains["north"] * 3.0);
+    }
+
+    #[test]
+    fn absorbed_solar_gains_oriented_walls() {
+        let model = Model::from_json(
+            r#"{
+            location: { latitude: 49.4949522, longitude: 17.4302361 },
+            materials: {
+                brick: { thermal_conductivity: 1, specific_heat_capacity: 1000, density: 1000 },
+            },
+            boundary_types: {
+                wall: { layers: [{ material: "brick", thickness: 0.3 }] },
+            },
+            zones: { a: { volume: 100 } },
+            boundaries: [
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10, tilt: 90, azimuth: 180 },
+                { boundary_type: "wall", zones: ["a", "outside"], area: 10, tilt: 90, azimuth: 0 },
+            ],
+        }"#,
+        )
+        .unwrap();
+        let network = RcNetwork::try_from(&model).unwrap();
+        let noon = DateTime::parse_from_rfc3339("2023-06-29T10:53:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let gains =
+            absorbed_solar_gains(&network, &model, &noon, Ratio::new::<ratio>(0.0)).unwrap();
+
+        let gain = |boundary: usize| {
+            let group = network.boundary_group_indices[boundary];
+            gains
+                .iter()
+                .find(|(node, _)| network.graph[**node].boundary_group_index == group)
+                .map_or(0.0, |(_, gain)| gain.get::<watt>())
+        };
+        assert!(gain(0) > 0.0);
+        assert!(gain(0) > gain(1));
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use na::DMatrix;
+use petgraph::graph::NodeIndex;
+use uom::si::{f64::Time, time::second};
+
+/// Linear continuous time system `dx/dt = A x + B u` of an RC network
+/// (see `RcNetwork::state_space`).
+/// States are temperatures of the network nodes with finite heat capacity [K],
+/// inputs are temperatures [K] and heat flows [W] described by `inputs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateSpace {
+    /// State matrix (states x states)
+    pub a: DMatrix<f64>,
+    /// Input matrix (states x inputs)
+    pub b: DMatrix<f64>,
+    /// Mapping of network node indices to state indices
+    pub state_indices: HashMap<NodeIndex, usize>,
+    /// Meaning of each input (column of `b`)
+    pub inputs: Vec<StateSpaceInput>,
+}
+
+/// Input of a `StateSpace` system.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StateSpaceInput {
+    /// Temperature of a node with infinite heat capacity (outside, ground)
+    Temperature(NodeIndex),
+    /// Heat flow injected into a zone or marker node (heating, solar gains, ...)
+    HeatFlow(NodeIndex),
+}
+
+impl StateSpace {
+    pub fn state_count(&self) -> usize {
+        self.a.nrows()
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.b.ncols()
+    }
+
+    /// Discretize the system with a time step, holding the inputs constant
+    /// during each step (zero order hold). Exact for piecewise constant inputs,
+    /// stays stable for any step, unlike the forward Euler method.
+    /// `c` is the output matrix of the discrete system (outputs x states).
+    pub fn discretize(&self, step: Time, c: DMatrix<f64>) -> anyhow::Result<DiscreteStateSpace> {
+        let n = self.state_count();
+        let m = self.input_count();
+        let mut augmented = DMatrix::zeros(n + m, n + m);
+        augmented.view_mut((0, 0), (n, n)).copy_from(&self.a);
+        augmented.view_mut((0, n), (n, m)).copy_from(&self.b);
+        let exponential = (augmented * step.get::<second>()).exp();
+        DiscreteStateSpace::new(
+            exponential.view((0, 0), (n, n)).into_owned(),
+            exponential.view((0, n), (n, m)).into_owned(),
+            c,
+        )
+    }
+}
+
+/// Linear discrete time system
+/// `x[k + 1] = A x[k] + B u[k]`, `y[k] = C x[k]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscreteStateSpace {
+    /// State transition matrix (states x states)
+    pub a: DMatrix<f64>,
+    /// Input matrix (states x inputs)
+    pub b: DMatrix<f64>,
+    /// Output matrix (outputs x states)
+    pub c: DMatrix<f64>,
+}
+
+impl DiscreteStateSpace {
+    /// Create the system, checking that the matrix dimensions match.
+    pub fn new(a: DMatrix<f64>, b: DMatrix<f64>, c: DMatrix<f64>) -> anyhow::Result<Self> {
+        if !a.is_square() {
+            anyhow::bail!(
+                "State matrix must be square, got {}x{}",
+                a.nrows(),
+                a.ncols()
+            );
+        }
+        if b.nrows() != a.nrows() {
+            anyhow::bail!(
+                "Input matrix has {} rows, expected {} (number of states)",
+                b.nrows(),
+                a.nrows()
+            );
+        }
+        if c.ncols() != a.nrows() {
+            anyhow::bail!(
+                "Output matrix has {} columns, expected {} (number of states)",
+                c.ncols(),
+                a.nrows()
+            );
+        }
+        Ok(DiscreteStateSpace { a, b, c })
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.a.nrows()
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.b.ncols()
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.c.nrows()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    /// Single node with heat capacity C = 1000 J/K connected to outside by conductance
+    /// G = 10 W/K, time constant RC = C / G = 100 s. With constant inputs over a step `t`
+    /// the exact solution is `x(t) = e^(-t/RC) x(0) + (1 - e^(-t/RC)) (T + P / G)`.
+    #[test]
+    fn discretize_single_node() {
+        let (heat_capacity, conductance) = (1000.0, 10.0);
+        let system = StateSpace {
+            a: DMatrix::from_element(1, 1, -conductance / heat_capacity),
+            b: DMatrix::from_row_slice(1, 2, &[conductance / heat_capacity, 1.0 / heat_capacity]),
+            state_indices: HashMap::from([(NodeIndex::new(1), 0)]),
+            inputs: vec![
+                StateSpaceInput::Temperature(NodeIndex::new(0)),
+                StateSpaceInput::HeatFlow(NodeIndex::new(1)),
+            ],
+        };
+
+        let discrete = system
+            .discretize(Time::new::<second>(50.0), DMatrix::identity(1, 1))
+            .unwrap();
+
+        let decay = (-50.0f64 / 100.0).exp();
+        assert_abs_diff_eq!(discrete.a[(0, 0)], decay, epsilon = 1e-12);
+        assert_abs_diff_eq!(discrete.b[(0, 0)], 1.0 - decay, epsilon = 1e-12);
+        assert_abs_diff_eq!(
+            discrete.b[(0, 1)],
+            (1.0 - decay) / conductance,
+            epsilon = 1e-12
+        );
+        assert_eq!(discrete.c, DMatrix::identity(1, 1));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod photovoltaics;
+pub mod psychrometrics;
+pub mod sky;
 pub mod sun;
 
 /// Calculate reciprocal sum of reciprocals.
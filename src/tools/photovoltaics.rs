@@ -0,0 +1,131 @@
+use uom::si::f64::*;
+use uom::si::heat_flux_density::watt_per_square_meter;
+use uom::si::power::watt;
+use uom::si::ratio::ratio;
+use uom::si::thermodynamic_temperature::degree_celsius;
+
+/// Cell temperature at standard test conditions [°C]
+const STC_CELL_TEMPERATURE: f64 = 25.0;
+/// Irradiance at the nominal operating cell temperature conditions [W/m²]
+const NOCT_IRRADIANCE: f64 = 800.0;
+/// Ambient temperature at the nominal operating cell temperature conditions [°C]
+const NOCT_AMBIENT_TEMPERATURE: f64 = 20.0;
+
+/// Typical nominal operating cell temperature of an open rack mounted crystalline
+/// silicon panel [°C]
+pub const TYPICAL_NOCT: f64 = 45.0;
+
+/// Calculate electrical power output of a photovoltaic panel,
+/// with efficiency derated linearly with the cell temperature
+/// `η = η_stc * (1 + temperature_coefficient * (T_cell - 25 °C))`
+///
+/// Arguments:
+/// * `poa_irradiance` - irradiance on the plane of the panel
+/// * `panel_area` - area of the panel
+/// * `stc_efficiency` - efficiency at standard test conditions (1000 W/m², 25 °C)
+/// * `temperature_coefficient` - relative change of efficiency per kelvin [1/K],
+///   typically around -0.004 for crystalline silicon
+/// * `cell_temperature` - temperature of the cells
+///
+/// Returns:
+/// * `Power` - electrical power, never negative
+pub fn pv_power(
+    poa_irradiance: HeatFluxDensity,
+    panel_area: Area,
+    stc_efficiency: Ratio,
+    temperature_coefficient: f64,
+    cell_temperature: ThermodynamicTemperature,
+) -> Power {
+    let derating = 1.0
+        + temperature_coefficient
+            * (cell_temperature.get::<degree_celsius>() - STC_CELL_TEMPERATURE);
+    let efficiency = stc_efficiency.get::<ratio>() * derating.max(0.0);
+    (poa_irradiance * panel_area * efficiency).max(Power::new::<watt>(0.0))
+}
+
+/// Estimate cell temperature of a photovoltaic panel from the ambient temperature,
+/// using the nominal operating cell temperature (NOCT) model
+/// `T_cell = T_ambient + (NOCT - 20 °C) * G / 800 W/m²`
+///
+/// Arguments:
+/// * `ambient_temperature` - temperature of the air around the panel
+/// * `poa_irradiance` - irradiance on the plane of the panel
+/// * `noct` - nominal operating cell temperature from the panel datasheet,
+///   see `TYPICAL_NOCT`
+///
+/// Returns:
+/// * `ThermodynamicTemperature` - estimated cell temperature
+pub fn noct_cell_temperature(
+    ambient_temperature: ThermodynamicTemperature,
+    poa_irradiance: HeatFluxDensity,
+    noct: ThermodynamicTemperature,
+) -> ThermodynamicTemperature {
+    let heating = (noct.get::<degree_celsius>() - NOCT_AMBIENT_TEMPERATURE)
+        * poa_irradiance.get::<watt_per_square_meter>()
+        / NOCT_IRRADIANCE;
+    ThermodynamicTemperature::new::<degree_celsius>(
+        ambient_temperature.get::<degree_celsius>() + heating,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use uom::si::area::square_meter;
+    use uom::si::ratio::percent;
+
+    fn celsius(value: f64) -> ThermodynamicTemperature {
+        ThermodynamicTemperature::new::<degree_celsius>(value)
+    }
+
+    fn panel_power(irradiance: f64, cell_temperature: ThermodynamicTemperature) -> f64 {
+        pv_power(
+            HeatFluxDensity::new::<watt_per_square_meter>(irradiance),
+            Area::new::<square_meter>(1.6),
+            Ratio::new::<percent>(20.0),
+            -0.004,
+            cell_temperature,
+        )
+        .get::<watt>()
+    }
+
+    #[test]
+    fn standard_test_conditions() {
+        assert_abs_diff_eq!(panel_power(1000.0, celsius(25.0)), 320.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn hot_day_derating() {
+        // 30 °C air, 900 W/m²: cells at 30 + 25 * 900 / 800 = 58.125 °C
+        let cell_temperature = noct_cell_temperature(
+            celsius(30.0),
+            HeatFluxDensity::new::<watt_per_square_meter>(900.0),
+            celsius(TYPICAL_NOCT),
+        );
+        assert_abs_diff_eq!(
+            cell_temperature.get::<degree_celsius>(),
+            58.125,
+            epsilon = 1e-9
+        );
+        let expected = 900.0 * 1.6 * 0.2 * (1.0 - 0.004 * 33.125);
+        assert_abs_diff_eq!(
+            panel_power(900.0, cell_temperature),
+            expected,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn no_irradiance() {
+        assert_eq!(panel_power(0.0, celsius(-10.0)), 0.0);
+        assert_eq!(
+            noct_cell_temperature(
+                celsius(-10.0),
+                HeatFluxDensity::new::<watt_per_square_meter>(0.0),
+                celsius(TYPICAL_NOCT)
+            ),
+            celsius(-10.0)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use uom::si::f64::*;
+use uom::si::frequency::hertz;
+use uom::si::power::watt;
+use uom::si::pressure::pascal;
+use uom::si::ratio::ratio;
+use uom::si::thermal_conductance::watt_per_kelvin;
+use uom::si::thermodynamic_temperature::{degree_celsius, kelvin};
+use uom::si::volume::cubic_meter;
+
+/// Standard atmospheric pressure [Pa]
+const ATMOSPHERIC_PRESSURE: f64 = 101325.0;
+/// Ratio of molar masses of water and dry air
+const MOLAR_MASS_RATIO: f64 = 0.621945;
+/// Specific gas constant of dry air [J/(kg.K)]
+const DRY_AIR_GAS_CONSTANT: f64 = 287.055;
+/// Latent heat of vaporization of water at 0 °C [J/kg]
+const LATENT_HEAT_OF_VAPORIZATION: f64 = 2.501e6;
+/// Specific heat capacity of dry air at constant pressure [J/(kg.K)]
+const DRY_AIR_SPECIFIC_HEAT: f64 = 1006.0;
+
+/// Calculate saturation vapor pressure over water
+/// using the Magnus formula (Alduchov and Eskridge coefficients)
+/// https://en.wikipedia.org/wiki/Clausius%E2%80%93Clapeyron_relation#Meteorology_and_climatology
+///
+/// Arguments:
+/// * `temperature` - air temperature
+///
+/// Returns:
+/// * `Pressure` - saturation vapor pressure
+pub fn saturation_vapor_pressure(temperature: ThermodynamicTemperature) -> Pressure {
+    let t = temperature.get::<degree_celsius>();
+    Pressure::new::<pascal>(610.94 * (17.625 * t / (t + 243.04)).exp())
+}
+
+/// Calculate dew point temperature by inverting the Magnus formula
+/// used in `saturation_vapor_pressure`
+///
+/// Arguments:
+/// * `temperature` - air temperature
+/// * `relative_humidity` - relative humidity of the air
+///
+/// Returns:
+/// * `ThermodynamicTemperature` - dew point temperature
+pub fn dew_point(
+    temperature: ThermodynamicTemperature,
+    relative_humidity: Ratio,
+) -> ThermodynamicTemperature {
+    let gamma = (vapor_pressure(temperature, relative_humidity) / 610.94).ln();
+    ThermodynamicTemperature::new::<degree_celsius>(243.04 * gamma / (17.625 - gamma))
+}
+
+/// Calculate humidity ratio (mass of water vapor per mass of dry air) of air
+/// at standard atmospheric pressure
+/// https://www.engineeringtoolbox.com/humidity-ratio-air-d_686.html
+///
+/// Arguments:
+/// * `temperature` - air temperature
+/// * `relative_humidity` - relative humidity of the air
+///
+/// Returns:
+/// * `Ratio` - humidity ratio [kg/kg]
+pub fn humidity_ratio(temperature: ThermodynamicTemperature, relative_humidity: Ratio) -> Ratio {
+    let vapor_pressure = vapor_pressure(temperature, relative_humidity);
+    Ratio::new::<ratio>(MOLAR_MASS_RATIO * vapor_pressure / (ATMOSPHERIC_PRESSURE - vapor_pressure))
+}
+
+/// Calculate the latent heat load caused by ventilating a zone with outdoor air
+/// of different humidity.
+/// This is the power needed to remove (positive values) or add (negative values)
+/// the moisture brought in by the ventilation air, so that the zone humidity stays constant.
+///
+/// Arguments:
+/// * `indoor_temperature` - temperature of the zone air
+/// * `indoor_humidity` - relative humidity of the zone air
+/// * `outdoor_temperature` - temperature of the incoming air
+/// * `outdoor_humidity` - relative humidity of the incoming air
+/// * `volume` - volume of the ventilated zone
+/// * `air_changes` - air change rate (e.g. `Frequency::new::<cycle_per_hour>(0.5)`)
+///
+/// Returns:
+/// * `Power` - latent ventilation load
+pub fn latent_ventilation_load(
+    indoor_temperature: ThermodynamicTemperature,
+    indoor_humidity: Ratio,
+    outdoor_temperature: ThermodynamicTemperature,
+    outdoor_humidity: Ratio,
+    volume: Volume,
+    air_changes: Frequency,
+) -> Power {
+    // The calculation is done outside of UOM, because the mixed kg/kg units would be awkward
+    let outdoor_vapor_pressure = vapor_pressure(outdoor_temperature, outdoor_humidity);
+    let dry_air_density = (ATMOSPHERIC_PRESSURE - outdoor_vapor_pressure)
+        / (DRY_AIR_GAS_CONSTANT * outdoor_temperature.get::<kelvin>());
+    let dry_air_mass_flow =
+        dry_air_density * volume.get::<cubic_meter>() * air_changes.get::<hertz>();
+
+    let humidity_ratio_difference = humidity_ratio(outdoor_temperature, outdoor_humidity)
+        - humidity_ratio(indoor_temperature, indoor_humidity);
+
+    Power::new::<watt>(
+        dry_air_mass_flow * LATENT_HEAT_OF_VAPORIZATION * humidity_ratio_difference.get::<ratio>(),
+    )
+}
+
+/// Calculate the sensible heat loss per kelvin caused by ventilating a zone
+/// with dry outdoor air at standard atmospheric pressure.
+///
+/// Arguments:
+/// * `outdoor_temperature` - temperature of the incoming air
+/// * `volume` - volume of the ventilated zone
+/// * `air_changes` - air change rate (e.g. `Frequency::new::<cycle_per_hour>(0.5)`)
+///
+/// Returns:
+/// * `ThermalConductance` - ventilation heat loss coefficient
+pub fn ventilation_conductance(
+    outdoor_temperature: ThermodynamicTemperature,
+    volume: Volume,
+    air_changes: Frequency,
+) -> ThermalConductance {
+    let density =
+        ATMOSPHERIC_PRESSURE / (DRY_AIR_GAS_CONSTANT * outdoor_temperature.get::<kelvin>());
+    ThermalConductance::new::<watt_per_kelvin>(
+        density * volume.get::<cubic_meter>() * air_changes.get::<hertz>() * DRY_AIR_SPECIFIC_HEAT,
+    )
+}
+
+/// Partial pressure of water vapor in air [Pa]
+fn vapor_pressure(temperature: ThermodynamicTemperature, relative_humidity: Ratio) -> f64 {
+    relative_humidity.get::<ratio>() * saturation_vapor_pressure(temperature).get::<pascal>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use test_case::test_case;
+    use uom::si::frequency::cycle_per_hour;
+    use uom::si::ratio::percent;
+
+    // Reference values from https://www.engineeringtoolbox.com/water-vapor-saturation-pressure-air-d_689.html
+    #[test_case(0.0, 611.0; "freezing")]
+    #[test_case(20.0, 2339.0; "room")]
+    #[test_case(30.0, 4246.0; "hot")]
+    fn saturation_vapor_pressure_example(temperature: f64, expected: f64) {
+        assert_abs_diff_eq!(
+            saturation_vapor_pressure(ThermodynamicTemperature::new::<degree_celsius>(temperature))
+                .get::<pascal>(),
+            expected,
+            epsilon = 10.0
+        );
+    }
+
+    // Reference values from a psychrometric chart
+    #[test_case(20.0, 50.0, 9.3; "room")]
+    #[test_case(30.0, 70.0, 23.9; "humid")]
+    #[test_case(10.0, 100.0, 10.0; "saturated")]
+    #[test_case(25.0, 60.0, 16.7; "summer")]
+    #[test_case(0.0, 80.0, -3.0; "below freezing")]
+    fn dew_point_example(temperature: f64, relative_humidity: f64, expected: f64) {
+        assert_abs_diff_eq!(
+            dew_point(
+                ThermodynamicTemperature::new::<degree_celsius>(temperature),
+                Ratio::new::<percent>(relative_humidity)
+            )
+            .get::<degree_celsius>(),
+            expected,
+            epsilon = 0.1
+        );
+    }
+
+    // Reference values from a psychrometric chart
+    #[test_case(20.0, 50.0, 0.0073; "room")]
+    #[test_case(30.0, 70.0, 0.0188; "humid")]
+    #[test_case(0.0, 100.0, 0.0038; "freezing")]
+    fn humidity_ratio_example(temperature: f64, relative_humidity: f64, expected: f64) {
+        assert_abs_diff_eq!(
+            humidity_ratio(
+                ThermodynamicTemperature::new::<degree_celsius>(temperature),
+                Ratio::new::<percent>(relative_humidity)
+            )
+            .get::<ratio>(),
+            expected,
+            epsilon = 0.0002
+        );
+    }
+
+    #[test]
+    fn ventilation_conductance_example() {
+        // 100 m3/h at ~1.2 kg/m3 is ~0.0333 kg/s, times ~1006 J/(kg.K)
+        assert_abs_diff_eq!(
+            ventilation_conductance(
+                ThermodynamicTemperature::new::<degree_celsius>(20.0),
+                Volume::new::<cubic_meter>(100.0),
+                Frequency::new::<cycle_per_hour>(1.0)
+            )
+            .get::<watt_per_kelvin>(),
+            33.5,
+            epsilon = 0.5
+        );
+    }
+
+    #[test]
+    fn latent_ventilation_load_same_air() {
+        let t = ThermodynamicTemperature::new::<degree_celsius>(22.0);
+        let rh = Ratio::new::<percent>(45.0);
+        assert_abs_diff_eq!(
+            latent_ventilation_load(
+                t,
+                rh,
+                t,
+                rh,
+                Volume::new::<cubic_meter>(100.0),
+                Frequency::new::<cycle_per_hour>(1.0)
+            )
+            .get::<watt>(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn latent_ventilation_load_humid_summer() {
+        // 100 m3/h of dry air at ~1.14 kg/m3, humidity ratio difference ~0.0094 kg/kg
+        let load = latent_ventilation_load(
+            ThermodynamicTemperature::new::<degree_celsius>(24.0),
+            Ratio::new::<percent>(50.0),
+            ThermodynamicTemperature::new::<degree_celsius>(30.0),
+            Ratio::new::<percent>(70.0),
+            Volume::new::<cubic_meter>(100.0),
+            Frequency::new::<cycle_per_hour>(1.0),
+        );
+        assert_abs_diff_eq!(load.get::<watt>(), 745.0, epsilon = 15.0);
+    }
+
+    #[test]
+    fn latent_ventilation_load_dry_winter() {
+        let load = latent_ventilation_load(
+            ThermodynamicTemperature::new::<degree_celsius>(21.0),
+            Ratio::new::<percent>(40.0),
+            ThermodynamicTemperature::new::<degree_celsius>(-5.0),
+            Ratio::new::<percent>(80.0),
+            Volume::new::<cubic_meter>(100.0),
+            Frequency::new::<cycle_per_hour>(0.5),
+        );
+        assert!(load.get::<watt>() < 0.0);
+    }
+}
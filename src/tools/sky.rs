@@ -0,0 +1,119 @@
+use uom::si::f64::*;
+use uom::si::ratio::ratio;
+use uom::si::thermodynamic_temperature::{degree_celsius, kelvin};
+
+use crate::tools::psychrometrics::dew_point;
+
+/// Correlation used for emissivity of a clear sky
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClearSkyModel {
+    /// Swinbank (1963), depends on air temperature only
+    Swinbank,
+    /// Berdahl and Martin (1984), depends on dew point
+    #[default]
+    BerdahlMartin,
+}
+
+/// Calculate effective sky temperature for longwave radiation exchange
+/// using the default clear sky model.
+///
+/// Arguments:
+/// * `air_temperature` - outdoor air temperature
+/// * `relative_humidity` - outdoor relative humidity
+/// * `cloud_cover` - cloud cover ratio
+///
+/// Returns:
+/// * `ThermodynamicTemperature` - sky temperature
+pub fn sky_temperature(
+    air_temperature: ThermodynamicTemperature,
+    relative_humidity: Ratio,
+    cloud_cover: Ratio,
+) -> ThermodynamicTemperature {
+    sky_temperature_with_model(
+        ClearSkyModel::default(),
+        air_temperature,
+        relative_humidity,
+        cloud_cover,
+    )
+}
+
+/// Calculate effective sky temperature for longwave radiation exchange.
+/// Clear sky emissivity is increased by clouds using the Clark and Allen correction
+/// (the same approach as EnergyPlus), so that overcast sky approaches the air temperature.
+///
+/// Arguments:
+/// * `model` - clear sky emissivity correlation
+/// * `air_temperature` - outdoor air temperature
+/// * `relative_humidity` - outdoor relative humidity
+/// * `cloud_cover` - cloud cover ratio
+///
+/// Returns:
+/// * `ThermodynamicTemperature` - sky temperature
+pub fn sky_temperature_with_model(
+    model: ClearSkyModel,
+    air_temperature: ThermodynamicTemperature,
+    relative_humidity: Ratio,
+    cloud_cover: Ratio,
+) -> ThermodynamicTemperature {
+    let clear_sky_emissivity = match model {
+        ClearSkyModel::Swinbank => 9.365e-6 * air_temperature.get::<kelvin>().powi(2),
+        ClearSkyModel::BerdahlMartin => {
+            let dew_point =
+                dew_point(air_temperature, relative_humidity).get::<degree_celsius>() / 100.0;
+            0.711 + 0.56 * dew_point + 0.73 * dew_point * dew_point
+        }
+    };
+    // Cloud cover in tenths
+    let n = 10.0 * cloud_cover.get::<ratio>().clamp(0.0, 1.0);
+    let cloud_factor = 1.0 + 0.0224 * n - 0.0035 * n * n + 0.00028 * n * n * n;
+    let emissivity = (clear_sky_emissivity * cloud_factor).min(1.0);
+
+    ThermodynamicTemperature::new::<kelvin>(air_temperature.get::<kelvin>() * emissivity.powf(0.25))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use uom::si::ratio::percent;
+
+    fn sky(model: ClearSkyModel, air: f64, rh: f64, cloud_cover: f64) -> f64 {
+        sky_temperature_with_model(
+            model,
+            ThermodynamicTemperature::new::<degree_celsius>(air),
+            Ratio::new::<percent>(rh),
+            Ratio::new::<percent>(cloud_cover),
+        )
+        .get::<degree_celsius>()
+    }
+
+    #[test]
+    fn swinbank_clear_sky() {
+        // Downwelling longwave radiation 5.31e-13 * T_air^6 [W/m²]
+        let radiation = 5.31e-13 * 293.15f64.powi(6);
+        let expected = (radiation / 5.670374e-8).powf(0.25) - 273.15;
+        assert_abs_diff_eq!(
+            sky(ClearSkyModel::Swinbank, 20.0, 50.0, 0.0),
+            expected,
+            epsilon = 0.05
+        );
+    }
+
+    #[test]
+    fn clear_vs_overcast() {
+        let clear = sky(ClearSkyModel::BerdahlMartin, 20.0, 50.0, 0.0);
+        let overcast = sky(ClearSkyModel::BerdahlMartin, 20.0, 50.0, 100.0);
+
+        assert!(clear < 5.0, "Clear sky should be much colder than air");
+        assert!(overcast > clear);
+        assert!(overcast < 20.0);
+        assert!(overcast > 10.0, "Overcast sky should be close to air");
+    }
+
+    #[test]
+    fn humid_air_warmer_sky() {
+        let dry = sky(ClearSkyModel::BerdahlMartin, 20.0, 20.0, 0.0);
+        let humid = sky(ClearSkyModel::BerdahlMartin, 20.0, 90.0, 0.0);
+        assert!(humid > dry);
+    }
+}